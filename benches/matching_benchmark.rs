@@ -1,37 +1,31 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
 use simple_stock_matcher_experiment::{
     bids::{Bid, BidProcessingType, SellingBid},
-    Pool,
+    generator::{GeneratorConfig, OrderGenerator, PriceDistribution},
+    BandedOrderBook, IncomingOrder, MatchingPolicy, OrderBook, Pool,
 };
 
-fn generate_matching_bids(
-    rng: &mut SmallRng,
-    max_price: u64,
-    how_many: usize,
-    user_id: u64,
-) -> Vec<Bid<SellingBid>> {
-    (0..how_many)
-        .map(|_| {
-            let price = rng.gen_range(0, max_price + 1);
-            let amount = rng.gen_range(1, 100);
-            Bid::empty().price(price).amount(amount).user_id(user_id)
-        })
-        .collect()
-}
-
-fn generate_non_matching_bids(
-    rng: &mut SmallRng,
+/// Generates selling bids of a single user priced between `min_price` and `max_price`.
+fn generate_bids(
+    seed: u64,
     min_price: u64,
+    max_price: u64,
     how_many: usize,
     user_id: u64,
 ) -> Vec<Bid<SellingBid>> {
+    let config = GeneratorConfig {
+        prices: PriceDistribution::Uniform {
+            min: min_price,
+            max: max_price,
+        },
+        buy_ratio: 0.0,
+        users: 1,
+        ..GeneratorConfig::default()
+    };
+    let mut generator = OrderGenerator::new(seed, config);
     (0..how_many)
-        .map(|_| {
-            let price = rng.gen_range(min_price + 1, u64::max_value());
-            let amount = rng.gen_range(1, 100);
-            Bid::empty().price(price).amount(amount).user_id(user_id)
-        })
+        .map(|_| generator.next_selling().0.user_id(user_id))
         .collect()
 }
 
@@ -42,17 +36,17 @@ fn generate_test_queue(
     price_level: u64,
     user_id: u64,
 ) -> (u64, Vec<Bid<SellingBid>>) {
-    let mut rng = SmallRng::seed_from_u64(seed);
-    let matching = generate_matching_bids(&mut rng, price_level, matching_amount, user_id);
+    let matching = generate_bids(seed, 0, price_level, matching_amount, user_id);
     let matching_items = matching.iter().map(|bid| bid.amount).sum();
-    let nonmatching = generate_non_matching_bids(
-        &mut rng,
-        price_level + 1,
+    let nonmatching = generate_bids(
+        seed + 1,
+        price_level + 2,
+        u64::MAX - 1,
         total_amount - matching_amount,
         user_id,
     );
     let mut combined: Vec<_> = matching.into_iter().chain(nonmatching).collect();
-    combined.shuffle(&mut rng);
+    combined.shuffle(&mut SmallRng::seed_from_u64(seed));
     (matching_items, combined)
 }
 
@@ -70,15 +64,138 @@ fn match_maker(c: &mut Criterion) {
     c.bench_function_over_inputs(
         "match_maker",
         move |bencher, &ty| {
-            bencher.iter_with_setup(|| pool.clone(), |mut pool| pool.process_bid(buying_bid, ty))
+            bencher.iter_with_setup(
+                || pool.clone(),
+                |mut pool| pool.process_bid(buying_bid, ty, &mut ()),
+            )
         },
         vec![
             BidProcessingType::Limit,
             BidProcessingType::FillOrKill,
             BidProcessingType::ImmediateOrCancel,
+            BidProcessingType::Market,
         ],
     );
 }
 
-criterion_group!(benches, match_maker);
+fn match_policy(c: &mut Criterion) {
+    let price = 20;
+    let seed = 10;
+    let (mathing_items_amount, pool) = generate_test_queue(seed, 7000, 20, price, 0);
+    let pool = Pool::from(pool);
+    let buying_bid = Bid::empty()
+        .price(price)
+        .amount(mathing_items_amount / 2)
+        .user_id(1);
+    c.bench_function_over_inputs(
+        "match_policy",
+        move |bencher, &policy| {
+            let mut pool = pool.clone();
+            pool.set_matching_policy(policy);
+            bencher.iter_with_setup(
+                || pool.clone(),
+                |mut pool| pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ()),
+            )
+        },
+        vec![MatchingPolicy::Fifo, MatchingPolicy::ProRata],
+    );
+}
+
+fn match_fill_or_kill(c: &mut Criterion) {
+    let price = 20;
+    let seed = 10;
+    let (mathing_items_amount, pool) = generate_test_queue(seed, 7000, 200, price, 0);
+    let pool = Pool::from(pool);
+    c.bench_function_over_inputs(
+        "match_fill_or_kill",
+        move |bencher, &amount| {
+            let buying_bid = Bid::empty().price(price).amount(amount).user_id(1);
+            bencher.iter_with_setup(
+                || pool.clone(),
+                |mut pool| pool.process_bid(buying_bid, BidProcessingType::FillOrKill, &mut ()),
+            )
+        },
+        // The first bid is filled completely, the second one is one item short and gets killed.
+        vec![mathing_items_amount, mathing_items_amount + 1],
+    );
+}
+
+fn match_stream(c: &mut Criterion) {
+    let price = 20;
+    let seed = 10;
+    let (mathing_items_amount, pool) = generate_test_queue(seed, 7000, 2000, price, 0);
+    let pool = Pool::from(pool);
+    // A stream of small bids, each of them filling a few resting bids, so the per-bid overhead of
+    // the matching dominates.
+    let buying_bids: Vec<_> = (0..mathing_items_amount / 100)
+        .map(|user_id| Bid::empty().price(price).amount(100).user_id(user_id + 1))
+        .collect();
+    c.bench_function("match_stream", move |bencher| {
+        bencher.iter_with_setup(
+            || pool.clone(),
+            |mut pool| {
+                for &buying_bid in &buying_bids {
+                    pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+                }
+                pool
+            },
+        )
+    });
+}
+
+fn banded_stream(c: &mut Criterion) {
+    let config = GeneratorConfig {
+        prices: PriceDistribution::Uniform { min: 1, max: 1000 },
+        ..GeneratorConfig::default()
+    };
+    let orders: Vec<_> = OrderGenerator::new(10, config).take(10_000).collect();
+    // Zero shards stand for a single-threaded `OrderBook`, the rest are `BandedOrderBook`s with
+    // bands of 50 prices.
+    c.bench_function_over_inputs(
+        "banded_stream",
+        move |bencher, &shards| {
+            bencher.iter(|| {
+                if shards == 0 {
+                    let mut order_book = OrderBook::empty();
+                    for order in &orders {
+                        match *order {
+                            IncomingOrder::Selling(bid, bid_type) => {
+                                order_book.process_selling(bid, bid_type);
+                            }
+                            IncomingOrder::Buying(bid, bid_type) => {
+                                order_book.process_buying(bid, bid_type);
+                            }
+                        }
+                    }
+                    order_book.depth(1)
+                } else {
+                    let mut order_book = BandedOrderBook::spawn(50, shards);
+                    for order in &orders {
+                        match *order {
+                            IncomingOrder::Selling(bid, bid_type) => {
+                                order_book.process_selling(bid, bid_type);
+                            }
+                            IncomingOrder::Buying(bid, bid_type) => {
+                                order_book.process_buying(bid, bid_type);
+                            }
+                        }
+                    }
+                    let depth = order_book.depth(1);
+                    order_book.join();
+                    depth
+                }
+            })
+        },
+        vec![0, 1, 2, 4],
+    );
+}
+
+criterion_group!(
+    benches,
+    match_maker,
+    match_policy,
+    match_fill_or_kill,
+    match_stream,
+    banded_stream
+);
 criterion_main!(benches);
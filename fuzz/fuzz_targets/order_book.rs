@@ -0,0 +1,116 @@
+//! Feeds arbitrary sequences of orders, cancellations, amendments and session changes to an
+//! order book built with the `debug-invariants` feature, so inconsistencies panic.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use simple_stock_matcher_experiment::{
+    bids::{Bid, BidProcessingType, TimeInForce},
+    OrderBook, OrderId, Trade,
+};
+
+#[derive(Debug, Arbitrary)]
+struct Order {
+    price: u32,
+    amount: u32,
+    display: Option<u32>,
+    min_fill: Option<u32>,
+    user_id: u8,
+    bid_type: u8,
+    time_in_force: u8,
+}
+
+#[derive(Debug, Arbitrary)]
+enum Action {
+    Sell(Order),
+    Buy(Order),
+    Cancel(u16),
+    Amend(u16, u32, u32),
+    AdvanceTime(u16),
+    StartAuction,
+    Uncross,
+    Halt,
+    Resume,
+}
+
+/// Builds a bid of the `order`, or `None` if it's empty. Like the engine, the book expects the
+/// bids to be validated, so empty ones are not submitted.
+fn bid<BidKind>(order: &Order, now: u64) -> Option<(Bid<BidKind>, BidProcessingType)> {
+    if order.amount == 0 {
+        return None;
+    }
+    let mut bid = Bid::empty()
+        .price(u64::from(order.price))
+        .amount(u64::from(order.amount))
+        .user_id(u64::from(order.user_id % 8))
+        .time_in_force(match order.time_in_force % 3 {
+            0 => TimeInForce::GoodTillCancel,
+            1 => TimeInForce::Day,
+            _ => TimeInForce::GoodTillDate(now + u64::from(order.time_in_force)),
+        });
+    if let Some(display) = order.display.filter(|&display| display != 0) {
+        bid = bid.display(u64::from(display));
+    }
+    if let Some(min_fill) = order.min_fill {
+        bid = bid.min_fill(u64::from(min_fill));
+    }
+    let bid_type = match order.bid_type % 5 {
+        0 => BidProcessingType::Limit,
+        1 => BidProcessingType::Market,
+        2 => BidProcessingType::ImmediateOrCancel,
+        3 => BidProcessingType::FillOrKill,
+        _ => BidProcessingType::PostOnly,
+    };
+    Some((bid, bid_type))
+}
+
+/// Checks the trades of an incoming bid. Auctions ignore the user groups, so their trades are not
+/// checked.
+fn check(trades: &[Trade]) {
+    for trade in trades {
+        assert_ne!(trade.maker_user_id, trade.taker_user_id, "A self-trade");
+        assert_ne!(trade.amount, 0, "An empty trade");
+    }
+}
+
+fuzz_target!(|actions: Vec<Action>| {
+    let mut order_book = OrderBook::empty();
+    let mut now = 0;
+    for action in actions {
+        match action {
+            Action::Sell(order) => {
+                if let Some((bid, bid_type)) = bid(&order, now) {
+                    check(&order_book.process_selling(bid, bid_type).trades);
+                }
+            }
+            Action::Buy(order) => {
+                if let Some((bid, bid_type)) = bid(&order, now) {
+                    check(&order_book.process_buying(bid, bid_type).trades);
+                }
+            }
+            Action::Cancel(order_id) => {
+                order_book.cancel(OrderId(usize::from(order_id)));
+            }
+            Action::Amend(order_id, price, size) => {
+                order_book.amend(
+                    OrderId(usize::from(order_id)),
+                    u64::from(price),
+                    u64::from(size),
+                );
+            }
+            Action::AdvanceTime(seconds) => {
+                now += u64::from(seconds);
+                order_book.advance_time(now);
+            }
+            Action::StartAuction => order_book.start_auction(),
+            Action::Uncross => {
+                order_book.uncross();
+            }
+            Action::Halt => order_book.halt(),
+            Action::Resume => {
+                order_book.resume();
+            }
+        }
+    }
+});
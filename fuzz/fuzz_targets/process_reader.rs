@@ -0,0 +1,13 @@
+//! Feeds arbitrary bytes to the `yaml` reader of orders.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use simple_stock_matcher_experiment::{process_reader, MatchingEngine, ProcessingOptions};
+
+fuzz_target!(|data: &[u8]| {
+    for &skip_invalid in &[false, true] {
+        let mut engine = MatchingEngine::new();
+        let _ = process_reader(&mut engine, data, ProcessingOptions { skip_invalid });
+    }
+});
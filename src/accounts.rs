@@ -0,0 +1,199 @@
+//! Per-user positions and cash balances.
+
+use crate::{bids::Side, trade::Trade};
+use std::collections::BTreeMap;
+
+/// Net position and cash balance of a user.
+///
+/// Buying increases the position by the traded amount and decreases the cash by the price times
+/// the amount, selling does the opposite. Both start at zero, so a negative position is a short
+/// one and a negative balance is money owed. The cash saturates, like the turnover of
+/// [`TradeStats`](crate::TradeStats).
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Account {
+    /// Net amount of items held.
+    pub position: i128,
+    /// Cash balance.
+    pub cash: i128,
+}
+
+impl Account {
+    fn trade(&mut self, side: Side, trade: &Trade) {
+        let amount = i128::from(trade.amount);
+        let notional = i128::from(trade.price).saturating_mul(amount);
+        match side {
+            Side::Buy => {
+                self.position += amount;
+                self.cash = self.cash.saturating_sub(notional);
+            }
+            Side::Sell => {
+                self.position -= amount;
+                self.cash = self.cash.saturating_add(notional);
+            }
+        }
+    }
+}
+
+/// Accounts of the users of a stream of trades, a toy clearing system.
+///
+/// An [`OrderBook`](crate::OrderBook) configured to track positions keeps the accounts of its own
+/// users, see [`OrderBook::accounts`](crate::OrderBook::accounts). A standalone instance can be fed
+/// with trades from any other source, e.g. the tape of a replayed book.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Accounts {
+    accounts: BTreeMap<u64, Account>,
+}
+
+impl Accounts {
+    /// Initializes accounts without any users.
+    pub fn new() -> Self {
+        Accounts::default()
+    }
+
+    /// Accounts for a trade on both the maker's and the taker's side.
+    pub fn record(&mut self, trade: &Trade) {
+        let maker_side = match trade.taker_side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        self.accounts
+            .entry(trade.taker_user_id)
+            .or_default()
+            .trade(trade.taker_side, trade);
+        self.accounts
+            .entry(trade.maker_user_id)
+            .or_default()
+            .trade(maker_side, trade);
+    }
+
+    /// Account of the user; users who haven't traded have empty accounts.
+    pub fn get(&self, user_id: u64) -> Account {
+        self.accounts.get(&user_id).copied().unwrap_or_default()
+    }
+
+    /// Net position of the user.
+    pub fn position(&self, user_id: u64) -> i128 {
+        self.get(user_id).position
+    }
+
+    /// Accounts of the users who have traded, in the order of their ids.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &Account)> {
+        self.accounts
+            .iter()
+            .map(|(&user_id, account)| (user_id, account))
+    }
+}
+
+impl<'a> Extend<&'a Trade> for Accounts {
+    fn extend<I: IntoIterator<Item = &'a Trade>>(&mut self, trades: I) {
+        trades.into_iter().for_each(|trade| self.record(trade))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accounts() {
+        let trade = Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Buy,
+            price: 100,
+            amount: 10,
+        };
+        let mut accounts = Accounts::new();
+        accounts.extend(&[
+            trade,
+            Trade {
+                maker_user_id: 3,
+                taker_user_id: 1,
+                price: 90,
+                amount: 4,
+                ..trade
+            },
+            Trade {
+                maker_user_id: 3,
+                taker_side: Side::Sell,
+                price: 110,
+                amount: 5,
+                ..trade
+            },
+        ]);
+        let expected = [
+            (
+                1,
+                Account {
+                    position: -6,
+                    cash: 640,
+                },
+            ),
+            (
+                2,
+                Account {
+                    position: 5,
+                    cash: -450,
+                },
+            ),
+            (
+                3,
+                Account {
+                    position: 1,
+                    cash: -190,
+                },
+            ),
+        ];
+        let actual: Vec<_> = accounts
+            .iter()
+            .map(|(user_id, account)| (user_id, *account))
+            .collect();
+        assert_eq!(actual, expected);
+        assert_eq!(accounts.position(2), 5);
+        assert_eq!(accounts.get(4), Account::default());
+    }
+
+    #[test]
+    fn test_self_trade() {
+        let mut accounts = Accounts::new();
+        accounts.record(&Trade {
+            maker_user_id: 1,
+            taker_user_id: 1,
+            taker_side: Side::Sell,
+            price: 100,
+            amount: 10,
+        });
+        assert_eq!(accounts.get(1), Account::default());
+        assert_eq!(accounts.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_huge_trades() {
+        let max = u64::MAX;
+        let trade = Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Buy,
+            price: max,
+            amount: max,
+        };
+        let mut accounts = Accounts::new();
+        accounts.extend(&[trade, trade]);
+        // The notional doesn't fit into the balance, which saturates.
+        let expected = Account {
+            position: 2 * i128::from(max),
+            cash: i128::MIN,
+        };
+        assert_eq!(accounts.get(2), expected);
+        assert_eq!(accounts.get(1).cash, i128::MAX);
+        assert_eq!(accounts.position(1), -2 * i128::from(max));
+    }
+}
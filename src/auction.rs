@@ -0,0 +1,197 @@
+//! Call auctions.
+
+use crate::{
+    bids::{Bid, BuyingBid, SellingBid, Side},
+    pool::saturate,
+    trade::Trade,
+};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BTreeMap,
+};
+
+/// Theoretical outcome of uncrossing an order book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Equilibrium {
+    /// Clearing price.
+    pub price: u64,
+    /// Amount of items that would be executed at the clearing price.
+    pub volume: u64,
+    /// Amount of items of the crossing bids that would be left unexecuted.
+    pub imbalance: u64,
+    /// Side of the unexecuted crossing bids, `None` if there's no imbalance.
+    pub imbalance_side: Option<Side>,
+}
+
+/// Finds the price that maximizes the executed volume if all the crossing bids were matched at it.
+///
+/// Levels are `(price, amount)` pairs. Among the prices with the same volume the one with the
+/// lowest imbalance wins, then the one closest to the `reference` price (if any), then the lowest
+/// one. Returns `None` if no bids cross.
+///
+/// The amounts are summed up in `u128`, the volume and the imbalance saturate at `u64::MAX`.
+pub(crate) fn clearing_price(
+    selling_levels: &[(u64, u64)],
+    buying_levels: &[(u64, u64)],
+    reference: Option<u64>,
+) -> Option<Equilibrium> {
+    let mut levels: BTreeMap<u64, (u128, u128)> = BTreeMap::new();
+    for &(price, amount) in selling_levels {
+        levels.entry(price).or_default().0 += u128::from(amount);
+    }
+    for &(price, amount) in buying_levels {
+        levels.entry(price).or_default().1 += u128::from(amount);
+    }
+    let total_demand: u128 = buying_levels
+        .iter()
+        .map(|&(_price, amount)| u128::from(amount))
+        .sum();
+    let mut supply = 0;
+    let mut demand_below = 0;
+    levels
+        .into_iter()
+        .filter_map(|(price, (selling, buying))| {
+            // Sellers agree to sell at this price or higher, buyers to buy at this price or lower.
+            supply += selling;
+            let demand = total_demand - demand_below;
+            demand_below += buying;
+            let volume = supply.min(demand);
+            if volume == 0 {
+                return None;
+            }
+            Some((price, supply, demand))
+        })
+        .min_by_key(|&(price, supply, demand)| {
+            let volume = supply.min(demand);
+            let distance =
+                reference.map_or(0, |reference| price.max(reference) - price.min(reference));
+            (
+                Reverse(volume),
+                supply.max(demand) - volume,
+                distance,
+                price,
+            )
+        })
+        .map(|(price, supply, demand)| {
+            let volume = supply.min(demand);
+            let imbalance_side = match supply.cmp(&demand) {
+                Ordering::Less => Some(Side::Buy),
+                Ordering::Equal => None,
+                Ordering::Greater => Some(Side::Sell),
+            };
+            Equilibrium {
+                price,
+                volume: saturate(volume),
+                imbalance: saturate(supply.max(demand) - volume),
+                imbalance_side,
+            }
+        })
+}
+
+/// Pairs the filled parts of selling and buying bids (given with their ids in the priority order)
+/// into trades at the given `price`.
+///
+/// The bid that has been submitted later is considered to be the taker.
+pub(crate) fn pair(
+    price: u64,
+    sold: Vec<(usize, Bid<SellingBid>)>,
+    bought: Vec<(usize, Bid<BuyingBid>)>,
+) -> Vec<Trade> {
+    let mut trades = Vec::new();
+    let mut sold = sold.into_iter().peekable();
+    let mut bought = bought.into_iter().peekable();
+    while let (Some((selling_id, selling)), Some((buying_id, buying))) =
+        (sold.peek_mut(), bought.peek_mut())
+    {
+        let amount = selling.amount.min(buying.amount);
+        let trade = if selling_id < buying_id {
+            Trade {
+                maker_user_id: selling.user_id,
+                taker_user_id: buying.user_id,
+                taker_side: Side::Buy,
+                price,
+                amount,
+            }
+        } else {
+            Trade {
+                maker_user_id: buying.user_id,
+                taker_user_id: selling.user_id,
+                taker_side: Side::Sell,
+                price,
+                amount,
+            }
+        };
+        trades.push(trade);
+        selling.amount -= amount;
+        buying.amount -= amount;
+        if selling.amount == 0 {
+            sold.next();
+        }
+        if buying.amount == 0 {
+            bought.next();
+        }
+    }
+    trades
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clearing_price() {
+        let selling = [(98, 3), (100, 4), (102, 5)];
+        let buying = [(103, 2), (101, 4), (99, 3)];
+        let expected = Equilibrium {
+            price: 100,
+            volume: 6,
+            imbalance: 1,
+            imbalance_side: Some(Side::Sell),
+        };
+        assert_eq!(clearing_price(&selling, &buying, None), Some(expected));
+        assert_eq!(clearing_price(&selling, &[(97, 10)], None), None);
+        // Both 99 and 100 execute 3 items with no imbalance.
+        let price = |reference| {
+            clearing_price(&[(99, 3)], &[(100, 3)], reference).map(|equilibrium| equilibrium.price)
+        };
+        assert_eq!(price(None), Some(99));
+        assert_eq!(price(Some(105)), Some(100));
+    }
+
+    #[test]
+    fn test_clearing_price_huge_amounts() {
+        let max = u64::MAX;
+        let expected = Equilibrium {
+            price: 101,
+            volume: 1,
+            imbalance: max - 1,
+            imbalance_side: Some(Side::Buy),
+        };
+        let buying = [(101, max), (100, max)];
+        assert_eq!(clearing_price(&[(100, 1)], &buying, None), Some(expected));
+        // More than `u64::MAX` items cross on both sides.
+        let selling = [(99, max), (100, max)];
+        let expected = Equilibrium {
+            price: 100,
+            volume: max,
+            imbalance: 0,
+            imbalance_side: None,
+        };
+        assert_eq!(clearing_price(&selling, &buying, None), Some(expected));
+    }
+
+    #[test]
+    fn test_pair() {
+        fn bid<BidKind>(user_id: u64, amount: u64) -> Bid<BidKind> {
+            Bid::empty().price(100).amount(amount).user_id(user_id)
+        }
+        let sold = vec![(0, bid(10, 3)), (3, bid(13, 2))];
+        let bought = vec![(1, bid(11, 4)), (2, bid(12, 1))];
+        let trades = pair(100, sold, bought);
+        let trades: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.taker_user_id, trade.amount))
+            .collect();
+        assert_eq!(trades, [(10, 11, 3), (11, 13, 1), (12, 13, 1)]);
+    }
+}
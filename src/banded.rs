@@ -0,0 +1,405 @@
+//! An experimental order book split into price bands, each of them matched by a thread of its
+//! own.
+
+use crate::{
+    bids::{Bid, BidProcessingType, BuyingBid, SellingBid, Side},
+    order_book::{Cancellation, Depth, IncomingOrder, OrderBook, OrderId, ProcessingResult},
+    trade::Trade,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::mpsc,
+    thread,
+};
+
+/// The best bid and offer of a band.
+type Best = (Option<u64>, Option<u64>);
+
+enum Request {
+    /// Processes an order in a band, replying with the trades if asked to.
+    Process(
+        u64,
+        OrderId,
+        IncomingOrder,
+        Option<mpsc::Sender<(Vec<Trade>, Best)>>,
+    ),
+    /// Checks how much of the order would be filled in a band right away.
+    Preview(u64, IncomingOrder, mpsc::Sender<u64>),
+    Cancel(u64, OrderId, mpsc::Sender<(Cancellation, Best)>),
+    Best(u64, mpsc::Sender<Best>),
+    Depth(usize, mpsc::Sender<Vec<Depth>>),
+}
+
+/// A single order book whose price range is cut into bands of equal width, spread over several
+/// matching threads (shards) in turn.
+///
+/// The book itself is a coordinator. Bids that can't cross the opposite side are sent to the
+/// band of their price without waiting for them to be processed, so bids of different bands rest
+/// in parallel. A bid that might cross sweeps the bands of the opposite side one by one, from the
+/// best price on, and waits for the outcome in each of them, which keeps the price-time priority
+/// of the whole book. The coordinator tracks the best prices of the bands to tell the two apart.
+///
+/// Bids are matched band by band, so the settings that span a whole bid (minimum fills, links,
+/// price protections) only apply within a band, and there's no time: bids never expire. See the
+/// `banded_stream` benchmark for a comparison with a single-threaded book.
+pub struct BandedOrderBook {
+    band_width: u64,
+    shards: Vec<mpsc::Sender<Request>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    /// Best prices of the bands, as far as the coordinator knows: they are never worse than the
+    /// actual ones, since resting bids are tracked as they are sent, but a removed bid is only
+    /// noticed once the band replies.
+    best: BTreeMap<u64, Best>,
+    /// Bands of the bids that might be resting.
+    orders: HashMap<OrderId, u64>,
+    next_order_id: usize,
+}
+
+impl BandedOrderBook {
+    /// Starts `shards` matching threads (at least one) for bands of `band_width` prices.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width of the bands is zero.
+    pub fn spawn(band_width: u64, shards: usize) -> Self {
+        assert!(band_width != 0, "Bands must be at least one price wide");
+        let (shards, workers) = (0..shards.max(1))
+            .map(|_| {
+                let (requests, incoming) = mpsc::channel();
+                (requests, thread::spawn(move || run_shard(incoming)))
+            })
+            .unzip();
+        BandedOrderBook {
+            band_width,
+            shards,
+            workers,
+            best: BTreeMap::new(),
+            orders: HashMap::new(),
+            next_order_id: 0,
+        }
+    }
+
+    fn band(&self, price: u64) -> u64 {
+        price / self.band_width
+    }
+
+    fn shard(&self, band: u64) -> &mpsc::Sender<Request> {
+        &self.shards[(band % self.shards.len() as u64) as usize]
+    }
+
+    fn send(&self, band: u64, request: Request) {
+        // A shard only stops if it has panicked, which is reported by `join`.
+        let _ = self.shard(band).send(request);
+    }
+
+    /// Sends a request to the shard of the band and waits for the reply.
+    fn ask<T>(&self, band: u64, request: impl FnOnce(mpsc::Sender<T>) -> Request) -> T {
+        ask(self.shard(band), request)
+    }
+
+    /// Processes a selling bid, see [`OrderBook::process_selling`].
+    pub fn process_selling(
+        &mut self,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.process(
+            Side::Sell,
+            bid.price,
+            bid.amount,
+            bid_type,
+            &|amount, bid_type| IncomingOrder::Selling(bid.amount(amount), bid_type),
+        )
+    }
+
+    /// Processes a buying bid, see [`OrderBook::process_buying`].
+    pub fn process_buying(
+        &mut self,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.process(
+            Side::Buy,
+            bid.price,
+            bid.amount,
+            bid_type,
+            &|amount, bid_type| IncomingOrder::Buying(bid.amount(amount), bid_type),
+        )
+    }
+
+    /// Processes a bid of the `side`, with `order` building its part of the given amount and type.
+    fn process(
+        &mut self,
+        side: Side,
+        price: u64,
+        amount: u64,
+        bid_type: BidProcessingType,
+        order: &dyn Fn(u64, BidProcessingType) -> IncomingOrder,
+    ) -> ProcessingResult {
+        let order_id = OrderId(self.next_order_id);
+        self.next_order_id += 1;
+        let limit = match bid_type {
+            BidProcessingType::Market => side.market_price(),
+            _ => price,
+        };
+        let crosses = |&(bid, ask): &Best| match side {
+            Side::Buy => ask.is_some_and(|ask| ask <= limit),
+            Side::Sell => bid.is_some_and(|bid| bid >= limit),
+        };
+        // Bands that might be crossed, from the best price on.
+        let mut crossed: Vec<u64> = self
+            .best
+            .iter()
+            .filter(|(_, best)| crosses(best))
+            .map(|(&band, _)| band)
+            .collect();
+        if side == Side::Sell {
+            crossed.reverse();
+        }
+        let mut result = ProcessingResult {
+            order_id,
+            trades: Vec::new(),
+        };
+        match bid_type {
+            BidProcessingType::FillOrKill => {
+                let mut available = 0_u64;
+                for &band in &crossed {
+                    if available >= amount {
+                        break;
+                    }
+                    let preview = order(amount - available, BidProcessingType::Limit);
+                    available += self.ask(band, |reply| Request::Preview(band, preview, reply));
+                }
+                if available < amount {
+                    return result;
+                }
+            }
+            BidProcessingType::PostOnly => {
+                for &band in &crossed {
+                    let best = self.ask(band, |reply| Request::Best(band, reply));
+                    self.best.insert(band, best);
+                    if crosses(&best) {
+                        return result;
+                    }
+                }
+                crossed.clear();
+            }
+            _ => {}
+        }
+        let home = self.band(price);
+        let rests = matches!(
+            bid_type,
+            BidProcessingType::Limit | BidProcessingType::PostOnly
+        );
+        let mut remaining = amount;
+        let mut home_visited = false;
+        for band in crossed {
+            if remaining == 0 {
+                break;
+            }
+            let sweep_type = match bid_type {
+                BidProcessingType::Market => BidProcessingType::Market,
+                _ if rests && band == home => bid_type,
+                _ => BidProcessingType::ImmediateOrCancel,
+            };
+            home_visited |= band == home;
+            let part = order(remaining, sweep_type);
+            let (trades, best) = self.ask(band, |reply| {
+                Request::Process(band, order_id, part, Some(reply))
+            });
+            self.best.insert(band, best);
+            remaining -= trades.iter().map(|trade| trade.amount).sum::<u64>();
+            result.trades.extend(trades);
+        }
+        if rests && remaining != 0 {
+            if !home_visited {
+                let part = order(remaining, bid_type);
+                self.send(home, Request::Process(home, order_id, part, None));
+                let best = self.best.entry(home).or_default();
+                match side {
+                    Side::Buy => best.0 = best.0.max(Some(price)),
+                    Side::Sell => best.1 = Some(best.1.map_or(price, |ask| ask.min(price))),
+                }
+            }
+            self.orders.insert(order_id, home);
+        }
+        result
+    }
+
+    /// Cancels a bid, see [`OrderBook::cancel`].
+    pub fn cancel(&mut self, order_id: OrderId) -> Cancellation {
+        let band = match self.orders.remove(&order_id) {
+            Some(band) => band,
+            None if order_id.0 < self.next_order_id => return Cancellation::AlreadyGone,
+            None => return Cancellation::Unknown,
+        };
+        let (cancellation, best) = self.ask(band, |reply| Request::Cancel(band, order_id, reply));
+        self.best.insert(band, best);
+        cancellation
+    }
+
+    /// Waits for the queued bids to be processed and returns the price levels of the book, see
+    /// [`OrderBook::depth`].
+    pub fn depth(&self, levels: usize) -> Depth {
+        let mut depth = Depth {
+            selling: Vec::new(),
+            buying: Vec::new(),
+        };
+        for shard in &self.shards {
+            for band in ask(shard, |reply| Request::Depth(levels, reply)) {
+                depth.selling.extend(band.selling);
+                depth.buying.extend(band.buying);
+            }
+        }
+        depth.selling.sort_unstable_by_key(|&(price, ..)| price);
+        depth
+            .buying
+            .sort_unstable_by_key(|&(price, ..)| std::cmp::Reverse(price));
+        depth.selling.truncate(levels);
+        depth.buying.truncate(levels);
+        depth
+    }
+
+    /// Waits for all the queued bids to be processed and stops the threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the threads has panicked.
+    pub fn join(self) {
+        drop(self.shards);
+        for worker in self.workers {
+            worker.join().expect("A matching thread has panicked");
+        }
+    }
+}
+
+/// Sends a request to a shard and waits for the reply.
+fn ask<T>(shard: &mpsc::Sender<Request>, request: impl FnOnce(mpsc::Sender<T>) -> Request) -> T {
+    let (reply, receiver) = mpsc::channel();
+    let _ = shard.send(request(reply));
+    receiver.recv().expect("A matching thread has panicked")
+}
+
+fn run_shard(incoming: mpsc::Receiver<Request>) {
+    let mut books: BTreeMap<u64, OrderBook> = BTreeMap::new();
+    // Ids the books have assigned to the bids, by the ids of the coordinator.
+    let mut ids = HashMap::new();
+    let best = |book: &OrderBook| (book.best_bid(), book.best_ask());
+    for request in incoming {
+        match request {
+            Request::Process(band, order_id, order, reply) => {
+                let book = books.entry(band).or_default();
+                let result = match order {
+                    IncomingOrder::Selling(bid, bid_type) => book.process_selling(bid, bid_type),
+                    IncomingOrder::Buying(bid, bid_type) => book.process_buying(bid, bid_type),
+                };
+                ids.insert(order_id, result.order_id);
+                if let Some(reply) = reply {
+                    let _ = reply.send((result.trades, best(book)));
+                }
+            }
+            Request::Preview(band, order, reply) => {
+                let filled = books.get(&band).map_or(0, |book| match order {
+                    IncomingOrder::Selling(bid, _) => book.preview_sell(bid.price, bid.amount),
+                    IncomingOrder::Buying(bid, _) => book.preview_buy(bid.price, bid.amount),
+                }
+                .filled);
+                let _ = reply.send(filled);
+            }
+            Request::Cancel(band, order_id, reply) => {
+                let book = books.entry(band).or_default();
+                let cancellation = match ids.remove(&order_id) {
+                    Some(local_id) => book.cancel(local_id),
+                    None => Cancellation::Unknown,
+                };
+                let _ = reply.send((cancellation, best(book)));
+            }
+            Request::Best(band, reply) => {
+                let _ = reply.send(books.get(&band).map(best).unwrap_or_default());
+            }
+            Request::Depth(levels, reply) => {
+                let _ = reply.send(books.values().map(|book| book.depth(levels)).collect());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Submits the same bids to a banded and to a single-threaded book and checks that they
+    /// trade and rest alike.
+    #[test]
+    fn test_same_as_order_book() {
+        let mut banded = BandedOrderBook::spawn(10, 3);
+        let mut order_book = OrderBook::empty();
+        let selling = [
+            (95, 5, 1),
+            (105, 5, 2),
+            (121, 5, 3),
+            (99, 2, 4),
+            (130, 4, 5),
+        ];
+        for &(price, amount, user_id) in &selling {
+            let bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+            banded.process_selling(bid, BidProcessingType::Limit);
+            order_book.process_selling(bid, BidProcessingType::Limit);
+        }
+        let buying = [
+            (90, 3, 10, BidProcessingType::Limit),
+            (122, 14, 11, BidProcessingType::Limit),
+            (200, 100, 12, BidProcessingType::FillOrKill),
+            (125, 4, 13, BidProcessingType::PostOnly),
+            (119, 4, 13, BidProcessingType::PostOnly),
+            (0, 3, 14, BidProcessingType::Market),
+            (135, 10, 15, BidProcessingType::ImmediateOrCancel),
+        ];
+        for &(price, amount, user_id, bid_type) in &buying {
+            let bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+            let expected = order_book.process_buying(bid, bid_type);
+            let result = banded.process_buying(bid, bid_type);
+            assert_eq!(result.trades, expected.trades, "{:?}", bid_type);
+            assert_eq!(result.order_id, expected.order_id);
+            assert_eq!(banded.depth(10), order_book.depth(10), "{:?}", bid_type);
+        }
+        // The buying bids sweep down across the bands.
+        let bid = Bid::empty().price(80).amount(10).user_id(16);
+        let result = banded.process_selling(bid, BidProcessingType::Limit);
+        assert_eq!(
+            result.trades,
+            order_book
+                .process_selling(bid, BidProcessingType::Limit)
+                .trades
+        );
+        assert_eq!(banded.depth(10), order_book.depth(10));
+        banded.join();
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut banded = BandedOrderBook::spawn(10, 2);
+        let bid = Bid::empty().price(100).amount(5).user_id(1);
+        let resting = banded
+            .process_selling(bid, BidProcessingType::Limit)
+            .order_id;
+        let other = banded.process_selling(bid.price(110), BidProcessingType::Limit);
+        match banded.cancel(resting) {
+            Cancellation::Cancelled(order) => assert_eq!((order.price, order.amount), (100, 5)),
+            cancellation => panic!("{:?}", cancellation),
+        }
+        assert_eq!(banded.cancel(resting), Cancellation::AlreadyGone);
+        assert_eq!(banded.cancel(OrderId(7)), Cancellation::Unknown);
+        // The coordinator learns that the band is empty, so the bid doesn't wait for it.
+        let buying_bid = Bid::empty().price(105).amount(5).user_id(2);
+        let result = banded.process_buying(buying_bid, BidProcessingType::Limit);
+        assert!(result.trades.is_empty());
+        let result = banded.process_buying(buying_bid.price(110), BidProcessingType::Limit);
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(banded.cancel(other.order_id), Cancellation::AlreadyGone);
+        let depth = banded.depth(10);
+        assert!(depth.selling.is_empty());
+        assert_eq!(depth.buying, [(105, 5, 1)]);
+        banded.join();
+    }
+}
@@ -1,10 +1,10 @@
 //! Bids-related types and traits.
 
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 use std::marker::PhantomData;
 
 /// Processing type of a bid.
-#[derive(Debug, Copy, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq)]
 pub enum BidProcessingType {
     /// The bid might be executed partially. The part that can not be executed immediately should be
     /// put on a queue.
@@ -14,10 +14,130 @@ pub enum BidProcessingType {
     /// The bid might be executed partially. The part that can not be executed immediately should be
     /// dropped.
     ImmediateOrCancel,
+    /// The bid should be executed against the best available bids regardless of their price. The
+    /// part that can not be executed immediately should be dropped. The price of the bid is
+    /// ignored.
+    Market,
+    /// The bid should only be put on a queue. If it would be executed immediately (even partially),
+    /// it is dropped instead.
+    PostOnly,
+}
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How long a bid might rest in the book.
+///
+/// Timestamps are the numbers of seconds since the Unix epoch.
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum TimeInForce {
+    /// The bid rests in the book until it's executed or cancelled.
+    #[default]
+    GoodTillCancel,
+    /// The bid expires at the given timestamp.
+    GoodTillDate(u64),
+    /// The bid expires at the end of the (UTC) day it has been added to the book.
+    Day,
+}
+
+impl TimeInForce {
+    /// Time a bid added to the book at the `timestamp` expires at, if it ever does.
+    pub fn expiry(self, timestamp: u64) -> Option<u64> {
+        match self {
+            TimeInForce::GoodTillCancel => None,
+            TimeInForce::GoodTillDate(expiry) => Some(expiry),
+            TimeInForce::Day => {
+                Some((timestamp / SECONDS_PER_DAY + 1).saturating_mul(SECONDS_PER_DAY))
+            }
+        }
+    }
+
+    /// Checks whether a bid added to the book at the `timestamp` has expired by `now`.
+    pub fn has_expired(self, timestamp: u64, now: u64) -> bool {
+        self.expiry(timestamp).is_some_and(|expiry| now >= expiry)
+    }
+}
+
+/// Protection of a `Market` bid against executing at unrealistic prices, e.g. in a thin book.
+///
+/// The bid executes as long as the next price level is within the protection, whatever is left of
+/// it then is dropped.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Protection {
+    /// The bid doesn't execute beyond the given price.
+    Price(u64),
+    /// The bid doesn't execute further than the given distance from the best price of the
+    /// opposite side at the time the bid arrives.
+    Slippage(u64),
+}
+
+impl Protection {
+    /// The worst price a bid of the `side` may execute at, given the best price of the opposite
+    /// side. There's none if a slippage is limited but the opposite side is empty.
+    pub fn limit_price(self, side: Side, best_opposite: Option<u64>) -> Option<u64> {
+        match self {
+            Protection::Price(price) => Some(price),
+            Protection::Slippage(slippage) => {
+                let best_opposite = best_opposite?;
+                Some(match side {
+                    Side::Buy => best_opposite.saturating_add(slippage),
+                    Side::Sell => best_opposite.saturating_sub(slippage),
+                })
+            }
+        }
+    }
+}
+
+/// Side of a bid.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Side {
+    /// A selling bid.
+    Sell,
+    /// A buying bid.
+    Buy,
+}
+
+impl Side {
+    /// Verb ("bought"/"sold") and direction ("from"/"to") of a deal made by a bid of this side.
+    ///
+    /// Use for sentences like "User XX bought YY items from user ...".
+    pub fn deal_verb_direction(self) -> (&'static str, &'static str) {
+        match self {
+            Side::Buy => ("bought", "from"),
+            Side::Sell => ("sold", "to"),
+        }
+    }
+
+    /// Literal name of the bid's kind.
+    pub fn kind_name(self) -> &'static str {
+        match self {
+            Side::Buy => "buying bid",
+            Side::Sell => "selling bid",
+        }
+    }
+
+    /// The most aggressive price, i.e. a price that matches any bid of the opposite side.
+    pub fn market_price(self) -> u64 {
+        match self {
+            Side::Buy => u64::MAX,
+            Side::Sell => 0,
+        }
+    }
 }
 
 /// A selling or a buying bid. Its kind depends on the `BidKind` generic argument.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+///
+/// With the `serde` feature, bids are (de)serialized along with their side, so a selling bid can't
+/// be loaded as a buying one:
+///
+/// ```yaml
+/// {side: Sell, price: 100, amount: 5, user_id: 15, display: ~, min_fill: ~, time_in_force: Day,
+///  link: ~, reduce_only: false, protection: ~}
+/// ```
+///
+/// `display`, `min_fill`, `time_in_force`, `link`, `reduce_only` and `protection` might be
+/// omitted.
+#[derive(Debug, PartialEq, Eq)]
 pub struct Bid<BidKind> {
     /// Price: either the highest price for a buying bid a the lowest price for a selling bid.
     pub price: u64,
@@ -25,9 +145,107 @@ pub struct Bid<BidKind> {
     pub amount: u64,
     /// Bid's user id.
     pub user_id: u64,
+    /// Display quantity of an iceberg bid: when the bid rests in a pool, only that much of its
+    /// amount is visible and could be matched at once. `None` means the whole amount is visible.
+    pub display: Option<u64>,
+    /// Minimum amount of items an incoming bid should be executed for. If not that many items are
+    /// available, the bid is not executed at all and is handled according to its processing type.
+    pub min_fill: Option<u64>,
+    /// How long the bid might rest in the book.
+    pub time_in_force: TimeInForce,
+    /// One-cancels-other link of the bid: once a bid is executed (even partially), the other bids
+    /// of the same user with the same link are cancelled.
+    pub link: Option<u64>,
+    /// The bid may only reduce the position of its user: its amount is capped at the user's
+    /// position of the opposite side (long for a selling bid, short for a buying one) when it's
+    /// processed. Books that don't track positions drop reduce-only bids.
+    pub reduce_only: bool,
+    /// Protection of a `Market` bid, ignored for the bids of the other types and during call
+    /// auctions.
+    pub protection: Option<Protection>,
     _marker: PhantomData<BidKind>,
 }
 
+/// Serialized form of a [`Bid`], tagged with its side.
+#[cfg(feature = "serde")]
+#[derive(Deserialize, Serialize)]
+struct TaggedBid {
+    side: Side,
+    price: u64,
+    amount: u64,
+    user_id: u64,
+    #[serde(default)]
+    display: Option<u64>,
+    #[serde(default)]
+    min_fill: Option<u64>,
+    #[serde(default)]
+    time_in_force: TimeInForce,
+    #[serde(default)]
+    link: Option<u64>,
+    #[serde(default)]
+    reduce_only: bool,
+    #[serde(default)]
+    protection: Option<Protection>,
+}
+
+#[cfg(feature = "serde")]
+impl<BidKind: GenericBid> serde::Serialize for Bid<BidKind> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let tagged = TaggedBid {
+            side: BidKind::side(),
+            price: self.price,
+            amount: self.amount,
+            user_id: self.user_id,
+            display: self.display,
+            min_fill: self.min_fill,
+            time_in_force: self.time_in_force,
+            link: self.link,
+            reduce_only: self.reduce_only,
+            protection: self.protection,
+        };
+        tagged.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, BidKind: GenericBid> serde::Deserialize<'de> for Bid<BidKind> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let tagged = TaggedBid::deserialize(deserializer)?;
+        if tagged.side != BidKind::side() {
+            return Err(serde::de::Error::custom(format!(
+                "expected a {}, got a {}",
+                BidKind::kind_name(),
+                tagged.side.kind_name()
+            )));
+        }
+        Ok(Bid {
+            price: tagged.price,
+            amount: tagged.amount,
+            user_id: tagged.user_id,
+            display: tagged.display,
+            min_fill: tagged.min_fill,
+            time_in_force: tagged.time_in_force,
+            link: tagged.link,
+            reduce_only: tagged.reduce_only,
+            protection: tagged.protection,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<BidKind> Copy for Bid<BidKind> {}
+impl<BidKind> Clone for Bid<BidKind> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
 impl<BidKind> Bid<BidKind> {
     /// Initializes an empty bid (with zero price, zero amount and zero user id).
     pub fn empty() -> Self {
@@ -35,6 +253,12 @@ impl<BidKind> Bid<BidKind> {
             price: 0,
             amount: 0,
             user_id: 0,
+            display: None,
+            min_fill: None,
+            time_in_force: TimeInForce::GoodTillCancel,
+            link: None,
+            reduce_only: false,
+            protection: None,
             _marker: PhantomData,
         }
     }
@@ -53,6 +277,54 @@ impl<BidKind> Bid<BidKind> {
     pub fn user_id(self, user_id: u64) -> Self {
         Bid { user_id, ..self }
     }
+
+    /// Turns the bid into an iceberg bid with the given display quantity.
+    pub fn display(self, display: u64) -> Self {
+        Bid {
+            display: Some(display),
+            ..self
+        }
+    }
+
+    /// Sets the minimum amount of items the bid should be executed for.
+    pub fn min_fill(self, min_fill: u64) -> Self {
+        Bid {
+            min_fill: Some(min_fill),
+            ..self
+        }
+    }
+
+    /// Updates the time in force.
+    pub fn time_in_force(self, time_in_force: TimeInForce) -> Self {
+        Bid {
+            time_in_force,
+            ..self
+        }
+    }
+
+    /// Links the bid to the other bids of its user with the same `link`.
+    pub fn link(self, link: u64) -> Self {
+        Bid {
+            link: Some(link),
+            ..self
+        }
+    }
+
+    /// Makes the bid reduce-only.
+    pub fn reduce_only(self) -> Self {
+        Bid {
+            reduce_only: true,
+            ..self
+        }
+    }
+
+    /// Sets the protection of a `Market` bid.
+    pub fn protection(self, protection: Protection) -> Self {
+        Bid {
+            protection: Some(protection),
+            ..self
+        }
+    }
 }
 
 /// A marker type that marks a `Bid` as a *selling* bid.
@@ -69,35 +341,67 @@ pub trait GenericBid: Sized {
     /// The opposite kind of bid.
     type Opposite: GenericBid<Opposite = Self>;
 
+    /// Side of the bid.
+    fn side() -> Side;
+
     /// Verb ("bought"/"sold") and direction ("from"/"to") of the deal.
     ///
     /// Use for sentences like "User XX bought YY items from user ...".
-    fn deal_verb_direction() -> (&'static str, &'static str);
+    fn deal_verb_direction() -> (&'static str, &'static str) {
+        Self::side().deal_verb_direction()
+    }
 
     /// Literal name of the bid's kind.
-    fn kind_name() -> &'static str;
+    fn kind_name() -> &'static str {
+        Self::side().kind_name()
+    }
+
+    /// The most aggressive price, i.e. a price that matches any bid of the opposite kind.
+    fn market_price() -> u64 {
+        Self::side().market_price()
+    }
 }
 
 impl GenericBid for BuyingBid {
     type Opposite = SellingBid;
 
-    fn deal_verb_direction() -> (&'static str, &'static str) {
-        ("bought", "from")
-    }
-
-    fn kind_name() -> &'static str {
-        "buying bid"
+    fn side() -> Side {
+        Side::Buy
     }
 }
 
 impl GenericBid for SellingBid {
     type Opposite = BuyingBid;
 
-    fn deal_verb_direction() -> (&'static str, &'static str) {
-        ("sold", "to")
+    fn side() -> Side {
+        Side::Sell
     }
+}
 
-    fn kind_name() -> &'static str {
-        "selling bid"
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serde() {
+        let selling_bid: Bid<SellingBid> = Bid::empty()
+            .price(100)
+            .amount(5)
+            .user_id(15)
+            .display(2)
+            .time_in_force(TimeInForce::Day)
+            .link(3)
+            .reduce_only();
+        let data = serde_json::to_string(&selling_bid).unwrap();
+        assert!(data.contains(r#""side":"Sell""#), "{}", data);
+        assert_eq!(
+            serde_json::from_str::<Bid<SellingBid>>(&data).unwrap(),
+            selling_bid
+        );
+        assert!(serde_json::from_str::<Bid<BuyingBid>>(&data).is_err());
+
+        let buying_bid: Bid<BuyingBid> =
+            serde_yaml::from_str("{side: Buy, price: 100, amount: 5, user_id: 16}").unwrap();
+        assert_eq!(buying_bid, Bid::empty().price(100).amount(5).user_id(16));
     }
 }
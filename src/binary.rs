@@ -0,0 +1,380 @@
+//! A compact binary order entry protocol.
+//!
+//! Every message starts with a one byte type followed by fixed width fields. Integers are
+//! big-endian `u64`s, symbols are eight ASCII bytes padded with spaces on the right:
+//!
+//! | Message     | Type  | Fields                                                            |
+//! |-------------|-------|-------------------------------------------------------------------|
+//! | Enter       | `O`   | symbol, side, price, size, user id, type, time in force, expiry   |
+//! | Cancel      | `X`   | symbol, order id                                                  |
+//! | Replace     | `U`   | symbol, order id, new price, new size                             |
+//! | MassCancel  | `M`   | symbol, user id                                                   |
+//!
+//! Side is `B` or `S`. Type is `L` (limit), `F` (fill or kill), `I` (immediate or cancel), `M`
+//! (market) or `P` (post only). Time in force is `C` (good till cancel), `D` (day) or `T` (good
+//! till date, the expiry timestamp is taken from the following field, which is zero otherwise).
+
+use crate::{
+    bids::{Bid, BidProcessingType, Side, TimeInForce},
+    engine::MatchingEngine,
+    error::Error,
+    order_book::{Amendment, OrderId},
+    raw::ProcessingSummary,
+};
+use std::io::{self, Read, Write};
+
+/// Width of a symbol in bytes.
+const SYMBOL_WIDTH: usize = 8;
+
+/// An order entry message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryMessage {
+    /// Enters a new order.
+    Enter {
+        symbol: String,
+        side: Side,
+        price: u64,
+        amount: u64,
+        user_id: u64,
+        bid_type: BidProcessingType,
+        time_in_force: TimeInForce,
+    },
+    /// Cancels an order.
+    Cancel { symbol: String, order_id: OrderId },
+    /// Amends the price and the remaining size of an order.
+    Replace {
+        symbol: String,
+        order_id: OrderId,
+        new_price: u64,
+        new_size: u64,
+    },
+    /// Cancels all the resting orders of a user.
+    MassCancel { symbol: String, user_id: u64 },
+}
+
+impl BinaryMessage {
+    /// Writes the message.
+    pub fn encode(&self, mut w: impl Write) -> io::Result<()> {
+        match self {
+            BinaryMessage::Enter {
+                symbol,
+                side,
+                price,
+                amount,
+                user_id,
+                bid_type,
+                time_in_force,
+            } => {
+                w.write_all(b"O")?;
+                write_symbol(&mut w, symbol)?;
+                w.write_all(match side {
+                    Side::Buy => b"B",
+                    Side::Sell => b"S",
+                })?;
+                for value in &[*price, *amount, *user_id] {
+                    w.write_all(&value.to_be_bytes())?;
+                }
+                w.write_all(match bid_type {
+                    BidProcessingType::Limit => b"L",
+                    BidProcessingType::FillOrKill => b"F",
+                    BidProcessingType::ImmediateOrCancel => b"I",
+                    BidProcessingType::Market => b"M",
+                    BidProcessingType::PostOnly => b"P",
+                })?;
+                let (tif, expiry) = match time_in_force {
+                    TimeInForce::GoodTillCancel => (b"C", 0),
+                    TimeInForce::Day => (b"D", 0),
+                    TimeInForce::GoodTillDate(expiry) => (b"T", *expiry),
+                };
+                w.write_all(tif)?;
+                w.write_all(&expiry.to_be_bytes())
+            }
+            BinaryMessage::Cancel { symbol, order_id } => {
+                w.write_all(b"X")?;
+                write_symbol(&mut w, symbol)?;
+                w.write_all(&(order_id.0 as u64).to_be_bytes())
+            }
+            BinaryMessage::Replace {
+                symbol,
+                order_id,
+                new_price,
+                new_size,
+            } => {
+                w.write_all(b"U")?;
+                write_symbol(&mut w, symbol)?;
+                for value in &[order_id.0 as u64, *new_price, *new_size] {
+                    w.write_all(&value.to_be_bytes())?;
+                }
+                Ok(())
+            }
+            BinaryMessage::MassCancel { symbol, user_id } => {
+                w.write_all(b"M")?;
+                write_symbol(&mut w, symbol)?;
+                w.write_all(&user_id.to_be_bytes())
+            }
+        }
+    }
+
+    /// Reads a message. Returns `None` if the reader is exhausted.
+    pub fn decode(mut r: impl Read) -> io::Result<Option<Self>> {
+        let mut message_type = [0];
+        if r.read(&mut message_type)? == 0 {
+            return Ok(None);
+        }
+        let symbol = read_symbol(&mut r)?;
+        let message = match &message_type {
+            b"O" => {
+                let side = match read_byte(&mut r)? {
+                    b'B' => Side::Buy,
+                    b'S' => Side::Sell,
+                    side => return Err(invalid_data(format!("unknown side {:?}", side as char))),
+                };
+                let price = read_u64(&mut r)?;
+                let amount = read_u64(&mut r)?;
+                let user_id = read_u64(&mut r)?;
+                let bid_type = match read_byte(&mut r)? {
+                    b'L' => BidProcessingType::Limit,
+                    b'F' => BidProcessingType::FillOrKill,
+                    b'I' => BidProcessingType::ImmediateOrCancel,
+                    b'M' => BidProcessingType::Market,
+                    b'P' => BidProcessingType::PostOnly,
+                    ty => return Err(invalid_data(format!("unknown type {:?}", ty as char))),
+                };
+                let tif = read_byte(&mut r)?;
+                let expiry = read_u64(&mut r)?;
+                let time_in_force = match tif {
+                    b'C' => TimeInForce::GoodTillCancel,
+                    b'D' => TimeInForce::Day,
+                    b'T' => TimeInForce::GoodTillDate(expiry),
+                    tif => {
+                        return Err(invalid_data(format!(
+                            "unknown time in force {:?}",
+                            tif as char
+                        )))
+                    }
+                };
+                BinaryMessage::Enter {
+                    symbol,
+                    side,
+                    price,
+                    amount,
+                    user_id,
+                    bid_type,
+                    time_in_force,
+                }
+            }
+            b"X" => BinaryMessage::Cancel {
+                symbol,
+                order_id: OrderId(read_u64(&mut r)? as usize),
+            },
+            b"U" => BinaryMessage::Replace {
+                symbol,
+                order_id: OrderId(read_u64(&mut r)? as usize),
+                new_price: read_u64(&mut r)?,
+                new_size: read_u64(&mut r)?,
+            },
+            b"M" => BinaryMessage::MassCancel {
+                symbol,
+                user_id: read_u64(&mut r)?,
+            },
+            _ => {
+                return Err(invalid_data(format!(
+                    "unknown message type {:?}",
+                    message_type[0] as char
+                )))
+            }
+        };
+        Ok(Some(message))
+    }
+}
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_symbol(mut w: impl Write, symbol: &str) -> io::Result<()> {
+    if symbol.len() > SYMBOL_WIDTH || !symbol.is_ascii() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "symbol {:?} doesn't fit into {} bytes",
+                symbol, SYMBOL_WIDTH
+            ),
+        ));
+    }
+    write!(w, "{:width$}", symbol, width = SYMBOL_WIDTH)
+}
+
+fn read_symbol(mut r: impl Read) -> io::Result<String> {
+    let mut symbol = [0; SYMBOL_WIDTH];
+    r.read_exact(&mut symbol)?;
+    let symbol = std::str::from_utf8(&symbol)
+        .map_err(|_| invalid_data("symbol is not ASCII".to_string()))?;
+    Ok(symbol.trim_end_matches(' ').to_string())
+}
+
+pub(crate) fn read_byte(mut r: impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    r.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+pub(crate) fn read_u64(mut r: impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Processes binary messages from a given reader, routing them to the books of their symbols.
+///
+/// Returns the summary of the processing, where every message counts as an order. Cancellations
+/// (including mass cancellations) and replacements are never rejected; replacements are not
+/// validated against the instruments. A malformed or truncated message stops the processing with
+/// [`Error::Parse`].
+pub fn process_binary_reader(
+    engine: &mut MatchingEngine,
+    r: impl Read,
+) -> Result<ProcessingSummary, Error> {
+    let mut r = io::BufReader::new(r);
+    let mut summary = ProcessingSummary::default();
+    loop {
+        let message = match BinaryMessage::decode(&mut r) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => match e.kind() {
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::parse(summary.orders_read, e))
+                }
+                _ => return Err(Error::Io(e)),
+            },
+        };
+        let result = match message {
+            BinaryMessage::Enter {
+                symbol,
+                side,
+                price,
+                amount,
+                user_id,
+                bid_type,
+                time_in_force,
+            } => match side {
+                Side::Sell => {
+                    let selling_bid = Bid::empty()
+                        .price(price)
+                        .amount(amount)
+                        .user_id(user_id)
+                        .time_in_force(time_in_force);
+                    engine
+                        .process_selling(&symbol, selling_bid, bid_type)
+                        .map(|result| result.trades)
+                }
+                Side::Buy => {
+                    let buying_bid = Bid::empty()
+                        .price(price)
+                        .amount(amount)
+                        .user_id(user_id)
+                        .time_in_force(time_in_force);
+                    engine
+                        .process_buying(&symbol, buying_bid, bid_type)
+                        .map(|result| result.trades)
+                }
+            },
+            BinaryMessage::Cancel { symbol, order_id } => {
+                engine.book_mut(&symbol).cancel(order_id);
+                Ok(Vec::new())
+            }
+            BinaryMessage::Replace {
+                symbol,
+                order_id,
+                new_price,
+                new_size,
+            } => match engine
+                .book_mut(&symbol)
+                .amend(order_id, new_price, new_size)
+            {
+                Amendment::Replaced(result) => Ok(result.trades),
+                _ => Ok(Vec::new()),
+            },
+            BinaryMessage::MassCancel { symbol, user_id } => {
+                engine.book_mut(&symbol).cancel_user(user_id);
+                Ok(Vec::new())
+            }
+        };
+        summary.record(result.as_ref().map(|trades| &trades[..]).map_err(|r| *r));
+    }
+    Ok(summary.finish(engine))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_codec() {
+        let messages = vec![
+            BinaryMessage::Enter {
+                symbol: "ABC".to_string(),
+                side: Side::Sell,
+                price: 100,
+                amount: 5,
+                user_id: 1,
+                bid_type: BidProcessingType::Limit,
+                time_in_force: TimeInForce::GoodTillDate(1_000_000),
+            },
+            BinaryMessage::Replace {
+                symbol: "ABC".to_string(),
+                order_id: OrderId(0),
+                new_price: 100,
+                new_size: 4,
+            },
+            BinaryMessage::Enter {
+                symbol: "ABC".to_string(),
+                side: Side::Buy,
+                price: 0,
+                amount: 3,
+                user_id: 2,
+                bid_type: BidProcessingType::Market,
+                time_in_force: TimeInForce::GoodTillCancel,
+            },
+            BinaryMessage::Cancel {
+                symbol: "ABCDEFGH".to_string(),
+                order_id: OrderId(7),
+            },
+            BinaryMessage::MassCancel {
+                symbol: "ABC".to_string(),
+                user_id: 2,
+            },
+        ];
+        let mut data = Vec::new();
+        for message in &messages {
+            message.encode(&mut data).unwrap();
+        }
+        assert_eq!(data.len(), 44 + 33 + 44 + 17 + 17);
+        let mut r = &data[..];
+        let mut decoded = Vec::new();
+        while let Some(message) = BinaryMessage::decode(&mut r).unwrap() {
+            decoded.push(message);
+        }
+        assert_eq!(decoded, messages);
+        assert!(BinaryMessage::decode(&data[..10]).is_err());
+        let cancel = BinaryMessage::Cancel {
+            symbol: "ABCDEFGHI".to_string(),
+            order_id: OrderId(0),
+        };
+        assert!(cancel.encode(&mut Vec::new()).is_err());
+
+        let mut engine = MatchingEngine::new();
+        let summary = process_binary_reader(&mut engine, &data[..]).unwrap();
+        assert_eq!(summary.orders_read, 5);
+        assert_eq!(summary.trades, 1);
+        assert!(summary.rejected.is_empty());
+        let error = process_binary_reader(&mut MatchingEngine::new(), &data[..50]).unwrap_err();
+        assert_eq!(error.entry(), Some(1));
+        let order_book = engine.book("ABC").unwrap();
+        let resting: Vec<_> = order_book
+            .sell_orders()
+            .map(|(_id, bid)| bid.amount)
+            .collect();
+        assert_eq!(resting, [1]);
+    }
+}
@@ -0,0 +1,230 @@
+//! Aggregation of trades into time-based OHLCV bars (candles).
+
+use crate::{clock::Clock, listener::OrderBookListener, trade::Trade};
+use std::collections::VecDeque;
+
+/// Open, high, low and close prices and the traded volume of a period of time.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    /// Start of the period, a multiple of the bar width.
+    pub start: u64,
+    /// Price of the first trade of the period.
+    pub open: u64,
+    /// The highest trade price of the period.
+    pub high: u64,
+    /// The lowest trade price of the period.
+    pub low: u64,
+    /// Price of the last trade of the period.
+    pub close: u64,
+    /// Total amount of items traded during the period, saturated at `u64::MAX`.
+    pub volume: u64,
+}
+
+impl Candle {
+    fn new(start: u64, trade: &Trade) -> Self {
+        Candle {
+            start,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.amount,
+        }
+    }
+
+    fn update(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume = self.volume.saturating_add(trade.amount);
+    }
+}
+
+/// Buckets timestamped trades into candles of a fixed width.
+///
+/// Timestamps are supplied along with the trades, so they might come from the orders, a replayed
+/// log or a clock. A candle is completed once a trade (or a call to
+/// [`advance`](CandleAggregator::advance)) falls into a later period; periods without trades
+/// produce no candles. A trade with a timestamp before the current period is accounted for in the
+/// current candle.
+#[derive(Debug, Clone)]
+pub struct CandleAggregator {
+    width: u64,
+    current: Option<Candle>,
+    completed: VecDeque<Candle>,
+}
+
+impl CandleAggregator {
+    /// Initializes an aggregator of candles that are `width` units of time wide (at least one).
+    pub fn new(width: u64) -> Self {
+        CandleAggregator {
+            width: width.max(1),
+            current: None,
+            completed: VecDeque::new(),
+        }
+    }
+
+    /// Width of the candles.
+    pub fn width(&self) -> u64 {
+        self.width
+    }
+
+    fn period(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.width
+    }
+
+    /// Accounts for a trade executed at the given time.
+    pub fn record(&mut self, timestamp: u64, trade: &Trade) {
+        self.advance(timestamp);
+        match &mut self.current {
+            Some(candle) => candle.update(trade),
+            None => self.current = Some(Candle::new(self.period(timestamp), trade)),
+        }
+    }
+
+    /// Completes the current candle if `now` is past its period.
+    pub fn advance(&mut self, now: u64) {
+        let period = self.period(now);
+        if self.current.is_some_and(|candle| candle.start < period) {
+            self.flush();
+        }
+    }
+
+    /// Completes the current candle regardless of the time, e.g. at the end of a replay.
+    pub fn flush(&mut self) {
+        self.completed.extend(self.current.take());
+    }
+
+    /// The candle of the current period, if there have been trades in it.
+    pub fn current(&self) -> Option<&Candle> {
+        self.current.as_ref()
+    }
+
+    /// Takes the completed candles, from the oldest to the newest.
+    pub fn completed(&mut self) -> impl Iterator<Item = Candle> + '_ {
+        self.completed.drain(..)
+    }
+}
+
+/// A listener that aggregates the trades of a book into candles, taking their timestamps from a
+/// clock, and passes every completed candle to a callback.
+pub struct CandleListener<C, Callback> {
+    aggregator: CandleAggregator,
+    clock: C,
+    callback: Callback,
+}
+
+impl<C, Callback> CandleListener<C, Callback>
+where
+    C: Clock,
+    Callback: FnMut(Candle),
+{
+    /// Initializes a listener producing candles of the given width.
+    pub fn new(width: u64, clock: C, callback: Callback) -> Self {
+        CandleListener {
+            aggregator: CandleAggregator::new(width),
+            clock,
+            callback,
+        }
+    }
+}
+
+impl<C, Callback> OrderBookListener for CandleListener<C, Callback>
+where
+    C: Clock,
+    Callback: FnMut(Candle),
+{
+    fn on_trade(&mut self, trade: &Trade) {
+        let now = self.clock.now();
+        self.aggregator.record(now, trade);
+        self.aggregator.completed().for_each(&mut self.callback);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType, Side},
+        clock::ManualClock,
+        OrderBook,
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    fn trade(price: u64, amount: u64) -> Trade {
+        Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Buy,
+            price,
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_aggregator() {
+        let mut aggregator = CandleAggregator::new(60);
+        aggregator.record(5, &trade(100, 1));
+        aggregator.record(30, &trade(103, 2));
+        aggregator.record(59, &trade(99, 3));
+        assert_eq!(aggregator.completed().count(), 0);
+        aggregator.record(61, &trade(101, 4));
+        // The clock goes backwards, the trade is put into the current candle.
+        aggregator.record(50, &trade(102, 1));
+        aggregator.record(200, &trade(104, 1));
+        aggregator.advance(239);
+        assert_eq!(aggregator.current().map(|candle| candle.start), Some(180));
+
+        let completed: Vec<_> = aggregator.completed().collect();
+        let expected = [
+            Candle {
+                start: 0,
+                open: 100,
+                high: 103,
+                low: 99,
+                close: 99,
+                volume: 6,
+            },
+            Candle {
+                start: 60,
+                open: 101,
+                high: 102,
+                low: 101,
+                close: 102,
+                volume: 5,
+            },
+        ];
+        assert_eq!(completed, expected);
+        aggregator.advance(240);
+        aggregator.record(241, &trade(100, 1));
+        let completed: Vec<_> = aggregator.completed().collect();
+        assert_eq!(completed, [Candle::new(180, &trade(104, 1))]);
+        aggregator.flush();
+        let completed: Vec<_> = aggregator.completed().collect();
+        assert_eq!(completed, [Candle::new(240, &trade(100, 1))]);
+    }
+
+    #[test]
+    fn test_listener() {
+        let clock = ManualClock::new(0);
+        let candles = Rc::new(RefCell::new(Vec::new()));
+        let callback = {
+            let candles = Rc::clone(&candles);
+            move |candle| candles.borrow_mut().push(candle)
+        };
+        let mut order_book = OrderBook::empty();
+        order_book.add_listener(CandleListener::new(10, clock.clone(), callback));
+
+        let selling_bid = Bid::empty().price(100).amount(10).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(100).amount(2).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        clock.set(15);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(*candles.borrow(), [Candle::new(0, &trade(100, 2))]);
+    }
+}
@@ -0,0 +1,109 @@
+//! Sources of time.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A source of the current time, in seconds since the Unix epoch.
+///
+/// Closures returning the time are clocks too.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// The system's wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+/// A clock that only moves when it's told to, e.g. in tests or replays.
+///
+/// Clones share the time, so a clone kept aside can drive the clocks given to books and
+/// listeners.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock(Rc<Cell<u64>>);
+
+impl ManualClock {
+    /// Initializes a clock showing the given time.
+    pub fn new(now: u64) -> Self {
+        ManualClock(Rc::new(Cell::new(now)))
+    }
+
+    /// Sets the time.
+    pub fn set(&self, now: u64) {
+        self.0.set(now)
+    }
+
+    /// Moves the time forward by `seconds`.
+    pub fn advance(&self, seconds: u64) {
+        self.0.set(self.0.get().saturating_add(seconds))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::default();
+        assert_eq!(clock.now(), 0);
+        let shared = clock.clone();
+        clock.advance(10);
+        assert_eq!(shared.now(), 10);
+        shared.set(5);
+        assert_eq!(clock.now(), 5);
+        // The time never wraps around.
+        clock.advance(u64::MAX);
+        assert_eq!(shared.now(), u64::MAX);
+        clock.advance(1);
+        assert_eq!(clock.now(), u64::MAX);
+        // Independent clocks don't share the time.
+        let other = ManualClock::new(42);
+        other.advance(1);
+        assert_eq!(other.now(), 43);
+        assert_eq!(clock.now(), u64::MAX);
+    }
+
+    #[test]
+    fn test_closure_clock() {
+        let clock = ManualClock::new(7);
+        let shifted = {
+            let clock = clock.clone();
+            move || clock.now() + 100
+        };
+        assert_eq!(Clock::now(&shifted), 107);
+        clock.advance(1);
+        assert_eq!(Clock::now(&shifted), 108);
+    }
+
+    #[test]
+    fn test_system_clock() {
+        let now = SystemClock.now();
+        // Some time after 2020-01-01.
+        assert!(now > 1_577_836_800);
+        assert!(SystemClock.now() >= now);
+    }
+}
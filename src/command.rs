@@ -0,0 +1,383 @@
+//! Commands of an order book and the events they result in, see [`OrderBook::apply`].
+
+use crate::{
+    bids::{Bid, BidProcessingType, BuyingBid, SellingBid},
+    conditional::Condition,
+    feed::FeedEvent,
+    listener::{OrderBookListener, OrderInfo},
+    order_book::{Amendment, Cancellation, IncomingOrder, OrderBook, OrderId, ProcessingResult},
+    session::Phase,
+    trade::Trade,
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// An input of an order book, e.g. a message of its [journal](crate::Journal).
+///
+/// Applying the same commands in the same order to equally configured empty books always results
+/// in the same state.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// See [`OrderBook::process_selling`].
+    Selling(Bid<SellingBid>, BidProcessingType),
+    /// See [`OrderBook::process_buying`].
+    Buying(Bid<BuyingBid>, BidProcessingType),
+    /// See [`OrderBook::process_selling_when`].
+    SellingWhen(Condition, Bid<SellingBid>, BidProcessingType),
+    /// See [`OrderBook::process_buying_when`].
+    BuyingWhen(Condition, Bid<BuyingBid>, BidProcessingType),
+    /// See [`OrderBook::process_selling_stop`].
+    SellingStop(u64, Bid<SellingBid>, BidProcessingType),
+    /// See [`OrderBook::process_buying_stop`].
+    BuyingStop(u64, Bid<BuyingBid>, BidProcessingType),
+    /// See [`OrderBook::cancel`].
+    Cancel(OrderId),
+    /// See [`OrderBook::cancel_user`].
+    CancelUser(u64),
+    /// See [`OrderBook::amend`].
+    Amend {
+        order_id: OrderId,
+        new_price: u64,
+        new_size: u64,
+    },
+    /// See [`OrderBook::advance_time`].
+    #[cfg_attr(feature = "serde", serde(rename = "Expire"))]
+    AdvanceTime(u64),
+    /// See [`OrderBook::halt`].
+    Halt,
+    /// See [`OrderBook::resume`].
+    Resume,
+    /// See [`OrderBook::start_auction`].
+    StartAuction,
+    /// See [`OrderBook::uncross`].
+    Uncross,
+    /// See [`OrderBook::set_reference_price`].
+    SetReferencePrice(Option<u64>),
+}
+
+impl From<IncomingOrder> for Command {
+    fn from(order: IncomingOrder) -> Self {
+        match order {
+            IncomingOrder::Selling(bid, bid_type) => Command::Selling(bid, bid_type),
+            IncomingOrder::Buying(bid, bid_type) => Command::Buying(bid, bid_type),
+        }
+    }
+}
+
+/// Result of applying a command to an order book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// A bid has been submitted.
+    Processed(ProcessingResult),
+    /// A bid has been cancelled (or not).
+    Cancelled(Cancellation),
+    /// Bids of a user have been cancelled.
+    CancelledUser(Vec<(OrderId, Cancellation)>),
+    /// A bid has been amended (or not).
+    Amended(Amendment),
+    /// The clock has been advanced and these bids have expired.
+    Expired(Vec<OrderId>),
+    /// Trading has been resumed or the book has been uncrossed.
+    Traded(Vec<Trade>),
+    /// The state of the book has been changed without any trading.
+    Done,
+}
+
+impl Command {
+    /// Applies the command to the book.
+    ///
+    /// See [`OrderBook::apply`] for the events it results in.
+    pub fn apply(self, order_book: &mut OrderBook) -> Outcome {
+        match self {
+            Command::Selling(bid, bid_type) => {
+                Outcome::Processed(order_book.process_selling(bid, bid_type))
+            }
+            Command::Buying(bid, bid_type) => {
+                Outcome::Processed(order_book.process_buying(bid, bid_type))
+            }
+            Command::SellingWhen(condition, bid, bid_type) => {
+                Outcome::Processed(order_book.process_selling_when(condition, bid, bid_type))
+            }
+            Command::BuyingWhen(condition, bid, bid_type) => {
+                Outcome::Processed(order_book.process_buying_when(condition, bid, bid_type))
+            }
+            Command::SellingStop(trigger_price, bid, bid_type) => {
+                Outcome::Processed(order_book.process_selling_stop(trigger_price, bid, bid_type))
+            }
+            Command::BuyingStop(trigger_price, bid, bid_type) => {
+                Outcome::Processed(order_book.process_buying_stop(trigger_price, bid, bid_type))
+            }
+            Command::Cancel(order_id) => Outcome::Cancelled(order_book.cancel(order_id)),
+            Command::CancelUser(user_id) => Outcome::CancelledUser(order_book.cancel_user(user_id)),
+            Command::Amend {
+                order_id,
+                new_price,
+                new_size,
+            } => Outcome::Amended(order_book.amend(order_id, new_price, new_size)),
+            Command::AdvanceTime(now) => Outcome::Expired(order_book.advance_time(now)),
+            Command::Halt => {
+                order_book.halt();
+                Outcome::Done
+            }
+            Command::Resume => Outcome::Traded(order_book.resume()),
+            Command::StartAuction => {
+                order_book.start_auction();
+                Outcome::Done
+            }
+            Command::Uncross => Outcome::Traded(order_book.uncross()),
+            Command::SetReferencePrice(reference_price) => {
+                order_book.set_reference_price(reference_price);
+                Outcome::Done
+            }
+        }
+    }
+}
+
+/// Something that has happened to an order book while applying a command.
+///
+/// Apart from [`Event::Accepted`] and [`Event::NotFound`], the events are the ones reported to
+/// the listeners of the book, see [`OrderBookListener`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A bid has been submitted and got the id. Comes before the events of the bid.
+    Accepted(OrderId),
+    /// The command refers to a bid that is not in the book (anymore).
+    NotFound(OrderId),
+    /// See [`OrderBookListener::on_trade`].
+    Trade(Trade),
+    /// See [`OrderBookListener::on_order_added`].
+    OrderAdded(OrderInfo),
+    /// See [`OrderBookListener::on_order_dropped`].
+    OrderDropped(OrderInfo),
+    /// See [`OrderBookListener::on_order_partially_filled`].
+    OrderPartiallyFilled(OrderInfo),
+    /// See [`OrderBookListener::on_order_cancelled`].
+    OrderCancelled(OrderInfo),
+    /// See [`OrderBookListener::on_order_amended`].
+    OrderAmended(OrderInfo),
+    /// See [`OrderBookListener::on_order_expired`].
+    OrderExpired(OrderInfo),
+    /// See [`OrderBookListener::on_feed_event`].
+    Feed(FeedEvent),
+    /// See [`OrderBookListener::on_phase_changed`].
+    PhaseChanged(Phase),
+    /// See [`OrderBookListener::on_protection_triggered`].
+    ProtectionTriggered(u64),
+}
+
+/// Collects the events reported by a book into a shared list.
+pub(crate) struct EventLog(pub(crate) Rc<RefCell<Vec<Event>>>);
+
+impl EventLog {
+    fn push(&mut self, event: Event) {
+        self.0.borrow_mut().push(event)
+    }
+}
+
+impl OrderBookListener for EventLog {
+    fn on_trade(&mut self, trade: &Trade) {
+        self.push(Event::Trade(*trade))
+    }
+
+    fn on_order_added(&mut self, order: &OrderInfo) {
+        self.push(Event::OrderAdded(*order))
+    }
+
+    fn on_order_dropped(&mut self, order: &OrderInfo) {
+        self.push(Event::OrderDropped(*order))
+    }
+
+    fn on_order_partially_filled(&mut self, order: &OrderInfo) {
+        self.push(Event::OrderPartiallyFilled(*order))
+    }
+
+    fn on_order_cancelled(&mut self, order: &OrderInfo) {
+        self.push(Event::OrderCancelled(*order))
+    }
+
+    fn on_order_amended(&mut self, order: &OrderInfo) {
+        self.push(Event::OrderAmended(*order))
+    }
+
+    fn on_order_expired(&mut self, order: &OrderInfo) {
+        self.push(Event::OrderExpired(*order))
+    }
+
+    fn on_feed_event(&mut self, event: &FeedEvent) {
+        self.push(Event::Feed(*event))
+    }
+
+    fn on_phase_changed(&mut self, phase: Phase) {
+        self.push(Event::PhaseChanged(phase))
+    }
+
+    fn on_protection_triggered(&mut self, user_id: u64) {
+        self.push(Event::ProtectionTriggered(user_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType, Side, TimeInForce},
+        order_book::OrderBook,
+    };
+
+    #[test]
+    fn test_apply() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let events = order_book.apply(Command::Selling(selling_bid, BidProcessingType::Limit));
+        let resting = OrderInfo::from(&selling_bid);
+        assert_eq!(
+            events,
+            [
+                Event::Accepted(OrderId(0)),
+                Event::OrderAdded(resting),
+                Event::Feed(FeedEvent::AddOrder {
+                    order_id: OrderId(0),
+                    side: Side::Sell,
+                    price: 100,
+                    amount: 5,
+                }),
+            ]
+        );
+
+        assert_eq!(order_book.apply(Command::Halt), []);
+        let buying_bid = Bid::empty().price(100).amount(2).user_id(2);
+        let events = order_book.apply(Command::Buying(buying_bid, BidProcessingType::Limit));
+        assert_eq!(events, [Event::Accepted(OrderId(1))]);
+        let events = order_book.apply(Command::Resume);
+        let trade = Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Buy,
+            price: 100,
+            amount: 2,
+        };
+        assert_eq!(
+            events,
+            [
+                Event::Trade(trade),
+                Event::Feed(FeedEvent::Execute {
+                    order_id: OrderId(0),
+                    amount: 2,
+                }),
+                Event::OrderPartiallyFilled(OrderInfo::from(&selling_bid.amount(3))),
+            ]
+        );
+
+        // Raising the price replaces the bid.
+        let events = order_book.apply(Command::Amend {
+            order_id: OrderId(0),
+            new_price: 101,
+            new_size: 3,
+        });
+        assert_eq!(
+            events,
+            [
+                Event::Feed(FeedEvent::Cancel {
+                    order_id: OrderId(0)
+                }),
+                Event::OrderCancelled(OrderInfo::from(&selling_bid.amount(3))),
+                Event::Accepted(OrderId(2)),
+                Event::OrderAdded(OrderInfo::from(&selling_bid.price(101).amount(3))),
+                Event::Feed(FeedEvent::AddOrder {
+                    order_id: OrderId(2),
+                    side: Side::Sell,
+                    price: 101,
+                    amount: 3,
+                }),
+            ]
+        );
+        assert_eq!(
+            order_book.apply(Command::Cancel(OrderId(0))),
+            [Event::NotFound(OrderId(0))]
+        );
+
+        let day_bid = Bid::empty()
+            .price(90)
+            .amount(1)
+            .user_id(3)
+            .time_in_force(TimeInForce::Day);
+        order_book.apply(Command::Buying(day_bid, BidProcessingType::Limit));
+        let events = order_book.apply(Command::AdvanceTime(24 * 60 * 60));
+        assert_eq!(
+            events,
+            [
+                Event::OrderExpired(OrderInfo::from(&day_bid)),
+                Event::Feed(FeedEvent::Cancel {
+                    order_id: OrderId(3)
+                }),
+            ]
+        );
+        assert_eq!(
+            order_book.apply(Command::Cancel(OrderId(2))),
+            [
+                Event::Feed(FeedEvent::Cancel {
+                    order_id: OrderId(2)
+                }),
+                Event::OrderCancelled(OrderInfo::from(&selling_bid.price(101).amount(3))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_order_of_events() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(3).user_id(1).link(7);
+        let buying_bid = Bid::empty().price(90).amount(3).user_id(1).link(7);
+        order_book.apply(Command::Selling(selling_bid, BidProcessingType::Limit));
+        order_book.apply(Command::Buying(buying_bid, BidProcessingType::Limit));
+        // The bid trades with the linked one, which cancels the other one of the link.
+        let events = order_book.apply(Command::Buying(
+            Bid::empty().price(100).amount(3).user_id(2),
+            BidProcessingType::Limit,
+        ));
+        assert_eq!(events[0], Event::Accepted(OrderId(2)));
+        assert_eq!(
+            events.last(),
+            Some(&Event::OrderCancelled(OrderInfo::from(&buying_bid)))
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, Event::Accepted(_)))
+                .count(),
+            1
+        );
+
+        // The replacement of an amended bid does the same.
+        let mut order_book = OrderBook::empty();
+        order_book.apply(Command::Selling(selling_bid, BidProcessingType::Limit));
+        order_book.apply(Command::Buying(buying_bid, BidProcessingType::Limit));
+        order_book.apply(Command::Buying(
+            Bid::empty().price(95).amount(3).user_id(2),
+            BidProcessingType::Limit,
+        ));
+        let events = order_book.apply(Command::Amend {
+            order_id: OrderId(0),
+            new_price: 95,
+            new_size: 3,
+        });
+        assert_eq!(
+            events[..3],
+            [
+                Event::Feed(FeedEvent::Cancel {
+                    order_id: OrderId(0)
+                }),
+                Event::OrderCancelled(OrderInfo::from(&selling_bid)),
+                Event::Accepted(OrderId(3)),
+            ]
+        );
+        assert!(matches!(events[3], Event::Trade(_)));
+        assert_eq!(
+            events.last(),
+            Some(&Event::OrderCancelled(OrderInfo::from(&buying_bid)))
+        );
+    }
+}
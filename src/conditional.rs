@@ -0,0 +1,173 @@
+//! Conditional bids that are held until the order book reaches a certain state.
+
+use crate::{
+    bids::{Bid, BidProcessingType, BuyingBid, SellingBid},
+    order_book::{OrderBook, OrderId},
+};
+use std::fmt;
+
+/// A predicate over the order book state.
+///
+/// Conditions on prices are never met while the corresponding side of the book is empty.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// The best buying price is at least the given value.
+    BestBidAtLeast(u64),
+    /// The best buying price is at most the given value.
+    BestBidAtMost(u64),
+    /// The best selling price is at least the given value.
+    BestAskAtLeast(u64),
+    /// The best selling price is at most the given value.
+    BestAskAtMost(u64),
+    /// The difference between the best selling and the best buying prices is at most the given
+    /// value.
+    SpreadAtMost(u64),
+    /// The total amount of traded items is at least the given value.
+    TradedVolumeAtLeast(u64),
+}
+
+impl Condition {
+    /// Checks whether the condition holds for the given order book.
+    pub fn is_met(&self, order_book: &OrderBook) -> bool {
+        let best_bid = order_book.best_bid();
+        let best_ask = order_book.best_ask();
+        match *self {
+            Condition::BestBidAtLeast(price) => best_bid.is_some_and(|bid| bid >= price),
+            Condition::BestBidAtMost(price) => best_bid.is_some_and(|bid| bid <= price),
+            Condition::BestAskAtLeast(price) => best_ask.is_some_and(|ask| ask >= price),
+            Condition::BestAskAtMost(price) => best_ask.is_some_and(|ask| ask <= price),
+            Condition::SpreadAtMost(spread) => {
+                order_book.spread().is_some_and(|current| current <= spread)
+            }
+            Condition::TradedVolumeAtLeast(volume) => {
+                let traded = order_book.sellers.traded_volume() + order_book.buyers.traded_volume();
+                traded >= volume
+            }
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::BestBidAtLeast(price) => write!(f, "best bid >= {}", price),
+            Condition::BestBidAtMost(price) => write!(f, "best bid <= {}", price),
+            Condition::BestAskAtLeast(price) => write!(f, "best ask >= {}", price),
+            Condition::BestAskAtMost(price) => write!(f, "best ask <= {}", price),
+            Condition::SpreadAtMost(spread) => write!(f, "spread <= {}", spread),
+            Condition::TradedVolumeAtLeast(volume) => write!(f, "traded volume >= {}", volume),
+        }
+    }
+}
+
+/// A bid waiting for its condition to be met.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum PendingBid {
+    Selling(OrderId, Condition, Bid<SellingBid>, BidProcessingType),
+    Buying(OrderId, Condition, Bid<BuyingBid>, BidProcessingType),
+}
+
+impl PendingBid {
+    pub(crate) fn order_id(&self) -> OrderId {
+        match self {
+            PendingBid::Selling(order_id, ..) | PendingBid::Buying(order_id, ..) => *order_id,
+        }
+    }
+
+    pub(crate) fn condition(&self) -> &Condition {
+        match self {
+            PendingBid::Selling(_, condition, ..) | PendingBid::Buying(_, condition, ..) => {
+                condition
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conditions() {
+        let mut order_book = OrderBook::empty();
+        assert!(!Condition::BestBidAtMost(100).is_met(&order_book));
+        assert!(!Condition::SpreadAtMost(100).is_met(&order_book));
+        assert!(Condition::TradedVolumeAtLeast(0).is_met(&order_book));
+
+        let buying_bid = Bid::empty().price(90).amount(5).user_id(1);
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        assert!(Condition::BestBidAtLeast(90).is_met(&order_book));
+        assert!(!Condition::BestBidAtLeast(91).is_met(&order_book));
+        assert!(Condition::BestAskAtMost(100).is_met(&order_book));
+        assert!(!Condition::BestAskAtLeast(101).is_met(&order_book));
+        assert!(Condition::SpreadAtMost(10).is_met(&order_book));
+        assert!(!Condition::SpreadAtMost(9).is_met(&order_book));
+
+        let selling_bid = Bid::empty().price(90).amount(3).user_id(2);
+        order_book.process_selling(selling_bid, BidProcessingType::ImmediateOrCancel);
+        assert!(Condition::TradedVolumeAtLeast(3).is_met(&order_book));
+        assert!(!Condition::TradedVolumeAtLeast(4).is_met(&order_book));
+    }
+
+    #[test]
+    fn test_release() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+
+        // Waits until someone else buys at least 2 items.
+        let conditional_bid = Bid::empty().price(100).amount(3).user_id(2);
+        order_book.process_buying_when(
+            Condition::TradedVolumeAtLeast(2),
+            conditional_bid,
+            BidProcessingType::Limit,
+        );
+        assert_eq!(order_book.pending.len(), 1);
+        assert_eq!(
+            order_book.sellers.view_bids().collect::<Vec<_>>(),
+            [&selling_bid]
+        );
+
+        let buying_bid = Bid::empty().price(100).amount(2).user_id(3);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert!(order_book.pending.is_empty());
+        assert_eq!(order_book.sellers.view_bids().count(), 0);
+        assert_eq!(order_book.buyers.view_bids().count(), 0);
+    }
+
+    #[test]
+    fn test_released_bid_priority() {
+        let mut order_book = OrderBook::empty();
+        let conditional_bid = Bid::empty().price(100).amount(2).user_id(1);
+        order_book.process_selling_when(
+            Condition::BestBidAtLeast(95),
+            conditional_bid,
+            BidProcessingType::Limit,
+        );
+        let selling_bid = Bid::empty().price(100).amount(2).user_id(2);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(95).amount(1).user_id(3);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert!(order_book.pending.is_empty());
+
+        // The released bid queues behind the one that was resting before the release.
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(4);
+        let result = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let makers: Vec<_> = result
+            .trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.amount))
+            .collect();
+        assert_eq!(makers, [(2, 2), (1, 1)]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Engine configuration.
+
+use crate::{
+    error::Error, groups::UserGroups, iceberg::IcebergRefresh, instrument::Instrument,
+    mmp::MakerProtection, order_book::HaltMode, policy::MatchingPolicy, risk::RiskLimits,
+    session::SessionSchedule, throttle::RateLimit,
+};
+use serde_derive::Deserialize;
+use std::{collections::BTreeMap, io::Read};
+
+/// Configuration of an order book.
+///
+/// Usually loaded from a `yaml` file:
+///
+/// ```yaml
+/// ---
+/// user_groups:
+///   firm_a: [1, 2, 3]
+///   firm_b: [4, 5]
+/// max_levels_per_order: 3
+/// matching_policy: ProRata
+/// iceberg_refresh:
+///   tranche_size:
+///     Randomized: {variance: 5, seed: 42}
+///   keep_priority: false
+/// price_band_bps: 500
+/// halt_mode: Reject
+/// rate_limit:
+///   per_second: 10
+///   burst: 20
+///   mode: Queue
+/// maker_protection:
+///   users: [7]
+///   window: 5
+///   max_fills: 10
+///   max_quantity: 5000
+/// track_positions: true
+/// session:
+///   opening_auction: 32400
+///   continuous: 34200
+///   closing_auction: 63000
+///   closed: 63300
+/// risk_limits:
+///   max_order_size: 1000
+///   banned_users: [13]
+/// instruments:
+///   ABC:
+///     tick_size: 5
+///     lot_size: 100
+///     min_price: 10
+///   XYZ:
+///     tick_size: 25
+///     price_scale: 2
+///     size_scale: 3
+/// ```
+///
+/// All the sections are optional.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Config {
+    /// Groups of users that are not allowed to trade with each other.
+    #[serde(default)]
+    pub user_groups: UserGroups,
+    /// Maximum number of distinct price levels a single incoming bid may consume.
+    pub max_levels_per_order: Option<usize>,
+    /// How incoming bids are allocated between the bids of a price level.
+    #[serde(default)]
+    pub matching_policy: MatchingPolicy,
+    /// How iceberg bids are refreshed once their displayed parts are filled.
+    #[serde(default)]
+    pub iceberg_refresh: IcebergRefresh,
+    /// Half-width of the price band around the reference (or the last trade) price, in basis
+    /// points. Bids priced outside of the band are rejected, market bids don't execute beyond it.
+    pub price_band_bps: Option<u64>,
+    /// What happens to bids submitted while trading is halted.
+    #[serde(default)]
+    pub halt_mode: HaltMode,
+    /// Limit of the rate of the bids of every user, if any.
+    pub rate_limit: Option<RateLimit>,
+    /// Market-maker protection of designated users, if any.
+    pub maker_protection: Option<MakerProtection>,
+    /// Whether books keep the positions and cash balances of their users, see
+    /// [`OrderBook::accounts`](crate::OrderBook::accounts).
+    #[serde(default)]
+    pub track_positions: bool,
+    /// Daily schedule of the trading session, if any. Without a schedule books trade
+    /// continuously all day long.
+    pub session: Option<SessionSchedule>,
+    /// Limits of the incoming bids checked by the `MatchingEngine` before the bids reach the books.
+    #[serde(default)]
+    pub risk_limits: RiskLimits,
+    /// Trading rules of the instruments, by symbol. Used by the `MatchingEngine`: bids of the
+    /// instruments that are not listed here are not validated.
+    #[serde(default)]
+    pub instruments: BTreeMap<String, Instrument>,
+}
+
+impl Config {
+    /// Loads the configuration from a `yaml` reader.
+    pub fn from_reader(r: impl Read) -> Result<Self, Error> {
+        Ok(serde_yaml::from_reader(r)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::session::SessionSchedule;
+
+    #[test]
+    fn test_from_reader() {
+        let data = br#"---
+matching_policy: ProRata
+halt_mode: Reject
+price_band_bps: 500
+track_positions: true
+session:
+  opening_auction: 100
+  continuous: 200
+  closing_auction: 300
+  closed: 400
+instruments:
+  ABC:
+    tick_size: 5
+"#;
+        let config = Config::from_reader(&data[..]).unwrap();
+        assert_eq!(config.matching_policy, MatchingPolicy::ProRata);
+        assert_eq!(config.halt_mode, HaltMode::Reject);
+        assert_eq!(config.price_band_bps, Some(500));
+        assert!(config.track_positions);
+        assert_eq!(
+            config.session,
+            Some(SessionSchedule {
+                opening_auction: 100,
+                continuous: 200,
+                closing_auction: 300,
+                closed: 400,
+            })
+        );
+        // Missing fields of an instrument keep their defaults.
+        let abc = config.instruments["ABC"];
+        assert_eq!(abc.tick_size, 5);
+        assert_eq!(abc.lot_size, 1);
+        assert_eq!(abc.min_price, None);
+        assert_eq!(config.max_levels_per_order, None);
+        assert_eq!(config.rate_limit, None);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::from_reader(&b"--- {}"[..]).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let documents: [&[u8]; 5] = [
+            b"",
+            b"matching_policy: [",
+            b"matching_policy: Lifo",
+            b"max_levels_per_order: many",
+            b"session:\n  opening_auction: 100\n",
+        ];
+        for data in documents.iter() {
+            let result = Config::from_reader(*data);
+            assert!(matches!(result, Err(Error::Yaml(_))), "{:?}", result);
+        }
+    }
+}
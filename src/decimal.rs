@@ -0,0 +1,169 @@
+//! Decimal prices and sizes of the input.
+
+use serde::{
+    de::{self, Deserializer, Visitor},
+    Deserialize, Serialize, Serializer,
+};
+use std::{fmt, str::FromStr};
+
+/// A non-negative fixed-point decimal number, like `100.25`.
+///
+/// The matcher itself works with integer prices and amounts, measured in the units of the last
+/// decimal place of an instrument (see [`Instrument::price_scale`](crate::Instrument::price_scale)
+/// and [`Instrument::size_scale`](crate::Instrument::size_scale)), so decimal numbers are only
+/// converted at the boundary of the engine.
+///
+/// Numbers are deserialized either from numbers or from strings, and serialized as integers when
+/// they are whole, and as strings otherwise, so no precision is lost to floating point numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: u64,
+    /// Number of decimal places, with no trailing zeros.
+    scale: u32,
+}
+
+/// A decimal price of the input.
+pub type Price = Decimal;
+
+/// A decimal size of the input.
+pub type Quantity = Decimal;
+
+impl Decimal {
+    /// Makes a number `mantissa / 10^scale`.
+    pub fn new(mut mantissa: u64, mut scale: u32) -> Self {
+        while scale != 0 && mantissa.is_multiple_of(10) {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        Decimal { mantissa, scale }
+    }
+
+    /// Converts the number to the units of the `scale`-th decimal place.
+    ///
+    /// Returns `None` if the number has more decimal places than that, or if it doesn't fit into
+    /// `u64`.
+    pub fn to_units(self, scale: u32) -> Option<u64> {
+        let factor = 10u64.checked_pow(scale.checked_sub(self.scale)?)?;
+        self.mantissa.checked_mul(factor)
+    }
+
+    /// Whether the number is zero.
+    pub fn is_zero(self) -> bool {
+        self.mantissa == 0
+    }
+}
+
+impl From<u64> for Decimal {
+    fn from(number: u64) -> Self {
+        Decimal::new(number, 0)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+        let digits = format!("{:0width$}", self.mantissa, width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        write!(f, "{}.{}", int, frac)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid decimal number {:?}", s);
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        if int.is_empty() || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let mantissa = format!("{}{}", int, frac).parse().map_err(|_| invalid())?;
+        Ok(Decimal::new(mantissa, frac.len() as u32))
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.scale == 0 {
+            serializer.serialize_u64(self.mantissa)
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+struct DecimalVisitor;
+
+impl<'de> Visitor<'de> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a non-negative decimal number")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Decimal, E> {
+        Ok(Decimal::from(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Decimal, E> {
+        if v < 0 {
+            return Err(E::invalid_value(de::Unexpected::Signed(v), &self));
+        }
+        Ok(Decimal::from(v as u64))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Decimal, E> {
+        // The shortest representation of the number is the one that has been written.
+        v.to_string()
+            .parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Float(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Decimal, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decimal() {
+        let price: Price = "100.250".parse().unwrap();
+        assert_eq!(price, Decimal::new(10025, 2));
+        assert_eq!(price.to_string(), "100.25");
+        assert_eq!(price.to_units(2), Some(10025));
+        assert_eq!(price.to_units(4), Some(1_002_500));
+        assert_eq!(price.to_units(1), None);
+        assert_eq!(Decimal::new(5, 3).to_string(), "0.005");
+        assert_eq!(Decimal::new(1200, 2), Decimal::from(12));
+        assert_eq!(Decimal::from(u64::MAX).to_units(1), None);
+        for invalid in &["", ".5", "1.2.3", "-1", "1e3", "1.x"] {
+            assert!(invalid.parse::<Decimal>().is_err(), "{:?}", invalid);
+        }
+
+        let prices: Vec<Decimal> = serde_yaml::from_str("[100, 100.25, '0.005', 1.10]").unwrap();
+        let expected = [
+            Decimal::from(100),
+            Decimal::new(10025, 2),
+            Decimal::new(5, 3),
+            Decimal::new(11, 1),
+        ];
+        assert_eq!(prices, expected);
+        assert_eq!(
+            serde_json::to_string(&prices).unwrap(),
+            r#"[100,"100.25","0.005","1.1"]"#
+        );
+        assert!(serde_yaml::from_str::<Decimal>("-1").is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Seeding and driving books with the depth data of crypto exchanges (Binance style).
+
+use crate::{
+    bids::{Bid, BidProcessingType, Side},
+    decimal::{Price, Quantity},
+    engine::MatchingEngine,
+    error::Error,
+    order_book::{Amendment, OrderBook, OrderId},
+    raw::ProcessingSummary,
+    trade::Trade,
+};
+use serde_derive::Deserialize;
+use std::{
+    collections::HashMap,
+    io::{BufReader, Read},
+};
+
+/// User id of the bids standing in for the levels.
+const LEVEL_USER_ID: u64 = 0;
+
+/// A snapshot of the depth, e.g. of the `/api/v3/depth` endpoint of Binance.
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+    #[serde(rename = "lastUpdateId")]
+    last_update_id: u64,
+    bids: Vec<(Price, Quantity)>,
+    asks: Vec<(Price, Quantity)>,
+}
+
+/// An incremental update of the depth, e.g. of the `<symbol>@depth` stream of Binance.
+#[derive(Debug, Deserialize)]
+struct DepthUpdate {
+    #[serde(rename = "U")]
+    first_update_id: u64,
+    #[serde(rename = "u")]
+    last_update_id: u64,
+    #[serde(rename = "b")]
+    bids: Vec<(Price, Quantity)>,
+    #[serde(rename = "a")]
+    asks: Vec<(Price, Quantity)>,
+}
+
+/// A book of the engine mirroring the depth of a symbol published by a crypto exchange.
+///
+/// The depth is a list of price levels of each side with their total quantities, so every level
+/// is a single `Limit` bid at its price, of the user `0`: the levels never trade with each other,
+/// but bids of other users trade with them as usual. Prices and quantities are decimal strings,
+/// converted to the units of the instrument of the symbol, see
+/// [`Instrument::price_scale`](crate::Instrument::price_scale).
+///
+/// ```norun
+/// {"lastUpdateId": 160, "bids": [["0.0024", "10"]], "asks": [["0.0026", "100"]]}
+/// ```
+///
+/// Updates set the total quantities of the levels, zero removing a level. A reduced level keeps
+/// its priority, a raised one goes to the back of the queue at its price:
+///
+/// ```norun
+/// {"e": "depthUpdate", "U": 157, "u": 160, "b": [["0.0024", "10"]], "a": [["0.0026", "0"]]}
+/// ```
+#[derive(Debug, Clone)]
+pub struct DepthImport {
+    symbol: String,
+    last_update_id: u64,
+    /// Whether an update has been applied since the snapshot.
+    updated: bool,
+    selling: HashMap<u64, OrderId>,
+    buying: HashMap<u64, OrderId>,
+}
+
+impl DepthImport {
+    /// Seeds the book of the `symbol` with a snapshot of the depth from a reader, in the JSON
+    /// format.
+    pub fn load_snapshot(
+        engine: &mut MatchingEngine,
+        symbol: &str,
+        r: impl Read,
+    ) -> Result<Self, Error> {
+        let snapshot: Snapshot = serde_json::from_reader(BufReader::new(r)).map_err(|e| {
+            if e.is_io() {
+                Error::Io(e.into())
+            } else {
+                Error::parse(0, e)
+            }
+        })?;
+        let mut import = DepthImport {
+            symbol: symbol.to_string(),
+            last_update_id: snapshot.last_update_id,
+            updated: false,
+            selling: HashMap::new(),
+            buying: HashMap::new(),
+        };
+        import
+            .apply(engine, &snapshot.bids, &snapshot.asks)
+            .map_err(|reason| Error::Invalid { entry: 0, reason })?;
+        Ok(import)
+    }
+
+    /// Id of the last update applied to the book (or of the snapshot).
+    pub fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+
+    /// Applies the updates of the depth from a reader, in the JSON Lines format, an update per
+    /// line.
+    ///
+    /// Returns the summary of the processing, where every update counts as an order. Updates the
+    /// snapshot (or the previous updates) already cover are skipped. The first applied update
+    /// must cover the id that follows the snapshot, and every next one must start right after the
+    /// previous one, otherwise the processing stops with [`Error::Invalid`]: the book is out of
+    /// sync and has to be seeded again.
+    pub fn process_updates(
+        &mut self,
+        engine: &mut MatchingEngine,
+        r: impl Read,
+    ) -> Result<ProcessingSummary, Error> {
+        let updates =
+            serde_json::Deserializer::from_reader(BufReader::new(r)).into_iter::<DepthUpdate>();
+        let mut summary = ProcessingSummary::default();
+        for (entry, update) in updates.enumerate() {
+            let update = update.map_err(|e| {
+                if e.is_io() {
+                    Error::Io(e.into())
+                } else {
+                    Error::parse(entry, e)
+                }
+            })?;
+            if update.last_update_id <= self.last_update_id {
+                summary.record(Ok(&[]));
+                continue;
+            }
+            let next = self.last_update_id + 1;
+            let in_sequence = if self.updated {
+                update.first_update_id == next
+            } else {
+                update.first_update_id <= next
+            };
+            if !in_sequence {
+                return Err(Error::Invalid {
+                    entry,
+                    reason: format!(
+                        "updates {}..={} don't follow {}",
+                        update.first_update_id, update.last_update_id, self.last_update_id
+                    ),
+                });
+            }
+            let trades = self
+                .apply(engine, &update.bids, &update.asks)
+                .map_err(|reason| Error::Invalid { entry, reason })?;
+            self.last_update_id = update.last_update_id;
+            self.updated = true;
+            summary.record(Ok(&trades));
+        }
+        Ok(summary.finish(engine))
+    }
+
+    /// Sets the quantities of the levels, the removed and the reduced ones first, so the sides
+    /// don't cross in between.
+    ///
+    /// Returns the trades, if the sides cross anyway.
+    fn apply(
+        &mut self,
+        engine: &mut MatchingEngine,
+        bids: &[(Price, Quantity)],
+        asks: &[(Price, Quantity)],
+    ) -> Result<Vec<Trade>, String> {
+        let instrument = engine.instrument(&self.symbol).copied().unwrap_or_default();
+        let mut changes = Vec::with_capacity(bids.len() + asks.len());
+        let levels = bids
+            .iter()
+            .map(|level| (Side::Buy, level))
+            .chain(asks.iter().map(|level| (Side::Sell, level)));
+        for (side, &(price, quantity)) in levels {
+            let price = price.to_units(instrument.price_scale).ok_or_else(|| {
+                format!(
+                    "price {} doesn't fit into {} decimal places",
+                    price, instrument.price_scale
+                )
+            })?;
+            let amount = quantity.to_units(instrument.size_scale).ok_or_else(|| {
+                format!(
+                    "quantity {} doesn't fit into {} decimal places",
+                    quantity, instrument.size_scale
+                )
+            })?;
+            changes.push((side, price, amount));
+        }
+        let order_book = engine.book_mut(&self.symbol);
+        changes.sort_by_key(|&(side, price, amount)| amount > self.level(order_book, side, price));
+        let mut trades = Vec::new();
+        for (side, price, amount) in changes {
+            trades.extend(self.set_level(order_book, side, price, amount));
+        }
+        Ok(trades)
+    }
+
+    /// Quantity of a level in the book.
+    fn level(&self, order_book: &OrderBook, side: Side, price: u64) -> u64 {
+        let levels = match side {
+            Side::Sell => &self.selling,
+            Side::Buy => &self.buying,
+        };
+        levels
+            .get(&price)
+            .and_then(|&order_id| order_book.get(order_id))
+            .map_or(0, |status| status.remaining)
+    }
+
+    fn set_level(
+        &mut self,
+        order_book: &mut OrderBook,
+        side: Side,
+        price: u64,
+        amount: u64,
+    ) -> Vec<Trade> {
+        let current = self.level(order_book, side, price);
+        let levels = match side {
+            Side::Sell => &mut self.selling,
+            Side::Buy => &mut self.buying,
+        };
+        if amount == current {
+            return Vec::new();
+        }
+        if amount == 0 {
+            if let Some(order_id) = levels.remove(&price) {
+                order_book.cancel(order_id);
+            }
+            return Vec::new();
+        }
+        if current != 0 {
+            let order_id = levels[&price];
+            return match order_book.amend(order_id, price, amount) {
+                Amendment::Replaced(result) => {
+                    levels.insert(price, result.order_id);
+                    result.trades
+                }
+                _ => Vec::new(),
+            };
+        }
+        let result = match side {
+            Side::Sell => {
+                let selling_bid = Bid::empty()
+                    .price(price)
+                    .amount(amount)
+                    .user_id(LEVEL_USER_ID);
+                order_book.process_selling(selling_bid, BidProcessingType::Limit)
+            }
+            Side::Buy => {
+                let buying_bid = Bid::empty()
+                    .price(price)
+                    .amount(amount)
+                    .user_id(LEVEL_USER_ID);
+                order_book.process_buying(buying_bid, BidProcessingType::Limit)
+            }
+        };
+        levels.insert(price, result.order_id);
+        result.trades
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::Config, instrument::Instrument};
+
+    #[test]
+    fn test_depth_import() {
+        let mut config = Config::default();
+        let instrument = Instrument {
+            price_scale: 1,
+            ..Instrument::default()
+        };
+        config.instruments.insert("BNBBTC".to_string(), instrument);
+        let mut engine = MatchingEngine::with_config(config);
+        let snapshot = br#"{"lastUpdateId": 160, "bids": [["99.50", "2"], ["99.0", "1"]], "asks": [["100.0", "3.000"]]}"#;
+        let mut import = DepthImport::load_snapshot(&mut engine, "BNBBTC", &snapshot[..]).unwrap();
+        let order_book = engine.book("BNBBTC").unwrap();
+        assert_eq!(order_book.depth(5).buying, [(995, 2, 1), (990, 1, 1)]);
+        assert_eq!(order_book.depth(5).selling, [(1000, 3, 1)]);
+
+        // Another user trades with a level.
+        let selling_bid = Bid::empty().price(995).amount(1).user_id(7);
+        engine
+            .process_selling("BNBBTC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+
+        let updates = br#"{"e": "depthUpdate", "U": 150, "u": 155, "b": [["99.5", "5"]], "a": []}
+{"e": "depthUpdate", "U": 157, "u": 162, "b": [["99.5", "0"], ["99.8", "4"], ["99.0", "2"]], "a": [["100", "1"], ["99.9", "2"]]}
+{"e": "depthUpdate", "U": 163, "u": 163, "b": [["99.8", "3"]], "a": []}
+"#;
+        let summary = import.process_updates(&mut engine, &updates[..]).unwrap();
+        assert_eq!(summary.orders_read, 3);
+        assert_eq!(summary.trades, 0);
+        assert_eq!(import.last_update_id(), 163);
+        let order_book = engine.book("BNBBTC").unwrap();
+        assert_eq!(order_book.depth(5).buying, [(998, 3, 1), (990, 2, 1)]);
+        assert_eq!(order_book.depth(5).selling, [(999, 2, 1), (1000, 1, 1)]);
+
+        let gap = br#"{"e": "depthUpdate", "U": 165, "u": 170, "b": [], "a": []}"#;
+        let error = import.process_updates(&mut engine, &gap[..]).unwrap_err();
+        assert!(
+            matches!(error, Error::Invalid { entry: 0, .. }),
+            "{}",
+            error
+        );
+        let error =
+            DepthImport::load_snapshot(&mut engine, "BNBBTC", &br#"{"bids": []}"#[..]).unwrap_err();
+        assert!(matches!(error, Error::Parse { entry: 0, .. }), "{}", error);
+    }
+}
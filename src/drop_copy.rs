@@ -0,0 +1,166 @@
+//! Drop copy: an independent record of the executions and the cancellations.
+
+use crate::{
+    bids::Side,
+    listener::{OrderBookListener, OrderInfo},
+    trade::Trade,
+};
+use log::warn;
+use serde_derive::Serialize;
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+/// Writes every execution and cancellation (including expiries) of the books it's registered
+/// with to a sink, one JSON object per line, no matter which user or session they originate
+/// from, e.g. for the back office to reconcile against.
+///
+/// It's independent of the other listeners: records are written and flushed as the events
+/// happen. Failures to write are logged. Use [`DropCopy::listener`] with
+/// [`MatchingEngine::add_listener_factory`](crate::MatchingEngine::add_listener_factory) to
+/// copy every book of an engine into the same sink (the first record is wrapped here):
+///
+/// ```norun
+/// {"seq":0,"symbol":"ABC","event":"trade","side":"Buy","price":100,"amount":5,"user_id":2,
+///  "maker_user_id":1}
+/// {"seq":1,"symbol":"ABC","event":"cancel","side":"Sell","price":101,"amount":3,"user_id":1}
+/// ```
+///
+/// Sequence numbers are shared by all the listeners of a drop copy, so gaps are easy to spot.
+/// For trades, the side and the user id are the ones of the incoming (taker) bid.
+pub struct DropCopy {
+    sink: Rc<RefCell<Sink>>,
+    symbol: String,
+}
+
+struct Sink {
+    w: Box<dyn Write>,
+    next_seq: u64,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    seq: u64,
+    symbol: &'a str,
+    event: &'static str,
+    side: Side,
+    price: u64,
+    amount: u64,
+    user_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maker_user_id: Option<u64>,
+}
+
+impl DropCopy {
+    /// Initializes a drop copy into the writer, as a listener of the book with an empty symbol.
+    pub fn new(w: impl Write + 'static) -> Self {
+        DropCopy {
+            sink: Rc::new(RefCell::new(Sink {
+                w: Box::new(w),
+                next_seq: 0,
+            })),
+            symbol: String::new(),
+        }
+    }
+
+    /// A listener of the book of the given symbol, writing to the same sink.
+    pub fn listener(&self, symbol: &str) -> DropCopy {
+        DropCopy {
+            sink: Rc::clone(&self.sink),
+            symbol: symbol.to_string(),
+        }
+    }
+
+    fn write(&self, event: &'static str, order: &OrderInfo, maker_user_id: Option<u64>) {
+        let mut sink = self.sink.borrow_mut();
+        let record = Record {
+            seq: sink.next_seq,
+            symbol: &self.symbol,
+            event,
+            side: order.side,
+            price: order.price,
+            amount: order.amount,
+            user_id: order.user_id,
+            maker_user_id,
+        };
+        sink.next_seq += 1;
+        let written = serde_json::to_writer(&mut sink.w, &record)
+            .map_err(Into::into)
+            .and_then(|()| writeln!(sink.w))
+            .and_then(|()| sink.w.flush());
+        if let Err(e) = written {
+            warn!("Can't write the drop copy record {}: {}", record.seq, e);
+        }
+    }
+}
+
+impl OrderBookListener for DropCopy {
+    fn on_trade(&mut self, trade: &Trade) {
+        let taker = OrderInfo {
+            side: trade.taker_side,
+            price: trade.price,
+            amount: trade.amount,
+            user_id: trade.taker_user_id,
+        };
+        self.write("trade", &taker, Some(trade.maker_user_id))
+    }
+
+    fn on_order_cancelled(&mut self, order: &OrderInfo) {
+        self.write("cancel", order, None)
+    }
+
+    fn on_order_expired(&mut self, order: &OrderInfo) {
+        self.write("expire", order, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType},
+        engine::MatchingEngine,
+        order_book::OrderId,
+    };
+    use std::io;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_drop_copy() {
+        let output = Shared::default();
+        let drop_copy = DropCopy::new(output.clone());
+        let mut engine = MatchingEngine::new();
+        engine.add_listener_factory(move |symbol| drop_copy.listener(symbol));
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        engine
+            .process_selling("XYZ", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        let buying_bid = Bid::empty().price(100).amount(2).user_id(2);
+        engine
+            .process_buying("XYZ", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+        engine.book_mut("ABC").cancel(OrderId(0));
+
+        let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+        let expected = concat!(
+            r#"{"seq":0,"symbol":"XYZ","event":"trade","side":"Buy","price":100,"amount":2,"user_id":2,"maker_user_id":1}"#,
+            "\n",
+            r#"{"seq":1,"symbol":"ABC","event":"cancel","side":"Sell","price":100,"amount":5,"user_id":1}"#,
+            "\n",
+        );
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,294 @@
+//! A matching engine for several instruments.
+
+use crate::{
+    bids::{Bid, BidProcessingType, BuyingBid, GenericBid, SellingBid},
+    clock::Clock,
+    config::Config,
+    feed::Divergence,
+    instrument::{Instrument, Rejection},
+    listener::{OrderBookListener, OrderInfo},
+    order_book::{OrderBook, ProcessingResult},
+    risk::RiskCheck,
+    shadow::ShadowBooks,
+};
+use std::collections::BTreeMap;
+
+type ListenerFactory = Box<dyn Fn(&str) -> Box<dyn OrderBookListener>>;
+type ClockFactory = Box<dyn Fn() -> Box<dyn Clock>>;
+
+/// A set of order books, one per symbol.
+///
+/// Books are created on demand with the same configuration and the same listeners. Bids are
+/// validated against the instruments listed in the configuration, and then go through the risk
+/// checks: the configured risk limits first, then the registered checks.
+#[derive(Default)]
+pub struct MatchingEngine {
+    books: BTreeMap<String, OrderBook>,
+    config: Config,
+    listeners: Vec<ListenerFactory>,
+    risk_checks: Vec<Box<dyn RiskCheck>>,
+    clock: Option<ClockFactory>,
+    now: u64,
+    shadow: Option<ShadowBooks>,
+}
+
+impl MatchingEngine {
+    /// Initializes an engine without any books.
+    pub fn new() -> Self {
+        MatchingEngine::default()
+    }
+
+    /// Initializes an engine without any books, which will be created with the given
+    /// configuration.
+    pub fn with_config(config: Config) -> Self {
+        MatchingEngine {
+            config,
+            ..MatchingEngine::default()
+        }
+    }
+
+    /// Registers a listener for every book, including the ones that will be created later.
+    pub fn add_listener(&mut self, listener: impl OrderBookListener + Clone + 'static) {
+        for book in self.books.values_mut() {
+            book.add_listener(listener.clone());
+        }
+        self.listeners
+            .push(Box::new(move |_symbol| Box::new(listener.clone())));
+    }
+
+    /// Registers a listener for every book, including the ones that will be created later, made
+    /// by the `factory` out of the book's symbol.
+    pub fn add_listener_factory<L>(&mut self, factory: impl Fn(&str) -> L + 'static)
+    where
+        L: OrderBookListener + 'static,
+    {
+        for (symbol, book) in &mut self.books {
+            book.add_listener(factory(symbol));
+        }
+        self.listeners
+            .push(Box::new(move |symbol| Box::new(factory(symbol))));
+    }
+
+    /// Sets the clock of every book, including the ones that will be created later, see
+    /// [`OrderBook::set_clock`].
+    pub fn set_clock(&mut self, clock: impl Clock + Clone + 'static) {
+        for book in self.books.values_mut() {
+            book.set_clock(clock.clone());
+        }
+        self.clock = Some(Box::new(move || Box::new(clock.clone())));
+    }
+
+    /// Keeps a shadow of every book, rebuilt from the book's feed, and compares the books with
+    /// their shadows once every `every` bids processed by the engine, see [`FeedBook::diff`].
+    ///
+    /// A safety net while the matching internals evolve: a divergence means that the feed doesn't
+    /// reflect the book. Divergences are logged as errors, see also
+    /// [`MatchingEngine::divergences`].
+    ///
+    /// [`FeedBook::diff`]: crate::FeedBook::diff
+    pub fn enable_shadow_books(&mut self, every: usize) {
+        let shadow = ShadowBooks::new(every);
+        for (symbol, book) in &mut self.books {
+            let listener = shadow.follow(symbol, book);
+            book.add_listener(listener);
+        }
+        let factory = shadow.factory();
+        self.listeners
+            .push(Box::new(move |symbol| Box::new(factory(symbol))));
+        self.shadow = Some(shadow);
+    }
+
+    /// Compares the books with their shadows right away, if they are enabled, see
+    /// [`MatchingEngine::enable_shadow_books`].
+    ///
+    /// Returns whether all the books match their shadows.
+    pub fn check_shadow_books(&mut self) -> bool {
+        match &mut self.shadow {
+            Some(shadow) => shadow.check(&self.books),
+            None => true,
+        }
+    }
+
+    /// The last divergences of the books from their shadows found so far, by symbol.
+    pub fn divergences(&self) -> impl Iterator<Item = (&str, &[Divergence])> {
+        self.shadow
+            .iter()
+            .flat_map(|shadow| shadow.divergences())
+            .map(|(symbol, divergences)| (symbol.as_str(), &divergences[..]))
+    }
+
+    /// Registers a risk check that every incoming bid has to pass, after the configured risk
+    /// limits and the previously registered checks.
+    pub fn add_risk_check(&mut self, risk_check: impl RiskCheck + 'static) {
+        self.risk_checks.push(Box::new(risk_check));
+    }
+
+    /// Registers (or replaces) the trading rules of an instrument.
+    pub fn add_instrument(&mut self, symbol: &str, instrument: Instrument) {
+        self.config
+            .instruments
+            .insert(symbol.to_string(), instrument);
+    }
+
+    /// Replaces the configuration at runtime, keeping the books and their bids.
+    ///
+    /// The risk limits and the instruments apply to the next bids, the limits of the existing
+    /// books are reloaded with [`OrderBook::reload_config`]. Books created afterwards get the whole
+    /// new configuration.
+    pub fn reload_config(&mut self, config: Config) {
+        for book in self.books.values_mut() {
+            book.reload_config(&config);
+        }
+        self.config = config;
+    }
+
+    /// Validates a selling bid and processes it in the book of the given symbol.
+    pub fn process_selling(
+        &mut self,
+        symbol: &str,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> Result<ProcessingResult, Rejection> {
+        self.validate(symbol, &bid, bid_type)?;
+        let result = self.book_mut(symbol).process_selling(bid, bid_type);
+        self.processed();
+        Ok(result)
+    }
+
+    /// Validates a buying bid and processes it in the book of the given symbol.
+    pub fn process_buying(
+        &mut self,
+        symbol: &str,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> Result<ProcessingResult, Rejection> {
+        self.validate(symbol, &bid, bid_type)?;
+        let result = self.book_mut(symbol).process_buying(bid, bid_type);
+        self.processed();
+        Ok(result)
+    }
+
+    /// Counts a processed bid for the shadow books, if they are enabled.
+    fn processed(&mut self) {
+        if let Some(shadow) = &mut self.shadow {
+            shadow.processed(&self.books);
+        }
+    }
+
+    fn validate<BidKind: GenericBid>(
+        &mut self,
+        symbol: &str,
+        bid: &Bid<BidKind>,
+        bid_type: BidProcessingType,
+    ) -> Result<(), Rejection> {
+        if let Some(instrument) = self.config.instruments.get(symbol) {
+            instrument.validate(bid, bid_type)?;
+        }
+        let band = self.books.get(symbol).and_then(OrderBook::price_band);
+        if let Some((low, high)) = band {
+            if bid_type != BidProcessingType::Market && (bid.price < low || bid.price > high) {
+                return Err(Rejection::OutsidePriceBand {
+                    price: bid.price,
+                    low,
+                    high,
+                });
+            }
+        }
+        let order = OrderInfo::from(bid);
+        let book = self.books.get(symbol);
+        self.config.risk_limits.check(&order, bid_type, book)?;
+        self.risk_checks
+            .iter_mut()
+            .try_for_each(|risk_check| risk_check.check(&order, bid_type, book))
+    }
+
+    /// Returns the trading rules of the given symbol, if they are configured.
+    pub fn instrument(&self, symbol: &str) -> Option<&Instrument> {
+        self.config.instruments.get(symbol)
+    }
+
+    /// Returns the book of the given symbol, if it exists.
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(symbol)
+    }
+
+    /// Returns the book of the given symbol, creating it if necessary.
+    pub fn book_mut(&mut self, symbol: &str) -> &mut OrderBook {
+        if !self.books.contains_key(symbol) {
+            let mut book = OrderBook::with_config(self.config.clone());
+            for listener in &self.listeners {
+                book.add_boxed_listener(listener(symbol));
+            }
+            if let Some(clock) = &self.clock {
+                book.set_boxed_clock(clock());
+            }
+            book.advance_time(self.now);
+            self.books.insert(symbol.to_string(), book);
+        }
+        self.books
+            .get_mut(symbol)
+            .expect("The book has just been created")
+    }
+
+    /// Symbols of all the books, in the alphabetical order.
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.books.keys().map(String::as_str)
+    }
+
+    /// The engine's time, in seconds since the Unix epoch.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Advances the clock of every book to `now`, see [`OrderBook::advance_time`].
+    ///
+    /// Books created afterwards start with this clock.
+    pub fn advance_time(&mut self, now: u64) {
+        self.now = now;
+        for book in self.books.values_mut() {
+            book.advance_time(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::risk::RiskLimits;
+
+    #[test]
+    fn test_reload_config() {
+        let mut engine = MatchingEngine::new();
+        let selling_bid = Bid::empty().price(100).amount(10).user_id(1);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        let buying_bid = Bid::empty().price(100).amount(2).user_id(2);
+        engine
+            .process_buying("ABC", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+
+        engine.reload_config(Config {
+            price_band_bps: Some(500),
+            risk_limits: RiskLimits {
+                max_order_size: Some(5),
+                ..RiskLimits::default()
+            },
+            ..Config::default()
+        });
+        // The book is kept.
+        assert_eq!(engine.book("ABC").unwrap().depth(1).selling, [(100, 8, 1)]);
+        assert!(matches!(
+            engine.process_buying("ABC", buying_bid.amount(6), BidProcessingType::Limit),
+            Err(Rejection::OrderTooLarge { .. })
+        ));
+        assert!(matches!(
+            engine.process_buying("ABC", buying_bid.price(106), BidProcessingType::Limit),
+            Err(Rejection::OutsidePriceBand { .. })
+        ));
+        let result = engine
+            .process_buying("ABC", buying_bid.price(105), BidProcessingType::Limit)
+            .unwrap();
+        assert_eq!(result.trades.len(), 1);
+    }
+}
@@ -0,0 +1,44 @@
+//! Errors of loading the configuration and processing orders.
+
+use std::{error::Error as StdError, io};
+use thiserror::Error;
+
+/// An error of loading the configuration or processing a list of orders.
+///
+/// Entries are counted from zero in the order they appear in the input. Orders before the failed
+/// entry remain processed.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The input can't be read.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// A `yaml` document (the configuration or a list of orders) is malformed as a whole.
+    #[error("{0}")]
+    Yaml(#[from] serde_yaml::Error),
+    /// An entry of the input can't be parsed.
+    #[error("entry {entry}: {source}")]
+    Parse {
+        entry: usize,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// An entry of the input is well-formed, but doesn't describe a valid order.
+    #[error("entry {entry}: {reason}")]
+    Invalid { entry: usize, reason: String },
+}
+
+impl Error {
+    /// Index of the failed entry, if the error is about a particular entry.
+    pub fn entry(&self) -> Option<usize> {
+        match self {
+            Error::Parse { entry, .. } | Error::Invalid { entry, .. } => Some(*entry),
+            Error::Io(_) | Error::Yaml(_) => None,
+        }
+    }
+
+    pub(crate) fn parse(entry: usize, source: impl StdError + Send + Sync + 'static) -> Self {
+        Error::Parse {
+            entry,
+            source: Box::new(source),
+        }
+    }
+}
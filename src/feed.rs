@@ -0,0 +1,564 @@
+//! An ITCH-style market data feed.
+//!
+//! The feed describes the displayed part of a book bid by bid, so downstream tools can rebuild
+//! the book from the feed alone (see [`FeedBook`]) and compare it with the book itself. Every
+//! message carries a sequence number, which starts at one and has no gaps.
+//!
+//! Messages are encoded like the order entry ones: a one byte type, the sequence number and fixed
+//! width fields. Integers are big-endian `u64`s, sides are `B` or `S`:
+//!
+//! | Message   | Type  | Fields                                    |
+//! |-----------|-------|-------------------------------------------|
+//! | Add order | `A`   | seq, order id, side, price, size          |
+//! | Execute   | `E`   | seq, order id, executed size              |
+//! | Reduce    | `R`   | seq, order id, cancelled size             |
+//! | Cancel    | `X`   | seq, order id                             |
+//! | Trade     | `P`   | seq, taker side, price, size              |
+
+use crate::{
+    bids::Side,
+    binary::{invalid_data, read_byte, read_u64},
+    listener::OrderBookListener,
+    order_book::{Depth, OrderBook, OrderId},
+    pool::saturate,
+    trade::Trade,
+};
+use log::error;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+};
+
+/// A change of the displayed part of a book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedEvent {
+    /// A bid has been put on the book with the given displayed amount.
+    ///
+    /// An order id that is already on the book means that the displayed part of an iceberg bid
+    /// has been replenished, and (unless the book is configured to keep the priority of refreshed
+    /// tranches) the bid has moved to the end of the queue at its price.
+    AddOrder {
+        order_id: OrderId,
+        side: Side,
+        price: u64,
+        amount: u64,
+    },
+    /// A bid resting in the book has been executed for the given amount.
+    Execute { order_id: OrderId, amount: u64 },
+    /// The displayed amount of a bid has been reduced by the given amount, keeping its priority.
+    Reduce { order_id: OrderId, amount: u64 },
+    /// A bid has been removed from the book, either cancelled or expired.
+    Cancel { order_id: OrderId },
+    /// A trade has been executed.
+    ///
+    /// Books report trades to their listeners with `on_trade`, so this event only shows up in
+    /// feeds.
+    Trade {
+        taker_side: Side,
+        price: u64,
+        amount: u64,
+    },
+}
+
+/// A sequenced feed message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeedMessage {
+    /// Sequence number of the message.
+    pub seq: u64,
+    /// What has happened.
+    pub event: FeedEvent,
+}
+
+impl FeedMessage {
+    /// Writes the message.
+    pub fn encode(&self, mut w: impl Write) -> io::Result<()> {
+        let message_type = match self.event {
+            FeedEvent::AddOrder { .. } => b"A",
+            FeedEvent::Execute { .. } => b"E",
+            FeedEvent::Reduce { .. } => b"R",
+            FeedEvent::Cancel { .. } => b"X",
+            FeedEvent::Trade { .. } => b"P",
+        };
+        w.write_all(message_type)?;
+        w.write_all(&self.seq.to_be_bytes())?;
+        match self.event {
+            FeedEvent::AddOrder {
+                order_id,
+                side,
+                price,
+                amount,
+            } => {
+                w.write_all(&(order_id.0 as u64).to_be_bytes())?;
+                w.write_all(side_code(side))?;
+                write_u64s(w, &[price, amount])
+            }
+            FeedEvent::Execute { order_id, amount } | FeedEvent::Reduce { order_id, amount } => {
+                write_u64s(w, &[order_id.0 as u64, amount])
+            }
+            FeedEvent::Cancel { order_id } => write_u64s(w, &[order_id.0 as u64]),
+            FeedEvent::Trade {
+                taker_side,
+                price,
+                amount,
+            } => {
+                w.write_all(side_code(taker_side))?;
+                write_u64s(w, &[price, amount])
+            }
+        }
+    }
+
+    /// Reads a message. Returns `None` if the reader is exhausted.
+    pub fn decode(mut r: impl Read) -> io::Result<Option<Self>> {
+        let mut message_type = [0];
+        if r.read(&mut message_type)? == 0 {
+            return Ok(None);
+        }
+        let seq = read_u64(&mut r)?;
+        let event = match &message_type {
+            b"A" => FeedEvent::AddOrder {
+                order_id: OrderId(read_u64(&mut r)? as usize),
+                side: read_side(&mut r)?,
+                price: read_u64(&mut r)?,
+                amount: read_u64(&mut r)?,
+            },
+            b"E" => FeedEvent::Execute {
+                order_id: OrderId(read_u64(&mut r)? as usize),
+                amount: read_u64(&mut r)?,
+            },
+            b"R" => FeedEvent::Reduce {
+                order_id: OrderId(read_u64(&mut r)? as usize),
+                amount: read_u64(&mut r)?,
+            },
+            b"X" => FeedEvent::Cancel {
+                order_id: OrderId(read_u64(&mut r)? as usize),
+            },
+            b"P" => FeedEvent::Trade {
+                taker_side: read_side(&mut r)?,
+                price: read_u64(&mut r)?,
+                amount: read_u64(&mut r)?,
+            },
+            _ => {
+                return Err(invalid_data(format!(
+                    "unknown message type {:?}",
+                    message_type[0] as char
+                )))
+            }
+        };
+        Ok(Some(FeedMessage { seq, event }))
+    }
+}
+
+fn side_code(side: Side) -> &'static [u8] {
+    match side {
+        Side::Buy => b"B",
+        Side::Sell => b"S",
+    }
+}
+
+fn write_u64s(mut w: impl Write, values: &[u64]) -> io::Result<()> {
+    for value in values {
+        w.write_all(&value.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_side(r: impl Read) -> io::Result<Side> {
+    match read_byte(r)? {
+        b'B' => Ok(Side::Buy),
+        b'S' => Ok(Side::Sell),
+        side => Err(invalid_data(format!("unknown side {:?}", side as char))),
+    }
+}
+
+/// A listener that publishes the feed of a book to a writer.
+///
+/// Write errors can't be reported back to the book, so they are logged and the message is lost,
+/// which shows up as a gap in the sequence numbers.
+pub struct MarketDataFeed<W> {
+    writer: W,
+    seq: u64,
+}
+
+impl<W: Write> MarketDataFeed<W> {
+    /// Initializes a feed that writes messages to the `writer`.
+    pub fn new(writer: W) -> Self {
+        MarketDataFeed { writer, seq: 0 }
+    }
+
+    fn publish(&mut self, event: FeedEvent) {
+        self.seq += 1;
+        let message = FeedMessage {
+            seq: self.seq,
+            event,
+        };
+        if let Err(e) = message.encode(&mut self.writer) {
+            error!("Unable to publish message #{}: {}", self.seq, e);
+        }
+    }
+}
+
+impl<W: Write> OrderBookListener for MarketDataFeed<W> {
+    fn on_trade(&mut self, trade: &Trade) {
+        self.publish(FeedEvent::Trade {
+            taker_side: trade.taker_side,
+            price: trade.price,
+            amount: trade.amount,
+        });
+    }
+
+    fn on_feed_event(&mut self, event: &FeedEvent) {
+        self.publish(*event);
+    }
+}
+
+/// A reason a feed message can't be applied to a [`FeedBook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedError {
+    /// The sequence number is not the next one.
+    Gap { expected: u64, received: u64 },
+    /// The message refers to a bid that is not on the book.
+    UnknownOrder(OrderId),
+}
+
+impl fmt::Display for FeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FeedError::Gap { expected, received } => write!(
+                f,
+                "Expected message #{}, but received #{}",
+                expected, received
+            ),
+            FeedError::UnknownOrder(order_id) => write!(f, "Order {} is unknown", order_id.0),
+        }
+    }
+}
+
+impl Error for FeedError {}
+
+/// A bid that differs between a book and the book rebuilt from its feed, see [`FeedBook::diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub order_id: OrderId,
+    /// Side, price and displayed amount of the bid in the book, if it's there.
+    pub book: Option<(Side, u64, u64)>,
+    /// Side, price and displayed amount of the bid in the rebuilt book, if it's there.
+    pub feed: Option<(Side, u64, u64)>,
+}
+
+/// Displayed part of a book rebuilt from its feed.
+#[derive(Debug, Clone, Default)]
+pub struct FeedBook {
+    last_seq: u64,
+    orders: HashMap<OrderId, (Side, u64, u64)>,
+    /// Total displayed amounts and numbers of the bids of the price levels, kept up to date as
+    /// the events are applied.
+    selling: BTreeMap<u64, (u128, usize)>,
+    buying: BTreeMap<u64, (u128, usize)>,
+}
+
+impl FeedBook {
+    /// Initializes an empty book, which expects the feed to start from the first message.
+    pub fn new() -> Self {
+        FeedBook::default()
+    }
+
+    /// Initializes a book with the displayed bids of the `order_book`, to follow its feed from now
+    /// on.
+    pub fn from_book(order_book: &OrderBook) -> Self {
+        let mut feed_book = FeedBook::new();
+        for (order_id, (side, price, amount)) in book_orders(order_book) {
+            feed_book.add(order_id, side, price, amount);
+        }
+        feed_book
+    }
+
+    /// Rebuilds a book from scratch out of a whole feed, as published by [`MarketDataFeed`].
+    ///
+    /// A message that can't be applied is reported as [`io::ErrorKind::InvalidData`].
+    pub fn rebuild(mut r: impl Read) -> io::Result<Self> {
+        let mut feed_book = FeedBook::new();
+        while let Some(message) = FeedMessage::decode(&mut r)? {
+            feed_book
+                .apply(&message)
+                .map_err(|e| invalid_data(e.to_string()))?;
+        }
+        Ok(feed_book)
+    }
+
+    /// Sequence number of the last applied message.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Applies the next message of the feed.
+    ///
+    /// A message that can't be applied leaves the book untouched.
+    pub fn apply(&mut self, message: &FeedMessage) -> Result<(), FeedError> {
+        if message.seq != self.last_seq + 1 {
+            return Err(FeedError::Gap {
+                expected: self.last_seq + 1,
+                received: message.seq,
+            });
+        }
+        self.apply_event(message.event)?;
+        self.last_seq = message.seq;
+        Ok(())
+    }
+
+    /// Applies an event regardless of the sequence numbers.
+    pub(crate) fn apply_event(&mut self, event: FeedEvent) -> Result<(), FeedError> {
+        match event {
+            FeedEvent::AddOrder {
+                order_id,
+                side,
+                price,
+                amount,
+            } => self.add(order_id, side, price, amount),
+            FeedEvent::Execute { order_id, amount } | FeedEvent::Reduce { order_id, amount } => {
+                let (side, price, displayed) = self
+                    .orders
+                    .get_mut(&order_id)
+                    .ok_or(FeedError::UnknownOrder(order_id))?;
+                let (side, price) = (*side, *price);
+                let amount = amount.min(*displayed);
+                *displayed -= amount;
+                let count = if *displayed == 0 {
+                    self.orders.remove(&order_id);
+                    1
+                } else {
+                    0
+                };
+                self.withdraw(side, price, amount, count);
+            }
+            FeedEvent::Cancel { order_id } => {
+                let (side, price, amount) = self
+                    .orders
+                    .remove(&order_id)
+                    .ok_or(FeedError::UnknownOrder(order_id))?;
+                self.withdraw(side, price, amount, 1);
+            }
+            FeedEvent::Trade { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Puts a bid on the book, replacing the one with the same order id.
+    fn add(&mut self, order_id: OrderId, side: Side, price: u64, amount: u64) {
+        if let Some((side, price, amount)) = self.orders.insert(order_id, (side, price, amount)) {
+            self.withdraw(side, price, amount, 1);
+        }
+        let (total, count) = self.levels_mut(side).entry(price).or_insert((0, 0));
+        *total += u128::from(amount);
+        *count += 1;
+    }
+
+    /// Takes the `amount` of items and the number of bids off the totals of a price level.
+    fn withdraw(&mut self, side: Side, price: u64, amount: u64, bids: usize) {
+        let levels = self.levels_mut(side);
+        if let Some((total, count)) = levels.get_mut(&price) {
+            *total -= u128::from(amount);
+            *count -= bids;
+            if *count == 0 {
+                levels.remove(&price);
+            }
+        }
+    }
+
+    fn levels_mut(&mut self, side: Side) -> &mut BTreeMap<u64, (u128, usize)> {
+        match side {
+            Side::Sell => &mut self.selling,
+            Side::Buy => &mut self.buying,
+        }
+    }
+
+    /// Side and price of a bid on the book.
+    #[cfg(feature = "websocket")]
+    pub(crate) fn order_level(&self, order_id: OrderId) -> Option<(Side, u64)> {
+        self.orders
+            .get(&order_id)
+            .map(|&(side, price, _amount)| (side, price))
+    }
+
+    /// Total displayed amount at a price level.
+    #[cfg(feature = "websocket")]
+    pub(crate) fn level_amount(&self, side: Side, price: u64) -> u64 {
+        let levels = match side {
+            Side::Sell => &self.selling,
+            Side::Buy => &self.buying,
+        };
+        levels
+            .get(&price)
+            .map_or(0, |&(total, _count)| saturate(total))
+    }
+
+    /// Compares the rebuilt book with the book itself, bid by bid.
+    ///
+    /// Returns the bids that differ, by order id, so an empty list means the feed reflects the
+    /// book exactly.
+    pub fn diff(&self, order_book: &OrderBook) -> Vec<Divergence> {
+        let mut book: BTreeMap<_, _> = book_orders(order_book).collect();
+        let mut divergences: Vec<_> = self
+            .orders
+            .iter()
+            .filter_map(|(&order_id, &feed)| {
+                let book = book.remove(&order_id);
+                (book != Some(feed)).then_some(Divergence {
+                    order_id,
+                    book,
+                    feed: Some(feed),
+                })
+            })
+            .collect();
+        divergences.extend(book.into_iter().map(|(order_id, book)| Divergence {
+            order_id,
+            book: Some(book),
+            feed: None,
+        }));
+        divergences.sort_by_key(|divergence| divergence.order_id);
+        divergences
+    }
+
+    /// Same as [`OrderBook::depth`](crate::OrderBook::depth), but of the rebuilt book. Its
+    /// [checksum](Depth::checksum) is the one to compare with the published ones.
+    ///
+    /// Only the requested levels are looked at, so it's cheap for the top of the book.
+    pub fn depth(&self, levels: usize) -> Depth {
+        let level =
+            |(price, (total, count)): (&u64, &(u128, usize))| (*price, saturate(*total), *count);
+        Depth {
+            selling: self.selling.iter().take(levels).map(level).collect(),
+            buying: self.buying.iter().rev().take(levels).map(level).collect(),
+        }
+    }
+}
+
+/// Side, price and displayed amount of the bids of a book, by order id.
+fn book_orders(order_book: &OrderBook) -> impl Iterator<Item = (OrderId, (Side, u64, u64))> + '_ {
+    order_book
+        .sell_orders()
+        .map(|(order_id, bid)| (order_id, (Side::Sell, bid.price, bid.amount)))
+        .chain(
+            order_book
+                .buy_orders()
+                .map(|(order_id, bid)| (order_id, (Side::Buy, bid.price, bid.amount))),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType, TimeInForce},
+        OrderBook,
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_huge_amounts() {
+        let max = u64::MAX;
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(max / 2 + 1).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let mut feed_book = FeedBook::from_book(&order_book);
+        assert_eq!(feed_book.depth(1), order_book.depth(1));
+        assert_eq!(feed_book.depth(1).selling, [(100, max, 2)]);
+
+        feed_book
+            .apply_event(FeedEvent::Cancel {
+                order_id: OrderId(0),
+            })
+            .unwrap();
+        assert_eq!(feed_book.depth(1).selling, [(100, max / 2 + 1, 1)]);
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let buffer = SharedBuffer::default();
+        let mut order_book = OrderBook::empty();
+        order_book.add_listener(MarketDataFeed::new(buffer.clone()));
+
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let buying_bid = Bid::empty().price(99).amount(3).user_id(2);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_selling(
+            selling_bid.price(101).amount(10).display(2),
+            BidProcessingType::Limit,
+        );
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        order_book.process_buying(buying_bid.price(98), BidProcessingType::Limit);
+        order_book.process_buying(buying_bid.price(101).amount(8), BidProcessingType::Limit);
+        order_book.amend(OrderId(2), 99, 1);
+        order_book.cancel(OrderId(3));
+        order_book.process_selling(
+            selling_bid
+                .amount(2)
+                .time_in_force(TimeInForce::GoodTillDate(10)),
+            BidProcessingType::Limit,
+        );
+        order_book.advance_time(20);
+        order_book.start_auction();
+        order_book.process_selling(selling_bid.price(99), BidProcessingType::Limit);
+        order_book.uncross();
+
+        let data = buffer.0.borrow().clone();
+        let mut r = &data[..];
+        let mut feed_book = FeedBook::new();
+        let mut trades = 0;
+        while let Some(message) = FeedMessage::decode(&mut r).unwrap() {
+            if let FeedEvent::Trade { .. } = message.event {
+                trades += 1;
+            }
+            feed_book.apply(&message).unwrap();
+        }
+        assert_eq!(trades, 4);
+        assert_eq!(feed_book.diff(&order_book), []);
+        assert_eq!(FeedBook::rebuild(&data[..]).unwrap().diff(&order_book), []);
+        assert_eq!(feed_book.depth(10), order_book.depth(10));
+        assert_eq!(
+            feed_book.depth(2).checksum(),
+            order_book.depth(2).checksum()
+        );
+
+        let message = FeedMessage {
+            seq: feed_book.last_seq() + 2,
+            event: FeedEvent::Cancel {
+                order_id: OrderId(0),
+            },
+        };
+        assert_eq!(
+            feed_book.apply(&message),
+            Err(FeedError::Gap {
+                expected: message.seq - 1,
+                received: message.seq
+            })
+        );
+
+        // A lost message shows up as a divergence.
+        let (order_id, bid) = order_book.sell_orders().next().unwrap();
+        feed_book
+            .apply_event(FeedEvent::Cancel { order_id })
+            .unwrap();
+        let divergence = Divergence {
+            order_id,
+            book: Some((Side::Sell, bid.price, bid.amount)),
+            feed: None,
+        };
+        assert_eq!(feed_book.diff(&order_book), [divergence]);
+    }
+}
@@ -0,0 +1,445 @@
+//! Order entry with FIX 4.4 tag=value messages.
+//!
+//! Supported messages are `NewOrderSingle` (`35=D`), `OrderCancelRequest` (`35=F`) and
+//! `OrderCancelReplaceRequest` (`35=G`). Fields are separated either by SOH (`\x01`) or by `|`;
+//! the header and the trailer (`8`, `9`, `10`, etc.) are not validated.
+
+use crate::{
+    bids::{Bid, BidProcessingType, Side, TimeInForce},
+    order_book::{Amendment, Cancellation, OrderBook, OrderId, ProcessingResult},
+};
+use std::{collections::HashMap, error::Error, fmt};
+
+/// A parsed order entry message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixMessage {
+    /// `NewOrderSingle`.
+    NewOrder {
+        /// `ClOrdID` (11).
+        cl_ord_id: String,
+        /// `Side` (54): `1` is buy, `2` is sell.
+        side: Side,
+        /// `Price` (44) in integer units, zero for market orders.
+        price: u64,
+        /// `OrderQty` (38).
+        amount: u64,
+        /// `Account` (1), which must be an integer.
+        user_id: u64,
+        /// Derived from `OrdType` (40), `TimeInForce` (59) and `ExecInst` (18).
+        bid_type: BidProcessingType,
+        /// Derived from `TimeInForce` (59), `Day` if it's missing.
+        time_in_force: TimeInForce,
+        /// `MaxFloor` (111), if any.
+        display: Option<u64>,
+        /// `MinQty` (110), if any.
+        min_fill: Option<u64>,
+    },
+    /// `OrderCancelRequest`.
+    Cancel {
+        /// `ClOrdID` (11) of the request.
+        cl_ord_id: String,
+        /// `OrigClOrdID` (41) of the order to cancel.
+        orig_cl_ord_id: String,
+    },
+    /// `OrderCancelReplaceRequest`.
+    Replace {
+        /// `ClOrdID` (11) the order will be known by afterwards.
+        cl_ord_id: String,
+        /// `OrigClOrdID` (41) of the order to replace.
+        orig_cl_ord_id: String,
+        /// `Price` (44).
+        price: u64,
+        /// `OrderQty` (38), which is treated as the new remaining size of the order.
+        amount: u64,
+    },
+}
+
+/// Response to an order entry message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixResponse {
+    /// The order has been submitted.
+    New(ProcessingResult),
+    /// The order has been cancelled (or not).
+    Cancel(Cancellation),
+    /// The order has been amended (or not).
+    Replace(Amendment),
+}
+
+/// A reason a message can't be processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixError {
+    /// A field is not a `tag=value` pair.
+    MalformedField(String),
+    /// A required tag is absent.
+    MissingTag(u32),
+    /// A tag has a value that is not supported.
+    InvalidValue { tag: u32, value: String },
+    /// The `MsgType` (35) is not supported.
+    UnsupportedMessage(String),
+    /// No order with such a `ClOrdID` has been submitted.
+    UnknownOrder(String),
+    /// An order with such a `ClOrdID` has already been submitted.
+    DuplicateOrder(String),
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FixError::MalformedField(field) => write!(f, "Malformed field {:?}", field),
+            FixError::MissingTag(tag) => write!(f, "Tag {} is missing", tag),
+            FixError::InvalidValue { tag, value } => {
+                write!(f, "Value {:?} of tag {} is not supported", value, tag)
+            }
+            FixError::UnsupportedMessage(msg_type) => {
+                write!(f, "Message type {:?} is not supported", msg_type)
+            }
+            FixError::UnknownOrder(cl_ord_id) => write!(f, "Unknown order {:?}", cl_ord_id),
+            FixError::DuplicateOrder(cl_ord_id) => {
+                write!(f, "Order {:?} has already been submitted", cl_ord_id)
+            }
+        }
+    }
+}
+
+impl Error for FixError {}
+
+/// Fields of a message by their tags.
+struct Fields<'a>(HashMap<u32, &'a str>);
+
+impl<'a> Fields<'a> {
+    fn parse(message: &'a str) -> Result<Self, FixError> {
+        let mut fields = HashMap::new();
+        for field in message
+            .trim_end()
+            .split(['\x01', '|'])
+            .filter(|field| !field.is_empty())
+        {
+            let mut parts = field.splitn(2, '=');
+            match (parts.next().map(str::parse), parts.next()) {
+                (Some(Ok(tag)), Some(value)) => {
+                    fields.insert(tag, value);
+                }
+                _ => return Err(FixError::MalformedField(field.to_string())),
+            }
+        }
+        Ok(Fields(fields))
+    }
+
+    fn get(&self, tag: u32) -> Option<&'a str> {
+        self.0.get(&tag).cloned()
+    }
+
+    fn string(&self, tag: u32) -> Result<String, FixError> {
+        self.get(tag)
+            .map(str::to_string)
+            .ok_or(FixError::MissingTag(tag))
+    }
+
+    fn number(&self, tag: u32) -> Result<u64, FixError> {
+        self.optional_number(tag)?.ok_or(FixError::MissingTag(tag))
+    }
+
+    fn optional_number(&self, tag: u32) -> Result<Option<u64>, FixError> {
+        self.get(tag)
+            .map(|value| value.parse().map_err(|_| invalid(tag, value)))
+            .transpose()
+    }
+}
+
+fn invalid(tag: u32, value: &str) -> FixError {
+    FixError::InvalidValue {
+        tag,
+        value: value.to_string(),
+    }
+}
+
+impl FixMessage {
+    /// Parses a message.
+    pub fn parse(message: &str) -> Result<Self, FixError> {
+        let fields = Fields::parse(message)?;
+        match fields.get(35).ok_or(FixError::MissingTag(35))? {
+            "D" => FixMessage::parse_new_order(&fields),
+            "F" => Ok(FixMessage::Cancel {
+                cl_ord_id: fields.string(11)?,
+                orig_cl_ord_id: fields.string(41)?,
+            }),
+            "G" => Ok(FixMessage::Replace {
+                cl_ord_id: fields.string(11)?,
+                orig_cl_ord_id: fields.string(41)?,
+                price: fields.number(44)?,
+                amount: fields.number(38)?,
+            }),
+            msg_type => Err(FixError::UnsupportedMessage(msg_type.to_string())),
+        }
+    }
+
+    fn parse_new_order(fields: &Fields) -> Result<Self, FixError> {
+        let side = match fields.get(54).ok_or(FixError::MissingTag(54))? {
+            "1" => Side::Buy,
+            "2" => Side::Sell,
+            value => return Err(invalid(54, value)),
+        };
+        let market = match fields.get(40).ok_or(FixError::MissingTag(40))? {
+            "1" => true,
+            "2" => false,
+            value => return Err(invalid(40, value)),
+        };
+        let (bid_type, time_in_force) = match fields.get(59).unwrap_or("0") {
+            _ if market => (BidProcessingType::Market, TimeInForce::GoodTillCancel),
+            "0" => (BidProcessingType::Limit, TimeInForce::Day),
+            "1" => (BidProcessingType::Limit, TimeInForce::GoodTillCancel),
+            "3" => (
+                BidProcessingType::ImmediateOrCancel,
+                TimeInForce::GoodTillCancel,
+            ),
+            "4" => (BidProcessingType::FillOrKill, TimeInForce::GoodTillCancel),
+            value => return Err(invalid(59, value)),
+        };
+        // "Participate don't initiate" makes a post-only order.
+        let post_only = fields
+            .get(18)
+            .is_some_and(|exec_inst| exec_inst.split(' ').any(|inst| inst == "6"));
+        let bid_type = match bid_type {
+            BidProcessingType::Limit if post_only => BidProcessingType::PostOnly,
+            bid_type => bid_type,
+        };
+        Ok(FixMessage::NewOrder {
+            cl_ord_id: fields.string(11)?,
+            side,
+            price: if market { 0 } else { fields.number(44)? },
+            amount: fields.number(38)?,
+            user_id: fields.number(1)?,
+            bid_type,
+            time_in_force,
+            display: fields.optional_number(111)?,
+            min_fill: fields.optional_number(110)?,
+        })
+    }
+}
+
+/// Feeds FIX messages into an order book, keeping track of the client order ids.
+///
+/// Only the ids of the orders in the book are kept: the ones of filled orders are forgotten
+/// whenever a message causes trades, so they are unknown to later requests and may be reused.
+#[derive(Debug, Default)]
+pub struct FixGateway {
+    orders: HashMap<String, OrderId>,
+}
+
+impl FixGateway {
+    /// Initializes a gateway that doesn't know of any orders.
+    pub fn new() -> Self {
+        FixGateway::default()
+    }
+
+    /// Parses a message and processes it in the order book.
+    pub fn process(
+        &mut self,
+        order_book: &mut OrderBook,
+        message: &str,
+    ) -> Result<FixResponse, FixError> {
+        match FixMessage::parse(message)? {
+            FixMessage::NewOrder {
+                cl_ord_id,
+                side,
+                price,
+                amount,
+                user_id,
+                bid_type,
+                time_in_force,
+                display,
+                min_fill,
+            } => {
+                if self.orders.contains_key(&cl_ord_id) {
+                    return Err(FixError::DuplicateOrder(cl_ord_id));
+                }
+                fn bid<BidKind>(
+                    price: u64,
+                    amount: u64,
+                    user_id: u64,
+                    time_in_force: TimeInForce,
+                    display: Option<u64>,
+                    min_fill: Option<u64>,
+                ) -> Bid<BidKind> {
+                    let mut bid = Bid::empty()
+                        .price(price)
+                        .amount(amount)
+                        .user_id(user_id)
+                        .time_in_force(time_in_force);
+                    bid.display = display;
+                    bid.min_fill = min_fill;
+                    bid
+                }
+                let result = match side {
+                    Side::Sell => order_book.process_selling(
+                        bid(price, amount, user_id, time_in_force, display, min_fill),
+                        bid_type,
+                    ),
+                    Side::Buy => order_book.process_buying(
+                        bid(price, amount, user_id, time_in_force, display, min_fill),
+                        bid_type,
+                    ),
+                };
+                self.orders.insert(cl_ord_id, result.order_id);
+                self.forget_filled(order_book, !result.trades.is_empty());
+                Ok(FixResponse::New(result))
+            }
+            FixMessage::Cancel { orig_cl_ord_id, .. } => {
+                let order_id = self.order_id(&orig_cl_ord_id)?;
+                self.orders.remove(&orig_cl_ord_id);
+                Ok(FixResponse::Cancel(order_book.cancel(order_id)))
+            }
+            FixMessage::Replace {
+                cl_ord_id,
+                orig_cl_ord_id,
+                price,
+                amount,
+            } => {
+                let order_id = self.order_id(&orig_cl_ord_id)?;
+                if cl_ord_id != orig_cl_ord_id && self.orders.contains_key(&cl_ord_id) {
+                    return Err(FixError::DuplicateOrder(cl_ord_id));
+                }
+                let amendment = order_book.amend(order_id, price, amount);
+                self.orders.remove(&orig_cl_ord_id);
+                match &amendment {
+                    Amendment::Amended(_) => {
+                        self.orders.insert(cl_ord_id, order_id);
+                    }
+                    Amendment::Replaced(result) => {
+                        self.orders.insert(cl_ord_id, result.order_id);
+                        self.forget_filled(order_book, !result.trades.is_empty());
+                    }
+                    _ => (),
+                }
+                Ok(FixResponse::Replace(amendment))
+            }
+        }
+    }
+
+    /// Forgets the ids of the orders that have left the book, if there have been any trades.
+    fn forget_filled(&mut self, order_book: &OrderBook, traded: bool) {
+        if traded {
+            self.orders
+                .retain(|_cl_ord_id, &mut order_id| order_book.get(order_id).is_some());
+        }
+    }
+
+    fn order_id(&self, cl_ord_id: &str) -> Result<OrderId, FixError> {
+        self.orders
+            .get(cl_ord_id)
+            .cloned()
+            .ok_or_else(|| FixError::UnknownOrder(cl_ord_id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{bids::SellingBid, listener::OrderInfo};
+
+    #[test]
+    fn test_parse() {
+        let message = "8=FIX.4.4|9=60|35=D|11=A1|1=7|54=2|38=100|40=2|44=15|59=4|10=000|";
+        let expected = FixMessage::NewOrder {
+            cl_ord_id: "A1".to_string(),
+            side: Side::Sell,
+            price: 15,
+            amount: 100,
+            user_id: 7,
+            bid_type: BidProcessingType::FillOrKill,
+            time_in_force: TimeInForce::GoodTillCancel,
+            display: None,
+            min_fill: None,
+        };
+        assert_eq!(FixMessage::parse(message), Ok(expected));
+        let message = "35=D\x0111=A2\x011=7\x0154=1\x0138=10\x0140=2\x0144=15\x0118=6\x0159=0\x01";
+        match FixMessage::parse(message).unwrap() {
+            FixMessage::NewOrder {
+                bid_type,
+                time_in_force,
+                ..
+            } => assert_eq!(
+                (bid_type, time_in_force),
+                (BidProcessingType::PostOnly, TimeInForce::Day)
+            ),
+            message => panic!("Unexpected message {:?}", message),
+        }
+        match FixMessage::parse("35=D|11=A3|1=7|54=1|38=10|40=2|44=15|").unwrap() {
+            FixMessage::NewOrder {
+                bid_type,
+                time_in_force,
+                ..
+            } => assert_eq!(
+                (bid_type, time_in_force),
+                (BidProcessingType::Limit, TimeInForce::Day)
+            ),
+            message => panic!("Unexpected message {:?}", message),
+        }
+        assert_eq!(
+            FixMessage::parse("35=D|11=A3|1=7|54=1|38=10|40=2|"),
+            Err(FixError::MissingTag(44))
+        );
+        assert_eq!(
+            FixMessage::parse("35=D|11=A3|1=7|54=1|38=10.5|40=1|"),
+            Err(FixError::InvalidValue {
+                tag: 38,
+                value: "10.5".to_string()
+            })
+        );
+        assert_eq!(
+            FixMessage::parse("35=0|"),
+            Err(FixError::UnsupportedMessage("0".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_gateway() {
+        let mut order_book = OrderBook::empty();
+        let mut gateway = FixGateway::new();
+        let mut process = |message| gateway.process(&mut order_book, message);
+        process("35=D|11=S1|1=1|54=2|38=5|40=2|44=100|").unwrap();
+        assert_eq!(
+            process("35=D|11=S1|1=1|54=2|38=5|40=2|44=100|"),
+            Err(FixError::DuplicateOrder("S1".to_string()))
+        );
+        let replaced = process("35=G|11=S2|41=S1|38=3|44=100|").unwrap();
+        let amended: Bid<SellingBid> = Bid::empty().price(100).amount(3).user_id(1);
+        assert_eq!(
+            replaced,
+            FixResponse::Replace(Amendment::Amended(OrderInfo::from(&amended)))
+        );
+        let bought = match process("35=D|11=B1|1=2|54=1|38=2|40=1|").unwrap() {
+            FixResponse::New(result) => result,
+            response => panic!("Unexpected response {:?}", response),
+        };
+        assert_eq!(bought.trades.len(), 1);
+        assert_eq!(bought.trades[0].amount, 2);
+        assert_eq!(
+            process("35=F|11=S3|41=S1|"),
+            Err(FixError::UnknownOrder("S1".to_string()))
+        );
+        match process("35=F|11=S3|41=S2|").unwrap() {
+            FixResponse::Cancel(Cancellation::PartiallyFilled(order)) => {
+                assert_eq!(order.amount, 1)
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        // The ids of the filled orders are forgotten.
+        process("35=D|11=S4|1=1|54=2|38=2|40=2|44=100|").unwrap();
+        process("35=D|11=B2|1=2|54=1|38=2|40=2|44=100|").unwrap();
+        assert_eq!(
+            process("35=F|11=S5|41=S4|"),
+            Err(FixError::UnknownOrder("S4".to_string()))
+        );
+        assert_eq!(
+            process("35=F|11=B3|41=B2|"),
+            Err(FixError::UnknownOrder("B2".to_string()))
+        );
+        assert_eq!(
+            process("35=F|11=B3|41=B1|"),
+            Err(FixError::UnknownOrder("B1".to_string()))
+        );
+        assert!(process("35=D|11=S4|1=1|54=2|38=2|40=2|44=100|").is_ok());
+    }
+}
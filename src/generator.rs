@@ -0,0 +1,234 @@
+//! Synthetic order flow.
+
+use crate::{
+    bids::{Bid, BidProcessingType, BuyingBid, SellingBid},
+    order_book::IncomingOrder,
+};
+use rand::{
+    distributions::{Distribution, Normal, Uniform, WeightedIndex},
+    rngs::SmallRng,
+    Rng, SeedableRng,
+};
+
+/// How the prices of the generated bids are distributed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceDistribution {
+    /// Prices are spread evenly between `min` and `max`, both inclusive.
+    Uniform {
+        /// The lowest price.
+        min: u64,
+        /// The highest price.
+        max: u64,
+    },
+    /// Prices cluster around the `mean`, following the normal distribution. Prices below one are
+    /// raised to one.
+    Normal {
+        /// The average price.
+        mean: u64,
+        /// The standard deviation of the prices.
+        std_dev: u64,
+    },
+}
+
+/// Configuration of an [`OrderGenerator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    /// Distribution of the prices.
+    pub prices: PriceDistribution,
+    /// The smallest amount of a bid.
+    pub min_size: u64,
+    /// The largest amount of a bid.
+    pub max_size: u64,
+    /// Share of buying bids in the flow, from zero (only selling bids) to one (only buying ones).
+    pub buy_ratio: f64,
+    /// Types of the bids along with their relative weights, e.g. `[(Limit, 9), (Market, 1)]`
+    /// makes one bid in ten a `Market` one. Types of zero weight are never picked, but at least
+    /// one weight has to be positive.
+    pub order_types: Vec<(BidProcessingType, u32)>,
+    /// Number of users submitting the bids; user ids go from zero up.
+    pub users: u64,
+}
+
+impl Default for GeneratorConfig {
+    /// `Limit` bids of 1 to 99 items from ten users, evenly split between the sides, priced
+    /// between 90 and 110.
+    fn default() -> Self {
+        GeneratorConfig {
+            prices: PriceDistribution::Uniform { min: 90, max: 110 },
+            min_size: 1,
+            max_size: 99,
+            buy_ratio: 0.5,
+            order_types: vec![(BidProcessingType::Limit, 1)],
+            users: 10,
+        }
+    }
+}
+
+/// A seedable source of random bids, for tests, benchmarks and demos.
+///
+/// Generators with the same seed and configuration produce the same bids (with the same version
+/// of the crate). As an iterator, the generator yields an endless mix of selling and buying
+/// orders:
+///
+/// ```
+/// use simple_stock_matcher_experiment::{
+///     generator::{GeneratorConfig, OrderGenerator},
+///     OrderBook,
+/// };
+///
+/// let mut order_book = OrderBook::empty();
+/// let orders = OrderGenerator::new(42, GeneratorConfig::default()).take(1000);
+/// let result = order_book.process_batch(orders);
+/// assert_eq!(result.order_ids.len(), 1000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OrderGenerator {
+    rng: SmallRng,
+    config: GeneratorConfig,
+    order_types: WeightedIndex<u32>,
+    prices: Prices,
+}
+
+/// Sampler of the prices.
+#[derive(Debug, Clone)]
+enum Prices {
+    Uniform(Uniform<u64>),
+    Normal(Normal),
+}
+
+impl OrderGenerator {
+    /// Initializes a generator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is inconsistent: the sizes or the prices are out of order, the
+    /// share of buying bids is out of range, no order type has a positive weight or there are no
+    /// users.
+    pub fn new(seed: u64, config: GeneratorConfig) -> Self {
+        assert!(
+            0 < config.min_size && config.min_size <= config.max_size,
+            "Sizes must be positive and in order"
+        );
+        assert!(
+            (0.0..=1.0).contains(&config.buy_ratio),
+            "The share of buying bids must be between zero and one"
+        );
+        assert!(config.users != 0, "There must be at least one user");
+        let order_types = WeightedIndex::new(config.order_types.iter().map(|(_, weight)| weight))
+            .expect("At least one order type must have a positive weight");
+        let prices = match config.prices {
+            PriceDistribution::Uniform { min, max } => {
+                assert!(min <= max, "Prices must be in order");
+                Prices::Uniform(Uniform::new_inclusive(min, max))
+            }
+            PriceDistribution::Normal { mean, std_dev } => {
+                Prices::Normal(Normal::new(mean as f64, std_dev as f64))
+            }
+        };
+        OrderGenerator {
+            rng: SmallRng::seed_from_u64(seed),
+            config,
+            order_types,
+            prices,
+        }
+    }
+
+    /// Generates a selling bid and its type.
+    pub fn next_selling(&mut self) -> (Bid<SellingBid>, BidProcessingType) {
+        let bid_type = self.next_type();
+        (self.next_bid(), bid_type)
+    }
+
+    /// Generates a buying bid and its type.
+    pub fn next_buying(&mut self) -> (Bid<BuyingBid>, BidProcessingType) {
+        let bid_type = self.next_type();
+        (self.next_bid(), bid_type)
+    }
+
+    fn next_type(&mut self) -> BidProcessingType {
+        self.config.order_types[self.order_types.sample(&mut self.rng)].0
+    }
+
+    fn next_bid<BidKind>(&mut self) -> Bid<BidKind> {
+        let price = match &self.prices {
+            Prices::Uniform(uniform) => uniform.sample(&mut self.rng),
+            Prices::Normal(normal) => normal.sample(&mut self.rng).round().max(1.0) as u64,
+        };
+        let amount = self
+            .rng
+            .gen_range(self.config.min_size, self.config.max_size + 1);
+        let user_id = self.rng.gen_range(0, self.config.users);
+        Bid::empty().price(price).amount(amount).user_id(user_id)
+    }
+}
+
+impl Iterator for OrderGenerator {
+    type Item = IncomingOrder;
+
+    fn next(&mut self) -> Option<IncomingOrder> {
+        let order = if self.rng.gen_bool(self.config.buy_ratio) {
+            let (bid, bid_type) = self.next_buying();
+            IncomingOrder::Buying(bid, bid_type)
+        } else {
+            let (bid, bid_type) = self.next_selling();
+            IncomingOrder::Selling(bid, bid_type)
+        };
+        Some(order)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generator() {
+        let config = GeneratorConfig {
+            prices: PriceDistribution::Uniform { min: 10, max: 12 },
+            min_size: 5,
+            max_size: 6,
+            buy_ratio: 1.0,
+            order_types: vec![
+                (BidProcessingType::Limit, 1),
+                (BidProcessingType::Market, 0),
+                (BidProcessingType::FillOrKill, 1),
+            ],
+            users: 2,
+        };
+        let orders: Vec<_> = OrderGenerator::new(7, config.clone()).take(100).collect();
+        for order in &orders {
+            match *order {
+                IncomingOrder::Buying(bid, bid_type) => {
+                    assert!((10..=12).contains(&bid.price));
+                    assert!((5..=6).contains(&bid.amount));
+                    assert!(bid.user_id < 2);
+                    assert_ne!(bid_type, BidProcessingType::Market);
+                }
+                IncomingOrder::Selling(..) => panic!("A selling bid: {:?}", order),
+            }
+        }
+        let again: Vec<_> = OrderGenerator::new(7, config.clone()).take(100).collect();
+        assert_eq!(orders, again);
+        let other: Vec<_> = OrderGenerator::new(8, config).take(100).collect();
+        assert_ne!(orders, other);
+    }
+
+    #[test]
+    fn test_normal_prices() {
+        let config = GeneratorConfig {
+            prices: PriceDistribution::Normal {
+                mean: 1000,
+                std_dev: 10,
+            },
+            buy_ratio: 0.0,
+            ..GeneratorConfig::default()
+        };
+        let mut generator = OrderGenerator::new(1, config);
+        let prices: Vec<_> = (0..1000)
+            .map(|_| generator.next_selling().0.price)
+            .collect();
+        let mean = prices.iter().sum::<u64>() / prices.len() as u64;
+        assert!((990..=1010).contains(&mean), "{}", mean);
+        assert!(prices.iter().all(|&price| (900..=1100).contains(&price)));
+    }
+}
@@ -0,0 +1,155 @@
+//! Groups of users that should never trade with each other.
+
+use serde::{de::Error, Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+
+/// A mapping of users to groups used by the self-match prevention.
+///
+/// Two bids never match if they come from the same user, or from users that belong to the same
+/// group (e.g. several accounts of one firm). Users that are not listed in any group only form a
+/// group with themselves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserGroups {
+    groups: HashMap<u64, usize>,
+    count: usize,
+}
+
+impl UserGroups {
+    /// Initializes an empty mapping, i.e. every user is in its own group.
+    pub fn new() -> Self {
+        UserGroups::default()
+    }
+
+    /// Adds a new group with the given members.
+    ///
+    /// A user can only belong to one group; if any of the `members` is already in a group it is
+    /// moved to the new one.
+    pub fn add_group(&mut self, members: impl IntoIterator<Item = u64>) {
+        let group = self.count;
+        self.count += 1;
+        self.groups
+            .extend(members.into_iter().map(|user_id| (user_id, group)));
+    }
+
+    /// Checks whether two users are the same user or belong to the same group.
+    pub fn same_group(&self, first_user_id: u64, second_user_id: u64) -> bool {
+        if first_user_id == second_user_id {
+            return true;
+        }
+        match (
+            self.groups.get(&first_user_id),
+            self.groups.get(&second_user_id),
+        ) {
+            (Some(first), Some(second)) => first == second,
+            _ => false,
+        }
+    }
+
+    /// Users that belong to the same group as the given one, including the user itself.
+    pub fn members(&self, user_id: u64) -> Vec<u64> {
+        match self.groups.get(&user_id) {
+            Some(group) => self
+                .groups
+                .iter()
+                .filter(|(_user_id, other)| *other == group)
+                .map(|(user_id, _group)| *user_id)
+                .collect(),
+            None => vec![user_id],
+        }
+    }
+}
+
+/// Groups are deserialized from a map of a group name to a list of user ids:
+///
+/// ```yaml
+/// firm_a: [1, 2, 3]
+/// firm_b: [4, 5]
+/// ```
+///
+/// A user that is listed in more than one group is an error.
+impl<'de> Deserialize<'de> for UserGroups {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let named: BTreeMap<String, Vec<u64>> = Deserialize::deserialize(deserializer)?;
+        let mut user_groups = UserGroups::new();
+        let mut names = HashMap::new();
+        for (name, members) in &named {
+            for user_id in members {
+                if let Some(other) = names.insert(*user_id, name) {
+                    return Err(D::Error::custom(format!(
+                        "user {} belongs to more than one group ({:?} and {:?})",
+                        user_id, other, name
+                    )));
+                }
+            }
+            user_groups.add_group(members.iter().cloned());
+        }
+        Ok(user_groups)
+    }
+}
+
+/// Groups are serialized in the same format, with generated group names.
+#[cfg(feature = "serde")]
+impl serde::Serialize for UserGroups {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut named: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        for (user_id, group) in &self.groups {
+            named
+                .entry(format!("group_{}", group))
+                .or_default()
+                .push(*user_id);
+        }
+        named.values_mut().for_each(|members| members.sort());
+        serde::Serialize::serialize(&named, serializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_same_group() {
+        let mut groups = UserGroups::new();
+        groups.add_group(vec![1, 2, 3]);
+        groups.add_group(vec![4, 5]);
+        assert!(groups.same_group(1, 1));
+        assert!(groups.same_group(1, 3));
+        assert!(groups.same_group(5, 4));
+        assert!(groups.same_group(10, 10));
+        assert!(!groups.same_group(1, 4));
+        assert!(!groups.same_group(1, 10));
+        assert!(!groups.same_group(10, 11));
+        let mut members = groups.members(2);
+        members.sort();
+        assert_eq!(members, [1, 2, 3]);
+        assert_eq!(groups.members(10), [10]);
+    }
+
+    #[test]
+    fn test_deser() {
+        let data = br#"---
+firm_a: [1, 2, 3]
+firm_b: [4, 5]
+"#;
+        let groups: UserGroups = serde_yaml::from_reader(&data[..]).unwrap();
+        assert!(groups.same_group(1, 2));
+        assert!(groups.same_group(4, 5));
+        assert!(!groups.same_group(3, 4));
+    }
+
+    #[test]
+    fn test_deser_duplicate_member() {
+        let data = br#"---
+firm_a: [1, 2, 3]
+firm_b: [3, 4]
+"#;
+        let result: Result<UserGroups, _> = serde_yaml::from_reader(&data[..]);
+        assert!(result.is_err());
+    }
+}
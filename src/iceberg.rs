@@ -0,0 +1,118 @@
+//! Refreshing of iceberg bids.
+
+use serde_derive::Deserialize;
+
+/// Size of the tranches an iceberg bid is refreshed with.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum TrancheSize {
+    /// The display quantity of the bid.
+    #[default]
+    Fixed,
+    /// A random size up to `variance` items away from the display quantity (but at least one
+    /// item), so the tranches are harder to spot. Sizes are drawn from a generator seeded with the
+    /// `seed`, so they are the same from run to run.
+    Randomized {
+        variance: u64,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+/// How the displayed part of an iceberg bid is refreshed from its hidden part once it's filled.
+///
+/// Venues differ, so it's configurable, e.g.
+///
+/// ```yaml
+/// tranche_size:
+///   Randomized: {variance: 5, seed: 42}
+/// keep_priority: true
+/// ```
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub struct IcebergRefresh {
+    /// Size of the refreshed tranches.
+    #[serde(default)]
+    pub tranche_size: TrancheSize,
+    /// Whether a refreshed tranche keeps the time priority of the bid. By default it goes to the
+    /// back of the queue at its price, like a new bid.
+    #[serde(default)]
+    pub keep_priority: bool,
+}
+
+/// Refreshes iceberg bids according to an [`IcebergRefresh`] policy.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Refresher {
+    policy: IcebergRefresh,
+    /// State of the generator of the randomized sizes.
+    state: u64,
+}
+
+impl Refresher {
+    pub fn new(policy: IcebergRefresh) -> Self {
+        let state = match policy.tranche_size {
+            TrancheSize::Fixed => 0,
+            TrancheSize::Randomized { seed, .. } => seed,
+        };
+        Refresher { policy, state }
+    }
+
+    /// Whether refreshed tranches keep the time priority of their bids.
+    pub fn keep_priority(&self) -> bool {
+        self.policy.keep_priority
+    }
+
+    /// Size of the next tranche of a bid with the `display` quantity, out of the `hidden` amount.
+    pub fn tranche(&mut self, display: u64, hidden: u64) -> u64 {
+        let size = match self.policy.tranche_size {
+            TrancheSize::Fixed => display,
+            TrancheSize::Randomized { variance, .. } => {
+                let low = display.saturating_sub(variance).max(1);
+                let high = display.saturating_add(variance);
+                let span = u128::from(high - low) + 1;
+                low + (u128::from(self.next()) % span) as u64
+            }
+        };
+        size.min(hidden)
+    }
+
+    /// Next number of the generator (SplitMix64).
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tranche() {
+        let mut fixed = Refresher::new(IcebergRefresh::default());
+        assert_eq!(fixed.tranche(5, 12), 5);
+        assert_eq!(fixed.tranche(5, 3), 3);
+
+        let policy = IcebergRefresh {
+            tranche_size: TrancheSize::Randomized {
+                variance: 3,
+                seed: 7,
+            },
+            keep_priority: false,
+        };
+        let mut randomized = Refresher::new(policy);
+        let sizes: Vec<_> = (0..100).map(|_| randomized.tranche(2, 100)).collect();
+        assert!(sizes.iter().all(|size| (1..=5).contains(size)));
+        assert!(sizes.contains(&1) && sizes.contains(&5));
+        let mut again = Refresher::new(policy);
+        assert!(sizes.iter().all(|&size| again.tranche(2, 100) == size));
+        assert!((0..100).all(|_| randomized.tranche(2, 4) <= 4));
+    }
+}
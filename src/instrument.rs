@@ -0,0 +1,235 @@
+//! Instruments and validation of bids against them.
+
+use crate::bids::{Bid, BidProcessingType};
+use serde_derive::Deserialize;
+use std::{error::Error, fmt};
+
+/// Trading rules of an instrument.
+///
+/// Zero tick and lot sizes mean there is no restriction. Prices and amounts are integers here,
+/// measured in the units of the `price_scale`-th and the `size_scale`-th decimal places.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Instrument {
+    /// Prices must be multiples of the tick size.
+    pub tick_size: u64,
+    /// Amounts must be multiples of the lot size.
+    pub lot_size: u64,
+    /// The lowest allowed price, if any.
+    pub min_price: Option<u64>,
+    /// The highest allowed price, if any.
+    pub max_price: Option<u64>,
+    /// Number of decimal places of the prices of the input: with the scale of 2, a price of
+    /// `100.25` is matched as `10025`. Prices with more decimal places are invalid.
+    pub price_scale: u32,
+    /// Number of decimal places of the sizes of the input: with the scale of 3, a size of `0.003`
+    /// is matched as `3`. Sizes with more decimal places are invalid. Since amounts are matched
+    /// in whole units, partial fills are rounded down to them.
+    pub size_scale: u32,
+}
+
+impl Default for Instrument {
+    fn default() -> Self {
+        Instrument {
+            tick_size: 1,
+            lot_size: 1,
+            min_price: None,
+            max_price: None,
+            price_scale: 0,
+            size_scale: 0,
+        }
+    }
+}
+
+impl Instrument {
+    /// Checks whether the `bid` conforms to the instrument.
+    ///
+    /// The price of `Market` bids is ignored, so it's not checked.
+    pub fn validate<BidKind>(
+        &self,
+        bid: &Bid<BidKind>,
+        bid_type: BidProcessingType,
+    ) -> Result<(), Rejection> {
+        if is_off_step(bid.amount, self.lot_size) {
+            return Err(Rejection::AmountNotInLots {
+                amount: bid.amount,
+                lot_size: self.lot_size,
+            });
+        }
+        if bid_type == BidProcessingType::Market {
+            return Ok(());
+        }
+        if is_off_step(bid.price, self.tick_size) {
+            return Err(Rejection::PriceNotOnTick {
+                price: bid.price,
+                tick_size: self.tick_size,
+            });
+        }
+        match (self.min_price, self.max_price) {
+            (Some(min_price), _) if bid.price < min_price => Err(Rejection::PriceTooLow {
+                price: bid.price,
+                min_price,
+            }),
+            (_, Some(max_price)) if bid.price > max_price => Err(Rejection::PriceTooHigh {
+                price: bid.price,
+                max_price,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks whether the `value` is not a multiple of the `step` (unless the step is zero).
+fn is_off_step(value: u64, step: u64) -> bool {
+    value.checked_rem(step).is_some_and(|rest| rest != 0)
+}
+
+/// A reason to reject a bid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The price is not a multiple of the instrument's tick size.
+    PriceNotOnTick { price: u64, tick_size: u64 },
+    /// The amount is not a multiple of the instrument's lot size.
+    AmountNotInLots { amount: u64, lot_size: u64 },
+    /// The price is below the instrument's minimum price.
+    PriceTooLow { price: u64, min_price: u64 },
+    /// The price is above the instrument's maximum price.
+    PriceTooHigh { price: u64, max_price: u64 },
+    /// The price is outside of the book's price band.
+    OutsidePriceBand { price: u64, low: u64, high: u64 },
+    /// The user is banned by a risk check.
+    UserBanned { user_id: u64 },
+    /// The amount is above the risk limit.
+    OrderTooLarge { amount: u64, max_order_size: u64 },
+    /// The price times the amount is above the risk limit.
+    NotionalTooLarge { notional: u128, max_notional: u64 },
+    /// The user already has as many bids resting in the book as the risk limit allows.
+    TooManyOpenOrders {
+        user_id: u64,
+        max_open_orders: usize,
+    },
+    /// The buying exposure of the user would be above the credit limit.
+    CreditLimitExceeded {
+        user_id: u64,
+        exposure: u128,
+        credit_limit: u64,
+    },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::PriceNotOnTick { price, tick_size } => write!(
+                f,
+                "Price {} is not a multiple of the tick size {}",
+                price, tick_size
+            ),
+            Rejection::AmountNotInLots { amount, lot_size } => write!(
+                f,
+                "Size {} is not a multiple of the lot size {}",
+                amount, lot_size
+            ),
+            Rejection::PriceTooLow { price, min_price } => {
+                write!(f, "Price {} is below the minimum of {}", price, min_price)
+            }
+            Rejection::PriceTooHigh { price, max_price } => {
+                write!(f, "Price {} is above the maximum of {}", price, max_price)
+            }
+            Rejection::OutsidePriceBand { price, low, high } => write!(
+                f,
+                "Price {} is outside of the price band {}..={}",
+                price, low, high
+            ),
+            Rejection::UserBanned { user_id } => write!(f, "User {} is banned", user_id),
+            Rejection::OrderTooLarge {
+                amount,
+                max_order_size,
+            } => write!(
+                f,
+                "Size {} is above the maximum of {}",
+                amount, max_order_size
+            ),
+            Rejection::NotionalTooLarge {
+                notional,
+                max_notional,
+            } => write!(
+                f,
+                "Notional {} is above the maximum of {}",
+                notional, max_notional
+            ),
+            Rejection::TooManyOpenOrders {
+                user_id,
+                max_open_orders,
+            } => write!(
+                f,
+                "User {} already has {} open orders",
+                user_id, max_open_orders
+            ),
+            Rejection::CreditLimitExceeded {
+                user_id,
+                exposure,
+                credit_limit,
+            } => write!(
+                f,
+                "Exposure {} of user {} would be above the credit limit of {}",
+                exposure, user_id, credit_limit
+            ),
+        }
+    }
+}
+
+impl Error for Rejection {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bids::SellingBid;
+
+    #[test]
+    fn test_validate() {
+        let instrument = Instrument {
+            tick_size: 5,
+            lot_size: 10,
+            min_price: Some(50),
+            max_price: Some(200),
+            price_scale: 0,
+            size_scale: 0,
+        };
+        let bid: Bid<SellingBid> = Bid::empty().price(100).amount(30);
+        let validate = |bid, bid_type| instrument.validate(&bid, bid_type);
+        assert_eq!(validate(bid, BidProcessingType::Limit), Ok(()));
+        assert_eq!(
+            validate(bid.price(101), BidProcessingType::Limit),
+            Err(Rejection::PriceNotOnTick {
+                price: 101,
+                tick_size: 5
+            })
+        );
+        assert_eq!(validate(bid.price(101), BidProcessingType::Market), Ok(()));
+        assert_eq!(
+            validate(bid.amount(35), BidProcessingType::Market),
+            Err(Rejection::AmountNotInLots {
+                amount: 35,
+                lot_size: 10
+            })
+        );
+        assert_eq!(
+            validate(bid.price(45), BidProcessingType::Limit),
+            Err(Rejection::PriceTooLow {
+                price: 45,
+                min_price: 50
+            })
+        );
+        assert_eq!(
+            validate(bid.price(205), BidProcessingType::Limit),
+            Err(Rejection::PriceTooHigh {
+                price: 205,
+                max_price: 200
+            })
+        );
+        assert_eq!(
+            Instrument::default().validate(&bid.price(7), BidProcessingType::Limit),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Write-ahead journal of order book inputs.
+
+use crate::{
+    command::{Command, Outcome},
+    order_book::OrderBook,
+};
+use std::io::{Read, Write};
+
+/// An append-only log of commands, one JSON object per line.
+pub struct Journal<W> {
+    writer: W,
+}
+
+impl<W: Write> Journal<W> {
+    /// Initializes a journal that appends commands to the `writer`.
+    pub fn new(writer: W) -> Self {
+        Journal { writer }
+    }
+
+    /// Appends a command to the journal and flushes the writer.
+    pub fn append(&mut self, command: &Command) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(&mut self.writer, command)?;
+        self.writer
+            .write_all(b"\n")
+            .and_then(|()| self.writer.flush())
+            .map_err(serde_json::Error::io)
+    }
+
+    /// Appends a command to the journal and then applies it to the book.
+    ///
+    /// The command is not applied if it can't be journaled.
+    pub fn apply(
+        &mut self,
+        command: Command,
+        order_book: &mut OrderBook,
+    ) -> Result<Outcome, serde_json::Error> {
+        self.append(&command)?;
+        Ok(command.apply(order_book))
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Applies the commands of a journal to the book, in order.
+///
+/// To reconstruct a book, replay its journal to an empty book with the same configuration (or to
+/// a book restored from a snapshot taken at the start of the journal). Listeners of the book are
+/// notified as usual. Returns the number of replayed commands.
+pub fn replay(order_book: &mut OrderBook, r: impl Read) -> Result<usize, serde_json::Error> {
+    let mut count = 0;
+    for command in serde_json::Deserializer::from_reader(r).into_iter::<Command>() {
+        command?.apply(order_book);
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType, TimeInForce},
+        order_book::OrderId,
+    };
+
+    #[test]
+    fn test_replay() {
+        let mut order_book = OrderBook::empty();
+        let mut journal = Journal::new(Vec::new());
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        let commands = [
+            Command::Selling(selling_bid, BidProcessingType::Limit),
+            Command::Selling(selling_bid.price(101), BidProcessingType::Limit),
+            Command::BuyingStop(101, buying_bid.price(105), BidProcessingType::Limit),
+            Command::Amend {
+                order_id: OrderId(0),
+                new_price: 100,
+                new_size: 4,
+            },
+            Command::Buying(buying_bid, BidProcessingType::Limit),
+            Command::Cancel(OrderId(1)),
+            Command::Selling(selling_bid.user_id(3), BidProcessingType::Limit),
+            Command::CancelUser(3),
+            Command::AdvanceTime(1000),
+        ];
+        for command in &commands {
+            journal.apply(*command, &mut order_book).unwrap();
+        }
+        let data = journal.into_inner();
+        assert_eq!(data.iter().filter(|&&byte| byte == b'\n').count(), 9);
+
+        let mut replayed = OrderBook::empty();
+        assert_eq!(replay(&mut replayed, &data[..]).unwrap(), 9);
+        assert_eq!(replayed.depth(10), order_book.depth(10));
+        assert_eq!(replayed.last_trade_price(), order_book.last_trade_price());
+        let next = Command::Buying(buying_bid.price(101), BidProcessingType::Limit);
+        assert_eq!(next.apply(&mut replayed), next.apply(&mut order_book));
+        assert_eq!(replayed.cancel(OrderId(2)), order_book.cancel(OrderId(2)));
+    }
+
+    #[test]
+    fn test_session_commands() {
+        let mut order_book = OrderBook::empty();
+        let mut journal = Journal::new(Vec::new());
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let buying_bid = Bid::empty().price(101).amount(3).user_id(2);
+        let commands = [
+            Command::Halt,
+            Command::Buying(buying_bid, BidProcessingType::Limit),
+            Command::Resume,
+            Command::StartAuction,
+            Command::Selling(selling_bid, BidProcessingType::Limit),
+            Command::Buying(buying_bid.amount(4), BidProcessingType::Limit),
+            Command::SetReferencePrice(Some(100)),
+            Command::Uncross,
+            Command::Halt,
+        ];
+        for command in &commands {
+            journal.apply(*command, &mut order_book).unwrap();
+        }
+        let data = journal.into_inner();
+
+        let mut replayed = OrderBook::empty();
+        assert_eq!(replay(&mut replayed, &data[..]).unwrap(), commands.len());
+        assert_eq!(replayed.depth(10), order_book.depth(10));
+        assert_eq!(replayed.last_trade_price(), order_book.last_trade_price());
+        assert_eq!(replayed.phase(), order_book.phase());
+        assert!(replayed.is_halted());
+        assert_eq!(replayed.stats(), order_book.stats());
+    }
+
+    #[test]
+    fn test_replay_legacy_expire() {
+        let mut order_book = OrderBook::empty();
+        let bid = Bid::empty()
+            .price(100)
+            .amount(5)
+            .user_id(1)
+            .time_in_force(TimeInForce::GoodTillDate(10));
+        Command::Selling(bid, BidProcessingType::Limit).apply(&mut order_book);
+        assert_eq!(
+            replay(&mut order_book, &b"{\"Expire\":10}\n"[..]).unwrap(),
+            1
+        );
+        assert_eq!(order_book.best_ask(), None);
+
+        let mut data = Vec::new();
+        Journal::new(&mut data)
+            .append(&Command::AdvanceTime(10))
+            .unwrap();
+        assert_eq!(data, b"{\"Expire\":10}\n");
+    }
+
+    #[test]
+    fn test_malformed_journal() {
+        let mut order_book = OrderBook::empty();
+        assert_eq!(replay(&mut order_book, &b""[..]).unwrap(), 0);
+
+        let bid = Bid::empty().price(100).amount(5).user_id(1);
+        let mut data = Vec::new();
+        Journal::new(&mut data)
+            .append(&Command::Selling(bid, BidProcessingType::Limit))
+            .unwrap();
+        for tail in [&b"{\"Cancel\":"[..], b"{\"Withdraw\":0}", b"garbage"].iter() {
+            let mut order_book = OrderBook::empty();
+            let journal = [&data[..], tail].concat();
+            assert!(replay(&mut order_book, &journal[..]).is_err());
+            // Commands before the malformed one are applied.
+            assert_eq!(order_book.best_ask(), Some(100));
+        }
+    }
+
+    struct BrokenWriter;
+
+    impl Write for BrokenWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_broken_writer() {
+        let mut order_book = OrderBook::empty();
+        let mut journal = Journal::new(BrokenWriter);
+        let bid = Bid::empty().price(100).amount(5).user_id(1);
+        let result = journal.apply(
+            Command::Selling(bid, BidProcessingType::Limit),
+            &mut order_book,
+        );
+        assert!(result.unwrap_err().is_io());
+        // The command is not applied since it hasn't been journaled.
+        assert_eq!(order_book.best_ask(), None);
+        assert_eq!(order_book.stats().trades(), 0);
+    }
+}
@@ -1,10 +1,17 @@
 use crate::bids::{BuyingBid, SellingBid};
 use std::{cmp::Ordering, marker::PhantomData};
 
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(bound = "")
+)]
 #[derive(PartialEq, Eq, Debug)]
 pub struct PoolKey<BidKind> {
     pub id: usize,
+    seq: usize,
     price: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
     _p: PhantomData<BidKind>,
 }
 
@@ -17,12 +24,30 @@ impl<BidKind> Clone for PoolKey<BidKind> {
 
 impl<BidKind> PoolKey<BidKind> {
     pub fn new(id: usize, price: u64) -> Self {
+        PoolKey::with_seq(id, id, price)
+    }
+
+    /// Creates a key with a time priority (`seq`) that differs from the `id`.
+    ///
+    /// Keys with the same price are ordered by their `seq`, then by their `id`.
+    pub fn with_seq(id: usize, seq: usize, price: u64) -> Self {
         PoolKey {
             id,
+            seq,
             price,
             _p: PhantomData,
         }
     }
+
+    /// Price of the bid.
+    pub fn price(&self) -> u64 {
+        self.price
+    }
+
+    /// Time priority of the bid within its price level, the lower the better.
+    pub(crate) fn priority(&self) -> (usize, usize) {
+        (self.seq, self.id)
+    }
 }
 
 impl<BidKind> PartialOrd for PoolKey<BidKind>
@@ -39,6 +64,7 @@ impl Ord for PoolKey<BuyingBid> {
         self.price
             .cmp(&other.price)
             .reverse()
+            .then_with(|| self.seq.cmp(&other.seq))
             .then_with(|| self.id.cmp(&other.id))
     }
 }
@@ -47,6 +73,64 @@ impl Ord for PoolKey<SellingBid> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.price
             .cmp(&other.price)
+            .then_with(|| self.seq.cmp(&other.seq))
             .then_with(|| self.id.cmp(&other.id))
     }
 }
+
+/// A key of a price level in a pool.
+///
+/// Price levels are ordered from the best price to the worst one, i.e. buying levels go from the
+/// highest price to the lowest and selling levels the other way round.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(bound = "")
+)]
+#[derive(PartialEq, Eq, Debug)]
+pub struct PriceKey<BidKind> {
+    price: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _p: PhantomData<BidKind>,
+}
+
+impl<BidKind> Copy for PriceKey<BidKind> {}
+impl<BidKind> Clone for PriceKey<BidKind> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<BidKind> PriceKey<BidKind> {
+    pub fn new(price: u64) -> Self {
+        PriceKey {
+            price,
+            _p: PhantomData,
+        }
+    }
+
+    pub fn price(&self) -> u64 {
+        self.price
+    }
+}
+
+impl<BidKind> PartialOrd for PriceKey<BidKind>
+where
+    PriceKey<BidKind>: Ord,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PriceKey<BuyingBid> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.price.cmp(&other.price).reverse()
+    }
+}
+
+impl Ord for PriceKey<SellingBid> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.price.cmp(&other.price)
+    }
+}
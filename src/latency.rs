@@ -0,0 +1,122 @@
+//! Order-arrival latencies of simulated participants.
+
+use rand::{
+    distributions::{Distribution, Uniform},
+    rngs::SmallRng,
+    SeedableRng,
+};
+use std::collections::BTreeMap;
+
+/// How long it takes an order to reach the book once it has been decided on, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    /// Every order takes the same time.
+    Fixed(u64),
+    /// Latencies are spread evenly between `min` and `max`, both inclusive.
+    Uniform {
+        /// The shortest latency.
+        min: u64,
+        /// The longest latency.
+        max: u64,
+    },
+}
+
+impl Latency {
+    fn check(self) {
+        if let Latency::Uniform { min, max } = self {
+            assert!(min <= max, "Latencies must be in order");
+        }
+    }
+}
+
+/// Latencies of the participants of a backtest, by user id, see
+/// [`Twap::execute_with_latency`](crate::Twap::execute_with_latency).
+///
+/// Latencies are drawn from a generator seeded with the `seed`, so a backtest with the same
+/// model gives the same results from run to run.
+#[derive(Debug, Clone)]
+pub struct LatencyModel {
+    default: Latency,
+    users: BTreeMap<u64, Latency>,
+    rng: SmallRng,
+}
+
+impl LatencyModel {
+    /// Initializes a model in which every participant has the `default` latency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bounds of a uniform latency are out of order.
+    pub fn new(default: Latency, seed: u64) -> Self {
+        default.check();
+        LatencyModel {
+            default,
+            users: BTreeMap::new(),
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Sets the latency of the orders of a user.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bounds of a uniform latency are out of order.
+    pub fn user(mut self, user_id: u64, latency: Latency) -> Self {
+        latency.check();
+        self.users.insert(user_id, latency);
+        self
+    }
+
+    /// Draws the latency of the next order of a user.
+    pub fn sample(&mut self, user_id: u64) -> u64 {
+        match self.users.get(&user_id).copied().unwrap_or(self.default) {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform { min, max } => Uniform::new_inclusive(min, max).sample(&mut self.rng),
+        }
+    }
+}
+
+impl Default for LatencyModel {
+    /// Orders reach the book as soon as they are decided on.
+    fn default() -> Self {
+        LatencyModel::new(Latency::Fixed(0), 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let model = LatencyModel::new(Latency::Fixed(5), 7)
+            .user(1, Latency::Uniform { min: 10, max: 20 })
+            .user(2, Latency::Uniform { min: 3, max: 3 });
+        let mut samples = model.clone();
+        let latencies: Vec<_> = (0..100).map(|_| samples.sample(1)).collect();
+        assert!(latencies.iter().all(|latency| (10..=20).contains(latency)));
+        assert!(latencies.iter().any(|&latency| latency != latencies[0]));
+        assert_eq!(samples.sample(2), 3);
+        assert_eq!(samples.sample(3), 5);
+
+        let mut again = model;
+        let repeated: Vec<_> = (0..100).map(|_| again.sample(1)).collect();
+        assert_eq!(latencies, repeated);
+
+        let mut huge = LatencyModel::new(
+            Latency::Uniform {
+                min: u64::MAX - 1,
+                max: u64::MAX,
+            },
+            0,
+        );
+        assert!(huge.sample(0) >= u64::MAX - 1);
+        assert_eq!(LatencyModel::default().sample(42), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Latencies must be in order")]
+    fn test_out_of_order() {
+        let _ = LatencyModel::default().user(1, Latency::Uniform { min: 2, max: 1 });
+    }
+}
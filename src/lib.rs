@@ -17,11 +17,106 @@
 //! for inclusion in the work by you, as defined in the Apache-2.0 license, shall be dual licensed as above, without any
 //! additional terms or conditions.
 
+mod accounts;
+mod auction;
+mod banded;
 pub mod bids;
+mod binary;
+mod candles;
+mod clock;
+mod command;
+mod conditional;
+mod config;
+mod decimal;
+mod depth_import;
+mod drop_copy;
+mod engine;
+mod error;
+mod feed;
+#[cfg(feature = "fix")]
+mod fix;
+pub mod generator;
+mod groups;
+mod iceberg;
+mod instrument;
+#[cfg(feature = "serde")]
+mod journal;
 pub mod key;
+mod latency;
+mod listener;
+mod lobster;
+mod mmp;
 mod order_book;
+mod policy;
 mod pool;
 mod range;
 mod raw;
+mod risk;
+mod session;
+mod shadow;
+mod sharded;
+mod shared;
+mod simulation;
+mod stats;
+mod stops;
+mod tape;
+mod tenants;
+mod throttle;
+mod trade;
+mod twap;
+#[cfg(feature = "websocket")]
+mod websocket;
 
-pub use crate::{order_book::OrderBook, pool::Pool, raw::process_reader};
+pub use crate::{
+    accounts::{Account, Accounts},
+    auction::Equilibrium,
+    banded::BandedOrderBook,
+    binary::{process_binary_reader, BinaryMessage},
+    candles::{Candle, CandleAggregator, CandleListener},
+    clock::{Clock, ManualClock, SystemClock},
+    command::{Command, Event, Outcome},
+    conditional::Condition,
+    config::Config,
+    decimal::{Decimal, Price, Quantity},
+    depth_import::DepthImport,
+    drop_copy::DropCopy,
+    engine::MatchingEngine,
+    error::Error,
+    feed::{Divergence, FeedBook, FeedError, FeedEvent, FeedMessage, MarketDataFeed},
+    groups::UserGroups,
+    iceberg::{IcebergRefresh, TrancheSize},
+    instrument::{Instrument, Rejection},
+    latency::{Latency, LatencyModel},
+    listener::{LoggingListener, OrderBookListener, OrderInfo},
+    lobster::process_lobster,
+    mmp::MakerProtection,
+    order_book::{
+        Amendment, BatchResult, Cancellation, Depth, HaltMode, IncomingOrder, OrderBook, OrderId,
+        OrderStatus, ProcessingResult,
+    },
+    policy::MatchingPolicy,
+    pool::{DuplicateId, Pool},
+    raw::{
+        dump_book, dump_book_csv, dump_book_json_lines, process_csv, process_json_lines,
+        process_reader, write_orders, write_orders_csv, write_orders_json_lines, ProcessingOptions,
+        ProcessingSummary,
+    },
+    risk::{RiskCheck, RiskLimits},
+    session::{Phase, SessionSchedule},
+    sharded::{ShardEvent, ShardedEngine},
+    shared::{Disconnected, Reply, SharedOrderBook},
+    simulation::{InsufficientLiquidity, Quote, Simulation},
+    stats::TradeStats,
+    tape::{TapeEntry, TradeId, TradeTape},
+    tenants::{Tenants, TENANT_SEPARATOR},
+    throttle::{RateLimit, ThrottleMode},
+    trade::Trade,
+    twap::{ChildOrder, Twap, TwapReport},
+};
+
+#[cfg(feature = "fix")]
+pub use crate::fix::{FixError, FixGateway, FixMessage, FixResponse};
+#[cfg(feature = "serde")]
+pub use crate::journal::{replay, Journal};
+#[cfg(feature = "websocket")]
+pub use crate::websocket::{BookPublisher, WebSocketPublisher};
@@ -0,0 +1,288 @@
+//! Order book events.
+
+use crate::{
+    bids::{Bid, GenericBid, Side},
+    feed::FeedEvent,
+    session::Phase,
+    trade::Trade,
+};
+use log::info;
+
+/// A side-agnostic description of a bid, as reported to listeners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderInfo {
+    /// Side of the bid.
+    pub side: Side,
+    /// Bid's price.
+    pub price: u64,
+    /// Amount of items relevant to the event.
+    pub amount: u64,
+    /// Bid's user id.
+    pub user_id: u64,
+}
+
+impl<BidKind: GenericBid> From<&Bid<BidKind>> for OrderInfo {
+    fn from(bid: &Bid<BidKind>) -> Self {
+        OrderInfo {
+            side: BidKind::side(),
+            price: bid.price,
+            amount: bid.amount,
+            user_id: bid.user_id,
+        }
+    }
+}
+
+/// A receiver of the order book events.
+///
+/// All the methods do nothing by default, so implementors only need to override the ones they're
+/// interested in.
+pub trait OrderBookListener {
+    /// Called for every executed trade.
+    fn on_trade(&mut self, _trade: &Trade) {}
+
+    /// Called when (the rest of) an incoming bid is added to the book.
+    fn on_order_added(&mut self, _order: &OrderInfo) {}
+
+    /// Called when (the rest of) an incoming bid is dropped without being added to the book.
+    ///
+    /// The `amount` of the `order` is the amount that has been dropped.
+    fn on_order_dropped(&mut self, _order: &OrderInfo) {}
+
+    /// Called when a bid resting in the book is partially filled.
+    ///
+    /// The `amount` of the `order` is the amount that is left in the book.
+    fn on_order_partially_filled(&mut self, _order: &OrderInfo) {}
+
+    /// Called when a bid is cancelled.
+    ///
+    /// The `amount` of the `order` is the amount that has been removed from the book.
+    fn on_order_cancelled(&mut self, _order: &OrderInfo) {}
+
+    /// Called when a bid is amended without losing its priority.
+    ///
+    /// The `order` holds the updated price and amount.
+    fn on_order_amended(&mut self, _order: &OrderInfo) {}
+
+    /// Called when a bid is removed from the book because its time in force has run out.
+    ///
+    /// The `amount` of the `order` is the amount that has been removed from the book.
+    fn on_order_expired(&mut self, _order: &OrderInfo) {}
+
+    /// Called when the displayed part of the book changes, bid by bid.
+    ///
+    /// Unlike the other events, these ones refer to the bids by their ids and never reveal the
+    /// hidden parts of iceberg bids. Trades are only reported with [`on_trade`].
+    ///
+    /// [`on_trade`]: OrderBookListener::on_trade
+    fn on_feed_event(&mut self, _event: &FeedEvent) {}
+
+    /// Called when the book's trading session moves to another phase, before the auction that has
+    /// just ended (if any) is uncrossed.
+    fn on_phase_changed(&mut self, _phase: Phase) {}
+
+    /// Called when the market-maker protection of the user is triggered, right before the user's
+    /// bids resting in the book are cancelled, see [`MakerProtection`](crate::MakerProtection).
+    fn on_protection_triggered(&mut self, _user_id: u64) {}
+}
+
+/// A listener that ignores all the events.
+impl OrderBookListener for () {}
+
+/// Forwards the events to every listener in the list.
+impl OrderBookListener for Vec<Box<dyn OrderBookListener>> {
+    fn on_trade(&mut self, trade: &Trade) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_trade(trade))
+    }
+
+    fn on_order_added(&mut self, order: &OrderInfo) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_order_added(order))
+    }
+
+    fn on_order_dropped(&mut self, order: &OrderInfo) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_order_dropped(order))
+    }
+
+    fn on_order_partially_filled(&mut self, order: &OrderInfo) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_order_partially_filled(order))
+    }
+
+    fn on_order_cancelled(&mut self, order: &OrderInfo) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_order_cancelled(order))
+    }
+
+    fn on_order_amended(&mut self, order: &OrderInfo) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_order_amended(order))
+    }
+
+    fn on_order_expired(&mut self, order: &OrderInfo) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_order_expired(order))
+    }
+
+    fn on_feed_event(&mut self, event: &FeedEvent) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_feed_event(event))
+    }
+
+    fn on_phase_changed(&mut self, phase: Phase) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_phase_changed(phase))
+    }
+
+    fn on_protection_triggered(&mut self, user_id: u64) {
+        self.iter_mut()
+            .for_each(|listener| listener.on_protection_triggered(user_id))
+    }
+}
+
+/// A listener that reports all the events except partial fills to the log with the `info` level.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoggingListener;
+
+impl OrderBookListener for LoggingListener {
+    fn on_trade(&mut self, trade: &Trade) {
+        let (verb, direction) = trade.taker_side.deal_verb_direction();
+        info!(
+            "[TRADE] User {} {} {} items {} user {} for price {}",
+            trade.taker_user_id, verb, trade.amount, direction, trade.maker_user_id, trade.price,
+        );
+    }
+
+    fn on_order_added(&mut self, order: &OrderInfo) {
+        info!(
+            "[ ADD ] Add a {} from user {} (price: {}, size: {}) to the pool",
+            order.side.kind_name(),
+            order.user_id,
+            order.price,
+            order.amount
+        );
+    }
+
+    fn on_order_dropped(&mut self, order: &OrderInfo) {
+        info!(
+            "[DROP ] Drop a {} from user {} (price: {}, size: {})",
+            order.side.kind_name(),
+            order.user_id,
+            order.price,
+            order.amount
+        );
+    }
+
+    fn on_order_cancelled(&mut self, order: &OrderInfo) {
+        info!(
+            "[ CXL ] Cancel a {} from user {} (price: {}, size: {})",
+            order.side.kind_name(),
+            order.user_id,
+            order.price,
+            order.amount
+        );
+    }
+
+    fn on_order_amended(&mut self, order: &OrderInfo) {
+        info!(
+            "[AMEND] Amend a {} from user {} (price: {}, size: {})",
+            order.side.kind_name(),
+            order.user_id,
+            order.price,
+            order.amount
+        );
+    }
+
+    fn on_order_expired(&mut self, order: &OrderInfo) {
+        info!(
+            "[ EXP ] Expire a {} from user {} (price: {}, size: {})",
+            order.side.kind_name(),
+            order.user_id,
+            order.price,
+            order.amount
+        );
+    }
+
+    fn on_phase_changed(&mut self, phase: Phase) {
+        info!("[PHASE] The session enters the {:?} phase", phase);
+    }
+
+    fn on_protection_triggered(&mut self, user_id: u64) {
+        info!("[ MMP ] Pull the quotes of user {}", user_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{bids::BidProcessingType, OrderBook};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Trade(Trade),
+        Added(OrderInfo),
+        Dropped(OrderInfo),
+        PartiallyFilled(OrderInfo),
+    }
+
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+    impl OrderBookListener for Recorder {
+        fn on_trade(&mut self, trade: &Trade) {
+            self.0.borrow_mut().push(Event::Trade(*trade))
+        }
+
+        fn on_order_added(&mut self, order: &OrderInfo) {
+            self.0.borrow_mut().push(Event::Added(*order))
+        }
+
+        fn on_order_dropped(&mut self, order: &OrderInfo) {
+            self.0.borrow_mut().push(Event::Dropped(*order))
+        }
+
+        fn on_order_partially_filled(&mut self, order: &OrderInfo) {
+            self.0.borrow_mut().push(Event::PartiallyFilled(*order))
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut order_book = OrderBook::empty();
+        order_book.add_listener(Recorder(Rc::clone(&events)));
+
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::ImmediateOrCancel);
+        let buying_bid = Bid::empty().price(100).amount(4).user_id(3);
+        order_book.process_buying(buying_bid, BidProcessingType::ImmediateOrCancel);
+        let buying_bid = Bid::empty().price(100).amount(4).user_id(3);
+        order_book.process_buying(buying_bid, BidProcessingType::FillOrKill);
+
+        let order = |side, price, amount, user_id| OrderInfo {
+            side,
+            price,
+            amount,
+            user_id,
+        };
+        let trade = |maker_user_id, taker_user_id, amount| Trade {
+            maker_user_id,
+            taker_user_id,
+            taker_side: Side::Buy,
+            price: 100,
+            amount,
+        };
+        let expected = vec![
+            Event::Added(order(Side::Sell, 100, 5, 1)),
+            Event::Trade(trade(1, 2, 3)),
+            Event::PartiallyFilled(order(Side::Sell, 100, 2, 1)),
+            Event::Trade(trade(1, 3, 2)),
+            Event::Dropped(order(Side::Buy, 100, 2, 3)),
+            Event::Dropped(order(Side::Buy, 100, 4, 3)),
+        ];
+        assert_eq!(*events.borrow(), expected);
+    }
+}
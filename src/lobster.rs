@@ -0,0 +1,229 @@
+//! Replaying the message files of the LOBSTER data sets.
+//!
+//! A message file is a CSV file without a header, a message per row:
+//!
+//! ```norun
+//! 34200.004241176,1,16113575,18,5853300,1
+//! 34200.025551909,4,16113575,3,5853300,1
+//! ```
+//!
+//! The columns are the time (seconds after midnight), the event type, the order id, the size, the
+//! price (in the units of `0.0001` dollars) and the direction of the order (`1` buy, `-1` sell).
+
+use crate::{
+    bids::{Bid, BidProcessingType, Side},
+    engine::MatchingEngine,
+    error::Error,
+    instrument::Rejection,
+    order_book::OrderId,
+    raw::ProcessingSummary,
+    trade::Trade,
+};
+use serde_derive::Deserialize;
+use std::{collections::HashMap, convert::TryFrom, io::Read};
+
+/// User id of the stand-ins of the incoming orders that are not in the data set.
+const TAKER_USER_ID: u64 = 0;
+
+#[derive(Debug, Deserialize)]
+struct LobsterMessage {
+    time: f64,
+    event_type: u8,
+    order_id: u64,
+    size: u64,
+    price: i64,
+    direction: i8,
+}
+
+impl LobsterMessage {
+    /// Side of the order the message refers to.
+    fn side(&self) -> Option<Side> {
+        match self.direction {
+            1 => Some(Side::Buy),
+            -1 => Some(Side::Sell),
+            _ => None,
+        }
+    }
+
+    /// Replays the message on the book of the `symbol`, given the ids the orders of the data set
+    /// have got in the book.
+    fn replay(
+        &self,
+        engine: &mut MatchingEngine,
+        symbol: &str,
+        ids: &mut HashMap<u64, OrderId>,
+    ) -> Result<Vec<Trade>, Rejection> {
+        if self.event_type == 7 {
+            let order_book = engine.book_mut(symbol);
+            return Ok(match self.price {
+                -1 => {
+                    order_book.halt();
+                    Vec::new()
+                }
+                1 => order_book.resume(),
+                _ => Vec::new(),
+            });
+        }
+        let (side, price) = match (self.side(), u64::try_from(self.price)) {
+            (Some(side), Ok(price)) => (side, price),
+            _ => return Ok(Vec::new()),
+        };
+        match self.event_type {
+            1 => {
+                let result = submit(
+                    engine,
+                    symbol,
+                    side,
+                    price,
+                    self.size,
+                    self.order_id,
+                    BidProcessingType::Limit,
+                )?;
+                ids.insert(self.order_id, result.0);
+                Ok(result.1)
+            }
+            2 => {
+                let order_id = match ids.get(&self.order_id) {
+                    Some(&order_id) => order_id,
+                    None => return Ok(Vec::new()),
+                };
+                let order_book = engine.book_mut(symbol);
+                if let Some(status) = order_book.get(order_id) {
+                    let remaining = status.remaining.saturating_sub(self.size);
+                    order_book.amend(order_id, status.price, remaining);
+                }
+                Ok(Vec::new())
+            }
+            3 => {
+                if let Some(order_id) = ids.remove(&self.order_id) {
+                    engine.book_mut(symbol).cancel(order_id);
+                }
+                Ok(Vec::new())
+            }
+            // The incoming order that has executed the resting one is not in the data set, so an
+            // immediate-or-cancel one of the opposite side stands in for it.
+            4 => {
+                let taker_side = match side {
+                    Side::Buy => Side::Sell,
+                    Side::Sell => Side::Buy,
+                };
+                submit(
+                    engine,
+                    symbol,
+                    taker_side,
+                    price,
+                    self.size,
+                    TAKER_USER_ID,
+                    BidProcessingType::ImmediateOrCancel,
+                )
+                .map(|(_order_id, trades)| trades)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+fn submit(
+    engine: &mut MatchingEngine,
+    symbol: &str,
+    side: Side,
+    price: u64,
+    amount: u64,
+    user_id: u64,
+    bid_type: BidProcessingType,
+) -> Result<(OrderId, Vec<Trade>), Rejection> {
+    let result = match side {
+        Side::Sell => {
+            let selling_bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+            engine.process_selling(symbol, selling_bid, bid_type)
+        }
+        Side::Buy => {
+            let buying_bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+            engine.process_buying(symbol, buying_bid, bid_type)
+        }
+    }?;
+    Ok((result.order_id, result.trades))
+}
+
+/// Replays a LOBSTER message file on the book of the `symbol`.
+///
+/// Returns the summary of the processing, where every message counts as an order. Event types
+/// are mapped as follows:
+///  * `1` (a new limit order) is submitted as a `Limit` bid,
+///  * `2` (a partial cancellation) reduces the size of the order, keeping its priority,
+///  * `3` (a deletion) cancels the order,
+///  * `4` (an execution of a visible order) submits an `ImmediateOrCancel` bid of the opposite side
+///    for the executed size at the price of the order, as the incoming order isn't in the file,
+///  * `5` (an execution of a hidden order) and `6` (a cross trade) are skipped, they don't change
+///    the visible book,
+///  * `7` halts trading (price `-1`) or resumes it (price `1`).
+///
+/// Prices are taken as they are. The data sets don't tell the users apart, so every order belongs
+/// to the user with the id of the order, and the stand-ins of the incoming orders to the user `0`;
+/// user groups are not to be configured. The clock of the engine advances to the (whole) seconds
+/// of the messages, unless it's ahead. Messages referring to orders that are not in the book (e.g.
+/// the ones submitted before the start of the file) and the ones with an unknown type or
+/// direction are skipped. A malformed row stops the processing with [`Error::Parse`].
+pub fn process_lobster(
+    engine: &mut MatchingEngine,
+    symbol: &str,
+    r: impl Read,
+) -> Result<ProcessingSummary, Error> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(r);
+    let mut summary = ProcessingSummary::default();
+    let mut ids = HashMap::new();
+    for (entry, message) in reader.deserialize::<LobsterMessage>().enumerate() {
+        let message = message.map_err(|e| {
+            if e.is_io_error() {
+                Error::Io(e.into())
+            } else {
+                Error::parse(entry, e)
+            }
+        })?;
+        let seconds = message.time as u64;
+        if seconds > engine.now() {
+            engine.advance_time(seconds);
+        }
+        let result = message.replay(engine, symbol, &mut ids);
+        summary.record(result.as_ref().map(|trades| &trades[..]).map_err(|r| *r));
+    }
+    Ok(summary.finish(engine))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_process_lobster() {
+        let data = b"34200.01,1,11,10,1000000,-1
+34200.02,1,12,5,1000000,-1
+34200.03,1,13,7,990000,1
+34200.5,2,11,4,1000000,-1
+34201.1,4,11,2,1000000,-1
+34201.2,5,99,3,995000,1
+34201.3,3,13,7,990000,1
+34201.4,3,77,7,990000,1
+34202,7,0,0,-1,-1
+34202.1,1,14,1,980000,1
+34203,7,0,0,1,-1
+";
+        let mut engine = MatchingEngine::new();
+        let summary = process_lobster(&mut engine, "AAPL", &data[..]).unwrap();
+        assert_eq!(summary.orders_read, 11);
+        assert_eq!(summary.trades, 1);
+        assert_eq!(summary.total_traded_volume, 2);
+        assert_eq!(engine.now(), 34203);
+        let order_book = engine.book("AAPL").unwrap();
+        let sizes: Vec<_> = order_book
+            .sell_orders()
+            .map(|(_id, bid)| bid.amount)
+            .collect();
+        // The first order is reduced by 4 and then executed for 2.
+        assert_eq!(sizes, [4, 5]);
+        assert_eq!(order_book.depth(1).buying, [(980_000, 1, 1)]);
+
+        let error = process_lobster(&mut engine, "AAPL", &b"34204,1,15,x,980000,1\n"[..]);
+        assert!(matches!(error, Err(Error::Parse { entry: 0, .. })));
+    }
+}
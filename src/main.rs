@@ -1,17 +1,300 @@
+use anyhow::{bail, Context, Result};
 use env_logger::fmt::Color;
-use failure::{Fallible, ResultExt};
-use log::{Level, LevelFilter};
-use simple_stock_matcher_experiment::{process_reader, OrderBook};
-use std::{fs::File, io::Write, path::PathBuf};
+use log::{info, warn, Level, LevelFilter};
+use serde::Serializer;
+use serde_derive::Serialize;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use simple_stock_matcher_experiment::{
+    bids::{Bid, BidProcessingType, Side},
+    dump_book, dump_book_csv, dump_book_json_lines,
+    generator::{GeneratorConfig, OrderGenerator, PriceDistribution},
+    process_binary_reader, process_csv, process_json_lines, process_lobster, process_reader,
+    write_orders, write_orders_csv, write_orders_json_lines, Cancellation, Clock,
+    Config as EngineConfig, DropCopy, LoggingListener, MatchingEngine, OrderBookListener, OrderId,
+    OrderInfo, ProcessingOptions, SystemClock, Trade,
+};
+use std::{
+    cell::RefCell,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
-#[structopt(about = "Simple stock matcher experiment.")]
+#[structopt(
+    about = "Simple stock matcher experiment.",
+    raw(setting = "structopt::clap::AppSettings::SubcommandsNegateReqs")
+)]
 struct Config {
-    #[structopt(long = "input", short = "i", help = "Path to a yaml file with bids.")]
-    bids_path: PathBuf,
+    #[structopt(
+        long = "input",
+        short = "i",
+        raw(required_unless = r#""interactive""#),
+        help = "Path to a file with bids: yaml, JSON Lines (*.jsonl), CSV (*.csv), binary (*.bin) \
+                or a LOBSTER message file (<symbol>_<date>_<start>_<end>_message_<levels>.csv)."
+    )]
+    bids_path: Option<PathBuf>,
+    #[structopt(
+        long = "config",
+        short = "c",
+        help = "Path to a yaml file with the engine configuration."
+    )]
+    config_path: Option<PathBuf>,
+    #[structopt(
+        long = "output",
+        short = "o",
+        help = "Path to a file to write the trades and the dropped bids to."
+    )]
+    output_path: Option<PathBuf>,
+    #[structopt(
+        long = "output-format",
+        default_value = "text",
+        raw(possible_values = r#"&["text", "json", "csv"]"#),
+        help = "Format of the output file."
+    )]
+    output_format: OutputFormat,
+    #[structopt(
+        long = "drop-copy",
+        help = "Path to a file to write every execution and cancellation to as they happen, in \
+                the JSON Lines format."
+    )]
+    drop_copy_path: Option<PathBuf>,
+    #[structopt(
+        long = "dump-book",
+        help = "Path to a file to write the resting bids to, in the input format: yaml, JSON Lines \
+                (*.jsonl) or CSV (*.csv)."
+    )]
+    dump_path: Option<PathBuf>,
+    #[structopt(
+        long = "interactive",
+        help = "Read commands from the standard input (after processing the input file, if any), \
+                type `help` for the list."
+    )]
+    interactive: bool,
+    #[structopt(
+        long = "skip-invalid",
+        help = "Skip the invalid bids of the input file (like the ones of a zero size) instead of \
+                stopping at the first one."
+    )]
+    skip_invalid: bool,
+    #[structopt(
+        long = "credit-limit",
+        help = "Maximum buying exposure of a user, overrides the one of the configuration."
+    )]
+    credit_limit: Option<u64>,
+    #[structopt(
+        long = "no-credit-check",
+        help = "Don't check the buying exposure of the users, even if the configuration has a \
+                credit limit."
+    )]
+    no_credit_check: bool,
+    #[structopt(
+        long = "shadow-check",
+        help = "Keep a shadow of every book rebuilt from its feed and compare them once every \
+                given number of bids, logging the divergences."
+    )]
+    shadow_check: Option<usize>,
+    #[cfg(feature = "websocket")]
+    #[structopt(
+        long = "websocket",
+        help = "Address to publish the market data over WebSocket on, e.g. 127.0.0.1:9001. The \
+                processing starts once a subscriber is connected, or once the --websocket-wait \
+                time is over. The server doesn't do TLS, put a TLS-terminating proxy in front of \
+                it to serve wss://."
+    )]
+    websocket_addr: Option<String>,
+    #[cfg(feature = "websocket")]
+    #[structopt(
+        long = "websocket-wait",
+        default_value = "10",
+        help = "Seconds to wait for a WebSocket subscriber before the processing starts."
+    )]
+    websocket_wait: u64,
+    #[cfg(feature = "websocket")]
+    #[structopt(
+        long = "websocket-tokens",
+        help = "Path to a yaml file that maps the tokens WebSocket subscribers must present to \
+                the user ids they identify. Without it any client may subscribe."
+    )]
+    websocket_tokens_path: Option<PathBuf>,
     #[structopt(long = "verbose", short = "v", help = "Enable debug output.")]
     verbose: bool,
+    #[structopt(subcommand)]
+    subcommand: Option<Subcommand>,
+}
+
+#[derive(StructOpt)]
+enum Subcommand {
+    #[structopt(
+        name = "generate",
+        about = "Writes a file of random orders, the same ones for the same seed."
+    )]
+    Generate(GenerateArgs),
+}
+
+#[derive(StructOpt)]
+struct GenerateArgs {
+    #[structopt(
+        long = "orders",
+        default_value = "10000",
+        help = "Number of orders to generate."
+    )]
+    orders: usize,
+    #[structopt(long = "seed", default_value = "0", help = "Seed of the generator.")]
+    seed: u64,
+    #[structopt(
+        long = "out",
+        help = "Path to a file to write the orders to: yaml, JSON Lines (*.jsonl) or CSV (*.csv)."
+    )]
+    out_path: PathBuf,
+    #[structopt(
+        long = "symbol",
+        default_value = "",
+        help = "Symbol of the orders, the default book if empty."
+    )]
+    symbol: String,
+    #[structopt(long = "users", default_value = "10", help = "Number of users.")]
+    users: u64,
+    #[structopt(long = "min-price", default_value = "90", help = "The lowest price.")]
+    min_price: u64,
+    #[structopt(long = "max-price", default_value = "110", help = "The highest price.")]
+    max_price: u64,
+    #[structopt(
+        long = "buy-ratio",
+        default_value = "0.5",
+        help = "Share of buying orders, from 0 to 1."
+    )]
+    buy_ratio: f64,
+}
+
+/// Format of the file with the trades and the dropped bids.
+#[derive(Debug, Clone, Copy)]
+enum OutputFormat {
+    /// One sentence per line.
+    Text,
+    /// An array of records.
+    Json,
+    /// A record per row, with a header.
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("Unknown output format {:?}", s)),
+        }
+    }
+}
+
+/// A trade or a dropped bid.
+///
+/// For trades, the side and the user id are the ones of the incoming (taker) bid.
+#[derive(Debug, Serialize)]
+struct Record {
+    event: &'static str,
+    #[serde(serialize_with = "serialize_side")]
+    side: Side,
+    user_id: u64,
+    price: u64,
+    amount: u64,
+    maker_user_id: Option<u64>,
+}
+
+fn serialize_side<S: Serializer>(side: &Side, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    })
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.maker_user_id {
+            Some(maker_user_id) => {
+                let (verb, direction) = self.side.deal_verb_direction();
+                write!(
+                    f,
+                    "User {} {} {} items {} user {} for price {}",
+                    self.user_id, verb, self.amount, direction, maker_user_id, self.price
+                )
+            }
+            None => write!(
+                f,
+                "Dropped a {} from user {} (price: {}, size: {})",
+                self.side.kind_name(),
+                self.user_id,
+                self.price,
+                self.amount
+            ),
+        }
+    }
+}
+
+/// Collects the trades and the dropped bids of every book.
+#[derive(Clone, Default)]
+struct Recorder(Rc<RefCell<Vec<Record>>>);
+
+impl OrderBookListener for Recorder {
+    fn on_trade(&mut self, trade: &Trade) {
+        self.0.borrow_mut().push(Record {
+            event: "trade",
+            side: trade.taker_side,
+            user_id: trade.taker_user_id,
+            price: trade.price,
+            amount: trade.amount,
+            maker_user_id: Some(trade.maker_user_id),
+        })
+    }
+
+    fn on_order_dropped(&mut self, order: &OrderInfo) {
+        self.0.borrow_mut().push(Record {
+            event: "drop",
+            side: order.side,
+            user_id: order.user_id,
+            price: order.price,
+            amount: order.amount,
+            maker_user_id: None,
+        })
+    }
+}
+
+fn write_records(records: &[Record], format: OutputFormat, mut w: impl Write) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            for record in records {
+                writeln!(w, "{}", record)?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut w, records)?;
+            writeln!(w)?;
+        }
+        OutputFormat::Csv => {
+            let mut w = csv::Writer::from_writer(&mut w);
+            for record in records {
+                w.serialize(record)?;
+            }
+            w.flush()?;
+        }
+    }
+    w.flush()?;
+    Ok(())
 }
 
 fn init_logging(verbose: bool) {
@@ -38,13 +321,734 @@ fn init_logging(verbose: bool) {
         .init()
 }
 
-fn main() -> Fallible<()> {
+const HELP: &str = "\
+Commands:
+  buy|sell <size>[@<price>] [user=<id>] [symbol=<symbol>] [limit|market|ioc|fok|post]
+  cancel <order id> [symbol=<symbol>]
+  book [symbol=<symbol>]
+  depth [<levels>] [symbol=<symbol>]
+  snapshot <path> [symbol=<symbol>]
+  reload
+  help
+  quit";
+
+/// A command of the interactive mode.
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Submit {
+        symbol: String,
+        side: Side,
+        price: Option<u64>,
+        amount: u64,
+        user_id: u64,
+        bid_type: BidProcessingType,
+    },
+    Cancel {
+        symbol: String,
+        order_id: OrderId,
+    },
+    Book {
+        symbol: String,
+        levels: usize,
+    },
+    Snapshot {
+        symbol: String,
+        path: PathBuf,
+    },
+    Reload,
+    Help,
+    Quit,
+}
+
+fn parse_number<T: FromStr>(what: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {} {:?}", what, value))
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or("help");
+        let mut symbol = String::new();
+        let mut user_id = 0;
+        let mut bid_type = BidProcessingType::Limit;
+        let mut positional = Vec::new();
+        for word in words {
+            match word {
+                "limit" => bid_type = BidProcessingType::Limit,
+                "market" => bid_type = BidProcessingType::Market,
+                "ioc" => bid_type = BidProcessingType::ImmediateOrCancel,
+                "fok" => bid_type = BidProcessingType::FillOrKill,
+                "post" => bid_type = BidProcessingType::PostOnly,
+                _ => {
+                    if let Some(value) = word.strip_prefix("user=") {
+                        user_id = parse_number("user id", value)?;
+                    } else if let Some(value) = word.strip_prefix("symbol=") {
+                        symbol = value.to_string();
+                    } else {
+                        positional.push(word);
+                    }
+                }
+            }
+        }
+        let command = match (command, &positional[..]) {
+            ("buy", [order]) | ("sell", [order]) => {
+                let (amount, price) = match order.split_once('@') {
+                    Some((amount, price)) => (amount, Some(parse_number("price", price)?)),
+                    None => (*order, None),
+                };
+                if price.is_none() && bid_type != BidProcessingType::Market {
+                    return Err(format!("Price is required for {:?} bids", bid_type));
+                }
+                let side = if command == "buy" {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                };
+                Command::Submit {
+                    symbol,
+                    side,
+                    price,
+                    amount: parse_number("size", amount)?,
+                    user_id,
+                    bid_type,
+                }
+            }
+            ("cancel", [order_id]) => Command::Cancel {
+                symbol,
+                order_id: OrderId(parse_number("order id", order_id)?),
+            },
+            ("book", []) => Command::Book {
+                symbol,
+                levels: usize::MAX,
+            },
+            ("depth", []) => Command::Book { symbol, levels: 5 },
+            ("depth", [levels]) => Command::Book {
+                symbol,
+                levels: parse_number("number of levels", levels)?,
+            },
+            ("snapshot", [path]) => Command::Snapshot {
+                symbol,
+                path: PathBuf::from(path),
+            },
+            ("reload", []) => Command::Reload,
+            ("help", []) => Command::Help,
+            ("quit", []) | ("exit", []) => Command::Quit,
+            _ => return Err(format!("Unknown command {:?}, try `help`", s.trim())),
+        };
+        Ok(command)
+    }
+}
+
+/// Prints the price levels of a book, the selling ones on top.
+fn print_book(engine: &MatchingEngine, symbol: &str, levels: usize) {
+    let order_book = match engine.book(symbol) {
+        Some(order_book) => order_book,
+        None => {
+            println!("The book is empty");
+            return;
+        }
+    };
+    let depth = order_book.depth(levels);
+    for (price, amount, count) in depth.selling.iter().rev() {
+        println!("  SELL {:>10} x {:<10} ({} bids)", price, amount, count);
+    }
+    match order_book.spread() {
+        Some(spread) => println!("  ---- spread {}", spread),
+        None => println!("  ----"),
+    }
+    for (price, amount, count) in &depth.buying {
+        println!("  BUY  {:>10} x {:<10} ({} bids)", price, amount, count);
+    }
+    if let Some(price) = order_book.last_trade_price() {
+        println!("Last trade price: {}", price);
+    }
+    println!("Checksum: {}", depth.checksum());
+}
+
+/// Writes the state of a book, the trade statistics included, to a file.
+///
+/// The snapshot is written to a temporary file next to the target first, which then replaces the
+/// target, so the file always holds a complete snapshot.
+#[cfg(feature = "serde")]
+fn save_snapshot(engine: &MatchingEngine, symbol: &str, path: &Path) -> Result<()> {
+    let order_book = engine
+        .book(symbol)
+        .with_context(|| format!("There is no book {:?}", symbol))?;
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut tmp = BufWriter::new(
+        File::create(&tmp_path).with_context(|| format!("Can't create {:?}", tmp_path))?,
+    );
+    order_book
+        .snapshot(&mut tmp)
+        .with_context(|| format!("Can't write {:?}", tmp_path))?;
+    tmp.into_inner()
+        .map_err(io::IntoInnerError::into_error)
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("Can't write {:?}", tmp_path))?;
+    std::fs::rename(&tmp_path, path).with_context(|| format!("Can't replace {:?}", path))
+}
+
+#[cfg(not(feature = "serde"))]
+fn save_snapshot(_engine: &MatchingEngine, _symbol: &str, _path: &Path) -> Result<()> {
+    bail!("Snapshots require the `serde` feature")
+}
+
+/// Executes a command, printing its outcome.
+fn execute(engine: &mut MatchingEngine, command: Command) {
+    match command {
+        Command::Submit {
+            symbol,
+            side,
+            price,
+            amount,
+            user_id,
+            bid_type,
+        } => {
+            let price = price.unwrap_or_default();
+            let result = match side {
+                Side::Sell => {
+                    let selling_bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+                    engine.process_selling(&symbol, selling_bid, bid_type)
+                }
+                Side::Buy => {
+                    let buying_bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+                    engine.process_buying(&symbol, buying_bid, bid_type)
+                }
+            };
+            match result {
+                Ok(result) => {
+                    println!("Order {}", result.order_id.0);
+                    for trade in &result.trades {
+                        let (verb, direction) = trade.taker_side.deal_verb_direction();
+                        println!(
+                            "  User {} {} {} items {} user {} for price {}",
+                            trade.taker_user_id,
+                            verb,
+                            trade.amount,
+                            direction,
+                            trade.maker_user_id,
+                            trade.price
+                        );
+                    }
+                }
+                Err(rejection) => println!("Rejected: {}", rejection),
+            }
+        }
+        Command::Cancel { symbol, order_id } => match engine.book_mut(&symbol).cancel(order_id) {
+            Cancellation::Cancelled(order) | Cancellation::PartiallyFilled(order) => {
+                println!("Cancelled {} items", order.amount)
+            }
+            Cancellation::AlreadyGone => println!("Order {} is already gone", order_id.0),
+            Cancellation::Unknown => println!("Order {} is unknown", order_id.0),
+        },
+        Command::Book { symbol, levels } => print_book(engine, &symbol, levels),
+        Command::Snapshot { symbol, path } => match save_snapshot(engine, &symbol, &path) {
+            Ok(()) => println!("The book is saved to {:?}", path),
+            Err(e) => println!("{:#}", e),
+        },
+        Command::Help => println!("{}", HELP),
+        Command::Reload | Command::Quit => {}
+    }
+}
+
+/// How often the interactive mode checks for a shutdown while waiting for a command.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// Reads commands from the standard input until it's exhausted, the `quit` command or the
+/// `shutdown` flag is raised.
+///
+/// The input is read in a thread of its own, so a shutdown doesn't wait for the next line.
+fn run_interactive(
+    engine: &mut MatchingEngine,
+    args: &Config,
+    shutdown: &AtomicBool,
+) -> Result<()> {
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    run_commands(engine, args, &lines, shutdown)
+}
+
+/// Executes commands from the `lines` until they are exhausted, the `quit` command or the
+/// `shutdown` flag is raised. The flag is only checked between the commands.
+///
+/// The `reload` command loads the configuration file again, see
+/// [`MatchingEngine::reload_config`].
+fn run_commands(
+    engine: &mut MatchingEngine,
+    args: &Config,
+    lines: &Receiver<io::Result<String>>,
+    shutdown: &AtomicBool,
+) -> Result<()> {
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let line = loop {
+            if shutdown.load(Ordering::Relaxed) {
+                println!();
+                return Ok(());
+            }
+            match lines.recv_timeout(SHUTDOWN_POLL) {
+                Ok(line) => break line?,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        engine.advance_time(SystemClock.now());
+        match line.parse() {
+            Ok(Command::Quit) => break,
+            Ok(Command::Reload) => match load_config(args) {
+                Ok(config) => {
+                    engine.reload_config(config);
+                    println!("The configuration is reloaded");
+                }
+                Err(e) => println!("{:#}", e),
+            },
+            Ok(command) => execute(engine, command),
+            Err(e) => println!("{}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Processes the bids from a file, choosing the format by its extension.
+fn process_file(
+    engine: &mut MatchingEngine,
+    bids_path: &Path,
+    options: ProcessingOptions,
+) -> Result<()> {
+    let input = File::open(bids_path).with_context(|| format!("Can't read {:?}", bids_path))?;
+    let file_name = bids_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let summary = match bids_path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") if file_name.contains("_message_") => {
+            let symbol = file_name.split('_').next().unwrap_or_default();
+            process_lobster(engine, symbol, input)
+        }
+        Some("jsonl") | Some("ndjson") => process_json_lines(engine, input, options),
+        Some("csv") => process_csv(engine, input, options),
+        Some("bin") => process_binary_reader(engine, input),
+        _ => process_reader(engine, input, options),
+    }
+    .with_context(|| format!("Can't process {:?}", bids_path))?;
+    for (index, rejection) in &summary.rejected {
+        warn!("Entry {} has been rejected: {}", index, rejection);
+    }
+    for (index, reason) in &summary.invalid {
+        warn!("Entry {} has been skipped: {}", index, reason);
+    }
+    info!("{:?}: {}", bids_path, summary);
+    Ok(())
+}
+
+/// Writes random orders to a file, choosing the format by its extension.
+fn generate(args: &GenerateArgs) -> Result<()> {
+    if args.min_price > args.max_price {
+        bail!("The minimal price exceeds the maximal one");
+    }
+    if !(0.0..=1.0).contains(&args.buy_ratio) {
+        bail!("The share of buying orders must be between 0 and 1");
+    }
+    if args.users == 0 {
+        bail!("There must be at least one user");
+    }
+    let config = GeneratorConfig {
+        prices: PriceDistribution::Uniform {
+            min: args.min_price,
+            max: args.max_price,
+        },
+        buy_ratio: args.buy_ratio,
+        users: args.users,
+        ..GeneratorConfig::default()
+    };
+    let orders = OrderGenerator::new(args.seed, config).take(args.orders);
+    let out_path = &args.out_path;
+    let out = BufWriter::new(
+        File::create(out_path).with_context(|| format!("Can't create {:?}", out_path))?,
+    );
+    match out_path.extension().and_then(|ext| ext.to_str()) {
+        Some("jsonl") | Some("ndjson") => write_orders_json_lines(&args.symbol, orders, out)
+            .with_context(|| format!("Can't write {:?}", out_path))?,
+        Some("csv") => write_orders_csv(&args.symbol, orders, out)
+            .with_context(|| format!("Can't write {:?}", out_path))?,
+        _ => write_orders(&args.symbol, orders, out)
+            .with_context(|| format!("Can't write {:?}", out_path))?,
+    }
+    info!("Wrote {} orders to {:?}", args.orders, out_path);
+    Ok(())
+}
+
+/// Loads the engine configuration from the file given in the arguments, if any, with the
+/// overrides of the arguments.
+fn load_config(args: &Config) -> Result<EngineConfig> {
+    let mut config = match &args.config_path {
+        Some(config_path) => {
+            let config_file =
+                File::open(config_path).with_context(|| format!("Can't read {:?}", config_path))?;
+            EngineConfig::from_reader(config_file)
+                .with_context(|| format!("Can't load {:?}", config_path))?
+        }
+        None => EngineConfig::default(),
+    };
+    if args.credit_limit.is_some() {
+        config.risk_limits.credit_limit = args.credit_limit;
+    }
+    if args.no_credit_check {
+        config.risk_limits.credit_limit = None;
+    }
+    Ok(config)
+}
+
+fn main() -> Result<()> {
     let args = Config::from_args();
     init_logging(args.verbose);
-    let input = File::open(&args.bids_path)
-        .with_context(|e| format!("Can't read {:?}: {}", args.bids_path, e))?;
-    let mut order_book = OrderBook::empty();
-    process_reader(&mut order_book, input)
-        .with_context(|e| format!("Can't process {:?}: {}", args.bids_path, e))?;
+    if let Some(Subcommand::Generate(generate_args)) = &args.subcommand {
+        return generate(generate_args);
+    }
+    let mut engine = MatchingEngine::with_config(load_config(&args)?);
+    engine.set_clock(SystemClock);
+    engine.add_listener(LoggingListener);
+    #[cfg(feature = "websocket")]
+    {
+        if let Some(addr) = &args.websocket_addr {
+            use simple_stock_matcher_experiment::WebSocketPublisher;
+            let publisher = match &args.websocket_tokens_path {
+                Some(tokens_path) => {
+                    let tokens_file = File::open(tokens_path)
+                        .with_context(|| format!("Can't read {:?}", tokens_path))?;
+                    let tokens = serde_yaml::from_reader(tokens_file)
+                        .with_context(|| format!("Can't load {:?}", tokens_path))?;
+                    WebSocketPublisher::bind_with_tokens(addr.as_str(), tokens)
+                }
+                None => WebSocketPublisher::bind(addr.as_str()),
+            }
+            .with_context(|| format!("Can't listen on {}", addr))?;
+            log::info!(
+                "Waiting for a subscriber on ws://{}",
+                publisher.local_addr()
+            );
+            let timeout = std::time::Duration::from_secs(args.websocket_wait);
+            if !publisher.wait_for_subscriber(timeout) {
+                warn!("No subscriber has connected, starting the processing anyway");
+            }
+            engine.add_listener_factory(move |symbol| publisher.listener(symbol));
+        }
+    }
+    let recorder = Recorder::default();
+    if args.output_path.is_some() {
+        engine.add_listener(recorder.clone());
+    }
+    if let Some(drop_copy_path) = &args.drop_copy_path {
+        let drop_copy = DropCopy::new(BufWriter::new(
+            File::create(drop_copy_path)
+                .with_context(|| format!("Can't create {:?}", drop_copy_path))?,
+        ));
+        engine.add_listener_factory(move |symbol| drop_copy.listener(symbol));
+    }
+    if let Some(every) = args.shadow_check {
+        engine.enable_shadow_books(every);
+    }
+    engine.advance_time(SystemClock.now());
+    if let Some(bids_path) = &args.bids_path {
+        let options = ProcessingOptions {
+            skip_invalid: args.skip_invalid,
+        };
+        process_file(&mut engine, bids_path, options)?;
+    }
+    if args.interactive {
+        // SIGINT and SIGTERM end the session as the `quit` command does, so the outputs are
+        // written as usual.
+        let shutdown = Arc::new(AtomicBool::new(false));
+        for &signal in &[SIGINT, SIGTERM] {
+            signal_hook::flag::register(signal, Arc::clone(&shutdown))
+                .context("Can't handle the signals")?;
+        }
+        run_interactive(&mut engine, &args, &shutdown)?;
+        if shutdown.load(Ordering::Relaxed) {
+            info!("Shutting down");
+            for symbol in engine.symbols() {
+                let stats = engine
+                    .book(symbol)
+                    .expect("Symbols are taken from the engine")
+                    .stats();
+                info!(
+                    "{:?}: {} trades, volume {}, last price {:?}",
+                    symbol,
+                    stats.trades(),
+                    stats.volume(),
+                    stats.last_price()
+                );
+            }
+        }
+    }
+    engine.advance_time(SystemClock.now());
+    if args.shadow_check.is_some() && !engine.check_shadow_books() {
+        warn!("Some books have diverged from their feeds");
+    }
+    if let Some(output_path) = &args.output_path {
+        let output =
+            File::create(output_path).with_context(|| format!("Can't create {:?}", output_path))?;
+        write_records(
+            &recorder.0.borrow(),
+            args.output_format,
+            BufWriter::new(output),
+        )
+        .with_context(|| format!("Can't write {:?}", output_path))?;
+    }
+    if let Some(dump_path) = &args.dump_path {
+        let dump = BufWriter::new(
+            File::create(dump_path).with_context(|| format!("Can't create {:?}", dump_path))?,
+        );
+        match dump_path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") | Some("ndjson") => dump_book_json_lines(&engine, dump)
+                .with_context(|| format!("Can't write {:?}", dump_path))?,
+            Some("csv") => dump_book_csv(&engine, dump)
+                .with_context(|| format!("Can't write {:?}", dump_path))?,
+            _ => {
+                dump_book(&engine, dump).with_context(|| format!("Can't write {:?}", dump_path))?
+            }
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        assert_eq!(
+            "buy 10@100 user=3 symbol=ABC ioc".parse(),
+            Ok(Command::Submit {
+                symbol: "ABC".to_string(),
+                side: Side::Buy,
+                price: Some(100),
+                amount: 10,
+                user_id: 3,
+                bid_type: BidProcessingType::ImmediateOrCancel,
+            })
+        );
+        assert_eq!(
+            "  sell 5 market ".parse(),
+            Ok(Command::Submit {
+                symbol: String::new(),
+                side: Side::Sell,
+                price: None,
+                amount: 5,
+                user_id: 0,
+                bid_type: BidProcessingType::Market,
+            })
+        );
+        assert_eq!(
+            "cancel 7 symbol=ABC".parse(),
+            Ok(Command::Cancel {
+                symbol: "ABC".to_string(),
+                order_id: OrderId(7),
+            })
+        );
+        assert_eq!(
+            "book".parse(),
+            Ok(Command::Book {
+                symbol: String::new(),
+                levels: usize::MAX,
+            })
+        );
+        assert_eq!(
+            "depth".parse(),
+            Ok(Command::Book {
+                symbol: String::new(),
+                levels: 5,
+            })
+        );
+        assert_eq!(
+            "depth 2 symbol=XYZ".parse(),
+            Ok(Command::Book {
+                symbol: "XYZ".to_string(),
+                levels: 2,
+            })
+        );
+        assert_eq!(
+            "snapshot /tmp/abc.yaml symbol=ABC".parse(),
+            Ok(Command::Snapshot {
+                symbol: "ABC".to_string(),
+                path: PathBuf::from("/tmp/abc.yaml"),
+            })
+        );
+        assert_eq!("reload".parse(), Ok(Command::Reload));
+        assert_eq!("".parse(), Ok(Command::Help));
+        assert_eq!("help".parse(), Ok(Command::Help));
+        assert_eq!("exit".parse(), Ok(Command::Quit));
+
+        assert_eq!(
+            "buy 10".parse::<Command>(),
+            Err("Price is required for Limit bids".to_string())
+        );
+        assert_eq!(
+            "sell 10@abc".parse::<Command>(),
+            Err("Invalid price \"abc\"".to_string())
+        );
+        assert_eq!(
+            "buy 10@100 user=x".parse::<Command>(),
+            Err("Invalid user id \"x\"".to_string())
+        );
+        assert_eq!(
+            "cancel".parse::<Command>(),
+            Err("Unknown command \"cancel\", try `help`".to_string())
+        );
+        assert_eq!(
+            "depth 1 2".parse::<Command>(),
+            Err("Unknown command \"depth 1 2\", try `help`".to_string())
+        );
+        assert_eq!(
+            "snapshot".parse::<Command>(),
+            Err("Unknown command \"snapshot\", try `help`".to_string())
+        );
+        assert_eq!(
+            "trade 5".parse::<Command>(),
+            Err("Unknown command \"trade 5\", try `help`".to_string())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_save_snapshot() {
+        use simple_stock_matcher_experiment::OrderBook;
+
+        let mut engine = MatchingEngine::new();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        engine
+            .process_buying("ABC", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("snapshot-{}.yaml", std::process::id()));
+        std::fs::write(&path, "garbage").unwrap();
+        save_snapshot(&engine, "ABC", &path).unwrap();
+        let restored = OrderBook::restore(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let order_book = engine.book("ABC").unwrap();
+        assert_eq!(restored.depth(10), order_book.depth(10));
+        assert_eq!(restored.stats(), order_book.stats());
+        assert_eq!(restored.stats().volume(), 3);
+
+        let error = save_snapshot(&engine, "XYZ", &path).unwrap_err();
+        assert_eq!(error.to_string(), "There is no book \"XYZ\"");
+        let missing = std::env::temp_dir()
+            .join("no-such-directory")
+            .join("snapshot.yaml");
+        assert!(save_snapshot(&engine, "ABC", &missing).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_run_commands() {
+        let args = Config::from_iter(&["matcher", "--interactive"]);
+        let mut engine = MatchingEngine::new();
+        let shutdown = AtomicBool::new(false);
+        let (sender, lines) = mpsc::channel();
+        for line in &["sell 5@100 user=1", "", "buy 3@100 user=2"] {
+            sender.send(Ok(line.to_string())).unwrap();
+        }
+        drop(sender);
+        run_commands(&mut engine, &args, &lines, &shutdown).unwrap();
+        assert_eq!(engine.book("").unwrap().stats().volume(), 3);
+
+        // Commands after `quit` are ignored.
+        let (sender, lines) = mpsc::channel();
+        for line in &["quit", "buy 2@100 user=2"] {
+            sender.send(Ok(line.to_string())).unwrap();
+        }
+        run_commands(&mut engine, &args, &lines, &shutdown).unwrap();
+        assert_eq!(engine.book("").unwrap().stats().volume(), 3);
+
+        // A shutdown doesn't wait for the input, and the queued commands are dropped.
+        shutdown.store(true, Ordering::Relaxed);
+        run_commands(&mut engine, &args, &lines, &shutdown).unwrap();
+        assert_eq!(engine.book("").unwrap().stats().volume(), 3);
+
+        let (sender, lines) = mpsc::channel();
+        sender.send(Err(io::ErrorKind::InvalidData.into())).unwrap();
+        shutdown.store(false, Ordering::Relaxed);
+        assert!(run_commands(&mut engine, &args, &lines, &shutdown).is_err());
+    }
+
+    #[test]
+    fn test_write_records() {
+        let records = [
+            Record {
+                event: "trade",
+                side: Side::Buy,
+                user_id: 2,
+                price: 100,
+                amount: 3,
+                maker_user_id: Some(1),
+            },
+            Record {
+                event: "drop",
+                side: Side::Sell,
+                user_id: 4,
+                price: 0,
+                amount: 5,
+                maker_user_id: None,
+            },
+        ];
+        let write = |format| {
+            let mut output = Vec::new();
+            write_records(&records, format, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(
+            write(OutputFormat::Text),
+            "User 2 bought 3 items from user 1 for price 100\n\
+             Dropped a selling bid from user 4 (price: 0, size: 5)\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Json),
+            r#"[
+  {
+    "event": "trade",
+    "side": "buy",
+    "user_id": 2,
+    "price": 100,
+    "amount": 3,
+    "maker_user_id": 1
+  },
+  {
+    "event": "drop",
+    "side": "sell",
+    "user_id": 4,
+    "price": 0,
+    "amount": 5,
+    "maker_user_id": null
+  }
+]
+"#
+        );
+        assert_eq!(
+            write(OutputFormat::Csv),
+            "event,side,user_id,price,amount,maker_user_id\n\
+             trade,buy,2,100,3,1\n\
+             drop,sell,4,0,5,\n"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! Market-maker protection: pulling the quotes of a user that is being filled too fast.
+
+use serde_derive::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+/// Limits of the executions of the quotes of designated users (market makers) within a rolling
+/// window.
+///
+/// Once a user's resting bids are executed more than `max_fills` times, or for more than
+/// `max_quantity` items, within the last `window` seconds, all the user's bids resting in the
+/// book are cancelled and the listeners are notified with [`on_protection_triggered`]. Only the
+/// executions of the user's resting bids count, the ones of the user's incoming bids don't.
+///
+/// [`on_protection_triggered`]: crate::OrderBookListener::on_protection_triggered
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct MakerProtection {
+    /// The protected users.
+    pub users: Vec<u64>,
+    /// Length of the window, in seconds.
+    pub window: u64,
+    /// Number of executions a user's bids may get within the window.
+    pub max_fills: Option<usize>,
+    /// Number of items a user's bids may get executed for within the window.
+    pub max_quantity: Option<u64>,
+}
+
+/// Recent executions of the protected users, see [`MakerProtection`].
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub(crate) struct MakerGuard {
+    limit: MakerProtection,
+    /// Times and amounts of the executions within the window, by user.
+    fills: HashMap<u64, VecDeque<(u64, u64)>>,
+}
+
+impl MakerGuard {
+    pub fn new(limit: MakerProtection) -> Self {
+        MakerGuard {
+            limit,
+            fills: HashMap::new(),
+        }
+    }
+
+    /// Records an execution of a resting bid of the user for the `amount` at the time `now`.
+    ///
+    /// Returns whether the user is over the limits, in which case the user's window starts over.
+    pub fn record(&mut self, user_id: u64, amount: u64, now: u64) -> bool {
+        if !self.limit.users.contains(&user_id) {
+            return false;
+        }
+        let window = self.limit.window;
+        let fills = self.fills.entry(user_id).or_default();
+        fills.push_back((now, amount));
+        while fills
+            .front()
+            .is_some_and(|&(time, _)| time.saturating_add(window) <= now)
+        {
+            fills.pop_front();
+        }
+        let quantity: u64 = fills.iter().map(|&(_, amount)| amount).sum();
+        let triggered = self.limit.max_fills.is_some_and(|max| fills.len() > max)
+            || self.limit.max_quantity.is_some_and(|max| quantity > max);
+        if triggered {
+            fills.clear();
+        }
+        triggered
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_guard() {
+        let mut guard = MakerGuard::new(MakerProtection {
+            users: vec![1],
+            window: 10,
+            max_fills: Some(2),
+            max_quantity: Some(100),
+        });
+        assert!(!guard.record(2, 1000, 0));
+        assert!(!guard.record(1, 1, 0));
+        assert!(!guard.record(1, 1, 5));
+        // The first fill is out of the window.
+        assert!(!guard.record(1, 1, 10));
+        assert!(guard.record(1, 1, 11));
+        // The window starts over.
+        assert!(!guard.record(1, 1, 11));
+        assert!(guard.record(1, 100, 12));
+    }
+}
@@ -1,14 +1,271 @@
 //! An order book.
 use crate::{
-    bids::{Bid, BidProcessingType, BuyingBid, SellingBid},
+    accounts::Accounts,
+    auction::{clearing_price, pair, Equilibrium},
+    bids::{Bid, BidProcessingType, BuyingBid, GenericBid, SellingBid, Side},
+    clock::Clock,
+    command::{Command, Event, EventLog, Outcome},
+    conditional::{Condition, PendingBid},
+    config::Config,
+    feed::FeedEvent,
+    key::PriceKey,
+    listener::{OrderBookListener, OrderInfo},
+    mmp::MakerGuard,
     pool::Pool,
+    session::{Phase, SessionSchedule},
+    simulation::{InsufficientLiquidity, Quote, Simulation},
+    stats::TradeStats,
+    stops::{StopBids, Trigger, TriggeredBid},
+    tape::TradeTape,
+    throttle::{RateLimiter, ThrottleMode},
+    trade::Trade,
 };
+use log::{debug, info};
+use serde_derive::Deserialize;
+#[cfg(feature = "serde")]
+use std::io::{Read, Write};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt, mem,
+    rc::Rc,
+};
+
+/// Identifier of a bid submitted to an order book.
+///
+/// Ids are assigned in the submission order, starting from zero.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrderId(pub usize);
+
+/// Result of submitting a bid to an order book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessingResult {
+    /// Id assigned to the bid.
+    pub order_id: OrderId,
+    /// Executed trades, including the ones of conditional bids released by the processing.
+    pub trades: Vec<Trade>,
+}
+
+/// Outcome of a cancellation request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cancellation {
+    /// The bid has been removed before any of its items were traded.
+    Cancelled(OrderInfo),
+    /// The bid has been partially executed, the remaining part (reported here) has been removed.
+    PartiallyFilled(OrderInfo),
+    /// The bid is not in the book anymore: it has been fully executed, dropped or cancelled.
+    AlreadyGone,
+    /// No bid with such an id has ever been submitted to the book.
+    Unknown,
+}
+
+/// Outcome of an amendment request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Amendment {
+    /// The bid has been updated in place and kept its id and priority.
+    Amended(OrderInfo),
+    /// The bid has been cancelled and submitted again with the new price and size, so it has lost
+    /// its priority and got a new id.
+    Replaced(ProcessingResult),
+    /// The size has been amended to zero, so the bid has been cancelled.
+    Cancelled(Cancellation),
+    /// The bid is not in the book anymore: it has been fully executed, dropped or cancelled.
+    AlreadyGone,
+    /// No bid with such an id has ever been submitted to the book.
+    Unknown,
+}
+
+/// An order submitted as a part of a batch, see [`OrderBook::process_batch`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IncomingOrder {
+    /// See [`OrderBook::process_selling`].
+    Selling(Bid<SellingBid>, BidProcessingType),
+    /// See [`OrderBook::process_buying`].
+    Buying(Bid<BuyingBid>, BidProcessingType),
+}
+
+/// Result of processing a batch of orders.
+///
+/// The book itself never rejects orders: the ones that can be neither executed nor added to the
+/// book are reported to the listeners as dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchResult {
+    /// Ids assigned to the orders, in the order they were submitted.
+    pub order_ids: Vec<OrderId>,
+    /// Executed trades of all the orders, including the ones of conditional bids released by the
+    /// processing.
+    pub trades: Vec<Trade>,
+}
+
+/// Aggregated view of the best price levels of an order book.
+///
+/// Levels are `(price, displayed size, number of bids)` tuples, from the best level to the worst
+/// one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Depth {
+    /// Levels of the selling side, from the lowest price up.
+    pub selling: Vec<(u64, u64, usize)>,
+    /// Levels of the buying side, from the highest price down.
+    pub buying: Vec<(u64, u64, usize)>,
+}
+
+impl Depth {
+    /// A deterministic checksum of the levels, so the consumers of the market data can verify
+    /// their copies of the book without requesting snapshots.
+    ///
+    /// It's the CRC-32 of the prices and the displayed sizes of the levels, `price:size`, joined
+    /// with `:` in the order of the best buying level, the best selling one, the second best
+    /// buying one and so on. Bid counts are not included.
+    pub fn checksum(&self) -> u32 {
+        let mut text = String::new();
+        let mut selling = self.selling.iter();
+        let mut buying = self.buying.iter();
+        loop {
+            let (bid, ask) = (buying.next(), selling.next());
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            for &(price, amount, _count) in bid.into_iter().chain(ask) {
+                if !text.is_empty() {
+                    text.push(':');
+                }
+                text += &format!("{}:{}", price, amount);
+            }
+        }
+        crc32(text.as_bytes())
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of the data.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// State of a bid that is still in the book, see [`OrderBook::get`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderStatus {
+    /// Side of the bid.
+    pub side: Side,
+    /// Bid's price.
+    pub price: u64,
+    /// Amount of items left, including the hidden part of an iceberg bid.
+    pub remaining: u64,
+    /// Amount of items the bid had before it was (partially) executed.
+    pub original: u64,
+}
+
+impl OrderStatus {
+    fn new<BidKind: GenericBid>(bid: &Bid<BidKind>, original: u64) -> Self {
+        OrderStatus {
+            side: BidKind::side(),
+            price: bid.price,
+            remaining: bid.amount,
+            original,
+        }
+    }
+}
+
+/// What happens to bids submitted while trading is halted.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum HaltMode {
+    /// Bids are queued and processed in the submission order once trading is resumed.
+    #[default]
+    Queue,
+    /// Bids are dropped.
+    Reject,
+}
+
+/// A bid submitted while trading is halted.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy)]
+enum QueuedBid {
+    Selling(OrderId, Bid<SellingBid>, BidProcessingType),
+    Buying(OrderId, Bid<BuyingBid>, BidProcessingType),
+}
+
+impl QueuedBid {
+    fn order_id(&self) -> OrderId {
+        match self {
+            QueuedBid::Selling(order_id, ..) | QueuedBid::Buying(order_id, ..) => *order_id,
+        }
+    }
+
+    fn user_id(&self) -> u64 {
+        match self {
+            QueuedBid::Selling(_, bid, _) => bid.user_id,
+            QueuedBid::Buying(_, bid, _) => bid.user_id,
+        }
+    }
+
+    fn order_info(&self) -> OrderInfo {
+        match self {
+            QueuedBid::Selling(_, bid, _) => OrderInfo::from(bid),
+            QueuedBid::Buying(_, bid, _) => OrderInfo::from(bid),
+        }
+    }
+}
 
 /// Bids queues.
+///
+/// With the `serde` feature the book can be saved with [`OrderBook::snapshot`] and loaded back
+/// with [`OrderBook::restore`].
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
 #[derive(Default)]
 pub struct OrderBook {
     pub(crate) sellers: Pool<SellingBid>,
     pub(crate) buyers: Pool<BuyingBid>,
+    pub(crate) pending: Vec<PendingBid>,
+    pub(crate) selling_stops: StopBids<SellingBid>,
+    pub(crate) buying_stops: StopBids<BuyingBid>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    listeners: Vec<Box<dyn OrderBookListener>>,
+    next_order_id: usize,
+    last_trade_price: Option<u64>,
+    stats: TradeStats,
+    tape: TradeTape,
+    accounts: Option<Accounts>,
+    reference_price: Option<u64>,
+    price_band_bps: Option<u64>,
+    auction: bool,
+    session: Option<SessionSchedule>,
+    phase: Phase,
+    halted: bool,
+    halt_mode: HaltMode,
+    queued: Vec<QueuedBid>,
+    rate_limiter: Option<RateLimiter>,
+    throttled: Vec<QueuedBid>,
+    maker_guard: Option<MakerGuard>,
+    now: u64,
+    links: BTreeMap<OrderId, (u64, u64)>,
+    /// The linked bids, by user id and link.
+    linked: BTreeMap<u64, BTreeMap<u64, BTreeSet<OrderId>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    executed: Vec<OrderId>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: Option<Box<dyn Clock>>,
 }
 
 impl OrderBook {
@@ -17,17 +274,2517 @@ impl OrderBook {
         OrderBook::default()
     }
 
-    /// Processes a selling bid.
-    pub fn process_selling(&mut self, bid: Bid<SellingBid>, bid_type: BidProcessingType) {
-        if let Some(rest_of_the_bid) = self.buyers.process_bid(bid, bid_type) {
-            self.sellers.push(rest_of_the_bid);
+    /// Initializes an empty order book with the given configuration.
+    pub fn with_config(config: Config) -> Self {
+        let mut order_book = OrderBook::default();
+        order_book
+            .sellers
+            .set_user_groups(config.user_groups.clone());
+        order_book.buyers.set_user_groups(config.user_groups);
+        order_book
+            .sellers
+            .set_max_levels(config.max_levels_per_order);
+        order_book
+            .buyers
+            .set_max_levels(config.max_levels_per_order);
+        order_book
+            .sellers
+            .set_matching_policy(config.matching_policy);
+        order_book
+            .buyers
+            .set_matching_policy(config.matching_policy);
+        order_book
+            .sellers
+            .set_iceberg_refresh(config.iceberg_refresh);
+        order_book
+            .buyers
+            .set_iceberg_refresh(config.iceberg_refresh);
+        order_book.price_band_bps = config.price_band_bps;
+        order_book.halt_mode = config.halt_mode;
+        order_book.rate_limiter = config.rate_limit.map(RateLimiter::new);
+        order_book.maker_guard = config.maker_protection.map(MakerGuard::new);
+        if let Some(session) = config.session {
+            order_book.session = Some(session);
+            order_book.phase = session.phase_at(order_book.now);
+            order_book.auction = order_book.phase.is_auction();
+        }
+        if config.track_positions || config.risk_limits.credit_limit.is_some() {
+            order_book.accounts = Some(Accounts::new());
         }
+        order_book
+    }
+
+    /// Applies the limits of a new configuration to the book, keeping its bids: the price band,
+    /// the maximum number of levels per order and the halt mode.
+    ///
+    /// The rest of the settings only take effect in the books created with the configuration.
+    pub fn reload_config(&mut self, config: &Config) {
+        self.sellers.set_max_levels(config.max_levels_per_order);
+        self.buyers.set_max_levels(config.max_levels_per_order);
+        self.price_band_bps = config.price_band_bps;
+        self.halt_mode = config.halt_mode;
+    }
+
+    /// Writes the state of the book, except for the listeners, to a `yaml` writer.
+    #[cfg(feature = "serde")]
+    pub fn snapshot(&self, w: impl Write) -> Result<(), serde_yaml::Error> {
+        serde_yaml::to_writer(w, self)
+    }
+
+    /// Loads a book saved with [`OrderBook::snapshot`] from a `yaml` reader.
+    ///
+    /// Listeners are not saved, so they have to be registered again.
+    #[cfg(feature = "serde")]
+    pub fn restore(r: impl Read) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_reader(r)
+    }
+
+    /// Registers a listener that will be notified about trades and bids added to or dropped by the
+    /// book.
+    pub fn add_listener(&mut self, listener: impl OrderBookListener + 'static) {
+        self.add_boxed_listener(Box::new(listener));
+    }
+
+    pub(crate) fn add_boxed_listener(&mut self, listener: Box<dyn OrderBookListener>) {
+        self.listeners.push(listener);
+    }
+
+    /// Sets the clock of the book: before processing anything the book advances its time to the
+    /// clock's one, as if [`OrderBook::advance_time`] was called. Without a clock the time only
+    /// moves with [`OrderBook::advance_time`].
+    ///
+    /// Clocks are not saved in snapshots.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.set_boxed_clock(Box::new(clock));
+    }
+
+    pub(crate) fn set_boxed_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = Some(clock);
+    }
+
+    /// The book's time, in seconds since the Unix epoch.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Processes a selling bid.
+    pub fn process_selling(
+        &mut self,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.tick();
+        let order_id = self.next_order_id();
+        self.link(order_id, &bid);
+        let mut trades = if self.throttle(QueuedBid::Selling(order_id, bid, bid_type)) {
+            self.match_selling(order_id, bid, bid_type)
+        } else {
+            Vec::new()
+        };
+        self.release_triggered(&mut trades);
+        ProcessingResult { order_id, trades }
     }
 
     /// Processes a buying bid.
-    pub fn process_buying(&mut self, bid: Bid<BuyingBid>, bid_type: BidProcessingType) {
-        if let Some(rest_of_the_bid) = self.sellers.process_bid(bid, bid_type) {
-            self.buyers.push(rest_of_the_bid);
+    pub fn process_buying(
+        &mut self,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.tick();
+        let order_id = self.next_order_id();
+        self.link(order_id, &bid);
+        let mut trades = if self.throttle(QueuedBid::Buying(order_id, bid, bid_type)) {
+            self.match_buying(order_id, bid, bid_type)
+        } else {
+            Vec::new()
+        };
+        self.release_triggered(&mut trades);
+        ProcessingResult { order_id, trades }
+    }
+
+    /// Processes a sequence of orders, one after another.
+    ///
+    /// The outcome is the same as of processing the orders one by one, but the trades are
+    /// collected into a single vector, which is cheaper for large batches, e.g. when replaying
+    /// recorded orders.
+    pub fn process_batch(
+        &mut self,
+        orders: impl IntoIterator<Item = IncomingOrder>,
+    ) -> BatchResult {
+        self.tick();
+        let orders = orders.into_iter();
+        let mut result = BatchResult {
+            order_ids: Vec::with_capacity(orders.size_hint().0),
+            trades: Vec::new(),
+        };
+        for order in orders {
+            let order_id = self.next_order_id();
+            match &order {
+                IncomingOrder::Selling(bid, _) => self.link(order_id, bid),
+                IncomingOrder::Buying(bid, _) => self.link(order_id, bid),
+            }
+            let first = result.trades.len();
+            let trades = match order {
+                IncomingOrder::Selling(bid, bid_type)
+                    if self.throttle(QueuedBid::Selling(order_id, bid, bid_type)) =>
+                {
+                    self.match_selling(order_id, bid, bid_type)
+                }
+                IncomingOrder::Buying(bid, bid_type)
+                    if self.throttle(QueuedBid::Buying(order_id, bid, bid_type)) =>
+                {
+                    self.match_buying(order_id, bid, bid_type)
+                }
+                _ => Vec::new(),
+            };
+            result.trades.extend(trades);
+            self.release_triggered_since(&mut result.trades, first);
+            result.order_ids.push(order_id);
+        }
+        debug!(
+            "Processed a batch of {} orders, {} trades",
+            result.order_ids.len(),
+            result.trades.len()
+        );
+        result
+    }
+
+    /// Applies a command to the book and returns the resulting events, in the order they have
+    /// been reported to the listeners.
+    ///
+    /// A single entry point that is easy to drive from servers, replayers and tests. Submitted
+    /// bids (including the replacements of amended ones) are reported with [`Event::Accepted`],
+    /// cancellations and amendments of bids that are not in the book with [`Event::NotFound`].
+    pub fn apply(&mut self, command: Command) -> Vec<Event> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        self.listeners.push(Box::new(EventLog(events.clone())));
+        let outcome = command.apply(self);
+        self.listeners.pop();
+        let mut events = mem::take(&mut *events.borrow_mut());
+        match (command, outcome) {
+            (_, Outcome::Processed(result)) => events.insert(0, Event::Accepted(result.order_id)),
+            (_, Outcome::Amended(Amendment::Replaced(result))) => {
+                // A replaced bid is cancelled before its replacement is submitted.
+                let position = events
+                    .iter()
+                    .position(|event| matches!(event, Event::OrderCancelled(_)))
+                    .map_or(0, |position| position + 1);
+                events.insert(position, Event::Accepted(result.order_id));
+            }
+            (
+                Command::Cancel(order_id),
+                Outcome::Cancelled(Cancellation::AlreadyGone | Cancellation::Unknown),
+            )
+            | (
+                Command::Amend { order_id, .. },
+                Outcome::Amended(Amendment::AlreadyGone | Amendment::Unknown),
+            ) => events.push(Event::NotFound(order_id)),
+            _ => {}
+        }
+        events
+    }
+
+    /// Starts a call auction: from now on incoming bids are added to the book without being
+    /// matched, until the book is uncrossed.
+    ///
+    /// `Limit` bids are added as they are, `Market` bids are added with the most aggressive price,
+    /// bids of other types are dropped.
+    pub fn start_auction(&mut self) {
+        self.auction = true;
+    }
+
+    /// Checks whether a call auction is in progress.
+    pub fn is_in_auction(&self) -> bool {
+        self.auction
+    }
+
+    /// Ends the call auction (if any) and matches the crossing bids at a single clearing price.
+    ///
+    /// The clearing price is the one that maximizes the executed volume, then minimizes the
+    /// imbalance between the crossing selling and buying amounts, then is the closest to the last
+    /// trade price. User groups and level limits don't apply to the auction.
+    pub fn uncross(&mut self) -> Vec<Trade> {
+        self.tick();
+        self.uncross_now()
+    }
+
+    fn uncross_now(&mut self) -> Vec<Trade> {
+        self.auction = false;
+        let mut trades = Vec::new();
+        if let Some(Equilibrium { price, volume, .. }) = self.indicative_price() {
+            let sold = self.sellers.take_best(volume, &mut self.listeners);
+            let bought = self.buyers.take_best(volume, &mut self.listeners);
+            self.executed
+                .extend(sold.iter().map(|&(id, _)| OrderId(id)));
+            self.executed
+                .extend(bought.iter().map(|&(id, _)| OrderId(id)));
+            trades = pair(price, sold, bought);
+            trades
+                .iter()
+                .for_each(|trade| self.listeners.on_trade(trade));
+        }
+        self.release_triggered(&mut trades);
+        trades
+    }
+
+    /// Phase of the trading session of the book, see [`SessionSchedule`]. Books without a
+    /// schedule always trade continuously, call auctions are started and uncrossed manually.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Halts trading: from now on incoming bids are not matched, but either queued until trading
+    /// is resumed or dropped, depending on the configured [`HaltMode`].
+    ///
+    /// Resting bids stay in the book and can still be cancelled or amended.
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    /// Checks whether trading is halted.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Resumes trading and processes the bids queued during the halt, in the submission order.
+    ///
+    /// Returns the trades of the queued bids, including the ones of conditional and stop bids
+    /// released by them.
+    pub fn resume(&mut self) -> Vec<Trade> {
+        self.tick();
+        self.halted = false;
+        let mut trades = Vec::new();
+        for queued in std::mem::take(&mut self.queued) {
+            match queued {
+                QueuedBid::Selling(order_id, bid, bid_type) => {
+                    trades.extend(self.match_selling(order_id, bid, bid_type))
+                }
+                QueuedBid::Buying(order_id, bid, bid_type) => {
+                    trades.extend(self.match_buying(order_id, bid, bid_type))
+                }
+            }
+        }
+        self.release_triggered(&mut trades);
+        trades
+    }
+
+    /// Selling bids resting in the book along with their order ids, from the highest priority to
+    /// the lowest one.
+    ///
+    /// Only the displayed parts of iceberg bids are reported.
+    pub fn sell_orders(&self) -> impl Iterator<Item = (OrderId, &Bid<SellingBid>)> {
+        self.sellers.orders()
+    }
+
+    /// Buying bids resting in the book along with their order ids, from the highest priority to
+    /// the lowest one.
+    ///
+    /// Only the displayed parts of iceberg bids are reported.
+    pub fn buy_orders(&self) -> impl Iterator<Item = (OrderId, &Bid<BuyingBid>)> {
+        self.buyers.orders()
+    }
+
+    /// The highest buying price, if any.
+    pub fn best_bid(&self) -> Option<u64> {
+        self.buyers.best_price()
+    }
+
+    /// The lowest selling price, if any.
+    pub fn best_ask(&self) -> Option<u64> {
+        self.sellers.best_price()
+    }
+
+    /// The difference between the best selling and the best buying prices, if both sides are
+    /// present. Zero if the book is crossed, which may happen during a call auction.
+    pub fn spread(&self) -> Option<u64> {
+        Some(self.best_ask()?.saturating_sub(self.best_bid()?))
+    }
+
+    /// The average of the best selling and the best buying prices (rounded down), if both sides
+    /// are present.
+    pub fn mid_price(&self) -> Option<u64> {
+        let (bid, ask) = (self.best_bid()?, self.best_ask()?);
+        Some(bid / 2 + ask / 2 + (bid % 2 + ask % 2) / 2)
+    }
+
+    /// Queue imbalance of the first `levels` price levels of each side: the difference between the
+    /// displayed buying and selling amounts divided by their sum. Ranges from -1 (only selling
+    /// bids) to 1 (only buying bids).
+    ///
+    /// Returns `None` if there are no bids within the levels.
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        let depth = self.depth(levels);
+        let buying = displayed_total(&depth.buying) as f64;
+        let selling = displayed_total(&depth.selling) as f64;
+        if buying + selling == 0. {
+            return None;
+        }
+        Some((buying - selling) / (buying + selling))
+    }
+
+    /// Mid-price weighted by the displayed amounts of the first `levels` price levels of each
+    /// side: the best bid weighted by the selling amount plus the best ask weighted by the buying
+    /// amount. The more there is to buy, the closer it is to the best ask.
+    ///
+    /// Returns `None` unless both sides are present and `levels` is not zero.
+    pub fn weighted_mid_price(&self, levels: usize) -> Option<f64> {
+        let depth = self.depth(levels);
+        let (bid, ask) = (
+            depth.buying.first()?.0 as f64,
+            depth.selling.first()?.0 as f64,
+        );
+        let buying = displayed_total(&depth.buying) as f64;
+        let selling = displayed_total(&depth.selling) as f64;
+        Some((bid * selling + ask * buying) / (buying + selling))
+    }
+
+    /// Returns up to `levels` best price levels of each side of the book.
+    pub fn depth(&self, levels: usize) -> Depth {
+        Depth {
+            selling: self.sellers.depth(levels),
+            buying: self.buyers.depth(levels),
+        }
+    }
+
+    /// Computes what uncrossing the book would result in right now, i.e. the indicative
+    /// equilibrium price and volume, and the imbalance left at that price.
+    ///
+    /// Returns `None` if no bids cross. See [`OrderBook::uncross`] for how the price is chosen.
+    pub fn indicative_price(&self) -> Option<Equilibrium> {
+        clearing_price(
+            &self.sellers.levels(),
+            &self.buyers.levels(),
+            self.last_trade_price,
+        )
+    }
+
+    /// Advances the book's clock to `now` (seconds since the Unix epoch) and removes the resting
+    /// bids whose time in force has run out.
+    ///
+    /// Expiry times are kept in a queue, so only the bids due by `now` are visited. Every removed
+    /// bid is reported to the listeners as expired and as a cancellation on the feed. Bids added
+    /// to the book afterwards are stamped with `now`. Bids queued by the rate limit are processed
+    /// if their users are within the limit by then; their trades are only reported to the
+    /// listeners. Returns the ids of the removed bids, in the order of their expiry times.
+    ///
+    /// If the book has a [`SessionSchedule`], the session moves to the phase of `now`: an auction
+    /// that has ended is uncrossed (the trades are only reported to the listeners) and a new one
+    /// starts collecting bids. Expired bids don't take part in the uncrossing.
+    pub fn advance_time(&mut self, now: u64) -> Vec<OrderId> {
+        self.now = now;
+        let mut expired = Vec::new();
+        for (id, bid) in self.sellers.expire(now) {
+            self.listeners.on_order_expired(&OrderInfo::from(&bid));
+            expired.push(OrderId(id));
+        }
+        for (id, bid) in self.buyers.expire(now) {
+            self.listeners.on_order_expired(&OrderInfo::from(&bid));
+            expired.push(OrderId(id));
+        }
+        for &order_id in &expired {
+            self.listeners
+                .on_feed_event(&FeedEvent::Cancel { order_id });
+            self.unlink(order_id);
+        }
+        self.update_phase();
+        self.release_throttled();
+        self.check_invariants();
+        expired
+    }
+
+    /// Looks a bid up by its id. Bids resting in the book, waiting for their conditions or
+    /// triggers and queued during a halt or by the rate limit are reported.
+    ///
+    /// Returns `None` if the bid is not in the book anymore (it has been fully executed, dropped or
+    /// cancelled) or has never been submitted.
+    pub fn get(&self, order_id: OrderId) -> Option<OrderStatus> {
+        if let Some(pending) = self
+            .pending
+            .iter()
+            .find(|pending| pending.order_id() == order_id)
+        {
+            Some(match pending {
+                PendingBid::Selling(_, _, bid, _) => OrderStatus::new(bid, bid.amount),
+                PendingBid::Buying(_, _, bid, _) => OrderStatus::new(bid, bid.amount),
+            })
+        } else if let Some(queued) = self
+            .queued
+            .iter()
+            .chain(&self.throttled)
+            .find(|queued| queued.order_id() == order_id)
+        {
+            Some(match queued {
+                QueuedBid::Selling(_, bid, _) => OrderStatus::new(bid, bid.amount),
+                QueuedBid::Buying(_, bid, _) => OrderStatus::new(bid, bid.amount),
+            })
+        } else if let Some(bid) = self.selling_stops.find(order_id) {
+            Some(OrderStatus::new(bid, bid.amount))
+        } else if let Some(bid) = self.buying_stops.find(order_id) {
+            Some(OrderStatus::new(bid, bid.amount))
+        } else {
+            status_in(&self.sellers, order_id).or_else(|| status_in(&self.buyers, order_id))
+        }
+    }
+
+    /// Cancels a bid, either resting in the book or waiting for its condition or trigger.
+    pub fn cancel(&mut self, order_id: OrderId) -> Cancellation {
+        let cancellation = if let Some(position) = self
+            .pending
+            .iter()
+            .position(|pending| pending.order_id() == order_id)
+        {
+            let order = match self.pending.remove(position) {
+                PendingBid::Selling(_, _, bid, _) => OrderInfo::from(&bid),
+                PendingBid::Buying(_, _, bid, _) => OrderInfo::from(&bid),
+            };
+            Cancellation::Cancelled(order)
+        } else if let Some(position) = self
+            .queued
+            .iter()
+            .position(|queued| queued.order_id() == order_id)
+        {
+            Cancellation::Cancelled(self.queued.remove(position).order_info())
+        } else if let Some(position) = self
+            .throttled
+            .iter()
+            .position(|queued| queued.order_id() == order_id)
+        {
+            Cancellation::Cancelled(self.throttled.remove(position).order_info())
+        } else if let Some(bid) = self.selling_stops.cancel(order_id) {
+            Cancellation::Cancelled(OrderInfo::from(&bid))
+        } else if let Some(bid) = self.buying_stops.cancel(order_id) {
+            Cancellation::Cancelled(OrderInfo::from(&bid))
+        } else if let Some(cancellation) =
+            cancel_in(&mut self.sellers, order_id, &mut self.listeners)
+        {
+            cancellation
+        } else if let Some(cancellation) =
+            cancel_in(&mut self.buyers, order_id, &mut self.listeners)
+        {
+            cancellation
+        } else if order_id.0 < self.next_order_id {
+            Cancellation::AlreadyGone
+        } else {
+            Cancellation::Unknown
+        };
+        if let Cancellation::Cancelled(order) | Cancellation::PartiallyFilled(order) = &cancellation
+        {
+            self.listeners.on_order_cancelled(order);
+        }
+        self.unlink(order_id);
+        self.check_invariants();
+        cancellation
+    }
+
+    /// Cancels all the bids of the user resting in the book, e.g. when the user disconnects.
+    ///
+    /// Bids waiting for their conditions or triggers and bids queued during a halt are kept.
+    /// Returns the ids of the cancelled bids along with their cancellations, selling bids first,
+    /// each side in the order of the ids.
+    pub fn cancel_user(&mut self, user_id: u64) -> Vec<(OrderId, Cancellation)> {
+        let order_ids: Vec<_> = self
+            .sellers
+            .user_orders(user_id)
+            .chain(self.buyers.user_orders(user_id))
+            .map(OrderId)
+            .collect();
+        order_ids
+            .into_iter()
+            .map(|order_id| (order_id, self.cancel(order_id)))
+            .collect()
+    }
+
+    /// Number of the bids of the user resting in the book.
+    ///
+    /// Bids waiting for their conditions or triggers and bids queued during a halt are not
+    /// counted.
+    pub fn open_orders(&self, user_id: u64) -> usize {
+        self.sellers.user_orders(user_id).count() + self.buyers.user_orders(user_id).count()
+    }
+
+    /// Buying exposure of the user: the cash the user owes (if the book tracks positions) plus
+    /// the price times the remaining amount of the user's buying bids resting in the book.
+    pub fn buying_exposure(&self, user_id: u64) -> u128 {
+        let owed = self.accounts.as_ref().map_or(0, |accounts| {
+            u128::try_from(accounts.get(user_id).cash.saturating_neg()).unwrap_or(0)
+        });
+        let resting: u128 = self
+            .buyers
+            .user_orders(user_id)
+            .filter_map(|id| self.buyers.find(id))
+            .map(|bid| u128::from(bid.price) * u128::from(bid.amount))
+            .sum();
+        owed + resting
+    }
+
+    /// Changes the price and the (remaining) size of a bid.
+    ///
+    /// Reducing the size of a resting bid keeps its time priority. Changing the price or increasing
+    /// the size is equivalent to cancelling the bid and submitting a new `Limit` bid, which might
+    /// be executed immediately, or a new `PostOnly` bid if the bid was submitted as such, which is
+    /// dropped if it would execute. Bids waiting for their conditions or triggers are simply
+    /// updated.
+    pub fn amend(&mut self, order_id: OrderId, new_price: u64, new_size: u64) -> Amendment {
+        if new_size == 0 {
+            return match self.cancel(order_id) {
+                Cancellation::AlreadyGone => Amendment::AlreadyGone,
+                Cancellation::Unknown => Amendment::Unknown,
+                cancellation => Amendment::Cancelled(cancellation),
+            };
+        }
+        if let Some(pending) = self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.order_id() == order_id)
+        {
+            let order = match pending {
+                PendingBid::Selling(_, _, bid, _) => {
+                    *bid = bid.price(new_price).amount(new_size);
+                    OrderInfo::from(&*bid)
+                }
+                PendingBid::Buying(_, _, bid, _) => {
+                    *bid = bid.price(new_price).amount(new_size);
+                    OrderInfo::from(&*bid)
+                }
+            };
+            self.listeners.on_order_amended(&order);
+            return Amendment::Amended(order);
+        }
+        if let Some(queued) = self
+            .queued
+            .iter_mut()
+            .chain(&mut self.throttled)
+            .find(|queued| queued.order_id() == order_id)
+        {
+            match queued {
+                QueuedBid::Selling(_, bid, _) => *bid = bid.price(new_price).amount(new_size),
+                QueuedBid::Buying(_, bid, _) => *bid = bid.price(new_price).amount(new_size),
+            }
+            let order = queued.order_info();
+            self.listeners.on_order_amended(&order);
+            return Amendment::Amended(order);
+        }
+        let stop = if let Some(bid) = self.selling_stops.find_mut(order_id) {
+            *bid = bid.price(new_price).amount(new_size);
+            Some(OrderInfo::from(&*bid))
+        } else if let Some(bid) = self.buying_stops.find_mut(order_id) {
+            *bid = bid.price(new_price).amount(new_size);
+            Some(OrderInfo::from(&*bid))
+        } else {
+            None
+        };
+        if let Some(order) = stop {
+            self.listeners.on_order_amended(&order);
+            return Amendment::Amended(order);
+        }
+        if let Some(bid) = self.sellers.find(order_id.0) {
+            if let Some(order) = amend_in(
+                &mut self.sellers,
+                order_id,
+                &bid,
+                new_price,
+                new_size,
+                &mut self.listeners,
+            ) {
+                self.listeners.on_order_amended(&order);
+                self.check_invariants();
+                return Amendment::Amended(order);
+            }
+            let bid_type = resubmitted_type(&self.sellers, order_id);
+            self.cancel(order_id);
+            let result = self.process_selling(bid.price(new_price).amount(new_size), bid_type);
+            return Amendment::Replaced(result);
+        }
+        if let Some(bid) = self.buyers.find(order_id.0) {
+            if let Some(order) = amend_in(
+                &mut self.buyers,
+                order_id,
+                &bid,
+                new_price,
+                new_size,
+                &mut self.listeners,
+            ) {
+                self.listeners.on_order_amended(&order);
+                self.check_invariants();
+                return Amendment::Amended(order);
+            }
+            let bid_type = resubmitted_type(&self.buyers, order_id);
+            self.cancel(order_id);
+            let result = self.process_buying(bid.price(new_price).amount(new_size), bid_type);
+            return Amendment::Replaced(result);
+        }
+        if order_id.0 < self.next_order_id {
+            Amendment::AlreadyGone
+        } else {
+            Amendment::Unknown
+        }
+    }
+
+    /// Computes what would happen if a selling bid was processed, without modifying the book.
+    ///
+    /// Pending conditional bids that might be released by the processing are not taken into
+    /// account.
+    pub fn simulate_selling(
+        &self,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> Simulation<SellingBid> {
+        self.buyers.simulate(bid, bid_type)
+    }
+
+    /// Computes what would happen if a buying bid was processed, without modifying the book.
+    ///
+    /// Pending conditional bids that might be released by the processing are not taken into
+    /// account.
+    pub fn simulate_buying(
+        &self,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> Simulation<BuyingBid> {
+        self.sellers.simulate(bid, bid_type)
+    }
+
+    /// Computes how much of `amount` items could be bought right now at the `price` or lower, and
+    /// at what prices, without modifying the book.
+    ///
+    /// The resting bids are taken as a whole, including the hidden parts of icebergs; user groups
+    /// are not taken into account.
+    pub fn preview_buy(&self, price: u64, amount: u64) -> Quote {
+        self.sellers
+            .quote(&Bid::empty().price(price).amount(amount))
+    }
+
+    /// Computes how much of `amount` items could be sold right now at the `price` or higher, and
+    /// at what prices, without modifying the book.
+    ///
+    /// The resting bids are taken as a whole, including the hidden parts of icebergs; user groups
+    /// are not taken into account.
+    pub fn preview_sell(&self, price: u64, amount: u64) -> Quote {
+        self.buyers.quote(&Bid::empty().price(price).amount(amount))
+    }
+
+    /// Estimates the market impact of buying or selling (depending on the `side`) the `amount` of
+    /// items right now: walks the opposite side of the book from the best price and reports the
+    /// notional cost, the average price and the number of price levels consumed.
+    ///
+    /// Bids are counted the same way as in [`OrderBook::preview_buy`]. Fails if the book can't
+    /// fill the whole amount, reporting what could be filled.
+    pub fn cost_to_fill(&self, side: Side, amount: u64) -> Result<Quote, InsufficientLiquidity> {
+        let quote = match side {
+            Side::Buy => self.preview_buy(u64::MAX, amount),
+            Side::Sell => self.preview_sell(0, amount),
+        };
+        if quote.filled < amount {
+            Err(InsufficientLiquidity(quote))
+        } else {
+            Ok(quote)
+        }
+    }
+
+    /// Holds a selling bid until the `condition` is met, and then processes it.
+    ///
+    /// If the condition already holds, the bid is processed immediately.
+    pub fn process_selling_when(
+        &mut self,
+        condition: Condition,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.tick();
+        log_hold(&condition, &bid);
+        let order_id = self.next_order_id();
+        self.link(order_id, &bid);
+        self.pending
+            .push(PendingBid::Selling(order_id, condition, bid, bid_type));
+        let mut trades = Vec::new();
+        self.release_triggered(&mut trades);
+        ProcessingResult { order_id, trades }
+    }
+
+    /// Holds a buying bid until the `condition` is met, and then processes it.
+    ///
+    /// If the condition already holds, the bid is processed immediately.
+    pub fn process_buying_when(
+        &mut self,
+        condition: Condition,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.tick();
+        log_hold(&condition, &bid);
+        let order_id = self.next_order_id();
+        self.link(order_id, &bid);
+        self.pending
+            .push(PendingBid::Buying(order_id, condition, bid, bid_type));
+        let mut trades = Vec::new();
+        self.release_triggered(&mut trades);
+        ProcessingResult { order_id, trades }
+    }
+
+    /// Holds a selling stop bid until a trade prints at or below the `trigger_price`, and then
+    /// processes it.
+    ///
+    /// A `Market` bid makes a stop order, a `Limit` bid makes a stop-limit order. If the last trade
+    /// price is already at or below the trigger, the bid is processed immediately.
+    pub fn process_selling_stop(
+        &mut self,
+        trigger_price: u64,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.tick();
+        log_hold(&Trigger(Side::Sell, trigger_price), &bid);
+        let order_id = self.next_order_id();
+        self.link(order_id, &bid);
+        self.selling_stops
+            .insert(order_id, trigger_price, bid, bid_type);
+        let mut trades = Vec::new();
+        self.release_triggered(&mut trades);
+        ProcessingResult { order_id, trades }
+    }
+
+    /// Holds a buying stop bid until a trade prints at or above the `trigger_price`, and then
+    /// processes it.
+    ///
+    /// A `Market` bid makes a stop order, a `Limit` bid makes a stop-limit order. If the last trade
+    /// price is already at or above the trigger, the bid is processed immediately.
+    pub fn process_buying_stop(
+        &mut self,
+        trigger_price: u64,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> ProcessingResult {
+        self.tick();
+        log_hold(&Trigger(Side::Buy, trigger_price), &bid);
+        let order_id = self.next_order_id();
+        self.link(order_id, &bid);
+        self.buying_stops
+            .insert(order_id, trigger_price, bid, bid_type);
+        let mut trades = Vec::new();
+        self.release_triggered(&mut trades);
+        ProcessingResult { order_id, trades }
+    }
+
+    /// Price of the most recent trade, if any.
+    pub fn last_trade_price(&self) -> Option<u64> {
+        self.last_trade_price
+    }
+
+    /// Statistics of the trades executed in the book.
+    pub fn stats(&self) -> &TradeStats {
+        &self.stats
+    }
+
+    /// The tape of the trades executed in the book, with their ids and sequence numbers.
+    pub fn trades(&self) -> &TradeTape {
+        &self.tape
+    }
+
+    /// Positions and cash balances of the users, if the book is configured to track them.
+    pub fn accounts(&self) -> Option<&Accounts> {
+        self.accounts.as_ref()
+    }
+
+    /// Sets the price the price band is built around. Without it the last trade price is used.
+    pub fn set_reference_price(&mut self, reference_price: Option<u64>) {
+        self.reference_price = reference_price;
+    }
+
+    /// The lowest and the highest prices of the price band, if the band is configured and there is
+    /// a reference (or a last trade) price to build it around.
+    pub fn price_band(&self) -> Option<(u64, u64)> {
+        let width = self.price_band_bps?;
+        let reference = self.reference_price.or(self.last_trade_price)?;
+        let delta = (u128::from(reference) * u128::from(width) / 10_000) as u64;
+        Some((
+            reference.saturating_sub(delta),
+            reference.saturating_add(delta),
+        ))
+    }
+
+    /// Advances the time to the clock's one, if the book has a clock and the clock is ahead.
+    fn tick(&mut self) {
+        if let Some(now) = self.clock.as_ref().map(|clock| clock.now()) {
+            if now > self.now {
+                self.advance_time(now);
+            }
+        }
+    }
+
+    fn next_order_id(&mut self) -> OrderId {
+        let order_id = OrderId(self.next_order_id);
+        self.next_order_id += 1;
+        order_id
+    }
+
+    /// Registers the one-cancels-other link of a submitted bid, if it has one.
+    fn link<BidKind>(&mut self, order_id: OrderId, bid: &Bid<BidKind>) {
+        if let Some(link) = bid.link {
+            self.links.insert(order_id, (bid.user_id, link));
+            self.linked
+                .entry(bid.user_id)
+                .or_default()
+                .entry(link)
+                .or_default()
+                .insert(order_id);
+        }
+    }
+
+    /// Forgets the one-cancels-other link of a bid, returning the ids of the other bids of the
+    /// link.
+    fn unlink(&mut self, order_id: OrderId) -> BTreeSet<OrderId> {
+        let (user_id, link) = match self.links.remove(&order_id) {
+            Some(link) => link,
+            None => return BTreeSet::new(),
+        };
+        let links = match self.linked.get_mut(&user_id) {
+            Some(links) => links,
+            None => return BTreeSet::new(),
+        };
+        let others = match links.get_mut(&link) {
+            Some(others) => {
+                others.remove(&order_id);
+                others.clone()
+            }
+            None => BTreeSet::new(),
+        };
+        if others.is_empty() {
+            links.remove(&link);
+            if links.is_empty() {
+                self.linked.remove(&user_id);
+            }
+        }
+        others
+    }
+
+    /// Cancels the bids linked to the bids executed since the last call.
+    fn cancel_linked(&mut self) {
+        for order_id in mem::take(&mut self.executed) {
+            for other_id in self.unlink(order_id) {
+                debug!(
+                    "Order {} has been executed, cancel the linked order {}",
+                    order_id.0, other_id.0
+                );
+                self.cancel(other_id);
+            }
+        }
+    }
+
+    /// Moves the trading session to the phase of the book's time, if the book has a schedule.
+    ///
+    /// Leaving an auction uncrosses the book, entering one starts it.
+    fn update_phase(&mut self) {
+        let phase = match self.session {
+            Some(session) => session.phase_at(self.now),
+            None => return,
+        };
+        if phase == self.phase {
+            return;
+        }
+        info!("The session moves from {:?} to {:?}", self.phase, phase);
+        let previous = mem::replace(&mut self.phase, phase);
+        self.listeners.on_phase_changed(phase);
+        if previous.is_auction() {
+            self.uncross_now();
+        }
+        self.auction = phase.is_auction();
+    }
+
+    /// Cancels the resting bids of the users whose market-maker protection is triggered by the
+    /// `trades`.
+    fn protect_makers(&mut self, trades: &[Trade]) {
+        let maker_guard = match &mut self.maker_guard {
+            Some(maker_guard) => maker_guard,
+            None => return,
+        };
+        let mut triggered = Vec::new();
+        for trade in trades {
+            if maker_guard.record(trade.maker_user_id, trade.amount, self.now)
+                && !triggered.contains(&trade.maker_user_id)
+            {
+                triggered.push(trade.maker_user_id);
+            }
+        }
+        for user_id in triggered {
+            info!(
+                "The market-maker protection of user {} is triggered",
+                user_id
+            );
+            self.listeners.on_protection_triggered(user_id);
+            self.cancel_user(user_id);
+        }
+    }
+
+    /// Queues or drops a bid submitted while trading is halted.
+    fn hold_while_halted(&mut self, queued: QueuedBid) {
+        match self.halt_mode {
+            HaltMode::Queue => {
+                debug!("Trading is halted, queue {:?}", queued);
+                self.queued.push(queued);
+            }
+            HaltMode::Reject => {
+                debug!("Trading is halted, drop {:?}", queued);
+                self.listeners.on_order_dropped(&queued.order_info());
+            }
+        }
+    }
+
+    /// Checks the rate limit of the user of a bid submitted to the book, if it's configured.
+    ///
+    /// Returns whether the bid may be processed right now, otherwise it has been queued or dropped,
+    /// depending on the configured [`ThrottleMode`]. Bids of a user that has queued bids are
+    /// queued after them.
+    fn throttle(&mut self, queued: QueuedBid) -> bool {
+        let rate_limiter = match &mut self.rate_limiter {
+            Some(rate_limiter) => rate_limiter,
+            None => return true,
+        };
+        let user_id = queued.user_id();
+        let has_queued = self
+            .throttled
+            .iter()
+            .any(|throttled| throttled.user_id() == user_id);
+        if !has_queued && rate_limiter.try_acquire(user_id, self.now) {
+            return true;
+        }
+        match rate_limiter.mode() {
+            ThrottleMode::Queue => {
+                debug!(
+                    "User {} is over the rate limit, queue {:?}",
+                    user_id, queued
+                );
+                self.throttled.push(queued);
+            }
+            ThrottleMode::Reject => {
+                debug!("User {} is over the rate limit, drop {:?}", user_id, queued);
+                self.listeners.on_order_dropped(&queued.order_info());
+            }
+        }
+        false
+    }
+
+    /// Processes the bids queued by the rate limit whose users are within the limit again, in the
+    /// submission order.
+    fn release_throttled(&mut self) {
+        let rate_limiter = match &mut self.rate_limiter {
+            Some(rate_limiter) => rate_limiter,
+            None => return,
+        };
+        let mut blocked = Vec::new();
+        let mut released = Vec::new();
+        for queued in std::mem::take(&mut self.throttled) {
+            let user_id = queued.user_id();
+            if !blocked.contains(&user_id) && rate_limiter.try_acquire(user_id, self.now) {
+                released.push(queued);
+            } else {
+                blocked.push(user_id);
+                self.throttled.push(queued);
+            }
+        }
+        for queued in released {
+            let mut trades = match queued {
+                QueuedBid::Selling(order_id, bid, bid_type) => {
+                    self.match_selling(order_id, bid, bid_type)
+                }
+                QueuedBid::Buying(order_id, bid, bid_type) => {
+                    self.match_buying(order_id, bid, bid_type)
+                }
+            };
+            self.release_triggered(&mut trades);
+        }
+    }
+
+    fn match_selling(
+        &mut self,
+        order_id: OrderId,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> Vec<Trade> {
+        if !self.phase.accepts_bids() {
+            debug!("The market is closed ({:?}), drop {:?}", self.phase, bid);
+            self.listeners.on_order_dropped(&OrderInfo::from(&bid));
+            return Vec::new();
+        }
+        if self.halted {
+            self.hold_while_halted(QueuedBid::Selling(order_id, bid, bid_type));
+            return Vec::new();
+        }
+        let bid = match cap_reduce_only(self.accounts.as_ref(), bid, &mut self.listeners) {
+            Some(bid) => bid,
+            None => return Vec::new(),
+        };
+        let original_amount = bid.amount;
+        let limit_price = protection_limit(&bid, bid_type, self.buyers.best_price());
+        let (trades, rest_of_the_bid) = if self.auction {
+            (
+                Vec::new(),
+                queue_for_auction(bid, bid_type, &mut self.listeners),
+            )
+        } else if let Some((bid, bid_type)) =
+            fit_into_band(self.price_band(), bid, bid_type, &mut self.listeners)
+                .map(|(bid, new_type)| protect(limit_price, bid, new_type))
+        {
+            let mut recorder = ExecutionRecorder {
+                listener: &mut self.listeners,
+                executed: &mut self.executed,
+            };
+            self.buyers.process_bid(bid, bid_type, &mut recorder)
+        } else {
+            (Vec::new(), None)
+        };
+        if !trades.is_empty() {
+            self.executed.push(order_id);
+        }
+        if let Some(rest_of_the_bid) = rest_of_the_bid {
+            self.listeners
+                .on_order_added(&OrderInfo::from(&rest_of_the_bid));
+            let displayed =
+                self.sellers
+                    .insert(order_id.0, rest_of_the_bid, bid_type, original_amount);
+            self.listeners.on_feed_event(&FeedEvent::AddOrder {
+                order_id,
+                side: Side::Sell,
+                price: rest_of_the_bid.price,
+                amount: displayed,
+            });
+        }
+        trades
+    }
+
+    fn match_buying(
+        &mut self,
+        order_id: OrderId,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> Vec<Trade> {
+        if !self.phase.accepts_bids() {
+            debug!("The market is closed ({:?}), drop {:?}", self.phase, bid);
+            self.listeners.on_order_dropped(&OrderInfo::from(&bid));
+            return Vec::new();
+        }
+        if self.halted {
+            self.hold_while_halted(QueuedBid::Buying(order_id, bid, bid_type));
+            return Vec::new();
+        }
+        let bid = match cap_reduce_only(self.accounts.as_ref(), bid, &mut self.listeners) {
+            Some(bid) => bid,
+            None => return Vec::new(),
+        };
+        let original_amount = bid.amount;
+        let limit_price = protection_limit(&bid, bid_type, self.sellers.best_price());
+        let (trades, rest_of_the_bid) = if self.auction {
+            (
+                Vec::new(),
+                queue_for_auction(bid, bid_type, &mut self.listeners),
+            )
+        } else if let Some((bid, bid_type)) =
+            fit_into_band(self.price_band(), bid, bid_type, &mut self.listeners)
+                .map(|(bid, new_type)| protect(limit_price, bid, new_type))
+        {
+            let mut recorder = ExecutionRecorder {
+                listener: &mut self.listeners,
+                executed: &mut self.executed,
+            };
+            self.sellers.process_bid(bid, bid_type, &mut recorder)
+        } else {
+            (Vec::new(), None)
+        };
+        if !trades.is_empty() {
+            self.executed.push(order_id);
+        }
+        if let Some(rest_of_the_bid) = rest_of_the_bid {
+            self.listeners
+                .on_order_added(&OrderInfo::from(&rest_of_the_bid));
+            let displayed =
+                self.buyers
+                    .insert(order_id.0, rest_of_the_bid, bid_type, original_amount);
+            self.listeners.on_feed_event(&FeedEvent::AddOrder {
+                order_id,
+                side: Side::Buy,
+                price: rest_of_the_bid.price,
+                amount: displayed,
+            });
+        }
+        trades
+    }
+
+    /// Processes stop bids triggered by the `trades` and conditional bids whose conditions are met.
+    ///
+    /// Since processing a bid changes the state of the book, it might trigger other stop and
+    /// conditional bids, so this goes on until nothing else is triggered. Trades of the released
+    /// bids are appended to the `trades`.
+    fn release_triggered(&mut self, trades: &mut Vec<Trade>) {
+        self.release_triggered_since(trades, 0)
+    }
+
+    /// Same as [`OrderBook::release_triggered`], but only the `trades` starting from the `first`
+    /// one are new.
+    fn release_triggered_since(&mut self, trades: &mut Vec<Trade>, first: usize) {
+        let mut checked = first;
+        self.tape.next_step();
+        loop {
+            let new_trades = &trades[checked..];
+            checked = trades.len();
+            if let Some(last) = new_trades.last() {
+                self.last_trade_price = Some(last.price);
+            }
+            self.stats.extend(new_trades);
+            self.tape.record(new_trades);
+            if let Some(accounts) = &mut self.accounts {
+                accounts.extend(new_trades);
+            }
+            self.cancel_linked();
+            self.protect_makers(new_trades);
+            let low = new_trades.iter().map(|trade| trade.price).min();
+            let high = new_trades.iter().map(|trade| trade.price).max();
+            if let (Some(low), Some(high)) = (
+                low.or(self.last_trade_price),
+                high.or(self.last_trade_price),
+            ) {
+                let buying = self.buying_stops.take_triggered(low, high);
+                let selling = self.selling_stops.take_triggered(low, high);
+                if !buying.is_empty() || !selling.is_empty() {
+                    for triggered in buying {
+                        let TriggeredBid {
+                            order_id,
+                            trigger_price,
+                            bid,
+                            bid_type,
+                        } = triggered;
+                        log_release(&Trigger(Side::Buy, trigger_price), &bid);
+                        trades.extend(self.match_buying(order_id, bid, bid_type));
+                    }
+                    for triggered in selling {
+                        let TriggeredBid {
+                            order_id,
+                            trigger_price,
+                            bid,
+                            bid_type,
+                        } = triggered;
+                        log_release(&Trigger(Side::Sell, trigger_price), &bid);
+                        trades.extend(self.match_selling(order_id, bid, bid_type));
+                    }
+                    continue;
+                }
+            }
+            let position = self
+                .pending
+                .iter()
+                .position(|pending| pending.condition().is_met(self));
+            match position.map(|position| self.pending.remove(position)) {
+                Some(PendingBid::Selling(order_id, condition, bid, bid_type)) => {
+                    log_release(&condition, &bid);
+                    trades.extend(self.match_selling(order_id, bid, bid_type));
+                }
+                Some(PendingBid::Buying(order_id, condition, bid, bid_type)) => {
+                    log_release(&condition, &bid);
+                    trades.extend(self.match_buying(order_id, bid, bid_type));
+                }
+                None => break,
+            }
+        }
+        self.check_invariants();
+    }
+
+    /// Panics if the book is inconsistent, see the `debug-invariants` feature.
+    ///
+    /// Besides the bookkeeping of the pools, checks that the book isn't crossed, except for the
+    /// bids that can't trade with each other: during an auction or with a level limit any bids
+    /// may cross, otherwise only the ones of users of the same group or with a minimum fill.
+    #[cfg(feature = "debug-invariants")]
+    fn check_invariants(&self) {
+        self.sellers.check_invariants();
+        self.buyers.check_invariants();
+        if self.auction || self.sellers.max_levels().is_some() || self.buyers.max_levels().is_some()
+        {
+            return;
+        }
+        let best_bid = match self.best_bid() {
+            Some(best_bid) => best_bid,
+            None => return,
+        };
+        let user_groups = self.buyers.user_groups();
+        for (_, selling_bid) in self
+            .sellers
+            .resting()
+            .take_while(|(_, bid)| bid.price <= best_bid)
+        {
+            for (_, buying_bid) in self
+                .buyers
+                .resting()
+                .take_while(|(_, bid)| bid.price >= selling_bid.price)
+            {
+                assert!(
+                    user_groups.same_group(selling_bid.user_id, buying_bid.user_id)
+                        || selling_bid.min_fill.is_some()
+                        || buying_bid.min_fill.is_some(),
+                    "The book is crossed: {:?} and {:?}",
+                    selling_bid,
+                    buying_bid
+                );
+            }
         }
     }
+
+    #[cfg(not(feature = "debug-invariants"))]
+    #[inline]
+    fn check_invariants(&self) {}
+}
+
+/// Forwards the events to a listener, recording the ids of the executed resting bids.
+struct ExecutionRecorder<'a> {
+    listener: &'a mut dyn OrderBookListener,
+    executed: &'a mut Vec<OrderId>,
+}
+
+impl OrderBookListener for ExecutionRecorder<'_> {
+    fn on_trade(&mut self, trade: &Trade) {
+        self.listener.on_trade(trade)
+    }
+
+    fn on_order_added(&mut self, order: &OrderInfo) {
+        self.listener.on_order_added(order)
+    }
+
+    fn on_order_dropped(&mut self, order: &OrderInfo) {
+        self.listener.on_order_dropped(order)
+    }
+
+    fn on_order_partially_filled(&mut self, order: &OrderInfo) {
+        self.listener.on_order_partially_filled(order)
+    }
+
+    fn on_order_cancelled(&mut self, order: &OrderInfo) {
+        self.listener.on_order_cancelled(order)
+    }
+
+    fn on_order_amended(&mut self, order: &OrderInfo) {
+        self.listener.on_order_amended(order)
+    }
+
+    fn on_order_expired(&mut self, order: &OrderInfo) {
+        self.listener.on_order_expired(order)
+    }
+
+    fn on_feed_event(&mut self, event: &FeedEvent) {
+        if let FeedEvent::Execute { order_id, .. } = event {
+            self.executed.push(*order_id);
+        }
+        self.listener.on_feed_event(event)
+    }
+
+    fn on_phase_changed(&mut self, phase: Phase) {
+        self.listener.on_phase_changed(phase)
+    }
+
+    fn on_protection_triggered(&mut self, user_id: u64) {
+        self.listener.on_protection_triggered(user_id)
+    }
+}
+
+/// Returns the part of a bid submitted during a call auction that should be added to the book.
+fn queue_for_auction<BidKind: GenericBid>(
+    bid: Bid<BidKind>,
+    bid_type: BidProcessingType,
+    listener: &mut dyn OrderBookListener,
+) -> Option<Bid<BidKind>> {
+    match bid_type {
+        BidProcessingType::Limit => Some(bid),
+        BidProcessingType::Market => Some(bid.price(BidKind::market_price())),
+        _ => {
+            listener.on_order_dropped(&OrderInfo::from(&bid));
+            None
+        }
+    }
+}
+
+/// Caps the amount of a reduce-only bid at the position of its user that it can reduce, dropping
+/// the excess.
+///
+/// Without the `accounts` the position is unknown, so reduce-only bids are dropped.
+fn cap_reduce_only<BidKind: GenericBid>(
+    accounts: Option<&Accounts>,
+    bid: Bid<BidKind>,
+    listener: &mut dyn OrderBookListener,
+) -> Option<Bid<BidKind>> {
+    if !bid.reduce_only {
+        return Some(bid);
+    }
+    let position = accounts.map_or(0, |accounts| accounts.position(bid.user_id));
+    let reducible = match BidKind::side() {
+        Side::Sell => position,
+        Side::Buy => position.saturating_neg(),
+    };
+    let reducible = u64::try_from(reducible.max(0)).unwrap_or(u64::MAX);
+    if bid.amount <= reducible {
+        return Some(bid);
+    }
+    debug!(
+        "User {} can only reduce its position by {} items",
+        bid.user_id, reducible
+    );
+    listener.on_order_dropped(&OrderInfo::from(&bid.amount(bid.amount - reducible)));
+    if reducible == 0 {
+        None
+    } else {
+        Some(bid.amount(reducible))
+    }
+}
+
+/// Fits a bid into the price `band`, if any.
+///
+/// Bids priced outside of the band are dropped, market bids are turned into immediate-or-cancel
+/// bids priced at the far edge of the band, so they don't execute beyond it.
+fn fit_into_band<BidKind: GenericBid>(
+    band: Option<(u64, u64)>,
+    bid: Bid<BidKind>,
+    bid_type: BidProcessingType,
+    listener: &mut dyn OrderBookListener,
+) -> Option<(Bid<BidKind>, BidProcessingType)> {
+    let (low, high) = match band {
+        Some(band) => band,
+        None => return Some((bid, bid_type)),
+    };
+    if bid_type == BidProcessingType::Market {
+        let limit = match BidKind::side() {
+            Side::Buy => high,
+            Side::Sell => low,
+        };
+        Some((bid.price(limit), BidProcessingType::ImmediateOrCancel))
+    } else if bid.price < low || bid.price > high {
+        debug!(
+            "The price {} is outside of the price band {}..={}",
+            bid.price, low, high
+        );
+        listener.on_order_dropped(&OrderInfo::from(&bid));
+        None
+    } else {
+        Some((bid, bid_type))
+    }
+}
+
+/// The worst price a `Market` bid with a protection may execute at, see
+/// [`Protection`](crate::bids::Protection).
+fn protection_limit<BidKind: GenericBid>(
+    bid: &Bid<BidKind>,
+    bid_type: BidProcessingType,
+    best_opposite: Option<u64>,
+) -> Option<u64> {
+    if bid_type != BidProcessingType::Market {
+        return None;
+    }
+    bid.protection?.limit_price(BidKind::side(), best_opposite)
+}
+
+/// Turns a protected `Market` bid into an immediate-or-cancel bid priced at the `limit_price`, so
+/// it doesn't execute beyond it. A bid that has been limited by the price band already keeps the
+/// tighter of the two limits.
+fn protect<BidKind: GenericBid>(
+    limit_price: Option<u64>,
+    bid: Bid<BidKind>,
+    bid_type: BidProcessingType,
+) -> (Bid<BidKind>, BidProcessingType) {
+    let limit_price = match limit_price {
+        Some(limit_price) => limit_price,
+        None => return (bid, bid_type),
+    };
+    let price = match (bid_type, BidKind::side()) {
+        (BidProcessingType::Market, _) => limit_price,
+        (_, Side::Buy) => bid.price.min(limit_price),
+        (_, Side::Sell) => bid.price.max(limit_price),
+    };
+    debug!("Protect a market bid at the price {}", price);
+    (bid.price(price), BidProcessingType::ImmediateOrCancel)
+}
+
+/// Cancels a bid in the pool, if it's there.
+fn cancel_in<BidKind>(
+    pool: &mut Pool<BidKind>,
+    order_id: OrderId,
+    listener: &mut dyn OrderBookListener,
+) -> Option<Cancellation>
+where
+    BidKind: GenericBid,
+    PriceKey<BidKind>: Ord,
+{
+    let original_amount = pool.original_amount(order_id.0)?;
+    let bid = pool.cancel(order_id.0)?;
+    listener.on_feed_event(&FeedEvent::Cancel { order_id });
+    let order = OrderInfo::from(&bid);
+    if bid.amount == original_amount {
+        Some(Cancellation::Cancelled(order))
+    } else {
+        Some(Cancellation::PartiallyFilled(order))
+    }
+}
+
+/// Total displayed amount of the levels of a [`Depth`].
+fn displayed_total(levels: &[(u64, u64, usize)]) -> u128 {
+    levels
+        .iter()
+        .map(|&(_price, amount, _bids)| u128::from(amount))
+        .sum()
+}
+
+/// Reports the status of a bid resting in the pool.
+fn status_in<BidKind>(pool: &Pool<BidKind>, order_id: OrderId) -> Option<OrderStatus>
+where
+    BidKind: GenericBid,
+    PriceKey<BidKind>: Ord,
+{
+    let original_amount = pool.original_amount(order_id.0)?;
+    let bid = pool.find(order_id.0)?;
+    Some(OrderStatus::new(&bid, original_amount))
+}
+
+/// Processing type to submit an amended bid resting in the pool with, if it has to be replaced.
+fn resubmitted_type<BidKind>(pool: &Pool<BidKind>, order_id: OrderId) -> BidProcessingType
+where
+    PriceKey<BidKind>: Ord,
+{
+    if pool.is_post_only(order_id.0) == Some(true) {
+        BidProcessingType::PostOnly
+    } else {
+        BidProcessingType::Limit
+    }
+}
+
+/// Amends a bid in place if that doesn't change its priority, i.e. if it's the same price and the
+/// same or a lower size.
+fn amend_in<BidKind>(
+    pool: &mut Pool<BidKind>,
+    order_id: OrderId,
+    bid: &Bid<BidKind>,
+    new_price: u64,
+    new_size: u64,
+    listener: &mut dyn OrderBookListener,
+) -> Option<OrderInfo>
+where
+    BidKind: GenericBid,
+    PriceKey<BidKind>: Ord,
+{
+    if bid.price != new_price || bid.amount < new_size {
+        return None;
+    } else if bid.amount == new_size {
+        return Some(OrderInfo::from(bid));
+    }
+    let displayed = pool.displayed_amount(order_id.0)?;
+    let reduced = pool.reduce(order_id.0, new_size)?;
+    let cut = displayed - pool.displayed_amount(order_id.0)?;
+    if cut != 0 {
+        listener.on_feed_event(&FeedEvent::Reduce {
+            order_id,
+            amount: cut,
+        });
+    }
+    Some(OrderInfo::from(&reduced))
+}
+
+fn log_hold<BidKind: GenericBid>(condition: &dyn fmt::Display, bid: &Bid<BidKind>) {
+    info!(
+        "[HOLD ] Hold a {} from user {} (price: {}, size: {}) until {}",
+        BidKind::kind_name(),
+        bid.user_id,
+        bid.price,
+        bid.amount,
+        condition
+    );
+}
+
+fn log_release<BidKind: GenericBid>(condition: &dyn fmt::Display, bid: &Bid<BidKind>) {
+    info!(
+        "[TRIG ] Release a {} from user {} (price: {}, size: {}) since {}",
+        BidKind::kind_name(),
+        bid.user_id,
+        bid.price,
+        bid.amount,
+        condition
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Protection, TimeInForce},
+        clock::ManualClock,
+        mmp::MakerProtection,
+        session::SessionSchedule,
+        tape::TradeId,
+        throttle::{RateLimit, ThrottleMode},
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_cancel() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let first = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let second = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(100).amount(7).user_id(2);
+        let third = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(
+            (first.order_id, second.order_id, third.order_id),
+            (OrderId(0), OrderId(1), OrderId(2))
+        );
+        let conditional = order_book.process_buying_when(
+            Condition::BestAskAtMost(90),
+            buying_bid,
+            BidProcessingType::Limit,
+        );
+
+        let partially_filled = OrderInfo {
+            side: Side::Sell,
+            price: 100,
+            amount: 3,
+            user_id: 1,
+        };
+        assert_eq!(
+            order_book.cancel(second.order_id),
+            Cancellation::PartiallyFilled(partially_filled)
+        );
+        assert_eq!(
+            order_book.cancel(conditional.order_id),
+            Cancellation::Cancelled(OrderInfo::from(&buying_bid))
+        );
+        assert_eq!(order_book.cancel(first.order_id), Cancellation::AlreadyGone);
+        assert_eq!(
+            order_book.cancel(second.order_id),
+            Cancellation::AlreadyGone
+        );
+        assert_eq!(order_book.cancel(OrderId(4)), Cancellation::Unknown);
+        assert_eq!(order_book.sellers.view_bids().count(), 0);
+        assert_eq!(order_book.buyers.view_bids().count(), 0);
+        assert!(order_book.pending.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_user() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_selling(selling_bid.price(101).user_id(2), BidProcessingType::Limit);
+        order_book.process_selling(selling_bid.price(102), BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(99).amount(3).user_id(1);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let stop = Bid::empty().price(105).amount(3).user_id(1);
+        let stop = order_book.process_buying_stop(101, stop, BidProcessingType::Limit);
+        order_book.process_buying(
+            buying_bid.price(100).amount(2).user_id(3),
+            BidProcessingType::Limit,
+        );
+
+        let cancelled = order_book.cancel_user(1);
+        let expected = vec![
+            (
+                OrderId(0),
+                Cancellation::PartiallyFilled(OrderInfo::from(&selling_bid.amount(3))),
+            ),
+            (
+                OrderId(2),
+                Cancellation::Cancelled(OrderInfo::from(&selling_bid.price(102))),
+            ),
+            (
+                OrderId(3),
+                Cancellation::Cancelled(OrderInfo::from(&buying_bid)),
+            ),
+        ];
+        assert_eq!(cancelled, expected);
+        assert_eq!(order_book.sell_orders().count(), 1);
+        assert_eq!(order_book.buy_orders().count(), 0);
+        assert!(order_book.get(stop.order_id).is_some());
+        assert_eq!(order_book.cancel_user(1), []);
+    }
+
+    #[test]
+    fn test_amend() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let first = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let second = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+
+        // Reducing the size keeps the priority.
+        assert_eq!(
+            order_book.amend(first.order_id, 100, 2),
+            Amendment::Amended(OrderInfo::from(&selling_bid.amount(2)))
+        );
+        let resting: Vec<_> = order_book.sellers.view_bids().cloned().collect();
+        assert_eq!(resting, [selling_bid.amount(2), selling_bid]);
+
+        // Increasing the size moves the bid to the end of the queue.
+        let replaced = match order_book.amend(first.order_id, 100, 3) {
+            Amendment::Replaced(result) => result,
+            amendment => panic!("Unexpected amendment {:?}", amendment),
+        };
+        assert_eq!(replaced.order_id, OrderId(2));
+        assert!(replaced.trades.is_empty());
+        let resting: Vec<_> = order_book.sellers.view_bids().cloned().collect();
+        assert_eq!(resting, [selling_bid, selling_bid.amount(3)]);
+        assert_eq!(
+            order_book.amend(first.order_id, 100, 3),
+            Amendment::AlreadyGone
+        );
+
+        // A bid that crosses the book after the amendment is executed.
+        let buying_bid = Bid::empty().price(90).amount(4).user_id(2);
+        let buying = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let replaced = match order_book.amend(buying.order_id, 100, 4) {
+            Amendment::Replaced(result) => result,
+            amendment => panic!("Unexpected amendment {:?}", amendment),
+        };
+        assert_eq!(replaced.trades.len(), 1);
+        assert_eq!(replaced.trades[0].maker_user_id, 1);
+        assert_eq!(replaced.trades[0].amount, 4);
+        assert_eq!(order_book.buyers.view_bids().count(), 0);
+
+        assert_eq!(
+            order_book.amend(second.order_id, 100, 0),
+            Amendment::Cancelled(Cancellation::PartiallyFilled(OrderInfo::from(
+                &selling_bid.amount(1)
+            )))
+        );
+        assert_eq!(order_book.amend(OrderId(10), 100, 1), Amendment::Unknown);
+
+        // A post-only bid stays post-only, so it's dropped rather than executed.
+        let post_only = order_book.process_buying(buying_bid, BidProcessingType::PostOnly);
+        assert_eq!(order_book.buyers.view_bids().count(), 1);
+        let replaced = match order_book.amend(post_only.order_id, 100, 4) {
+            Amendment::Replaced(result) => result,
+            amendment => panic!("Unexpected amendment {:?}", amendment),
+        };
+        assert!(replaced.trades.is_empty());
+        assert_eq!(order_book.buyers.view_bids().count(), 0);
+        assert_eq!(order_book.sellers.view_bids().count(), 1);
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut order_book = OrderBook::empty();
+        let day = 24 * 60 * 60;
+        order_book.advance_time(10 * day + 100);
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let gtc = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let gtd = order_book.process_selling(
+            selling_bid.time_in_force(TimeInForce::GoodTillDate(10 * day + 200)),
+            BidProcessingType::Limit,
+        );
+        let day_bid = order_book.process_selling(
+            selling_bid.time_in_force(TimeInForce::Day),
+            BidProcessingType::Limit,
+        );
+        assert!(order_book.advance_time(10 * day + 199).is_empty());
+        assert_eq!(order_book.advance_time(10 * day + 200), [gtd.order_id]);
+        assert!(order_book.advance_time(11 * day - 1).is_empty());
+        assert_eq!(order_book.advance_time(11 * day), [day_bid.order_id]);
+        assert!(order_book.advance_time(100 * day).is_empty());
+        assert_eq!(
+            order_book.cancel(gtc.order_id),
+            Cancellation::Cancelled(OrderInfo::from(&selling_bid))
+        );
+    }
+
+    #[test]
+    fn test_advance_time() {
+        struct ExpiryListener(Rc<RefCell<Vec<OrderInfo>>>);
+
+        impl OrderBookListener for ExpiryListener {
+            fn on_order_expired(&mut self, order: &OrderInfo) {
+                self.0.borrow_mut().push(*order)
+            }
+        }
+
+        let expired = Rc::new(RefCell::new(Vec::new()));
+        let mut order_book = OrderBook::empty();
+        order_book.add_listener(ExpiryListener(expired.clone()));
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let mut sell = |price, expiry| {
+            order_book
+                .process_selling(
+                    selling_bid
+                        .price(price)
+                        .time_in_force(TimeInForce::GoodTillDate(expiry)),
+                    BidProcessingType::Limit,
+                )
+                .order_id
+        };
+        let first = sell(100, 30);
+        let second = sell(101, 10);
+        let third = sell(102, 20);
+        let fourth = sell(103, 20);
+        let cancelled = sell(104, 10);
+        order_book.cancel(cancelled);
+        // A partial fill doesn't change the expiry.
+        order_book.process_buying(
+            Bid::empty().price(100).amount(2).user_id(2),
+            BidProcessingType::Limit,
+        );
+        assert_eq!(order_book.advance_time(20), [second, third, fourth]);
+        assert_eq!(
+            *expired.borrow(),
+            [
+                OrderInfo::from(&selling_bid.price(101)),
+                OrderInfo::from(&selling_bid.price(102)),
+                OrderInfo::from(&selling_bid.price(103)),
+            ]
+        );
+        assert!(order_book.advance_time(29).is_empty());
+        assert_eq!(order_book.advance_time(30), [first]);
+        assert_eq!(
+            expired.borrow().last(),
+            Some(&OrderInfo::from(&selling_bid.amount(3)))
+        );
+    }
+
+    #[test]
+    fn test_uncross() {
+        let mut order_book = OrderBook::empty();
+        order_book.start_auction();
+        let selling_bid = Bid::empty().price(98).amount(3).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(101).amount(4).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let selling_bid = Bid::empty().amount(2).user_id(3);
+        order_book.process_selling(selling_bid, BidProcessingType::Market);
+        order_book.process_selling(selling_bid, BidProcessingType::ImmediateOrCancel);
+        let buying_bid = Bid::empty().price(97).amount(1).user_id(4);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert!(order_book.is_in_auction());
+        assert_eq!(order_book.sellers.view_bids().count(), 2);
+        assert_eq!(order_book.buyers.view_bids().count(), 2);
+        let equilibrium = order_book.indicative_price().unwrap();
+        assert_eq!(
+            (equilibrium.price, equilibrium.volume, equilibrium.imbalance),
+            (98, 4, 1)
+        );
+        assert_eq!(equilibrium.imbalance_side, Some(Side::Sell));
+
+        let trades: Vec<_> = order_book
+            .uncross()
+            .iter()
+            .map(|trade| {
+                (
+                    trade.maker_user_id,
+                    trade.taker_user_id,
+                    trade.price,
+                    trade.amount,
+                )
+            })
+            .collect();
+        assert_eq!(trades, [(2, 3, 98, 2), (1, 2, 98, 2)]);
+        assert!(!order_book.is_in_auction());
+        assert_eq!(order_book.indicative_price(), None);
+        assert_eq!(order_book.last_trade_price(), Some(98));
+        let resting: Vec<_> = order_book.sellers.view_bids().cloned().collect();
+        assert_eq!(resting, [Bid::empty().price(98).amount(1).user_id(1)]);
+    }
+
+    #[test]
+    fn test_uncross_huge_amounts() {
+        let max = u64::MAX;
+        let mut order_book = OrderBook::empty();
+        order_book.start_auction();
+        let buying_bid = Bid::empty().price(101).amount(max).user_id(1);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        order_book.process_buying(buying_bid.price(100), BidProcessingType::Limit);
+        let selling_bid = Bid::empty().price(100).amount(1).user_id(2);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let equilibrium = order_book.indicative_price().unwrap();
+        assert_eq!(
+            (equilibrium.price, equilibrium.volume, equilibrium.imbalance),
+            (101, 1, max - 1)
+        );
+
+        let trades: Vec<_> = order_book
+            .uncross()
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.price, trade.amount))
+            .collect();
+        assert_eq!(trades, [(1, 101, 1)]);
+        assert_eq!(
+            order_book.depth(2).buying,
+            [(101, max - 1, 1), (100, max, 1)]
+        );
+    }
+
+    #[test]
+    fn test_price_band() {
+        let mut order_book = OrderBook::with_config(Config {
+            price_band_bps: Some(1000),
+            ..Config::default()
+        });
+        assert_eq!(order_book.price_band(), None);
+        order_book.set_reference_price(Some(100));
+        assert_eq!(order_book.price_band(), Some((90, 110)));
+        for &price in &[85, 95, 115] {
+            let selling_bid = Bid::empty().price(price).amount(2).user_id(1);
+            order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        }
+        let resting: Vec<_> = order_book
+            .sellers
+            .view_bids()
+            .map(|bid| bid.price)
+            .collect();
+        assert_eq!(resting, [95]);
+
+        // A market bid doesn't go beyond the band.
+        let selling_bid = Bid::empty().price(108).amount(2).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.set_reference_price(Some(96));
+        let buying_bid = Bid::empty().amount(5).user_id(2);
+        let result = order_book.process_buying(buying_bid, BidProcessingType::Market);
+        let traded: u64 = result.trades.iter().map(|trade| trade.amount).sum();
+        assert_eq!(traded, 2);
+        assert_eq!(order_book.buyers.view_bids().count(), 0);
+        assert_eq!(order_book.price_band(), Some((87, 105)));
+    }
+
+    #[test]
+    fn test_protection() {
+        let mut order_book = OrderBook::empty();
+        for &price in &[100, 101, 105] {
+            let selling_bid = Bid::empty().price(price).amount(2).user_id(1);
+            order_book.process_selling(selling_bid, BidProcessingType::Limit);
+            let buying_bid = Bid::empty().price(price - 10).amount(2).user_id(1);
+            order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        }
+        let prices = |trades: &[Trade]| -> Vec<_> {
+            trades
+                .iter()
+                .map(|trade| (trade.price, trade.amount))
+                .collect()
+        };
+
+        let buying_bid = Bid::empty()
+            .amount(10)
+            .user_id(2)
+            .protection(Protection::Price(101));
+        let result = order_book.process_buying(buying_bid, BidProcessingType::Market);
+        assert_eq!(prices(&result.trades), [(100, 2), (101, 2)]);
+        assert_eq!(order_book.best_ask(), Some(105));
+        assert_eq!(order_book.best_bid(), Some(95));
+
+        // The slippage is counted from the best bid, 95.
+        let selling_bid = Bid::empty()
+            .amount(10)
+            .user_id(2)
+            .protection(Protection::Slippage(4));
+        let result = order_book.process_selling(selling_bid, BidProcessingType::Market);
+        assert_eq!(prices(&result.trades), [(95, 2), (91, 2)]);
+        assert_eq!(order_book.best_bid(), Some(90));
+        assert_eq!(order_book.sell_orders().count(), 1);
+
+        // The price band is tighter than the protection.
+        let mut order_book = OrderBook::with_config(Config {
+            price_band_bps: Some(100),
+            ..Config::default()
+        });
+        order_book.set_reference_price(Some(100));
+        for &price in &[100, 101, 102] {
+            let selling_bid = Bid::empty().price(price).amount(2).user_id(1);
+            order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        }
+        let result = order_book.process_buying(
+            buying_bid.protection(Protection::Slippage(5)),
+            BidProcessingType::Market,
+        );
+        assert_eq!(prices(&result.trades), [(100, 2), (101, 2)]);
+    }
+
+    #[test]
+    fn test_session() {
+        let mut order_book = OrderBook::with_config(Config {
+            session: Some(SessionSchedule {
+                opening_auction: 100,
+                continuous: 200,
+                closing_auction: 300,
+                closed: 400,
+            }),
+            ..Config::default()
+        });
+        assert_eq!(order_book.phase(), Phase::PreOpen);
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let buying_bid = Bid::empty().price(105).amount(5).user_id(2);
+        let dropped = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        assert_eq!(order_book.get(dropped.order_id), None);
+
+        order_book.advance_time(100);
+        assert_eq!(order_book.phase(), Phase::OpeningAuction);
+        assert!(order_book.is_in_auction());
+        order_book.process_selling(selling_bid.amount(8), BidProcessingType::Limit);
+        let result = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert!(result.trades.is_empty());
+
+        // The opening auction is uncrossed.
+        order_book.advance_time(200);
+        assert_eq!(order_book.phase(), Phase::Continuous);
+        assert!(!order_book.is_in_auction());
+        assert_eq!(order_book.trades().entries().len(), 1);
+        assert_eq!(order_book.trades().entries()[0].trade.amount, 5);
+        let result = order_book.process_buying(buying_bid.amount(1), BidProcessingType::Limit);
+        assert_eq!(result.trades.len(), 1);
+
+        order_book.advance_time(300);
+        assert_eq!(order_book.phase(), Phase::ClosingAuction);
+        let result = order_book.process_buying(buying_bid.amount(1), BidProcessingType::Limit);
+        assert!(result.trades.is_empty());
+
+        // The closing auction is uncrossed.
+        order_book.advance_time(400);
+        assert_eq!(order_book.phase(), Phase::Closed);
+        assert_eq!(order_book.trades().entries().len(), 3);
+        assert_eq!(order_book.best_ask(), Some(100));
+        let dropped = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(order_book.get(dropped.order_id), None);
+
+        // The next day begins.
+        order_book.advance_time(24 * 60 * 60);
+        assert_eq!(order_book.phase(), Phase::PreOpen);
+    }
+
+    #[test]
+    fn test_halt() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.halt();
+        assert!(order_book.is_halted());
+        let buying_bid = Bid::empty().price(100).amount(2).user_id(2);
+        let first = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let second = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let third = order_book.process_buying(buying_bid, BidProcessingType::Market);
+        assert!(first.trades.is_empty() && second.trades.is_empty() && third.trades.is_empty());
+        assert_eq!(
+            order_book.cancel(second.order_id),
+            Cancellation::Cancelled(OrderInfo::from(&buying_bid))
+        );
+        assert_eq!(
+            order_book.amend(third.order_id, 100, 4),
+            Amendment::Amended(OrderInfo::from(&buying_bid.amount(4)))
+        );
+
+        let trades: Vec<_> = order_book
+            .resume()
+            .iter()
+            .map(|trade| (trade.taker_user_id, trade.amount))
+            .collect();
+        assert_eq!(trades, [(2, 2), (2, 3)]);
+        assert!(!order_book.is_halted());
+
+        let mut order_book = OrderBook::with_config(Config {
+            halt_mode: HaltMode::Reject,
+            ..Config::default()
+        });
+        order_book.halt();
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        assert!(order_book.resume().is_empty());
+        assert_eq!(order_book.sellers.view_bids().count(), 0);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let rate_limit = RateLimit {
+            per_second: 1,
+            burst: 2,
+            mode: ThrottleMode::Queue,
+        };
+        let mut order_book = OrderBook::with_config(Config {
+            rate_limit: Some(rate_limit),
+            ..Config::default()
+        });
+        order_book.advance_time(10);
+        let selling_bid = Bid::empty().price(100).amount(1).user_id(1);
+        let ids: Vec<_> = (0..4)
+            .map(|_| {
+                order_book
+                    .process_selling(selling_bid, BidProcessingType::Limit)
+                    .order_id
+            })
+            .collect();
+        order_book.process_selling(selling_bid.user_id(2), BidProcessingType::Limit);
+        assert_eq!(order_book.open_orders(1), 2);
+        assert_eq!(order_book.open_orders(2), 1);
+        assert!(order_book.get(ids[2]).is_some());
+        assert_eq!(
+            order_book.cancel(ids[2]),
+            Cancellation::Cancelled(OrderInfo::from(&selling_bid))
+        );
+        order_book.advance_time(11);
+        assert_eq!(order_book.open_orders(1), 3);
+        let buying_bid = Bid::empty().price(100).amount(4).user_id(3);
+        let result = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        // The released bid queues behind the bid of user 2 that was resting before the release.
+        let makers: Vec<_> = result
+            .trades
+            .iter()
+            .map(|trade| trade.maker_user_id)
+            .collect();
+        assert_eq!(makers, [1, 1, 2, 1]);
+
+        let mut order_book = OrderBook::with_config(Config {
+            rate_limit: Some(RateLimit {
+                mode: ThrottleMode::Reject,
+                ..rate_limit
+            }),
+            ..Config::default()
+        });
+        for _ in 0..3 {
+            order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        }
+        order_book.advance_time(100);
+        assert_eq!(order_book.open_orders(1), 2);
+    }
+
+    #[test]
+    fn test_maker_protection() {
+        let mut order_book = OrderBook::with_config(Config {
+            maker_protection: Some(MakerProtection {
+                users: vec![7],
+                window: 5,
+                max_fills: Some(2),
+                max_quantity: None,
+            }),
+            ..Config::default()
+        });
+        let quote = Bid::empty().price(100).amount(10).user_id(7);
+        order_book.process_selling(quote, BidProcessingType::Limit);
+        order_book.process_selling(quote.price(101), BidProcessingType::Limit);
+        order_book.process_buying(
+            Bid::empty().price(90).amount(10).user_id(7),
+            BidProcessingType::Limit,
+        );
+        let buying_bid = Bid::empty().price(100).amount(1).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        order_book.advance_time(3);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        // The first fill is out of the window by now.
+        order_book.advance_time(5);
+        let events = order_book.apply(Command::Buying(buying_bid, BidProcessingType::Limit));
+        assert!(!events.contains(&Event::ProtectionTriggered(7)));
+        assert_eq!(order_book.open_orders(7), 3);
+
+        // Selling to the buying quote is another fill, over the limit.
+        let selling_bid = Bid::empty().price(90).amount(2).user_id(3);
+        let events = order_book.apply(Command::Selling(selling_bid, BidProcessingType::Limit));
+        let triggered = events
+            .iter()
+            .position(|event| *event == Event::ProtectionTriggered(7))
+            .unwrap();
+        assert!(events[..triggered]
+            .iter()
+            .any(|event| matches!(event, Event::Trade(_))));
+        let cancelled = events[triggered..]
+            .iter()
+            .filter(|event| matches!(event, Event::OrderCancelled(_)))
+            .count();
+        assert_eq!(cancelled, 3);
+        assert_eq!(order_book.open_orders(7), 0);
+        assert_eq!(order_book.open_orders(3), 0);
+    }
+
+    #[test]
+    fn test_one_cancels_other() {
+        let take_profit = Bid::empty().price(110).amount(10).user_id(1).link(7);
+        let stop_loss = take_profit.price(0);
+        let mut order_book = OrderBook::empty();
+        let limit = order_book.process_selling(take_profit, BidProcessingType::Limit);
+        let stop = order_book.process_selling_stop(90, stop_loss, BidProcessingType::Market);
+        // Another link of the same user and the same link of another user are not affected.
+        let other_link =
+            order_book.process_selling(take_profit.price(120).link(8), BidProcessingType::Limit);
+        let other_user =
+            order_book.process_selling(take_profit.price(120).user_id(2), BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(110).amount(3).user_id(3);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(
+            order_book
+                .get(limit.order_id)
+                .map(|status| status.remaining),
+            Some(7)
+        );
+        assert_eq!(order_book.get(stop.order_id), None);
+        assert!(order_book.get(other_link.order_id).is_some());
+        assert!(order_book.get(other_user.order_id).is_some());
+
+        let mut order_book = OrderBook::empty();
+        let limit = order_book.process_selling(take_profit, BidProcessingType::Limit);
+        order_book.process_selling_stop(90, stop_loss, BidProcessingType::Market);
+        order_book.process_buying(buying_bid.price(90).amount(20), BidProcessingType::Limit);
+        let selling_bid = Bid::empty().price(90).amount(1).user_id(2);
+        let result = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        // The trade has triggered the stop bid, and its execution has cancelled the limit bid.
+        let sellers: Vec<_> = result
+            .trades
+            .iter()
+            .map(|trade| (trade.taker_user_id, trade.amount))
+            .collect();
+        assert_eq!(sellers, [(2, 1), (1, 10)]);
+        assert_eq!(order_book.get(limit.order_id), None);
+        assert_eq!(order_book.best_ask(), None);
+        assert!(order_book.links.is_empty());
+        assert!(order_book.linked.is_empty());
+    }
+
+    #[test]
+    fn test_reduce_only() {
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        let mut order_book = OrderBook::empty();
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_buying(buying_bid.reduce_only(), BidProcessingType::Limit);
+        assert_eq!(order_book.best_ask(), Some(100));
+
+        let mut order_book = OrderBook::with_config(Config {
+            track_positions: true,
+            ..Config::default()
+        });
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        // User 1 is short of 3 items, user 2 is long.
+        order_book.process_selling(selling_bid.user_id(2), BidProcessingType::Limit);
+        let result = order_book.process_buying(
+            buying_bid.user_id(1).amount(5).reduce_only(),
+            BidProcessingType::Limit,
+        );
+        let traded: Vec<_> = result.trades.iter().map(|trade| trade.amount).collect();
+        assert_eq!(traded, [3]);
+        assert_eq!(order_book.accounts().unwrap().position(1), 0);
+        assert_eq!(order_book.best_bid(), None);
+        assert!(order_book
+            .process_selling(
+                selling_bid.user_id(1).reduce_only(),
+                BidProcessingType::Limit
+            )
+            .trades
+            .is_empty());
+        // Only the rest of the first selling bid.
+        assert_eq!(order_book.open_orders(1), 1);
+    }
+
+    #[test]
+    fn test_clock() {
+        let clock = ManualClock::new(100);
+        let mut order_book = OrderBook::empty();
+        order_book.set_clock(clock.clone());
+        let selling_bid = Bid::empty()
+            .price(100)
+            .amount(5)
+            .user_id(1)
+            .time_in_force(TimeInForce::GoodTillDate(110));
+        let result = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        assert_eq!(order_book.now(), 100);
+        clock.advance(10);
+        // The bid has expired before the buying bid is processed.
+        let buying_bid = Bid::empty().price(100).amount(5).user_id(2);
+        let trades = order_book
+            .process_buying(buying_bid, BidProcessingType::Limit)
+            .trades;
+        assert!(trades.is_empty());
+        assert_eq!(order_book.now(), 110);
+        assert_eq!(order_book.get(result.order_id), None);
+        // The clock doesn't move the time backwards.
+        clock.set(50);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        assert_eq!(order_book.now(), 110);
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut order_book = OrderBook::empty();
+        for &(price, amount) in &[(101, 2), (100, 3), (101, 4), (103, 1)] {
+            let selling_bid = Bid::empty().price(price).amount(amount).user_id(1);
+            order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        }
+        let iceberg = Bid::empty().price(98).amount(10).display(2).user_id(2);
+        order_book.process_buying(iceberg, BidProcessingType::Limit);
+        let expected = Depth {
+            selling: vec![(100, 3, 1), (101, 6, 2)],
+            buying: vec![(98, 2, 1)],
+        };
+        assert_eq!(order_book.depth(2), expected);
+        assert_eq!(order_book.depth(5).selling.len(), 3);
+        assert_eq!(order_book.depth(0).buying, []);
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let depth = Depth {
+            selling: vec![(100, 5, 1), (101, 2, 1)],
+            buying: vec![(99, 3, 2), (98, 1, 1)],
+        };
+        // CRC-32 of "99:3:100:5:98:1:101:2".
+        assert_eq!(depth.checksum(), 2_798_320_264);
+        let depth = Depth {
+            selling: vec![(100, 5, 1)],
+            buying: Vec::new(),
+        };
+        assert_eq!(depth.checksum(), crc32(b"100:5"));
+        assert_eq!(OrderBook::empty().depth(10).checksum(), 0);
+    }
+
+    #[test]
+    fn test_imbalance() {
+        let mut order_book = OrderBook::empty();
+        assert_eq!(order_book.imbalance(5), None);
+        let selling_bid = Bid::empty().price(102).amount(1).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_selling(selling_bid.price(103).amount(5), BidProcessingType::Limit);
+        assert_eq!(order_book.imbalance(5), Some(-1.));
+        assert_eq!(order_book.weighted_mid_price(5), None);
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+
+        assert_eq!(order_book.imbalance(1), Some(0.5));
+        assert_eq!(order_book.weighted_mid_price(1), Some(101.5));
+        assert_eq!(order_book.imbalance(2), Some(-1. / 3.));
+        // (100 * 6 + 102 * 3) / 9
+        assert_eq!(order_book.weighted_mid_price(2), Some(906. / 9.));
+        assert_eq!(order_book.imbalance(0), None);
+        assert_eq!(order_book.weighted_mid_price(0), None);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        let selling = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let iceberg = Bid::empty().price(98).amount(10).display(2).user_id(2);
+        let iceberg = order_book.process_buying(iceberg, BidProcessingType::Limit);
+        let stop = Bid::empty().price(105).amount(3).user_id(3);
+        let stop = order_book.process_buying_stop(101, stop, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(100).amount(2).user_id(4);
+        let buying = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+
+        let expected = OrderStatus {
+            side: Side::Sell,
+            price: 100,
+            remaining: 3,
+            original: 5,
+        };
+        assert_eq!(order_book.get(selling.order_id), Some(expected));
+        let expected = OrderStatus {
+            side: Side::Buy,
+            price: 98,
+            remaining: 10,
+            original: 10,
+        };
+        assert_eq!(order_book.get(iceberg.order_id), Some(expected));
+        let expected = OrderStatus {
+            side: Side::Buy,
+            price: 105,
+            remaining: 3,
+            original: 3,
+        };
+        assert_eq!(order_book.get(stop.order_id), Some(expected));
+        assert_eq!(order_book.get(buying.order_id), None);
+        assert_eq!(order_book.get(OrderId(10)), None);
+    }
+
+    #[test]
+    fn test_fill_or_kill() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(3).user_id(1);
+        let first = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let second = order_book.process_selling(selling_bid.amount(4), BidProcessingType::Limit);
+        let third = order_book.process_selling(selling_bid.price(101), BidProcessingType::Limit);
+
+        // The bids a fill-or-kill bid consumes completely leave the book.
+        let buying_bid = Bid::empty().price(100).amount(7).user_id(2);
+        let result = order_book.process_buying(buying_bid, BidProcessingType::FillOrKill);
+        assert_eq!(result.trades.len(), 2);
+        assert_eq!(order_book.get(first.order_id), None);
+        assert_eq!(order_book.get(second.order_id), None);
+        assert!(order_book.get(third.order_id).is_some());
+        assert_eq!(order_book.sell_orders().count(), 1);
+        assert_eq!(order_book.depth(5).selling, [(101, 3, 1)]);
+
+        // Nothing is left to trade with at the price.
+        let simulation = order_book.simulate_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(simulation.traded_amount(), 0);
+        let result = order_book.process_buying(buying_bid, BidProcessingType::FillOrKill);
+        assert!(result.trades.is_empty());
+    }
+
+    #[test]
+    fn test_orders() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(101).amount(2).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_selling(selling_bid.price(100), BidProcessingType::Limit);
+        order_book.process_selling(selling_bid.amount(4), BidProcessingType::Limit);
+        let iceberg = Bid::empty().price(98).amount(10).display(2).user_id(2);
+        order_book.process_buying(iceberg, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(99).amount(1).user_id(3);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+
+        let selling: Vec<_> = order_book.sell_orders().collect();
+        let expected = [
+            (OrderId(1), &selling_bid.price(100)),
+            (OrderId(0), &selling_bid),
+            (OrderId(2), &selling_bid.amount(4)),
+        ];
+        assert_eq!(selling, expected);
+        let buying: Vec<_> = order_book.buy_orders().collect();
+        assert_eq!(
+            buying,
+            [(OrderId(4), &buying_bid), (OrderId(3), &iceberg.amount(2))]
+        );
+    }
+
+    #[test]
+    fn test_batch() {
+        let orders = vec![
+            IncomingOrder::Selling(
+                Bid::empty().price(100).amount(5).user_id(1),
+                BidProcessingType::Limit,
+            ),
+            IncomingOrder::Selling(
+                Bid::empty().price(102).amount(5).user_id(1),
+                BidProcessingType::Limit,
+            ),
+            IncomingOrder::Buying(
+                Bid::empty().price(100).amount(2).user_id(2),
+                BidProcessingType::Limit,
+            ),
+            IncomingOrder::Buying(
+                Bid::empty().price(0).amount(4).user_id(3),
+                BidProcessingType::Market,
+            ),
+        ];
+        let stop = Bid::empty().price(105).amount(3).user_id(4);
+
+        let mut order_book = OrderBook::empty();
+        order_book.process_buying_stop(101, stop, BidProcessingType::Limit);
+        let mut trades = Vec::new();
+        let mut order_ids = Vec::new();
+        for &order in &orders {
+            let result = match order {
+                IncomingOrder::Selling(bid, bid_type) => order_book.process_selling(bid, bid_type),
+                IncomingOrder::Buying(bid, bid_type) => order_book.process_buying(bid, bid_type),
+            };
+            order_ids.push(result.order_id);
+            trades.extend(result.trades);
+        }
+
+        let mut batched = OrderBook::empty();
+        batched.process_buying_stop(101, stop, BidProcessingType::Limit);
+        let result = batched.process_batch(orders);
+        assert_eq!(result.order_ids, order_ids);
+        assert_eq!(result.trades, trades);
+        // The market bid has reached 102 and triggered the stop bid.
+        assert_eq!(trades.len(), 4);
+        assert_eq!(batched.depth(5), order_book.depth(5));
+        assert_eq!(batched.stats(), order_book.stats());
+        assert_eq!(order_book.stats().trades(), 4);
+        assert_eq!(order_book.stats().last_price(), Some(102));
+        assert_eq!(order_book.stats().low(), Some(100));
+
+        // Trades of the stop bid share the sequence number of the market bid that triggered it.
+        assert_eq!(batched.trades(), order_book.trades());
+        let tape: Vec<_> = order_book
+            .trades()
+            .entries()
+            .iter()
+            .map(|entry| (entry.trade_id, entry.seq, entry.trade))
+            .collect();
+        let expected: Vec<_> = trades
+            .iter()
+            .zip(vec![4, 5, 5, 5])
+            .enumerate()
+            .map(|(i, (&trade, seq))| (TradeId(i as u64 + 1), seq, trade))
+            .collect();
+        assert_eq!(tape, expected);
+        assert_eq!(order_book.trades().last_seq(), 5);
+    }
+
+    #[test]
+    fn test_accounts() {
+        let mut order_book = OrderBook::empty();
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(order_book.accounts(), None);
+
+        let config = Config {
+            track_positions: true,
+            ..Config::default()
+        };
+        let mut order_book = OrderBook::with_config(config);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_buying_stop(100, buying_bid, BidProcessingType::Market);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let accounts = order_book.accounts().unwrap();
+        // The stop bid has been released by the trade and filled the rest of the selling bid.
+        assert_eq!(accounts.get(1).position, -5);
+        assert_eq!(accounts.get(1).cash, 500);
+        assert_eq!(accounts.get(2).position, 5);
+        assert_eq!(accounts.get(2).cash, -500);
+
+        let mut replayed = Accounts::new();
+        replayed.extend(
+            order_book
+                .trades()
+                .entries()
+                .iter()
+                .map(|entry| &entry.trade),
+        );
+        assert_eq!(&replayed, accounts);
+    }
+
+    #[test]
+    fn test_best_prices() {
+        let mut order_book = OrderBook::empty();
+        assert_eq!(order_book.best_bid(), None);
+        assert_eq!(order_book.spread(), None);
+        let selling_bid = Bid::empty().price(105).amount(1).user_id(1);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        order_book.process_selling(selling_bid.price(103), BidProcessingType::Limit);
+        assert_eq!(order_book.best_ask(), Some(103));
+        assert_eq!(order_book.mid_price(), None);
+        let buying_bid = Bid::empty().price(100).amount(1).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(order_book.best_bid(), Some(100));
+        assert_eq!(order_book.spread(), Some(3));
+        assert_eq!(order_book.mid_price(), Some(101));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot() {
+        let mut config = Config::default();
+        config.user_groups.add_group(vec![1, 2]);
+        let mut order_book = OrderBook::with_config(config);
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        order_book.process_selling(selling_bid.display(2), BidProcessingType::Limit);
+        order_book.process_selling(selling_bid.price(101), BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(101).amount(3).user_id(3);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        order_book.process_buying_stop(101, buying_bid, BidProcessingType::Market);
+        order_book.process_selling_when(
+            Condition::BestBidAtLeast(105),
+            selling_bid,
+            BidProcessingType::Limit,
+        );
+
+        let mut data = Vec::new();
+        order_book.snapshot(&mut data).unwrap();
+        let mut restored = OrderBook::restore(&data[..]).unwrap();
+        assert_eq!(restored.depth(10), order_book.depth(10));
+        assert_eq!(restored.last_trade_price(), order_book.last_trade_price());
+        assert_eq!(restored.stats(), order_book.stats());
+        assert_eq!(restored.trades(), order_book.trades());
+
+        // Both books behave the same way, including user groups and the pending bids.
+        let buying_bid = Bid::empty().price(101).amount(6).user_id(2);
+        let expected = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let actual = restored.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(actual, expected);
+        assert_eq!(restored.depth(10), order_book.depth(10));
+        assert_eq!(
+            restored.cancel(OrderId(4)),
+            Cancellation::Cancelled(OrderInfo::from(&selling_bid))
+        );
+    }
 }
@@ -0,0 +1,150 @@
+//! Allocation of incoming bids between resting bids.
+
+use crate::{bids::Bid, key::PoolKey};
+use serde_derive::Deserialize;
+
+/// How an incoming bid is allocated between the resting bids of a price level.
+///
+/// Price levels themselves are always consumed from the best to the worst one.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum MatchingPolicy {
+    /// Bids are filled one by one in the order they were added to the pool.
+    #[default]
+    Fifo,
+    /// Bids are filled proportionally to their amounts. Items left after rounding down go one by
+    /// one to the bids in the order they were added to the pool.
+    ProRata,
+}
+
+impl MatchingPolicy {
+    /// Splits the `amount` between the `bids`, which are expected to be in the priority order.
+    ///
+    /// Returns the keys of the bids to be filled along with the amounts, in the priority order.
+    pub(crate) fn allocate<BidKind>(
+        self,
+        bids: impl Iterator<Item = (PoolKey<BidKind>, Bid<BidKind>)>,
+        amount: u64,
+    ) -> Vec<(PoolKey<BidKind>, u64)> {
+        let mut allocations = Vec::new();
+        let mut left = amount;
+        match self {
+            MatchingPolicy::Fifo => {
+                for (key, bid) in bids {
+                    if left == 0 {
+                        break;
+                    }
+                    let current = bid.amount.min(left);
+                    left -= current;
+                    allocations.push((key, current));
+                }
+            }
+            MatchingPolicy::ProRata => {
+                let mut bids = bids.peekable();
+                while left != 0 {
+                    let price = match bids.peek() {
+                        Some((_key, bid)) => bid.price,
+                        None => break,
+                    };
+                    let mut level = Vec::new();
+                    while let Some((key, bid)) = bids.next_if(|(_key, bid)| bid.price == price) {
+                        level.push((key, bid.amount));
+                    }
+                    left -= allocate_level(&level, left, &mut allocations);
+                }
+            }
+        }
+        allocations
+    }
+}
+
+/// Splits up to `amount` items between the bids of a price level proportionally to their amounts.
+///
+/// Returns the number of allocated items.
+fn allocate_level<BidKind>(
+    level: &[(PoolKey<BidKind>, u64)],
+    amount: u64,
+    allocations: &mut Vec<(PoolKey<BidKind>, u64)>,
+) -> u64 {
+    // Amounts of the bids may add up to more than `u64::MAX`.
+    let total: u128 = level
+        .iter()
+        .map(|&(_key, bid_amount)| u128::from(bid_amount))
+        .sum();
+    if total <= u128::from(amount) {
+        allocations.extend_from_slice(level);
+        return total as u64;
+    }
+    let mut shares: Vec<u64> = level
+        .iter()
+        .map(|&(_key, bid_amount)| (u128::from(bid_amount) * u128::from(amount) / total) as u64)
+        .collect();
+    // Every share is rounded down by less than one item, and since the level is not consumed
+    // completely, none of the shares covers its bid, so there is room for one more item in each.
+    let mut rest = amount - shares.iter().sum::<u64>();
+    for share in shares.iter_mut() {
+        if rest == 0 {
+            break;
+        }
+        *share += 1;
+        rest -= 1;
+    }
+    allocations.extend(
+        level
+            .iter()
+            .zip(shares)
+            .filter(|(_, share)| *share != 0)
+            .map(|(&(key, _), share)| (key, share)),
+    );
+    amount
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bids::SellingBid;
+
+    #[test]
+    fn test_allocate() {
+        let bids: Vec<(PoolKey<SellingBid>, Bid<SellingBid>)> =
+            [(100, 10), (100, 20), (100, 30), (101, 40)]
+                .iter()
+                .enumerate()
+                .map(|(id, &(price, amount))| {
+                    let bid = Bid::empty().price(price).amount(amount);
+                    (PoolKey::new(id, price), bid)
+                })
+                .collect();
+        let allocate = |policy: MatchingPolicy, amount| -> Vec<_> {
+            policy
+                .allocate(bids.iter().copied(), amount)
+                .into_iter()
+                .map(|(key, amount)| (key.id, amount))
+                .collect()
+        };
+        assert_eq!(allocate(MatchingPolicy::Fifo, 25), [(0, 10), (1, 15)]);
+        assert_eq!(
+            allocate(MatchingPolicy::ProRata, 25),
+            [(0, 5), (1, 8), (2, 12)]
+        );
+        assert_eq!(allocate(MatchingPolicy::ProRata, 2), [(0, 1), (2, 1)]);
+        assert_eq!(
+            allocate(MatchingPolicy::ProRata, 70),
+            [(0, 10), (1, 20), (2, 30), (3, 10)]
+        );
+    }
+
+    #[test]
+    fn test_allocate_huge() {
+        let max = u64::MAX;
+        let bids: Vec<(PoolKey<SellingBid>, Bid<SellingBid>)> = (0..2)
+            .map(|id| (PoolKey::new(id, 100), Bid::empty().price(100).amount(max)))
+            .collect();
+        let allocations: Vec<_> = MatchingPolicy::ProRata
+            .allocate(bids.iter().copied(), max)
+            .into_iter()
+            .map(|(key, amount)| (key.id, amount))
+            .collect();
+        assert_eq!(allocations, [(0, max / 2 + 1), (1, max / 2)]);
+    }
+}
@@ -2,100 +2,893 @@
 
 use crate::{
     bids::{Bid, BidProcessingType, GenericBid},
-    key::PoolKey,
+    feed::FeedEvent,
+    groups::UserGroups,
+    iceberg::{IcebergRefresh, Refresher},
+    key::{PoolKey, PriceKey},
+    listener::{OrderBookListener, OrderInfo},
+    order_book::OrderId,
+    policy::MatchingPolicy,
     range::MatchingRange,
+    simulation::{Quote, Simulation},
+    trade::Trade,
 };
-use log::{debug, info};
-use std::{cmp::Ord, collections::BTreeMap};
+use log::debug;
+use std::{
+    cmp::Ord,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    error::Error,
+    fmt, iter, mem,
+    ops::{Index, IndexMut, RangeBounds},
+};
+
+/// Price, time priority, hidden amount, original amount and timestamp of a bid resting in a pool,
+/// and whether it may only rest in the book.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    price: u64,
+    seq: usize,
+    timestamp: u64,
+    hidden: u64,
+    original_amount: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    post_only: bool,
+}
+
+impl Entry {
+    fn key<BidKind>(&self, id: usize) -> PoolKey<BidKind> {
+        PoolKey::with_seq(id, self.seq, self.price)
+    }
+}
+
+/// A bid resting in a pool: its id, its entry and its displayed part.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(bound = "BidKind: GenericBid")
+)]
+#[derive(Debug)]
+struct Slot<BidKind> {
+    id: usize,
+    entry: Entry,
+    bid: Bid<BidKind>,
+}
+
+impl<BidKind> Clone for Slot<BidKind> {
+    fn clone(&self) -> Self {
+        Slot {
+            id: self.id,
+            entry: self.entry,
+            bid: self.bid,
+        }
+    }
+}
+
+impl<BidKind> Slot<BidKind> {
+    fn key(&self) -> PoolKey<BidKind> {
+        self.entry.key(self.id)
+    }
+
+    /// The bid with its hidden part.
+    fn with_hidden(&self) -> Bid<BidKind> {
+        self.bid.amount(self.bid.amount + self.entry.hidden)
+    }
+}
+
+/// Storage of the bids resting in a pool, addressed by small integer handles.
+///
+/// Slots of the removed bids are reused by the new ones, so a handle is only valid while its bid
+/// is in the pool.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(bound = "BidKind: GenericBid")
+)]
+#[derive(Debug)]
+struct Slab<BidKind> {
+    slots: Vec<Option<Slot<BidKind>>>,
+    free: Vec<usize>,
+}
+
+impl<BidKind> Clone for Slab<BidKind> {
+    fn clone(&self) -> Self {
+        Slab {
+            slots: self.slots.clone(),
+            free: self.free.clone(),
+        }
+    }
+}
+
+impl<BidKind> Default for Slab<BidKind> {
+    fn default() -> Self {
+        Slab {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<BidKind> Slab<BidKind> {
+    /// Stores a bid and returns its handle.
+    fn insert(&mut self, slot: Slot<BidKind>) -> usize {
+        match self.free.pop() {
+            Some(handle) => {
+                self.slots[handle] = Some(slot);
+                handle
+            }
+            None => {
+                self.slots.push(Some(slot));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn remove(&mut self, handle: usize) -> Option<Slot<BidKind>> {
+        let slot = self.slots.get_mut(handle)?.take()?;
+        self.free.push(handle);
+        Some(slot)
+    }
+}
+
+impl<BidKind> Index<usize> for Slab<BidKind> {
+    type Output = Slot<BidKind>;
+
+    fn index(&self, handle: usize) -> &Self::Output {
+        self.slots[handle]
+            .as_ref()
+            .expect("A handle of a removed bid")
+    }
+}
+
+impl<BidKind> IndexMut<usize> for Slab<BidKind> {
+    fn index_mut(&mut self, handle: usize) -> &mut Self::Output {
+        self.slots[handle]
+            .as_mut()
+            .expect("A handle of a removed bid")
+    }
+}
+
+/// Handles of the bids resting at the same price, in the order of their time priority.
+///
+/// Total displayed and hidden amounts of the bids, as well as the amounts of each user, are kept
+/// up to date as bids are added, filled and removed, so the level can be accounted for as a whole.
+/// The totals are wide enough to never overflow, they are only saturated once reported.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(bound = "")
+)]
+#[derive(Debug)]
+struct PriceLevel<BidKind> {
+    bids: VecDeque<(PoolKey<BidKind>, usize)>,
+    displayed: u128,
+    hidden: u128,
+    /// Amount of items (including the hidden ones) and the number of bids of each user.
+    users: HashMap<u64, (u128, usize)>,
+}
+
+impl<BidKind> Clone for PriceLevel<BidKind> {
+    fn clone(&self) -> Self {
+        PriceLevel {
+            bids: self.bids.clone(),
+            displayed: self.displayed,
+            hidden: self.hidden,
+            users: self.users.clone(),
+        }
+    }
+}
+
+impl<BidKind> Default for PriceLevel<BidKind> {
+    fn default() -> Self {
+        PriceLevel {
+            bids: VecDeque::new(),
+            displayed: 0,
+            hidden: 0,
+            users: HashMap::new(),
+        }
+    }
+}
+
+impl<BidKind> PriceLevel<BidKind> {
+    /// Total amount of items at the level, including the hidden ones.
+    fn total(&self) -> u128 {
+        self.displayed + self.hidden
+    }
+
+    /// Looks up a bid by its key. Returns the index where it would be inserted if it's not there.
+    fn position(&self, key: &PoolKey<BidKind>) -> Result<usize, usize> {
+        let priority = key.priority();
+        self.bids
+            .binary_search_by(|(other, _handle)| other.priority().cmp(&priority))
+    }
 
+    /// Puts a bid into the queue according to its time priority, which is usually the lowest one.
+    fn insert(&mut self, slot: &Slot<BidKind>, handle: usize) {
+        let key = slot.key();
+        let index = self.position(&key).unwrap_or_else(|index| index);
+        self.displayed += u128::from(slot.bid.amount);
+        self.hidden += u128::from(slot.entry.hidden);
+        let user = self.users.entry(slot.bid.user_id).or_default();
+        user.0 += u128::from(slot.bid.amount) + u128::from(slot.entry.hidden);
+        user.1 += 1;
+        self.bids.insert(index, (key, handle));
+    }
+
+    fn remove(&mut self, slot: &Slot<BidKind>) {
+        if let Ok(index) = self.position(&slot.key()) {
+            self.bids.remove(index);
+            self.displayed -= u128::from(slot.bid.amount);
+            self.hidden -= u128::from(slot.entry.hidden);
+            let amount = u128::from(slot.bid.amount) + u128::from(slot.entry.hidden);
+            self.withdraw(slot.bid.user_id, amount, 1);
+        }
+    }
+
+    /// Takes the `amount` of items and the number of `bids` off the user's totals.
+    fn withdraw(&mut self, user_id: u64, amount: u128, bids: usize) {
+        if let Some(user) = self.users.get_mut(&user_id) {
+            user.0 -= amount;
+            user.1 -= bids;
+            if user.1 == 0 {
+                self.users.remove(&user_id);
+            }
+        }
+    }
+
+    /// Takes the `amount` of items from the displayed part of a bid of the user.
+    fn fill(&mut self, user_id: u64, amount: u64) {
+        self.displayed -= u128::from(amount);
+        self.withdraw(user_id, u128::from(amount), 0);
+    }
+
+    /// Takes the `amount` of items from the hidden part of a bid of the user.
+    fn cut_hidden(&mut self, user_id: u64, amount: u64) {
+        self.hidden -= u128::from(amount);
+        self.withdraw(user_id, u128::from(amount), 0);
+    }
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(bound(
+        serialize = "BidKind: GenericBid",
+        deserialize = "BidKind: GenericBid, PriceKey<BidKind>: Ord"
+    ))
+)]
 #[derive(Clone, Debug)]
-pub struct Pool<BidKind>(BTreeMap<PoolKey<BidKind>, Bid<BidKind>>, usize);
+pub struct Pool<BidKind> {
+    levels: BTreeMap<PriceKey<BidKind>, PriceLevel<BidKind>>,
+    bids: Slab<BidKind>,
+    handles: HashMap<usize, usize>,
+    /// Ids of the bids of each user, so they can be found without scanning the pool.
+    users: HashMap<u64, BTreeSet<usize>>,
+    /// Keys of the bids completely filled by the incoming bid, kept between the bids to reuse the
+    /// allocation.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    filled: Vec<PoolKey<BidKind>>,
+    last_id: usize,
+    last_seq: usize,
+    user_groups: UserGroups,
+    max_levels: Option<usize>,
+    policy: MatchingPolicy,
+    refresher: Refresher,
+    traded_volume: u64,
+    now: u64,
+    /// Expiry times and ids of the bids with a limited time in force, so the expired bids can be
+    /// found without scanning the pool.
+    expiries: BTreeSet<(u64, usize)>,
+}
 
 impl<BidKind> Default for Pool<BidKind>
 where
-    PoolKey<BidKind>: Ord,
+    PriceKey<BidKind>: Ord,
 {
     fn default() -> Self {
-        Pool(BTreeMap::new(), 0)
+        Pool {
+            levels: BTreeMap::new(),
+            bids: Slab::default(),
+            handles: HashMap::new(),
+            users: HashMap::new(),
+            filled: Vec::new(),
+            last_id: 0,
+            last_seq: 0,
+            user_groups: UserGroups::default(),
+            max_levels: None,
+            policy: MatchingPolicy::Fifo,
+            refresher: Refresher::default(),
+            traded_volume: 0,
+            now: 0,
+            expiries: BTreeSet::new(),
+        }
     }
 }
 
 impl<BidKind> Pool<BidKind>
 where
-    PoolKey<BidKind>: Ord,
+    PriceKey<BidKind>: Ord,
 {
     pub fn new() -> Self {
         Pool::default()
     }
 
+    /// Initializes a pool from bids with explicit ids.
+    ///
+    /// Bids with the same price are prioritized by their ids (the lower the better), so this allows
+    /// to reconstruct a pool with specific priorities, e.g. from a snapshot. Bids pushed afterwards
+    /// get ids greater than any of the given ones.
+    pub fn with_ids(
+        data: impl IntoIterator<Item = (usize, Bid<BidKind>)>,
+    ) -> Result<Self, DuplicateId> {
+        let mut pool = Pool::default();
+        let mut ids = HashSet::new();
+        for (id, bid) in data {
+            if !ids.insert(id) {
+                return Err(DuplicateId(id));
+            }
+            pool.insert_by_id(id, bid);
+        }
+        Ok(pool)
+    }
+
     pub fn push(&mut self, bid: Bid<BidKind>) {
-        self.1 += 1;
-        let key = PoolKey::new(self.1, bid.price);
-        self.0.insert(key, bid);
+        let original_amount = bid.amount;
+        self.insert(
+            self.last_id + 1,
+            bid,
+            BidProcessingType::Limit,
+            original_amount,
+        );
+    }
+
+    /// Inserts a bid with the given id, which is expected to be unique.
+    ///
+    /// The bid gets the lowest time priority at its price level, whatever its id is: bids that
+    /// have been held back (by a condition, a trigger, a halt or the rate limit) queue behind the
+    /// bids that were resting before they were released.
+    ///
+    /// The `original_amount` is the amount of the bid before it was (partially) executed. Only the
+    /// display quantity of an iceberg bid is put on the book, the rest of it is hidden. The
+    /// processing type of a `PostOnly` bid is remembered, see [`Pool::is_post_only`]. Returns the
+    /// displayed amount.
+    pub(crate) fn insert(
+        &mut self,
+        id: usize,
+        bid: Bid<BidKind>,
+        bid_type: BidProcessingType,
+        original_amount: u64,
+    ) -> u64 {
+        self.last_id = self.last_id.max(id);
+        let seq = self.next_seq();
+        let post_only = bid_type == BidProcessingType::PostOnly;
+        self.insert_with_seq(id, seq, bid, post_only, original_amount)
+    }
+
+    /// Inserts a bid prioritized by its id, see [`Pool::with_ids`].
+    fn insert_by_id(&mut self, id: usize, bid: Bid<BidKind>) {
+        self.last_id = self.last_id.max(id);
+        let original_amount = bid.amount;
+        self.insert_with_seq(id, id, bid, false, original_amount);
+    }
+
+    /// Time priority that comes after the ones of all the bids in the pool.
+    fn next_seq(&mut self) -> usize {
+        self.last_seq = self.last_seq.max(self.last_id) + 1;
+        self.last_seq
+    }
+
+    fn insert_with_seq(
+        &mut self,
+        id: usize,
+        seq: usize,
+        bid: Bid<BidKind>,
+        post_only: bool,
+        original_amount: u64,
+    ) -> u64 {
+        let visible = visible_amount(&bid, bid.amount);
+        let entry = Entry {
+            price: bid.price,
+            seq,
+            timestamp: self.now,
+            hidden: bid.amount - visible,
+            original_amount,
+            post_only,
+        };
+        self.put(Slot {
+            id,
+            entry,
+            bid: bid.amount(visible),
+        });
+        visible
+    }
+
+    /// Stores a bid and puts its handle on the price level.
+    fn put(&mut self, slot: Slot<BidKind>) {
+        let price = PriceKey::new(slot.entry.price);
+        let id = slot.id;
+        self.users.entry(slot.bid.user_id).or_default().insert(id);
+        if let Some(expiry) = slot.bid.time_in_force.expiry(slot.entry.timestamp) {
+            self.expiries.insert((expiry, id));
+        }
+        let handle = self.bids.insert(slot);
+        self.handles.insert(id, handle);
+        self.levels
+            .entry(price)
+            .or_default()
+            .insert(&self.bids[handle], handle);
+    }
+
+    /// Removes a bid from the storage and from its price level, dropping the level if it's left
+    /// empty.
+    fn take(&mut self, id: usize) -> Option<Slot<BidKind>> {
+        let handle = self.handles.remove(&id)?;
+        let slot = self.bids.remove(handle)?;
+        if let Some(ids) = self.users.get_mut(&slot.bid.user_id) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.users.remove(&slot.bid.user_id);
+            }
+        }
+        if let Some(expiry) = slot.bid.time_in_force.expiry(slot.entry.timestamp) {
+            self.expiries.remove(&(expiry, id));
+        }
+        let price = PriceKey::new(slot.entry.price);
+        if let Some(level) = self.levels.get_mut(&price) {
+            level.remove(&slot);
+            if level.bids.is_empty() {
+                self.levels.remove(&price);
+            }
+        }
+        Some(slot)
+    }
+
+    /// Removes a bid with the given id from the pool, if it is there.
+    ///
+    /// The amount of the returned bid includes its hidden part.
+    pub fn cancel(&mut self, id: usize) -> Option<Bid<BidKind>> {
+        self.take(id).map(|slot| slot.with_hidden())
+    }
+
+    /// Looks up a bid resting in the pool by its id.
+    fn get(&self, id: usize) -> Option<&Slot<BidKind>> {
+        self.handles.get(&id).map(|&handle| &self.bids[handle])
+    }
+
+    /// Looks up a bid resting in the pool by its id.
+    ///
+    /// The amount of the returned bid includes its hidden part.
+    pub fn find(&self, id: usize) -> Option<Bid<BidKind>> {
+        self.get(id).map(Slot::with_hidden)
+    }
+
+    /// Ids of the bids of the user resting in the pool, in ascending order.
+    pub fn user_orders(&self, user_id: u64) -> impl Iterator<Item = usize> + '_ {
+        self.users.get(&user_id).into_iter().flatten().copied()
+    }
+
+    /// Displayed amount of a bid resting in the pool, i.e. without its hidden part.
+    pub(crate) fn displayed_amount(&self, id: usize) -> Option<u64> {
+        self.get(id).map(|slot| slot.bid.amount)
+    }
+
+    /// Reduces the amount of a bid resting in the pool, keeping its priority.
+    ///
+    /// The hidden part of an iceberg bid is reduced first. Returns the updated bid, or `None` if
+    /// there's no such bid or the `new_amount` isn't less than the current one.
+    pub fn reduce(&mut self, id: usize, new_amount: u64) -> Option<Bid<BidKind>> {
+        let handle = *self.handles.get(&id)?;
+        let slot = &mut self.bids[handle];
+        let amount = slot.bid.amount + slot.entry.hidden;
+        if new_amount >= amount {
+            return None;
+        }
+        let cut = amount - new_amount;
+        let hidden_cut = cut.min(slot.entry.hidden);
+        slot.entry.original_amount -= cut;
+        slot.entry.hidden -= hidden_cut;
+        slot.bid.amount -= cut - hidden_cut;
+        if let Some(level) = self.levels.get_mut(&PriceKey::new(slot.entry.price)) {
+            level.cut_hidden(slot.bid.user_id, hidden_cut);
+            level.fill(slot.bid.user_id, cut - hidden_cut);
+        }
+        Some(slot.bid.amount(new_amount))
+    }
+
+    /// Takes the `amount` of items from the displayed part of a resting bid, which is expected to
+    /// be larger than that. Returns the rest of the bid.
+    fn fill(&mut self, id: usize, amount: u64) -> Option<&Bid<BidKind>> {
+        let handle = *self.handles.get(&id)?;
+        let slot = &mut self.bids[handle];
+        slot.bid.amount -= amount;
+        if let Some(level) = self.levels.get_mut(&PriceKey::new(slot.entry.price)) {
+            level.fill(slot.bid.user_id, amount);
+        }
+        Some(&slot.bid)
+    }
+
+    /// Checks whether a bid resting in the pool has been submitted as a `PostOnly` one.
+    pub(crate) fn is_post_only(&self, id: usize) -> Option<bool> {
+        self.get(id).map(|slot| slot.entry.post_only)
+    }
+
+    /// Returns the amount a bid resting in the pool had before it was (partially) executed.
+    pub fn original_amount(&self, id: usize) -> Option<u64> {
+        self.get(id).map(|slot| slot.entry.original_amount)
+    }
+
+    /// Advances the pool's clock to `now` and removes the bids that have expired by then.
+    ///
+    /// Bids are stamped with the pool's clock when they are added. Only the bids due by `now` are
+    /// looked at, so advancing the clock is cheap however many bids rest in the pool. Returns the
+    /// removed bids (with their ids) in the order of their expiry times, then ids.
+    pub fn expire(&mut self, now: u64) -> Vec<(usize, Bid<BidKind>)> {
+        self.now = now;
+        let expired: Vec<_> = self
+            .expiries
+            .range(..=(now, usize::MAX))
+            .map(|&(_expiry, id)| id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| Some((id, self.cancel(id)?)))
+            .collect()
+    }
+
+    /// Fills the best bids of the pool for the `amount` of items in total, regardless of user
+    /// groups and level limits.
+    ///
+    /// Returns the ids of the filled bids along with the filled parts, in the priority order.
+    pub(crate) fn take_best(
+        &mut self,
+        amount: u64,
+        listener: &mut dyn OrderBookListener,
+    ) -> Vec<(usize, Bid<BidKind>)>
+    where
+        BidKind: GenericBid,
+    {
+        let mut filled = Vec::new();
+        let mut left = amount;
+        while left != 0 {
+            let (key, bid) = match self.iter().next() {
+                Some((key, bid)) => (*key, *bid),
+                None => break,
+            };
+            let current = bid.amount.min(left);
+            left -= current;
+            self.traded_volume = self.traded_volume.saturating_add(current);
+            filled.push((key.id, bid.amount(current)));
+            listener.on_feed_event(&FeedEvent::Execute {
+                order_id: OrderId(key.id),
+                amount: current,
+            });
+            if current == bid.amount {
+                self.remove_keys(iter::once(key), listener);
+            } else if let Some(bid) = self.fill(key.id, current) {
+                listener.on_order_partially_filled(&OrderInfo::from(bid));
+            }
+        }
+        filled
+    }
+
+    /// Removes the bids that have been completely executed.
+    ///
+    /// Iceberg bids with a hidden amount are replenished instead, according to the refresh policy:
+    /// by default with their display quantity and losing their time priority. Returns whether any
+    /// bid has been replenished.
+    fn remove_keys(
+        &mut self,
+        keys: impl IntoIterator<Item = PoolKey<BidKind>>,
+        listener: &mut dyn OrderBookListener,
+    ) -> bool
+    where
+        BidKind: GenericBid,
+    {
+        let mut replenished = false;
+        for key in keys {
+            let mut slot = match self.take(key.id) {
+                Some(slot) if slot.entry.hidden != 0 => slot,
+                _ => continue,
+            };
+            let visible = match slot.bid.display {
+                Some(display) if display != 0 => self.refresher.tranche(display, slot.entry.hidden),
+                _ => slot.entry.hidden,
+            };
+            if !self.refresher.keep_priority() {
+                slot.entry.seq = self.next_seq();
+            }
+            slot.entry.hidden -= visible;
+            slot.bid.amount = visible;
+            listener.on_feed_event(&FeedEvent::AddOrder {
+                order_id: OrderId(key.id),
+                side: BidKind::side(),
+                price: slot.bid.price,
+                amount: visible,
+            });
+            self.put(slot);
+            replenished = true;
+        }
+        replenished
+    }
+
+    /// Bids resting in the pool, in the priority order.
+    fn slots(&self) -> impl Iterator<Item = &Slot<BidKind>> {
+        self.levels
+            .values()
+            .flat_map(|level| level.bids.iter())
+            .map(move |&(_key, handle)| &self.bids[handle])
+    }
+
+    /// Bids resting in the pool along with their keys, in the priority order.
+    fn iter(&self) -> impl Iterator<Item = (&PoolKey<BidKind>, &Bid<BidKind>)> {
+        self.range(..)
+    }
+
+    /// Bids resting at the given range of price levels, in the priority order.
+    fn range(
+        &self,
+        range: impl RangeBounds<PriceKey<BidKind>>,
+    ) -> impl Iterator<Item = (&PoolKey<BidKind>, &Bid<BidKind>)> {
+        self.levels
+            .range(range)
+            .flat_map(|(_price, level)| level.bids.iter())
+            .map(move |(key, handle)| (key, &self.bids[*handle].bid))
     }
 
     pub fn view_bids(&self) -> impl Iterator<Item = &Bid<BidKind>> {
-        self.0.values()
+        self.iter().map(|(_key, bid)| bid)
+    }
+
+    /// Bids resting in the pool along with their order ids, in the priority order.
+    ///
+    /// Amounts of the bids are their displayed parts.
+    pub(crate) fn orders(&self) -> impl Iterator<Item = (OrderId, &Bid<BidKind>)> {
+        self.iter().map(|(key, bid)| (OrderId(key.id), bid))
+    }
+
+    /// Bids resting in the pool along with their ids, in the priority order.
+    ///
+    /// Amounts of the bids include their hidden parts.
+    pub(crate) fn resting(&self) -> impl Iterator<Item = (usize, Bid<BidKind>)> + '_ {
+        self.slots().map(|slot| (slot.id, slot.with_hidden()))
+    }
+
+    /// Price of the bid with the highest priority, if any.
+    pub fn best_price(&self) -> Option<u64> {
+        self.levels.keys().next().map(PriceKey::price)
+    }
+
+    /// Total amount of items (including the hidden ones) at each price level, from the best level
+    /// to the worst one.
+    pub(crate) fn levels(&self) -> Vec<(u64, u64)> {
+        self.levels
+            .iter()
+            .map(|(price, level)| (price.price(), saturate(level.total())))
+            .collect()
+    }
+
+    /// Displayed amount of items and the number of bids at each of the first `max_levels` price
+    /// levels, from the best level to the worst one.
+    ///
+    /// Hidden parts of iceberg bids are not included.
+    pub fn depth(&self, max_levels: usize) -> Vec<(u64, u64, usize)> {
+        self.levels
+            .iter()
+            .take(max_levels)
+            .map(|(price, level)| {
+                let displayed = saturate(level.displayed);
+                (price.price(), displayed, level.bids.len())
+            })
+            .collect()
+    }
+
+    /// Total amount of items that has been traded against the bids of the pool.
+    pub fn traded_volume(&self) -> u64 {
+        self.traded_volume
+    }
+
+    /// Sets up groups of users that are not allowed to trade with each other.
+    pub fn set_user_groups(&mut self, user_groups: UserGroups) {
+        self.user_groups = user_groups;
+    }
+
+    /// Limits how many distinct price levels a single incoming bid may consume.
+    ///
+    /// Whatever is left of the bid after sweeping `max_levels` levels is handled according to the
+    /// bid's processing type. `None` (the default) means no limit.
+    pub fn set_max_levels(&mut self, max_levels: Option<usize>) {
+        self.max_levels = max_levels;
+    }
+
+    /// Groups of users that are not allowed to trade with each other.
+    #[cfg(feature = "debug-invariants")]
+    pub(crate) fn user_groups(&self) -> &UserGroups {
+        &self.user_groups
+    }
+
+    /// Maximum number of price levels a single incoming bid may consume, if limited.
+    #[cfg(feature = "debug-invariants")]
+    pub(crate) fn max_levels(&self) -> Option<usize> {
+        self.max_levels
+    }
+
+    /// Panics if the bookkeeping of the pool is inconsistent: a bid with nothing displayed, an
+    /// empty price level, a level or a user total that doesn't add up, or a bid missing from one
+    /// of the indices.
+    #[cfg(feature = "debug-invariants")]
+    pub(crate) fn check_invariants(&self) {
+        let mut bids = 0;
+        let mut expiring = 0;
+        for (price, level) in &self.levels {
+            assert!(!level.bids.is_empty(), "Empty level at {}", price.price());
+            let mut displayed = 0;
+            let mut hidden = 0;
+            let mut users = HashMap::<u64, (u128, usize)>::new();
+            for &(key, handle) in &level.bids {
+                let slot = &self.bids[handle];
+                assert_eq!(
+                    slot.key().priority(),
+                    key.priority(),
+                    "Stale key of bid {}",
+                    key.id
+                );
+                assert_eq!(
+                    slot.entry.price,
+                    price.price(),
+                    "Bid {} at a wrong level",
+                    key.id
+                );
+                assert_ne!(slot.bid.amount, 0, "Bid {} displays nothing", key.id);
+                assert_eq!(
+                    self.handles.get(&key.id),
+                    Some(&handle),
+                    "Bid {} isn't indexed",
+                    key.id
+                );
+                assert!(
+                    self.users
+                        .get(&slot.bid.user_id)
+                        .is_some_and(|ids| ids.contains(&key.id)),
+                    "Bid {} is missing from the ids of user {}",
+                    key.id,
+                    slot.bid.user_id
+                );
+                if slot
+                    .bid
+                    .time_in_force
+                    .expiry(slot.entry.timestamp)
+                    .is_some()
+                {
+                    expiring += 1;
+                }
+                displayed += u128::from(slot.bid.amount);
+                hidden += u128::from(slot.entry.hidden);
+                let user = users.entry(slot.bid.user_id).or_default();
+                user.0 += u128::from(slot.bid.amount) + u128::from(slot.entry.hidden);
+                user.1 += 1;
+                bids += 1;
+            }
+            assert_eq!(
+                level.displayed,
+                displayed,
+                "Displayed total at {}",
+                price.price()
+            );
+            assert_eq!(level.hidden, hidden, "Hidden total at {}", price.price());
+            assert_eq!(level.users, users, "User totals at {}", price.price());
+        }
+        assert_eq!(self.handles.len(), bids, "Indexed bids");
+        let user_bids: usize = self.users.values().map(BTreeSet::len).sum();
+        assert_eq!(user_bids, bids, "Bids indexed by users");
+        assert_eq!(self.expiries.len(), expiring, "Scheduled expiries");
+    }
+
+    /// Sets up how incoming bids are allocated between the bids of a price level.
+    pub fn set_matching_policy(&mut self, policy: MatchingPolicy) {
+        self.policy = policy;
+    }
+
+    /// Sets up how iceberg bids are refreshed once their displayed parts are filled.
+    pub fn set_iceberg_refresh(&mut self, policy: IcebergRefresh) {
+        self.refresher = Refresher::new(policy);
     }
 }
 
 impl<BidKind, I> From<I> for Pool<BidKind>
 where
-    PoolKey<BidKind>: Ord,
+    PriceKey<BidKind>: Ord,
     I: IntoIterator<Item = Bid<BidKind>>,
 {
     fn from(data: I) -> Self {
-        let map: BTreeMap<_, _> = data
-            .into_iter()
+        let mut pool = Pool::default();
+        data.into_iter()
             .zip(0..)
-            .map(|(bid, id)| (PoolKey::new(id, bid.price), bid))
-            .collect();
-        let count = map.len();
-        Pool(map, count)
+            .for_each(|(bid, id)| pool.insert_by_id(id, bid));
+        pool
     }
 }
 
-struct MatchingResult<BidKind> {
-    keys_to_drop: Vec<PoolKey<BidKind>>,
-    items_processed: u64,
+/// An error returned when the same id is given to more than one bid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateId(pub usize);
+
+impl fmt::Display for DuplicateId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bid id {} is used more than once", self.0)
+    }
 }
 
+impl Error for DuplicateId {}
+
 impl<BidKind> Pool<BidKind>
 where
     BidKind: GenericBid,
     Bid<BidKind::Opposite>: MatchingRange<BidKind>,
-    PoolKey<BidKind>: Ord,
+    PriceKey<BidKind>: Ord,
 {
-    fn get_suitable(
-        &mut self,
+    /// Resting bids the `active_bid` might be matched against, in the priority order.
+    fn matchable(
+        &self,
         active_bid: &Bid<BidKind::Opposite>,
-    ) -> impl Iterator<Item = (&PoolKey<BidKind>, &mut Bid<BidKind>)> {
-        let active_user_id = active_bid.user_id;
+    ) -> impl Iterator<Item = (PoolKey<BidKind>, Bid<BidKind>)> + '_ {
         let range = active_bid.what_matches();
-        let max_amount = active_bid.amount;
-        self.0
-            .range_mut(range)
-            .filter(move |(_key, pool_bid)| pool_bid.user_id != active_user_id)
-            .scan(max_amount, move |left, (key, pool_bid)| {
-                if *left == 0 {
-                    None
-                } else {
-                    let amount = pool_bid.amount;
-                    if amount > *left {
-                        *left = 0;
-                    } else {
-                        *left -= amount;
-                    }
-                    Some((key, pool_bid))
-                }
-            })
+        take_matchable(
+            self.range(range).map(|(key, bid)| (*key, *bid)),
+            active_bid,
+            &self.user_groups,
+            self.max_levels,
+        )
     }
 
+    /// Computes what would happen if the `active_bid` was processed, without modifying the pool.
+    ///
+    /// The pool is walked read-only: only the bids the `active_bid` reaches are visited, and
+    /// their remaining (displayed and hidden) amounts are tracked aside, iceberg replenishment
+    /// included. Randomized tranches are drawn from a copy of the refresher, so they are the ones
+    /// processing the bid would draw.
+    pub fn simulate(
+        &self,
+        active_bid: Bid<BidKind::Opposite>,
+        ty: BidProcessingType,
+    ) -> Simulation<BidKind::Opposite> {
+        match self.plan(active_bid, ty) {
+            Plan::Rest(bid) => Simulation {
+                trades: Vec::new(),
+                rest: Some(bid),
+            },
+            Plan::Drop(_bid) => Simulation {
+                trades: Vec::new(),
+                rest: None,
+            },
+            Plan::Execute {
+                active_bid,
+                allocations,
+                limit_price: _,
+            } => {
+                let (trades, items_processed) = DryRun::new(self).execute(active_bid, allocations);
+                let rest = match ty {
+                    BidProcessingType::Limit => unfilled(active_bid, items_processed),
+                    _ => None,
+                };
+                Simulation { trades, rest }
+            }
+        }
+    }
+
+    /// Matches the `active_bid` against the pool, reporting what happens to the `listener`.
+    ///
+    /// Returns the executed trades and the part of the `active_bid` that should be added to the
+    /// opposite pool, if any.
     pub fn process_bid(
         &mut self,
         active_bid: Bid<BidKind::Opposite>,
         ty: BidProcessingType,
-    ) -> Option<Bid<BidKind::Opposite>> {
+        listener: &mut dyn OrderBookListener,
+    ) -> (Vec<Trade>, Option<Bid<BidKind::Opposite>>) {
         debug!(
             "Processing a {} from user {} (price: {}, size: {})",
             BidKind::Opposite::kind_name(),
@@ -103,119 +896,510 @@ where
             active_bid.price,
             active_bid.amount
         );
-        let suitable_bids = self.get_suitable(&active_bid);
-        let bid = match ty {
-            BidProcessingType::Limit => {
-                let MatchingResult {
-                    items_processed,
-                    keys_to_drop,
-                } = process_items(suitable_bids, &active_bid);
-                keys_to_drop.into_iter().for_each(|key| {
-                    self.0.remove(&key);
-                });
-                if items_processed == active_bid.amount {
-                    None
-                } else {
-                    let mut active_bid = active_bid;
-                    active_bid.amount -= items_processed;
-                    Some(active_bid)
+        match self.plan(active_bid, ty) {
+            Plan::Rest(bid) => (Vec::new(), Some(bid)),
+            Plan::Drop(bid) => {
+                listener.on_order_dropped(&OrderInfo::from(&bid));
+                (Vec::new(), None)
+            }
+            Plan::Execute {
+                active_bid,
+                allocations,
+                limit_price,
+            } => {
+                let (trades, items_processed) =
+                    self.execute_allocated(active_bid, allocations, listener);
+                let rest = unfilled(active_bid, items_processed);
+                match ty {
+                    BidProcessingType::Limit => (trades, rest),
+                    _ => {
+                        debug_assert!(ty != BidProcessingType::FillOrKill || rest.is_none());
+                        if let Some(rest) = rest {
+                            let dropped = rest.price(limit_price);
+                            listener.on_order_dropped(&OrderInfo::from(&dropped));
+                        }
+                        (trades, None)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Decides what to do with the `active_bid` before it touches the pool.
+    fn plan(&self, active_bid: Bid<BidKind::Opposite>, ty: BidProcessingType) -> Plan<BidKind> {
+        let limit_price = active_bid.price;
+        let active_bid = match ty {
+            BidProcessingType::Market => active_bid.price(BidKind::Opposite::market_price()),
+            _ => active_bid,
+        };
+        if let Some(min_fill) = active_bid.min_fill {
+            if ty != BidProcessingType::PostOnly && self.available_amount(&active_bid) < min_fill {
+                debug!(
+                    "Less than {} items are available, skipping the execution",
+                    min_fill
+                );
+                if ty == BidProcessingType::Limit {
+                    return Plan::Rest(active_bid);
                 }
+                return Plan::Drop(active_bid.price(limit_price));
             }
+        }
+        match ty {
             BidProcessingType::FillOrKill => {
-                let needed_amount = active_bid.amount;
-                let available_amount: u64 = suitable_bids.map(|(_key, value)| value.amount).sum();
-                if available_amount >= needed_amount {
-                    let suitable_bids = self.get_suitable(&active_bid);
-                    let MatchingResult {
-                        items_processed, ..
-                    } = process_items(suitable_bids, &active_bid);
-                    debug_assert_eq!(items_processed, active_bid.amount);
+                // The fills are staged first and only committed if they are enough.
+                let allocations = self.allocate(&active_bid);
+                if self.staged_amount(&allocations) >= active_bid.amount {
+                    Plan::Execute {
+                        active_bid,
+                        allocations,
+                        limit_price,
+                    }
                 } else {
-                    info!(
-                        "[DROP ] Drop a {} from user {} (price: {}, size: {})",
-                        BidKind::Opposite::kind_name(),
-                        active_bid.user_id,
-                        active_bid.price,
-                        active_bid.amount
-                    );
+                    Plan::Drop(active_bid)
                 }
-                None
             }
-            BidProcessingType::ImmediateOrCancel => {
-                let MatchingResult {
-                    keys_to_drop,
-                    items_processed,
-                } = process_items(suitable_bids, &active_bid);
-                keys_to_drop.into_iter().for_each(|key| {
-                    self.0.remove(&key);
+            BidProcessingType::PostOnly => {
+                if self
+                    .levels
+                    .range(active_bid.what_matches())
+                    .next()
+                    .is_some()
+                {
+                    Plan::Drop(active_bid)
+                } else {
+                    Plan::Rest(active_bid)
+                }
+            }
+            BidProcessingType::Limit
+            | BidProcessingType::ImmediateOrCancel
+            | BidProcessingType::Market => Plan::Execute {
+                active_bid,
+                allocations: self.allocate(&active_bid),
+                limit_price,
+            },
+        }
+    }
+
+    /// Amount of items available to the `active_bid`, including the hidden ones.
+    ///
+    /// Price levels are counted by their cached totals, less the amounts of the users the
+    /// `active_bid` can't be matched against, so only the levels are walked, not the bids. Stops
+    /// counting once it reaches the amount of the `active_bid`, and saturates at `u64::MAX`.
+    fn available_amount(&self, active_bid: &Bid<BidKind::Opposite>) -> u64 {
+        let excluded_users = self.user_groups.members(active_bid.user_id);
+        let mut available = 0;
+        let mut levels = 0;
+        for (_price, level) in self.levels.range(active_bid.what_matches()) {
+            if available >= active_bid.amount || Some(levels) == self.max_levels {
+                break;
+            }
+            let (excluded, excluded_count) = excluded_users
+                .iter()
+                .filter_map(|user_id| level.users.get(user_id))
+                .fold((0, 0), |(amount, count), (user_amount, user_count)| {
+                    (amount + user_amount, count + user_count)
                 });
-                if items_processed == 0 {
-                    info!(
-                        "[DROP ] Drop a {} from user {} (price: {}, size: {})",
-                        BidKind::Opposite::kind_name(),
-                        active_bid.user_id,
-                        active_bid.price,
-                        active_bid.amount
-                    );
+            if excluded_count != level.bids.len() {
+                available = available.saturating_add(saturate(level.total() - excluded));
+                levels += 1;
+            }
+        }
+        available
+    }
+
+    /// Walks the price levels the `active_bid` could be matched against, from the best one, and
+    /// reports how much of it would be filled and at what prices.
+    ///
+    /// Levels are taken by their totals, including the hidden amounts, regardless of the users and
+    /// the matching policy. The maximum number of levels to match is respected.
+    pub(crate) fn quote(&self, active_bid: &Bid<BidKind::Opposite>) -> Quote {
+        let mut quote = Quote::default();
+        for (price, level) in self.levels.range(active_bid.what_matches()) {
+            if quote.filled >= active_bid.amount || Some(quote.levels) == self.max_levels {
+                break;
+            }
+            let amount = saturate(level.total()).min(active_bid.amount - quote.filled);
+            quote.filled += amount;
+            quote.notional += u128::from(price.price()) * u128::from(amount);
+            quote.levels += 1;
+        }
+        quote
+    }
+
+    /// Amount of items available through the staged `allocations`, including the hidden parts of
+    /// the allocated bids.
+    ///
+    /// Unless the allocations cover the whole incoming bid, every matchable bid is allocated in
+    /// full, so the hidden parts are the only other items available. Saturates at `u64::MAX`.
+    fn staged_amount(&self, allocations: &[(PoolKey<BidKind>, u64)]) -> u64 {
+        allocations
+            .iter()
+            .map(|(key, amount)| {
+                let hidden = self.get(key.id).map_or(0, |slot| slot.entry.hidden);
+                amount.saturating_add(hidden)
+            })
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Splits the `active_bid` between the matchable bids according to the matching policy.
+    fn allocate(&self, active_bid: &Bid<BidKind::Opposite>) -> Vec<(PoolKey<BidKind>, u64)> {
+        self.policy
+            .allocate(self.matchable(active_bid), active_bid.amount)
+    }
+
+    /// Cuts the FIFO `allocations` after the first iceberg bid that is allocated in full, if
+    /// refreshed tranches keep their priority: the refreshed tranche is to be matched before the
+    /// bids behind it.
+    fn stop_at_refresh(&self, allocations: &mut Vec<(PoolKey<BidKind>, u64)>) {
+        if !self.refresher.keep_priority() || self.policy != MatchingPolicy::Fifo {
+            return;
+        }
+        let refreshed = allocations.iter().position(|(key, amount)| {
+            self.get(key.id)
+                .is_some_and(|slot| slot.entry.hidden != 0 && slot.bid.amount == *amount)
+        });
+        if let Some(index) = refreshed {
+            allocations.truncate(index + 1);
+        }
+    }
+
+    /// Executes the `active_bid` against the pool as much as possible, starting with the given
+    /// `allocations` of it.
+    fn execute_allocated(
+        &mut self,
+        mut active_bid: Bid<BidKind::Opposite>,
+        mut allocations: Vec<(PoolKey<BidKind>, u64)>,
+        listener: &mut dyn OrderBookListener,
+    ) -> (Vec<Trade>, u64) {
+        let mut trades = Vec::new();
+        let mut total_processed = 0;
+        loop {
+            let reached = allocations.last().map(|(key, _amount)| key.price());
+            self.stop_at_refresh(&mut allocations);
+            let items_processed =
+                process_items(self, &allocations, &active_bid, &mut trades, listener);
+            self.traded_volume = self.traded_volume.saturating_add(items_processed);
+            total_processed += items_processed;
+            let mut filled = mem::take(&mut self.filled);
+            let replenished = self.remove_keys(filled.drain(..), listener);
+            self.filled = filled;
+            match reached {
+                // Replenished iceberg bids are matchable again, but only at the price levels that
+                // have already been reached.
+                Some(price) if replenished && items_processed != active_bid.amount => {
+                    active_bid = active_bid
+                        .amount(active_bid.amount - items_processed)
+                        .price(price);
+                    allocations = self.allocate(&active_bid);
                 }
-                None
+                _ => break,
             }
-        };
-        if let Some(active_bid) = bid.as_ref() {
-            info!(
-                "[ ADD ] Add a {} from user {} (price: {}, size: {}) to the pool",
-                BidKind::Opposite::kind_name(),
-                active_bid.user_id,
-                active_bid.price,
-                active_bid.amount
-            );
         }
-        bid
+        (trades, total_processed)
     }
 }
 
-fn process_items<'a, BidKind: 'a>(
-    items: impl IntoIterator<Item = (&'a PoolKey<BidKind>, &'a mut Bid<BidKind>)>,
+/// Narrows a total amount of items down to `u64`, saturating.
+pub(crate) fn saturate(amount: u128) -> u64 {
+    u64::try_from(amount).unwrap_or(u64::MAX)
+}
+
+/// Amount of the `bid` to put on the book out of the `available` amount.
+fn visible_amount<BidKind>(bid: &Bid<BidKind>, available: u64) -> u64 {
+    match bid.display {
+        Some(display) if display != 0 => display.min(available),
+        _ => available,
+    }
+}
+
+/// Limits `bids` (that are expected to be in the matching price range) to the ones the
+/// `active_bid` might be matched against.
+fn take_matchable<'a, BidKind>(
+    bids: impl Iterator<Item = (PoolKey<BidKind>, Bid<BidKind>)> + 'a,
     active_bid: &Bid<BidKind::Opposite>,
-) -> MatchingResult<BidKind>
+    user_groups: &'a UserGroups,
+    max_levels: Option<usize>,
+) -> impl Iterator<Item = (PoolKey<BidKind>, Bid<BidKind>)> + 'a
+where
+    BidKind: GenericBid + 'a,
+{
+    let active_user_id = active_bid.user_id;
+    bids.filter(move |(_key, pool_bid)| !user_groups.same_group(pool_bid.user_id, active_user_id))
+        .scan((None, 0), move |(last_price, levels), (key, pool_bid)| {
+            if *last_price != Some(pool_bid.price) {
+                if Some(*levels) == max_levels {
+                    return None;
+                }
+                *last_price = Some(pool_bid.price);
+                *levels += 1;
+            }
+            Some((key, pool_bid))
+        })
+}
+
+/// Fills the bids of the pool according to the `allocations`, adding the executed trades to
+/// `trades`.
+///
+/// Keys of the completely filled bids are left in the pool's `filled` buffer. Returns the amount of
+/// items processed.
+fn process_items<BidKind>(
+    pool: &mut Pool<BidKind>,
+    allocations: &[(PoolKey<BidKind>, u64)],
+    active_bid: &Bid<BidKind::Opposite>,
+    trades: &mut Vec<Trade>,
+    listener: &mut dyn OrderBookListener,
+) -> u64
 where
     BidKind: GenericBid,
     Bid<BidKind::Opposite>: MatchingRange<BidKind>,
-    PoolKey<BidKind>: Ord,
+    PriceKey<BidKind>: Ord,
 {
-    let amount_needed = active_bid.amount;
-    let mut keys_to_drop = Vec::new();
-    let mut items_left = amount_needed;
-    items.into_iter().for_each(|(key, pool_bid)| {
-        let current_items = pool_bid.amount;
-        if current_items <= items_left {
-            items_left -= current_items;
-            keys_to_drop.push(*key);
-            let (verb, direction) = BidKind::Opposite::deal_verb_direction();
-            info!(
-                "[TRADE] User {} {} {} items {} user {} for price {}",
-                active_bid.user_id,
-                verb,
-                current_items,
-                direction,
-                pool_bid.user_id,
-                pool_bid.price,
-            );
-        } else {
-            pool_bid.amount -= items_left;
-            items_left = 0;
+    let mut items_processed = 0;
+    allocations.iter().for_each(|&(key, current_items)| {
+        let pool_bid = pool
+            .get(key.id)
+            .expect("Allocated bids are taken from the pool")
+            .bid;
+        items_processed += current_items;
+        let trade = Trade {
+            maker_user_id: pool_bid.user_id,
+            taker_user_id: active_bid.user_id,
+            taker_side: BidKind::Opposite::side(),
+            price: pool_bid.price,
+            amount: current_items,
+        };
+        listener.on_trade(&trade);
+        listener.on_feed_event(&FeedEvent::Execute {
+            order_id: OrderId(key.id),
+            amount: current_items,
+        });
+        trades.push(trade);
+        if current_items == pool_bid.amount {
+            pool.filled.push(key);
+        } else if let Some(pool_bid) = pool.fill(key.id, current_items) {
+            listener.on_order_partially_filled(&OrderInfo::from(pool_bid));
         }
     });
-    MatchingResult {
-        keys_to_drop,
-        items_processed: amount_needed - items_left,
+    items_processed
+}
+
+/// What to do with an incoming bid, decided before it touches the pool.
+enum Plan<BidKind: GenericBid> {
+    /// Put the bid on the opposite pool as it is.
+    Rest(Bid<BidKind::Opposite>),
+    /// Drop the bid.
+    Drop(Bid<BidKind::Opposite>),
+    /// Match the bid, starting with the given allocations of it.
+    Execute {
+        active_bid: Bid<BidKind::Opposite>,
+        allocations: Vec<(PoolKey<BidKind>, u64)>,
+        /// Price of the bid as it was submitted, which differs from the one of a market bid.
+        limit_price: u64,
+    },
+}
+
+/// The part of the `active_bid` left after `items_processed` items of it are executed, if any.
+fn unfilled<BidKind>(active_bid: Bid<BidKind>, items_processed: u64) -> Option<Bid<BidKind>> {
+    (items_processed != active_bid.amount)
+        .then(|| active_bid.amount(active_bid.amount - items_processed))
+}
+
+/// Remaining amounts of a bid visited by a [`DryRun`].
+#[derive(Debug, Clone, Copy)]
+struct Visit {
+    displayed: u64,
+    hidden: u64,
+    /// Time priority of a replenished bid that has gone to the back of its queue.
+    moved: Option<usize>,
+    /// Whether the bid has been filled completely.
+    gone: bool,
+}
+
+/// A read-only run of an incoming bid against a pool, see [`Pool::simulate`].
+///
+/// Only the bids the incoming bid reaches are visited. What happens to them is kept aside, the pool
+/// itself isn't touched.
+struct DryRun<'a, BidKind> {
+    pool: &'a Pool<BidKind>,
+    visits: HashMap<usize, Visit>,
+    /// Ids of the replenished bids that have gone to the back of their queues, by price, in the
+    /// order of their new time priorities.
+    moved: HashMap<u64, Vec<usize>>,
+    refresher: Refresher,
+    last_seq: usize,
+}
+
+impl<'a, BidKind> DryRun<'a, BidKind>
+where
+    BidKind: GenericBid,
+    Bid<BidKind::Opposite>: MatchingRange<BidKind>,
+    PriceKey<BidKind>: Ord,
+{
+    fn new(pool: &'a Pool<BidKind>) -> Self {
+        DryRun {
+            pool,
+            visits: HashMap::new(),
+            moved: HashMap::new(),
+            refresher: pool.refresher,
+            last_seq: pool.last_seq,
+        }
+    }
+
+    /// Current amounts of a bid of the pool.
+    fn visit(&self, slot: &Slot<BidKind>) -> Visit {
+        self.visits.get(&slot.id).copied().unwrap_or(Visit {
+            displayed: slot.bid.amount,
+            hidden: slot.entry.hidden,
+            moved: None,
+            gone: false,
+        })
+    }
+
+    fn slot(&self, id: usize) -> &'a Slot<BidKind> {
+        self.pool
+            .get(id)
+            .expect("Visited bids are taken from the pool")
+    }
+
+    /// Bids at the price levels the `active_bid` matches, as they are by now, in the priority
+    /// order.
+    fn range(
+        &self,
+        active_bid: &Bid<BidKind::Opposite>,
+    ) -> impl Iterator<Item = (PoolKey<BidKind>, Bid<BidKind>)> + '_ {
+        let pool = self.pool;
+        pool.levels
+            .range(active_bid.what_matches())
+            .flat_map(move |(price, level)| {
+                let queued = level.bids.iter().filter_map(move |&(key, handle)| {
+                    let slot = &pool.bids[handle];
+                    let visit = self.visit(slot);
+                    (!visit.gone && visit.moved.is_none())
+                        .then(|| (key, slot.bid.amount(visit.displayed)))
+                });
+                let moved = self
+                    .moved
+                    .get(&price.price())
+                    .into_iter()
+                    .flatten()
+                    .map(move |&id| {
+                        let slot = self.slot(id);
+                        let visit = self.visit(slot);
+                        let seq = visit.moved.unwrap_or(slot.entry.seq);
+                        (
+                            PoolKey::with_seq(id, seq, slot.entry.price),
+                            slot.bid.amount(visit.displayed),
+                        )
+                    });
+                queued.chain(moved)
+            })
+    }
+
+    /// Same as [`Pool::allocate`], but with the bids as they are by now.
+    fn allocate(&self, active_bid: &Bid<BidKind::Opposite>) -> Vec<(PoolKey<BidKind>, u64)> {
+        let matchable = take_matchable(
+            self.range(active_bid),
+            active_bid,
+            &self.pool.user_groups,
+            self.pool.max_levels,
+        );
+        self.pool.policy.allocate(matchable, active_bid.amount)
+    }
+
+    /// Same as [`Pool::execute_allocated`], but without modifying the pool.
+    fn execute(
+        mut self,
+        mut active_bid: Bid<BidKind::Opposite>,
+        mut allocations: Vec<(PoolKey<BidKind>, u64)>,
+    ) -> (Vec<Trade>, u64) {
+        let mut trades = Vec::new();
+        let mut total_processed = 0;
+        loop {
+            let reached = allocations.last().map(|(key, _amount)| key.price());
+            if self.refresher.keep_priority() && self.pool.policy == MatchingPolicy::Fifo {
+                let refreshed = allocations.iter().position(|(key, amount)| {
+                    let visit = self.visit(self.slot(key.id));
+                    visit.hidden != 0 && visit.displayed == *amount
+                });
+                if let Some(index) = refreshed {
+                    allocations.truncate(index + 1);
+                }
+            }
+            let mut items_processed = 0;
+            let mut filled = Vec::new();
+            for &(key, amount) in &allocations {
+                let slot = self.slot(key.id);
+                let mut visit = self.visit(slot);
+                items_processed += amount;
+                trades.push(Trade {
+                    maker_user_id: slot.bid.user_id,
+                    taker_user_id: active_bid.user_id,
+                    taker_side: BidKind::Opposite::side(),
+                    price: slot.bid.price,
+                    amount,
+                });
+                visit.displayed -= amount;
+                if visit.displayed == 0 {
+                    filled.push(key.id);
+                }
+                self.visits.insert(key.id, visit);
+            }
+            total_processed += items_processed;
+            let replenished = self.replenish(filled);
+            match reached {
+                Some(price) if replenished && items_processed != active_bid.amount => {
+                    active_bid = active_bid
+                        .amount(active_bid.amount - items_processed)
+                        .price(price);
+                    allocations = self.allocate(&active_bid);
+                }
+                _ => break,
+            }
+        }
+        (trades, total_processed)
+    }
+
+    /// Same as [`Pool::remove_keys`], but for the visited bids.
+    fn replenish(&mut self, ids: Vec<usize>) -> bool {
+        let mut replenished = false;
+        for id in ids {
+            let slot = self.slot(id);
+            let mut visit = self.visit(slot);
+            if visit.moved.is_some() {
+                if let Some(moved) = self.moved.get_mut(&slot.entry.price) {
+                    moved.retain(|&other| other != id);
+                }
+            }
+            if visit.hidden == 0 {
+                visit.gone = true;
+                self.visits.insert(id, visit);
+                continue;
+            }
+            let visible = match slot.bid.display {
+                Some(display) if display != 0 => self.refresher.tranche(display, visit.hidden),
+                _ => visit.hidden,
+            };
+            if !self.refresher.keep_priority() {
+                self.last_seq = self.last_seq.max(self.pool.last_id) + 1;
+                visit.moved = Some(self.last_seq);
+                self.moved.entry(slot.entry.price).or_default().push(id);
+            }
+            visit.hidden -= visible;
+            visit.displayed = visible;
+            self.visits.insert(id, visit);
+            replenished = true;
+        }
+        replenished
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::bids::{BuyingBid, SellingBid};
+    use crate::{
+        bids::{BuyingBid, SellingBid, Side},
+        iceberg::TrancheSize,
+    };
 
     #[test]
     fn test_sorting_buy() {
@@ -240,8 +1424,7 @@ mod test {
         ];
         assert_eq!(
             sorted,
-            pool.0
-                .iter()
+            pool.iter()
                 .map(|(key, value)| (key.id, *value))
                 .collect::<Vec<_>>()
         );
@@ -269,7 +1452,6 @@ mod test {
             (4, Bid::empty().price(100).amount(6).user_id(1)),
         ];
         let matched: Vec<_> = pool
-            .0
             .range(rng)
             .map(|(key, value)| (key.id, *value))
             .collect();
@@ -299,8 +1481,7 @@ mod test {
         ];
         assert_eq!(
             sorted,
-            pool.0
-                .iter()
+            pool.iter()
                 .map(|(key, value)| (key.id, *value))
                 .collect::<Vec<_>>()
         );
@@ -328,7 +1509,6 @@ mod test {
             (6, Bid::empty().price(100).amount(2).user_id(1)),
         ];
         let matched: Vec<_> = pool
-            .0
             .range(rng)
             .map(|(key, value)| (key.id, *value))
             .collect();
@@ -338,7 +1518,7 @@ mod test {
     #[test]
     fn test_suitable_buying_pool() {
         let selling_bid = Bid::empty().price(100).amount(15).user_id(0);
-        let mut pool: Pool<BuyingBid> = vec![
+        let pool: Pool<BuyingBid> = vec![
             Bid::empty().price(100).amount(4).user_id(1),
             Bid::empty().price(150).amount(2).user_id(1),
             Bid::empty().price(90).amount(5).user_id(1),
@@ -349,22 +1529,24 @@ mod test {
         ]
         .into();
         let check: Vec<_> = pool
-            .get_suitable(&selling_bid)
-            .map(|(key, value)| (key.id, *value))
+            .matchable(&selling_bid)
+            .map(|(key, value)| (key.id, value))
             .collect();
         let expected = vec![
             (1, Bid::empty().price(150).amount(2).user_id(1)),
             (5, Bid::empty().price(101).amount(5).user_id(1)),
             (0, Bid::empty().price(100).amount(4).user_id(1)),
             (4, Bid::empty().price(100).amount(6).user_id(1)),
+            (6, Bid::empty().price(100).amount(2).user_id(1)),
         ];
         assert_eq!(expected, check);
+        assert_eq!(pool.available_amount(&selling_bid), 2 + 5 + 12);
     }
 
     #[test]
     fn test_suitable_selling_pool() {
         let buying_bid = Bid::empty().price(100).amount(15).user_id(0);
-        let mut pool: Pool<SellingBid> = vec![
+        let pool: Pool<SellingBid> = vec![
             Bid::empty().price(100).amount(4).user_id(1),
             Bid::empty().price(150).amount(2).user_id(1),
             Bid::empty().price(90).amount(5).user_id(1),
@@ -378,11 +1560,506 @@ mod test {
             (2, Bid::empty().price(90).amount(5).user_id(1)),
             (0, Bid::empty().price(100).amount(4).user_id(1)),
             (4, Bid::empty().price(100).amount(6).user_id(1)),
+            (6, Bid::empty().price(100).amount(2).user_id(1)),
         ];
         let check: Vec<_> = pool
-            .get_suitable(&buying_bid)
-            .map(|(key, value)| (key.id, *value))
+            .matchable(&buying_bid)
+            .map(|(key, value)| (key.id, value))
             .collect();
         assert_eq!(reference, check);
+        assert_eq!(pool.available_amount(&buying_bid), 5 + 12);
+    }
+
+    #[test]
+    fn test_suitable_user_groups() {
+        let buying_bid = Bid::empty().price(100).amount(15).user_id(0);
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(4).user_id(1),
+            Bid::empty().price(90).amount(5).user_id(2),
+            Bid::empty().price(70).amount(5).user_id(3),
+            Bid::empty().price(100).amount(6).user_id(4),
+        ]
+        .into();
+        let mut user_groups = UserGroups::new();
+        user_groups.add_group(vec![0, 3]);
+        user_groups.add_group(vec![1, 2]);
+        pool.set_user_groups(user_groups);
+        let reference = vec![
+            (1, Bid::empty().price(90).amount(5).user_id(2)),
+            (0, Bid::empty().price(100).amount(4).user_id(1)),
+            (3, Bid::empty().price(100).amount(6).user_id(4)),
+        ];
+        let check: Vec<_> = pool
+            .matchable(&buying_bid)
+            .map(|(key, value)| (key.id, value))
+            .collect();
+        assert_eq!(reference, check);
+        assert_eq!(pool.available_amount(&buying_bid), 15);
+    }
+
+    #[test]
+    fn test_suitable_max_levels() {
+        let buying_bid = Bid::empty().price(100).amount(15).user_id(0);
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(4).user_id(1),
+            Bid::empty().price(90).amount(2).user_id(0),
+            Bid::empty().price(90).amount(3).user_id(1),
+            Bid::empty().price(70).amount(5).user_id(2),
+            Bid::empty().price(90).amount(1).user_id(2),
+        ]
+        .into();
+        pool.set_max_levels(Some(2));
+        let reference = vec![
+            (3, Bid::empty().price(70).amount(5).user_id(2)),
+            (2, Bid::empty().price(90).amount(3).user_id(1)),
+            (4, Bid::empty().price(90).amount(1).user_id(2)),
+        ];
+        let check: Vec<_> = pool
+            .matchable(&buying_bid)
+            .map(|(key, value)| (key.id, value))
+            .collect();
+        assert_eq!(reference, check);
+        assert_eq!(pool.available_amount(&buying_bid), 5 + 4);
+
+        let (_trades, rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        assert_eq!(rest, Some(buying_bid.amount(6)));
+    }
+
+    #[test]
+    fn test_with_ids() {
+        let mut pool: Pool<SellingBid> = Pool::with_ids(vec![
+            (7, Bid::empty().price(100).amount(4).user_id(1)),
+            (2, Bid::empty().price(100).amount(5).user_id(2)),
+            (5, Bid::empty().price(90).amount(6).user_id(3)),
+        ])
+        .unwrap();
+        pool.push(Bid::empty().price(90).amount(1).user_id(4));
+        let sorted = vec![
+            (5, Bid::empty().price(90).amount(6).user_id(3)),
+            (8, Bid::empty().price(90).amount(1).user_id(4)),
+            (2, Bid::empty().price(100).amount(5).user_id(2)),
+            (7, Bid::empty().price(100).amount(4).user_id(1)),
+        ];
+        assert_eq!(
+            sorted,
+            pool.iter()
+                .map(|(key, value)| (key.id, *value))
+                .collect::<Vec<_>>()
+        );
+
+        let duplicate = Pool::<SellingBid>::with_ids(vec![
+            (1, Bid::empty().price(100).amount(4).user_id(1)),
+            (1, Bid::empty().price(90).amount(5).user_id(2)),
+        ]);
+        assert_eq!(duplicate.unwrap_err(), DuplicateId(1));
+    }
+
+    #[test]
+    fn test_process_trades() {
+        let buying_bid = Bid::empty().price(100).amount(8).user_id(0);
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(4).user_id(1),
+            Bid::empty().price(90).amount(5).user_id(2),
+            Bid::empty().price(95).amount(6).user_id(3),
+        ]
+        .into();
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        let expected = vec![
+            Trade {
+                maker_user_id: 2,
+                taker_user_id: 0,
+                taker_side: Side::Buy,
+                price: 90,
+                amount: 5,
+            },
+            Trade {
+                maker_user_id: 3,
+                taker_user_id: 0,
+                taker_side: Side::Buy,
+                price: 95,
+                amount: 3,
+            },
+        ];
+        assert_eq!(trades, expected);
+        assert_eq!(rest, None);
+        assert_eq!(pool.traded_volume(), 8);
+        let resting: Vec<_> = pool.view_bids().cloned().collect();
+        assert_eq!(
+            resting,
+            [
+                Bid::empty().price(95).amount(3).user_id(3),
+                Bid::empty().price(100).amount(4).user_id(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_market() {
+        let selling_bid = Bid::empty().amount(12).user_id(0);
+        let mut pool: Pool<BuyingBid> = vec![
+            Bid::empty().price(100).amount(4).user_id(1),
+            Bid::empty().price(1).amount(5).user_id(2),
+            Bid::empty().price(150).amount(2).user_id(3),
+        ]
+        .into();
+        let simulation = pool.simulate(selling_bid, BidProcessingType::Market);
+        let (trades, rest) = pool.process_bid(selling_bid, BidProcessingType::Market, &mut ());
+        assert_eq!(simulation.trades, trades);
+        assert_eq!(simulation.rest, None);
+        assert_eq!(rest, None);
+        let trades: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.price, trade.amount))
+            .collect();
+        assert_eq!(trades, [(3, 150, 2), (1, 100, 4), (2, 1, 5)]);
+        assert_eq!(pool.view_bids().count(), 0);
+    }
+
+    #[test]
+    fn test_iceberg() {
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(10).user_id(1).display(3),
+            Bid::empty().price(100).amount(2).user_id(2),
+            Bid::empty().price(101).amount(5).user_id(3),
+        ]
+        .into();
+        let summary = |trades: Vec<Trade>| -> Vec<_> {
+            trades
+                .iter()
+                .map(|trade| (trade.maker_user_id, trade.price, trade.amount))
+                .collect()
+        };
+
+        let buying_bid = Bid::empty().price(100).amount(7).user_id(0);
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        assert_eq!(summary(trades), [(1, 100, 3), (2, 100, 2), (1, 100, 2)]);
+        assert_eq!(rest, None);
+        assert_eq!(pool.find(0).map(|bid| bid.amount), Some(5));
+        let resting: Vec<_> = pool.view_bids().map(|bid| bid.amount).collect();
+        assert_eq!(resting, [1, 5]);
+
+        let buying_bid = buying_bid.amount(6);
+        let (trades, _rest) = pool.process_bid(buying_bid, BidProcessingType::FillOrKill, &mut ());
+        assert!(trades.is_empty());
+
+        let simulation = pool.simulate(buying_bid, BidProcessingType::ImmediateOrCancel);
+        let (trades, rest) =
+            pool.process_bid(buying_bid, BidProcessingType::ImmediateOrCancel, &mut ());
+        assert_eq!(simulation.trades, trades);
+        assert_eq!(rest, None);
+        assert_eq!(summary(trades), [(1, 100, 1), (1, 100, 3), (1, 100, 1)]);
+        assert_eq!(pool.find(0), None);
+        assert_eq!(pool.view_bids().count(), 1);
+    }
+
+    #[test]
+    fn test_iceberg_refresh() {
+        let bids = vec![
+            Bid::empty().price(100).amount(10).user_id(1).display(3),
+            Bid::empty().price(100).amount(2).user_id(2),
+        ];
+        let makers = |trades: Vec<Trade>| -> Vec<_> {
+            trades
+                .iter()
+                .map(|trade| (trade.maker_user_id, trade.amount))
+                .collect()
+        };
+        let buying_bid = Bid::empty().price(100).amount(7).user_id(0);
+
+        let mut pool: Pool<SellingBid> = bids.clone().into();
+        pool.set_iceberg_refresh(IcebergRefresh {
+            keep_priority: true,
+            ..IcebergRefresh::default()
+        });
+        let (trades, _rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        assert_eq!(makers(trades), [(1, 3), (1, 3), (1, 1)]);
+        let resting: Vec<_> = pool.view_bids().map(|bid| bid.amount).collect();
+        assert_eq!(resting, [2, 2]);
+
+        let mut pool: Pool<SellingBid> = bids.into();
+        pool.set_iceberg_refresh(IcebergRefresh {
+            tranche_size: TrancheSize::Randomized {
+                variance: 2,
+                seed: 1,
+            },
+            keep_priority: false,
+        });
+        let (trades, _rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        let trades = makers(trades);
+        assert_eq!(trades[..2], [(1, 3), (2, 2)]);
+        assert_eq!(trades.iter().map(|(_, amount)| amount).sum::<u64>(), 7);
+        assert_eq!(pool.find(0).map(|bid| bid.amount), Some(5));
+        assert!((1..=5).contains(&pool.displayed_amount(0).unwrap()));
+    }
+
+    #[test]
+    fn test_simulate() {
+        let bids = vec![
+            Bid::empty().price(100).amount(10).user_id(1).display(3),
+            Bid::empty().price(100).amount(2).user_id(2),
+            Bid::empty().price(100).amount(7).user_id(3).display(2),
+            Bid::empty().price(101).amount(4).user_id(4),
+            Bid::empty().price(101).amount(9).user_id(5).display(4),
+            Bid::empty().price(102).amount(3).user_id(0),
+        ];
+        let refreshes = [
+            IcebergRefresh::default(),
+            IcebergRefresh {
+                keep_priority: true,
+                ..IcebergRefresh::default()
+            },
+            IcebergRefresh {
+                tranche_size: TrancheSize::Randomized {
+                    variance: 2,
+                    seed: 3,
+                },
+                keep_priority: false,
+            },
+            IcebergRefresh {
+                tranche_size: TrancheSize::Randomized {
+                    variance: 1,
+                    seed: 5,
+                },
+                keep_priority: true,
+            },
+        ];
+        let types = [
+            BidProcessingType::Limit,
+            BidProcessingType::ImmediateOrCancel,
+            BidProcessingType::FillOrKill,
+            BidProcessingType::Market,
+        ];
+        for &policy in &[MatchingPolicy::Fifo, MatchingPolicy::ProRata] {
+            for &refresh in &refreshes {
+                let mut pool: Pool<SellingBid> = bids.clone().into();
+                pool.set_matching_policy(policy);
+                pool.set_iceberg_refresh(refresh);
+                for &ty in &types {
+                    for amount in [5, 14, 25, 40] {
+                        let buying_bid = Bid::empty().price(102).amount(amount).user_id(0);
+                        let resting: Vec<_> = pool.resting().collect();
+                        let simulation = pool.simulate(buying_bid, ty);
+                        assert_eq!(pool.resting().collect::<Vec<_>>(), resting);
+                        let (trades, rest) = pool.clone().process_bid(buying_bid, ty, &mut ());
+                        let case = (policy, refresh, ty, amount);
+                        assert_eq!(simulation.trades, trades, "{:?}", case);
+                        assert_eq!(simulation.rest, rest, "{:?}", case);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_or_kill() {
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(10).user_id(1).display(3),
+            Bid::empty().price(100).amount(2).user_id(2),
+            Bid::empty().price(101).amount(5).user_id(3),
+        ]
+        .into();
+
+        let buying_bid = Bid::empty().price(100).amount(13).user_id(0);
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::FillOrKill, &mut ());
+        assert!(trades.is_empty());
+        assert_eq!(rest, None);
+        let resting: Vec<_> = pool.view_bids().map(|bid| bid.amount).collect();
+        assert_eq!(resting, [3, 2, 5]);
+
+        let buying_bid = buying_bid.amount(12);
+        let (trades, _rest) = pool.process_bid(buying_bid, BidProcessingType::FillOrKill, &mut ());
+        let filled: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.amount))
+            .collect();
+        assert_eq!(filled, [(1, 3), (2, 2), (1, 3), (1, 3), (1, 1)]);
+        let resting: Vec<_> = pool.view_bids().map(|bid| bid.amount).collect();
+        assert_eq!(resting, [5]);
+    }
+
+    #[test]
+    fn test_huge_amounts() {
+        let max = u64::MAX;
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(max - 1).user_id(1),
+            Bid::empty().price(100).amount(5).user_id(2),
+            Bid::empty().price(101).amount(max).user_id(3),
+        ]
+        .into();
+        assert_eq!(pool.levels(), [(100, max), (101, max)]);
+        assert_eq!(pool.depth(1), [(100, max, 2)]);
+        let buying_bid = Bid::empty().price(100).amount(max).user_id(0);
+        assert_eq!(pool.available_amount(&buying_bid), max);
+
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::FillOrKill, &mut ());
+        let filled: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.amount))
+            .collect();
+        assert_eq!(filled, [(1, max - 1), (2, 1)]);
+        assert_eq!(rest, None);
+        assert_eq!(pool.traded_volume(), max);
+        assert_eq!(pool.levels(), [(100, 4), (101, max)]);
+
+        // Only 4 items are left at the limit price.
+        let (trades, _rest) = pool.process_bid(buying_bid, BidProcessingType::FillOrKill, &mut ());
+        assert!(trades.is_empty());
+        let (trades, _rest) = pool.process_bid(
+            buying_bid.price(101),
+            BidProcessingType::FillOrKill,
+            &mut (),
+        );
+        let filled: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.amount))
+            .collect();
+        assert_eq!(filled, [(2, 4), (3, max - 4)]);
+        assert_eq!(pool.traded_volume(), max);
+        assert_eq!(pool.levels(), [(101, 4)]);
+    }
+
+    #[test]
+    fn test_level_totals() {
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(10).user_id(1).display(3),
+            Bid::empty().price(100).amount(2).user_id(2),
+            Bid::empty().price(101).amount(5).user_id(1),
+            Bid::empty().price(101).amount(4).user_id(3),
+        ]
+        .into();
+        let check = |pool: &Pool<SellingBid>| {
+            for level in pool.levels.values() {
+                let mut users: HashMap<u64, (u128, usize)> = HashMap::new();
+                for &(_key, handle) in &level.bids {
+                    let slot = &pool.bids[handle];
+                    let user = users.entry(slot.bid.user_id).or_default();
+                    user.0 += u128::from(slot.with_hidden().amount);
+                    user.1 += 1;
+                }
+                assert_eq!(level.users, users);
+                let total: u128 = users.values().map(|(amount, _count)| amount).sum();
+                assert_eq!(level.total(), total);
+            }
+        };
+        check(&pool);
+
+        let buying_bid = Bid::empty().price(101).amount(20).user_id(3);
+        assert_eq!(pool.available_amount(&buying_bid), 12 + 5);
+
+        pool.reduce(0, 8);
+        pool.process_bid(buying_bid.amount(4), BidProcessingType::Limit, &mut ());
+        check(&pool);
+        assert_eq!(pool.levels(), [(100, 6), (101, 9)]);
+        pool.cancel(2);
+        check(&pool);
+        assert_eq!(pool.levels(), [(100, 6), (101, 4)]);
+        assert_eq!(pool.available_amount(&buying_bid), 6);
+
+        // Slots of the removed bids are reused.
+        pool.push(Bid::empty().price(102).amount(1).user_id(4));
+        check(&pool);
+        assert_eq!(pool.bids.slots.len(), 4);
+        assert_eq!(
+            pool.find(4),
+            Some(Bid::empty().price(102).amount(1).user_id(4))
+        );
+    }
+
+    #[test]
+    fn test_user_orders() {
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(2).user_id(1),
+            Bid::empty().price(100).amount(5).user_id(2),
+            Bid::empty().price(101).amount(10).display(2).user_id(1),
+        ]
+        .into();
+        assert_eq!(pool.user_orders(1).collect::<Vec<_>>(), [0, 2]);
+        assert_eq!(pool.user_orders(2).collect::<Vec<_>>(), [1]);
+        assert_eq!(pool.user_orders(3).count(), 0);
+
+        // The first bid is filled, the iceberg is replenished.
+        let buying_bid = Bid::empty().price(101).amount(9).user_id(3);
+        pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        assert_eq!(pool.user_orders(1).collect::<Vec<_>>(), [2]);
+        assert_eq!(pool.user_orders(2).count(), 0);
+
+        pool.cancel(2).unwrap();
+        assert_eq!(pool.user_orders(1).count(), 0);
+        assert!(pool.users.is_empty());
+    }
+
+    #[test]
+    fn test_min_fill() {
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(2).user_id(1),
+            Bid::empty().price(101).amount(2).user_id(2),
+        ]
+        .into();
+        let buying_bid = Bid::empty().price(100).amount(5).user_id(0).min_fill(3);
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        assert!(trades.is_empty());
+        assert_eq!(rest, Some(buying_bid));
+        let (trades, rest) =
+            pool.process_bid(buying_bid, BidProcessingType::ImmediateOrCancel, &mut ());
+        assert!(trades.is_empty());
+        assert_eq!(rest, None);
+        assert_eq!(pool.traded_volume(), 0);
+
+        let buying_bid = buying_bid.price(101);
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        assert_eq!(trades.len(), 2);
+        assert_eq!(rest, Some(buying_bid.amount(1)));
+    }
+
+    #[test]
+    fn test_pro_rata() {
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(2).user_id(1),
+            Bid::empty().price(100).amount(6).user_id(2),
+            Bid::empty().price(101).amount(5).user_id(3),
+        ]
+        .into();
+        pool.set_matching_policy(MatchingPolicy::ProRata);
+        let buying_bid = Bid::empty().price(101).amount(4).user_id(0);
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::Limit, &mut ());
+        let trades: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.amount))
+            .collect();
+        assert_eq!(trades, [(1, 1), (2, 3)]);
+        assert_eq!(rest, None);
+        let resting: Vec<_> = pool.view_bids().map(|bid| bid.amount).collect();
+        assert_eq!(resting, [1, 3, 5]);
+    }
+
+    #[test]
+    fn test_post_only() {
+        let mut pool: Pool<SellingBid> = vec![Bid::empty().price(100).amount(4).user_id(1)].into();
+        let buying_bid = Bid::empty().price(99).amount(2).user_id(0);
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::PostOnly, &mut ());
+        assert!(trades.is_empty());
+        assert_eq!(rest, Some(buying_bid));
+
+        let buying_bid = buying_bid.price(100);
+        let (trades, rest) = pool.process_bid(buying_bid, BidProcessingType::PostOnly, &mut ());
+        assert!(trades.is_empty());
+        assert_eq!(rest, None);
+        assert_eq!(pool.view_bids().count(), 1);
+    }
+
+    #[cfg(feature = "debug-invariants")]
+    #[test]
+    #[should_panic(expected = "Displayed total at 100")]
+    fn test_invariants() {
+        let mut pool: Pool<SellingBid> = vec![
+            Bid::empty().price(100).amount(4).user_id(1),
+            Bid::empty().price(100).amount(6).display(2).user_id(2),
+        ]
+        .into();
+        pool.check_invariants();
+        if let Some(level) = pool.levels.values_mut().next() {
+            level.displayed += 1;
+        }
+        pool.check_invariants();
     }
 }
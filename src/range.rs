@@ -1,24 +1,24 @@
 use crate::{
     bids::{Bid, BuyingBid, SellingBid},
-    key::PoolKey,
+    key::PriceKey,
 };
-use std::ops::RangeTo;
+use std::ops::RangeToInclusive;
 
 pub trait MatchingRange<Against>: Sized {
-    /// Returns a range that should match (by price) current `self`.
-    fn what_matches(&self) -> RangeTo<PoolKey<Against>>;
+    /// Returns a range of price levels that should match current `self`.
+    fn what_matches(&self) -> RangeToInclusive<PriceKey<Against>>;
 }
 
 impl MatchingRange<SellingBid> for Bid<BuyingBid> {
-    fn what_matches(&self) -> RangeTo<PoolKey<SellingBid>> {
+    fn what_matches(&self) -> RangeToInclusive<PriceKey<SellingBid>> {
         let maximum_buying_price = self.price;
-        ..PoolKey::new(usize::max_value(), maximum_buying_price)
+        ..=PriceKey::new(maximum_buying_price)
     }
 }
 
 impl MatchingRange<BuyingBid> for Bid<SellingBid> {
-    fn what_matches(&self) -> RangeTo<PoolKey<BuyingBid>> {
+    fn what_matches(&self) -> RangeToInclusive<PriceKey<BuyingBid>> {
         let minimum_selling_price = self.price;
-        ..PoolKey::new(usize::max_value(), minimum_selling_price)
+        ..=PriceKey::new(minimum_selling_price)
     }
 }
@@ -1,52 +1,397 @@
 //! Raw data processing.
 
 use crate::{
-    bids::{Bid, BidProcessingType},
-    order_book::OrderBook,
+    bids::{Bid, BidProcessingType, Side, TimeInForce},
+    decimal::{Price, Quantity},
+    engine::MatchingEngine,
+    error::Error,
+    instrument::Rejection,
+    order_book::{IncomingOrder, ProcessingResult},
+    trade::Trade,
+};
+use serde::de::{
+    self, value::MapAccessDeserializer, Deserialize, DeserializeSeed, Deserializer,
+    IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::{BufReader, Read, Write},
 };
-use serde_derive::Deserialize;
-use std::io::Read;
-
-#[derive(Debug, Deserialize, PartialEq)]
-enum Side {
-    Sell,
-    Buy,
-}
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct RawBid {
+    #[serde(default)]
+    symbol: String,
     side: Side,
-    price: u64,
+    #[serde(default)]
+    price: Option<Price>,
     #[serde(rename = "size")]
-    amount: u64,
+    amount: Quantity,
     user_id: u64,
     #[serde(rename = "type")]
     processing_type: BidProcessingType,
+    #[serde(default)]
+    time_in_force: TimeInForce,
+}
+
+impl RawBid {
+    /// Checks what can't be checked by the deserialization itself.
+    ///
+    /// Returns the price and the amount in the units of the instrument of the bid. The price is
+    /// zero for `Market` bids without a price.
+    fn check(&self, engine: &MatchingEngine) -> Result<(u64, u64), String> {
+        let priced = self.processing_type != BidProcessingType::Market;
+        if self.amount.is_zero() {
+            return Err("size must be positive".to_string());
+        }
+        let instrument = engine.instrument(&self.symbol).copied().unwrap_or_default();
+        let amount = self.amount.to_units(instrument.size_scale).ok_or_else(|| {
+            format!(
+                "size {} doesn't fit into {} decimal places",
+                self.amount, instrument.size_scale
+            )
+        })?;
+        let price = match self.price {
+            Some(price) if priced && price.is_zero() => {
+                return Err(format!(
+                    "price must be positive for {:?} bids",
+                    self.processing_type
+                ))
+            }
+            Some(price) => price,
+            None if priced => {
+                return Err(format!(
+                    "price is required for {:?} bids",
+                    self.processing_type
+                ))
+            }
+            None => return Ok((0, amount)),
+        };
+        let price = price.to_units(instrument.price_scale).ok_or_else(|| {
+            format!(
+                "price {} doesn't fit into {} decimal places",
+                price, instrument.price_scale
+            )
+        })?;
+        Ok((price, amount))
+    }
+
+    /// Routes the bid to the book of its symbol, with the `price` and the `amount` in the units of
+    /// the instrument.
+    fn process(
+        self,
+        price: u64,
+        amount: u64,
+        engine: &mut MatchingEngine,
+    ) -> Result<ProcessingResult, Rejection> {
+        let symbol = &self.symbol;
+        match self.side {
+            Side::Sell => {
+                let selling_bid = Bid::empty()
+                    .price(price)
+                    .amount(amount)
+                    .user_id(self.user_id)
+                    .time_in_force(self.time_in_force);
+                engine.process_selling(symbol, selling_bid, self.processing_type)
+            }
+            Side::Buy => {
+                let buying_bid = Bid::empty()
+                    .price(price)
+                    .amount(amount)
+                    .user_id(self.user_id)
+                    .time_in_force(self.time_in_force);
+                engine.process_buying(symbol, buying_bid, self.processing_type)
+            }
+        }
+    }
+}
+
+/// An entry of the input: either an order or a tick of the clock.
+#[derive(Debug, PartialEq)]
+enum RawRecord {
+    Bid(RawBid),
+    /// Advances the clock of the engine to the `time`, see [`MatchingEngine::advance_time`].
+    Tick {
+        time: u64,
+    },
+}
+
+#[derive(Deserialize)]
+struct RawTick {
+    time: u64,
+}
+
+impl<'de> Deserialize<'de> for RawRecord {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(RecordVisitor)
+    }
+}
+
+/// Tells a tick (a map with the only `Tick` key) from an order by the first key of the map.
+struct RecordVisitor;
+
+impl<'de> Visitor<'de> for RecordVisitor {
+    type Value = RawRecord;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an order or a tick")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let first_key = match map.next_key::<String>()? {
+            Some(key) if key == "Tick" => {
+                let RawTick { time } = map.next_value()?;
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::custom("a tick has no other fields"));
+                }
+                return Ok(RawRecord::Tick { time });
+            }
+            first_key => first_key,
+        };
+        RawBid::deserialize(MapAccessDeserializer::new(Replayed { first_key, map }))
+            .map(RawRecord::Bid)
+    }
+}
+
+/// A map with its first key already read.
+struct Replayed<A> {
+    first_key: Option<String>,
+    map: A,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Replayed<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.first_key.take() {
+            Some(key) => seed.deserialize(key.into_deserializer()).map(Some),
+            None => self.map.next_key_seed(seed),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.map.next_value_seed(seed)
+    }
+}
+
+/// Options of processing a list of orders.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessingOptions {
+    /// Skip the entries that don't describe a valid order (like the ones of a zero size) and go
+    /// on with the rest of the input, instead of stopping with [`Error::Invalid`]. Skipped entries
+    /// are reported in [`ProcessingSummary::invalid`].
+    pub skip_invalid: bool,
+}
+
+/// Statistics of processing a list of orders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessingSummary {
+    /// Number of orders read from the input.
+    pub orders_read: usize,
+    /// Number of ticks of the clock read from the input.
+    pub ticks: usize,
+    /// Number of executed trades.
+    pub trades: usize,
+    /// Total amount of items traded.
+    pub total_traded_volume: u128,
+    /// Orders that have been rejected by the engine, along with their (zero-based) indices in the
+    /// input.
+    pub rejected: Vec<(usize, Rejection)>,
+    /// Entries that have been skipped as invalid, along with their indices in the input and the
+    /// reasons.
+    pub invalid: Vec<(usize, String)>,
+    /// Number of bids resting in all the books of the engine once the orders are processed.
+    pub resting_after: usize,
+}
+
+impl ProcessingSummary {
+    /// Accounts for the next order of the input, given the trades it has caused or its rejection.
+    pub(crate) fn record(&mut self, result: Result<&[Trade], Rejection>) {
+        match result {
+            Ok(trades) => {
+                self.trades += trades.len();
+                self.total_traded_volume += trades
+                    .iter()
+                    .map(|trade| u128::from(trade.amount))
+                    .sum::<u128>();
+            }
+            Err(rejection) => self.rejected.push((self.entries(), rejection)),
+        }
+        self.orders_read += 1;
+    }
+
+    /// Number of entries (orders and ticks) read from the input.
+    fn entries(&self) -> usize {
+        self.orders_read + self.ticks
+    }
+
+    /// Accounts for the next entry of the input, which is skipped as invalid.
+    fn skip(&mut self, reason: String) {
+        self.invalid.push((self.entries(), reason));
+        self.orders_read += 1;
+    }
+
+    /// Processes the next entry of the input.
+    fn process(
+        &mut self,
+        record: RawRecord,
+        engine: &mut MatchingEngine,
+        options: ProcessingOptions,
+    ) -> Result<(), Error> {
+        let raw_bid = match record {
+            RawRecord::Bid(raw_bid) => raw_bid,
+            RawRecord::Tick { time } => return self.tick(time, engine, options),
+        };
+        let (price, amount) = match self.check(&raw_bid, engine, options)? {
+            Some(units) => units,
+            None => return Ok(()),
+        };
+        let result = raw_bid.process(price, amount, engine);
+        self.record(
+            result
+                .as_ref()
+                .map(|result| &result.trades[..])
+                .map_err(|r| *r),
+        );
+        Ok(())
+    }
+
+    /// Advances the clock of the `engine` to the `time` of the next entry of the input. The clock
+    /// can't go backwards.
+    fn tick(
+        &mut self,
+        time: u64,
+        engine: &mut MatchingEngine,
+        options: ProcessingOptions,
+    ) -> Result<(), Error> {
+        if time < engine.now() {
+            let reason = format!("time {} is before the current {}", time, engine.now());
+            if !options.skip_invalid {
+                return Err(Error::Invalid {
+                    entry: self.entries(),
+                    reason,
+                });
+            }
+            self.invalid.push((self.entries(), reason));
+        } else {
+            engine.advance_time(time);
+        }
+        self.ticks += 1;
+        Ok(())
+    }
+
+    /// Checks the next entry of the input, either skipping it or failing if it's invalid.
+    ///
+    /// Returns the price and the amount of the entry in the units of its instrument if it has to be
+    /// processed.
+    fn check(
+        &mut self,
+        raw_bid: &RawBid,
+        engine: &MatchingEngine,
+        options: ProcessingOptions,
+    ) -> Result<Option<(u64, u64)>, Error> {
+        match raw_bid.check(engine) {
+            Ok(units) => Ok(Some(units)),
+            Err(reason) if options.skip_invalid => {
+                self.skip(reason);
+                Ok(None)
+            }
+            Err(reason) => Err(Error::Invalid {
+                entry: self.entries(),
+                reason,
+            }),
+        }
+    }
+
+    /// Counts the bids resting in the books of the `engine`.
+    pub(crate) fn finish(mut self, engine: &MatchingEngine) -> Self {
+        self.resting_after = engine
+            .symbols()
+            .filter_map(|symbol| engine.book(symbol))
+            .map(|order_book| order_book.sell_orders().count() + order_book.buy_orders().count())
+            .sum();
+        self
+    }
+}
+
+impl fmt::Display for ProcessingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} orders read, {} ticks, {} trades for {} items in total, {} orders rejected, {} \
+             invalid entries skipped, {} bids resting",
+            self.orders_read,
+            self.ticks,
+            self.trades,
+            self.total_traded_volume,
+            self.rejected.len(),
+            self.invalid.len(),
+            self.resting_after
+        )
+    }
 }
 
-/// Processes orders (bids) from a given reader.
+/// Processes orders (bids) from a given reader, routing them to the books of their symbols.
+///
+/// Returns the summary of the processing, including the bids that have been rejected by the
+/// engine.
+///
+/// Orders are processed one by one as they are deserialized rather than collected first, so a
+/// malformed order stops the processing with [`Error::Parse`] or [`Error::Invalid`], but the
+/// orders before it remain processed. Invalid orders (of a zero size, or without a positive price
+/// unless they are `Market` ones) can be skipped instead, see [`ProcessingOptions`]. Note that the
+/// `yaml` parser still loads the whole document; use [`process_json_lines`] for huge inputs.
 ///
 /// The data is expected to be a list of orders (bids) in the `yaml` format with the following
 /// structure:
 ///
 /// ```norun
 /// ---
-/// - side: ..
+/// - symbol: ..
+///   side: ..
 ///   price: ..
 ///   size: ..
 ///   user_id: ..
 ///   type: ..
+///   time_in_force: ..
 /// - ...
 /// ```
 ///
 /// Where ...
+///  * `symbol` is the name of the instrument, bids without it go to the book with an empty name,
 ///  * `side` could be either `Sell` or `Buy`,
-///  * `price`, `size` and `user_id` are unsigned integers (`u64`),
-///  * `type` is either `Limit`, `FillOrKill` or `ImmediateOrCancel`.
+///  * `price`, `size` and `user_id` are unsigned integers (`u64`), but the `price` and the `size`
+///    might also be decimal numbers, if the instrument has a
+///    [price scale](crate::Instrument::price_scale) or a
+///    [size scale](crate::Instrument::size_scale),
+///  * `type` is either `Limit`, `FillOrKill`, `ImmediateOrCancel`, `Market` or `PostOnly`,
+///  * `price` might be omitted for `Market` bids (and is ignored for them anyway),
+///  * `time_in_force` is optional and is either `GoodTillCancel` (the default), `Day` or
+///    `GoodTillDate: <timestamp>`.
+///
+/// The list might also contain ticks of the clock, `Tick: {time: <timestamp>}`, which advance the
+/// clock of the engine (see [`MatchingEngine::advance_time`]), so expiries, sessions and rate
+/// limits play out the same way on every run. The time can't go backwards; such a tick is an
+/// invalid entry. Ticks are counted in [`ProcessingSummary::ticks`], but still take their indices
+/// in the input.
 ///
 /// ```yaml
 /// ---
-/// - side: Sell
+/// - symbol: ABC
+///   side: Sell
 ///   price: 100500
 ///   size: 999
 ///   user_id: 15
@@ -56,32 +401,325 @@ struct RawBid {
 ///   size: 999
 ///   user_id: 15
 ///   type: ImmediateOrCancel
+/// - side: Sell
+///   price: 100600
+///   size: 10
+///   user_id: 16
+///   type: Limit
+///   time_in_force: Day
+/// - Tick: {time: 1000}
+/// - side: Sell
+///   size: 10
+///   user_id: 16
+///   type: Market
 /// ```
-pub fn process_reader(order_book: &mut OrderBook, r: impl Read) -> Result<(), serde_yaml::Error> {
-    let raw_bids: Vec<RawBid> = serde_yaml::from_reader(r)?;
-    raw_bids.into_iter().for_each(|raw_bid| match raw_bid.side {
-        Side::Sell => {
-            let selling_bid = Bid::empty()
-                .price(raw_bid.price)
-                .amount(raw_bid.amount)
-                .user_id(raw_bid.user_id);
-            order_book.process_selling(selling_bid, raw_bid.processing_type);
+pub fn process_reader(
+    engine: &mut MatchingEngine,
+    r: impl Read,
+    options: ProcessingOptions,
+) -> Result<ProcessingSummary, Error> {
+    let mut summary = None;
+    let mut invalid = None;
+    let processed = SeqProcessor {
+        engine: &mut *engine,
+        options,
+        summary: &mut summary,
+        invalid: &mut invalid,
+    }
+    .deserialize(serde_yaml::Deserializer::from_reader(r));
+    let summary = match (processed, summary) {
+        (Ok(()), summary) => summary.unwrap_or_default(),
+        // The document is not even a list.
+        (Err(e), None) => return Err(Error::Yaml(e)),
+        (Err(e), Some(summary)) => {
+            return Err(invalid.unwrap_or_else(|| Error::parse(summary.entries(), e)))
         }
-        Side::Buy => {
-            let buying_bid = Bid::empty()
-                .price(raw_bid.price)
-                .amount(raw_bid.amount)
-                .user_id(raw_bid.user_id);
-            order_book.process_buying(buying_bid, raw_bid.processing_type);
+    };
+    Ok(summary.finish(engine))
+}
+
+/// Processes a list of orders element by element, as they are deserialized, so the orders are
+/// never collected in memory.
+///
+/// The summary is kept outside, so it's available even if an entry fails: the failed entry is the
+/// one after the last read order.
+struct SeqProcessor<'a> {
+    engine: &'a mut MatchingEngine,
+    options: ProcessingOptions,
+    /// Set once the list is entered.
+    summary: &'a mut Option<ProcessingSummary>,
+    /// Failure of an entry that has been parsed, but is invalid.
+    invalid: &'a mut Option<Error>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for SeqProcessor<'a> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for SeqProcessor<'a> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of orders")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let summary = self.summary.get_or_insert_with(ProcessingSummary::default);
+        while let Some(record) = seq.next_element::<RawRecord>()? {
+            if let Err(e) = summary.process(record, self.engine, self.options) {
+                let de_error = de::Error::custom(&e);
+                *self.invalid = Some(e);
+                return Err(de_error);
+            }
         }
-    });
+        Ok(())
+    }
+}
+
+/// Processes orders (bids) from a given reader in the CSV format, with a header.
+///
+/// Orders are processed as soon as they are read, like in [`process_json_lines`]. Columns are
+/// named after the fields of [`process_reader`]. The `symbol` and `time_in_force` columns are
+/// optional, but their cells can't be left empty, and `time_in_force` can't be `GoodTillDate`.
+/// The `price` of `Market` bids might be left empty. Invalid orders are handled like in
+/// [`process_reader`]:
+///
+/// A tick of the clock is a row of two cells, `Tick` and the time:
+///
+/// ```norun
+/// symbol,side,price,size,user_id,type
+/// ABC,Sell,100500,999,15,Limit
+/// Tick,1000
+/// ABC,Buy,,10,16,Market
+/// ```
+pub fn process_csv(
+    engine: &mut MatchingEngine,
+    r: impl Read,
+    options: ProcessingOptions,
+) -> Result<ProcessingSummary, Error> {
+    let csv_error = |entry, e: csv::Error| {
+        if e.is_io_error() {
+            Error::Io(e.into())
+        } else {
+            Error::parse(entry, e)
+        }
+    };
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(r);
+    let headers = reader.headers().map_err(|e| csv_error(0, e))?.clone();
+    let mut summary = ProcessingSummary::default();
+    for (entry, row) in reader.records().enumerate() {
+        let row = row.map_err(|e| csv_error(entry, e))?;
+        let record = if row.get(0) == Some("Tick") {
+            row.deserialize::<(String, u64)>(None)
+                .map(|(_tick, time)| RawRecord::Tick { time })
+        } else {
+            row.deserialize::<RawBid>(Some(&headers))
+                .map(RawRecord::Bid)
+        };
+        let record = record.map_err(|e| csv_error(entry, e))?;
+        summary.process(record, engine, options)?;
+    }
+    Ok(summary.finish(engine))
+}
+
+/// Processes orders (bids) from a given reader in the JSON Lines format, one order per line.
+///
+/// Orders are processed as soon as they are read, so the input is never loaded into memory as a
+/// whole. Since orders are not validated in advance, a malformed order stops the processing, but
+/// the orders before it remain processed. Fields and the handling of invalid orders are the same
+/// as for [`process_reader`]:
+///
+/// ```norun
+/// {"symbol": "ABC", "side": "Sell", "price": 100500, "size": 999, "user_id": 15, "type": "Limit"}
+/// {"Tick": {"time": 1000}}
+/// {"side": "Buy", "size": 10, "user_id": 16, "type": "Market", "time_in_force": "Day"}
+/// ```
+pub fn process_json_lines(
+    engine: &mut MatchingEngine,
+    r: impl Read,
+    options: ProcessingOptions,
+) -> Result<ProcessingSummary, Error> {
+    let records = serde_json::Deserializer::from_reader(BufReader::new(r)).into_iter::<RawRecord>();
+    let mut summary = ProcessingSummary::default();
+    for (entry, record) in records.enumerate() {
+        let record = record.map_err(|e| {
+            if e.is_io() {
+                Error::Io(e.into())
+            } else {
+                Error::parse(entry, e)
+            }
+        })?;
+        summary.process(record, engine, options)?;
+    }
+    Ok(summary.finish(engine))
+}
+
+/// Bids resting in the books of the engine as `Limit` orders, by symbol, sells first, in the
+/// priority order.
+fn resting_bids(engine: &MatchingEngine) -> Vec<RawBid> {
+    let mut raw_bids = Vec::new();
+    for symbol in engine.symbols() {
+        let order_book = engine
+            .book(symbol)
+            .expect("Symbols are taken from the engine");
+        let instrument = engine.instrument(symbol).copied().unwrap_or_default();
+        let to_raw = |side, price, amount, user_id, time_in_force| RawBid {
+            symbol: symbol.to_string(),
+            side,
+            price: Some(Price::new(price, instrument.price_scale)),
+            amount: Quantity::new(amount, instrument.size_scale),
+            user_id,
+            processing_type: BidProcessingType::Limit,
+            time_in_force,
+        };
+        raw_bids.extend(order_book.sellers.resting().map(|(_id, bid)| {
+            to_raw(
+                Side::Sell,
+                bid.price,
+                bid.amount,
+                bid.user_id,
+                bid.time_in_force,
+            )
+        }));
+        raw_bids.extend(order_book.buyers.resting().map(|(_id, bid)| {
+            to_raw(
+                Side::Buy,
+                bid.price,
+                bid.amount,
+                bid.user_id,
+                bid.time_in_force,
+            )
+        }));
+    }
+    raw_bids
+}
+
+/// Writes the bids resting in the books of the engine in the format of [`process_reader`], so
+/// they can be loaded into another engine.
+///
+/// Bids are written as `Limit` orders, by symbol, selling bids first, in the priority order, so
+/// loading them keeps their relative priorities. Iceberg bids are written as a whole, and bids
+/// waiting for their conditions or triggers are not written at all.
+pub fn dump_book(engine: &MatchingEngine, w: impl Write) -> Result<(), serde_yaml::Error> {
+    serde_yaml::to_writer(w, &resting_bids(engine))
+}
+
+/// Same as [`dump_book`], but in the format of [`process_json_lines`].
+pub fn dump_book_json_lines(
+    engine: &MatchingEngine,
+    mut w: impl Write,
+) -> Result<(), serde_json::Error> {
+    for raw_bid in resting_bids(engine) {
+        serde_json::to_writer(&mut w, &raw_bid)?;
+        w.write_all(b"\n").map_err(serde_json::Error::io)?;
+    }
+    w.flush().map_err(serde_json::Error::io)
+}
+
+/// Same as [`dump_book`], but in the format of [`process_csv`]. Bids that are good till a date
+/// can't be written in this format.
+pub fn dump_book_csv(engine: &MatchingEngine, w: impl Write) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(w);
+    for raw_bid in resting_bids(engine) {
+        writer.serialize(raw_bid)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Orders for the `symbol` as raw bids.
+fn raw_orders(symbol: &str, orders: impl IntoIterator<Item = IncomingOrder>) -> Vec<RawBid> {
+    let to_raw = |side, bid_type, price, amount, user_id, time_in_force| RawBid {
+        symbol: symbol.to_string(),
+        side,
+        price: match bid_type {
+            BidProcessingType::Market => None,
+            _ => Some(Price::new(price, 0)),
+        },
+        amount: Quantity::new(amount, 0),
+        user_id,
+        processing_type: bid_type,
+        time_in_force,
+    };
+    orders
+        .into_iter()
+        .map(|order| match order {
+            IncomingOrder::Selling(bid, bid_type) => to_raw(
+                Side::Sell,
+                bid_type,
+                bid.price,
+                bid.amount,
+                bid.user_id,
+                bid.time_in_force,
+            ),
+            IncomingOrder::Buying(bid, bid_type) => to_raw(
+                Side::Buy,
+                bid_type,
+                bid.price,
+                bid.amount,
+                bid.user_id,
+                bid.time_in_force,
+            ),
+        })
+        .collect()
+}
+
+/// Writes orders for the `symbol` in the format of [`process_reader`], e.g. the ones of an
+/// [`OrderGenerator`](crate::generator::OrderGenerator).
+///
+/// Prices and sizes are written as they are, so the instrument of the `symbol` is expected to
+/// have neither a price scale nor a size scale. Other properties of the bids, like the display
+/// quantity, can't be written in this format.
+pub fn write_orders(
+    symbol: &str,
+    orders: impl IntoIterator<Item = IncomingOrder>,
+    w: impl Write,
+) -> Result<(), serde_yaml::Error> {
+    serde_yaml::to_writer(w, &raw_orders(symbol, orders))
+}
+
+/// Same as [`write_orders`], but in the format of [`process_json_lines`].
+pub fn write_orders_json_lines(
+    symbol: &str,
+    orders: impl IntoIterator<Item = IncomingOrder>,
+    mut w: impl Write,
+) -> Result<(), serde_json::Error> {
+    for raw_bid in raw_orders(symbol, orders) {
+        serde_json::to_writer(&mut w, &raw_bid)?;
+        w.write_all(b"\n").map_err(serde_json::Error::io)?;
+    }
+    w.flush().map_err(serde_json::Error::io)
+}
+
+/// Same as [`write_orders`], but in the format of [`process_csv`]. Bids that are good till a date
+/// can't be written in this format.
+pub fn write_orders_csv(
+    symbol: &str,
+    orders: impl IntoIterator<Item = IncomingOrder>,
+    w: impl Write,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(w);
+    for raw_bid in raw_orders(symbol, orders) {
+        writer.serialize(raw_bid)?;
+    }
+    writer.flush()?;
     Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::bids::Bid;
+    use crate::{bids::Bid, instrument::Instrument};
 
     #[test]
     fn test_deser() {
@@ -101,41 +739,52 @@ mod test {
   size: 35923852309
   user_id: 1543923349209
   type: FillOrKill
+  time_in_force:
+    GoodTillDate: 1000000
 - side: Buy
   price: 0
   size: 0
   user_id: 0
   type: ImmediateOrCancel
+  time_in_force: Day
 "#;
         let data: Vec<RawBid> = serde_yaml::from_reader(&data[..]).unwrap();
         let expected = vec![
             RawBid {
+                symbol: String::new(),
                 side: Side::Sell,
-                price: 10,
-                amount: 99,
+                price: Some(Price::from(10)),
+                amount: Quantity::from(99),
                 user_id: 15,
                 processing_type: BidProcessingType::Limit,
+                time_in_force: TimeInForce::GoodTillCancel,
             },
             RawBid {
+                symbol: String::new(),
                 side: Side::Buy,
-                price: 100_500,
-                amount: 104,
+                price: Some(Price::from(100_500)),
+                amount: Quantity::from(104),
                 user_id: 16,
                 processing_type: BidProcessingType::Limit,
+                time_in_force: TimeInForce::GoodTillCancel,
             },
             RawBid {
+                symbol: String::new(),
                 side: Side::Buy,
-                price: 904_902_491,
-                amount: 35_923_852_309,
+                price: Some(Price::from(904_902_491)),
+                amount: Quantity::from(35_923_852_309),
                 user_id: 1_543_923_349_209,
                 processing_type: BidProcessingType::FillOrKill,
+                time_in_force: TimeInForce::GoodTillDate(1_000_000),
             },
             RawBid {
+                symbol: String::new(),
                 side: Side::Buy,
-                price: 0,
-                amount: 0,
+                price: Some(Price::from(0)),
+                amount: Quantity::from(0),
                 user_id: 0,
                 processing_type: BidProcessingType::ImmediateOrCancel,
+                time_in_force: TimeInForce::Day,
             },
         ];
         assert_eq!(data, expected);
@@ -155,12 +804,515 @@ mod test {
   user_id: 16
   type: Limit
 "#;
-        let mut order_book = OrderBook::default();
-        process_reader(&mut order_book, &data[..]).unwrap();
-        let selling_bids: Vec<_> = order_book.sellers.view_bids().collect();
-        let buying_bids: Vec<_> = order_book.buyers.view_bids().collect();
+        let mut engine = MatchingEngine::new();
+        let summary = process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap();
+        let expected_summary = ProcessingSummary {
+            orders_read: 2,
+            ticks: 0,
+            trades: 1,
+            total_traded_volume: 99,
+            rejected: Vec::new(),
+            invalid: Vec::new(),
+            resting_after: 1,
+        };
+        assert_eq!(summary, expected_summary);
+        let order_book = engine.book("").unwrap();
+        let selling_bids: Vec<_> = order_book.sell_orders().map(|(_id, bid)| bid).collect();
+        let buying_bids: Vec<_> = order_book.buy_orders().map(|(_id, bid)| bid).collect();
         let expected_buying = [&Bid::empty().price(100_500).amount(5).user_id(16)];
         assert!(selling_bids.is_empty(), "{:?}", selling_bids);
         assert_eq!(buying_bids, expected_buying);
     }
+
+    #[test]
+    fn test_process_market() {
+        let data = br#"---
+- side: Buy
+  price: 10
+  size: 5
+  user_id: 15
+  type: Limit
+- side: Sell
+  size: 7
+  user_id: 16
+  type: Market
+"#;
+        let mut engine = MatchingEngine::new();
+        process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap();
+        let order_book = engine.book("").unwrap();
+        assert_eq!(order_book.sell_orders().count(), 0);
+        assert_eq!(order_book.buy_orders().count(), 0);
+        assert_eq!(order_book.buyers.traded_volume(), 5);
+    }
+
+    #[test]
+    fn test_process_symbols() {
+        let data = br#"---
+- symbol: ABC
+  side: Buy
+  price: 10
+  size: 5
+  user_id: 15
+  type: Limit
+- symbol: XYZ
+  side: Sell
+  price: 10
+  size: 7
+  user_id: 16
+  type: Limit
+"#;
+        let mut engine = MatchingEngine::new();
+        process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap();
+        assert_eq!(engine.symbols().collect::<Vec<_>>(), ["ABC", "XYZ"]);
+        let abc = engine.book("ABC").unwrap();
+        let xyz = engine.book("XYZ").unwrap();
+        assert_eq!(abc.buy_orders().count(), 1);
+        assert_eq!(xyz.sell_orders().count(), 1);
+    }
+
+    #[test]
+    fn test_process_rejected() {
+        let data = br#"---
+- symbol: ABC
+  side: Buy
+  price: 11
+  size: 5
+  user_id: 15
+  type: Limit
+- symbol: ABC
+  side: Sell
+  price: 10
+  size: 5
+  user_id: 16
+  type: Limit
+"#;
+        let mut engine = MatchingEngine::new();
+        let instrument = Instrument {
+            tick_size: 2,
+            ..Instrument::default()
+        };
+        engine.add_instrument("ABC", instrument);
+        let summary = process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap();
+        let rejection = Rejection::PriceNotOnTick {
+            price: 11,
+            tick_size: 2,
+        };
+        assert_eq!(summary.rejected, [(0, rejection)]);
+        let order_book = engine.book("ABC").unwrap();
+        assert_eq!(order_book.sell_orders().count(), 1);
+        assert_eq!(order_book.buy_orders().count(), 0);
+    }
+
+    #[test]
+    fn test_decimal_prices() {
+        let data = br#"---
+- {symbol: XYZ, side: Sell, price: 100.25, size: 5, user_id: 15, type: Limit}
+- {symbol: XYZ, side: Sell, price: "100.5", size: 5, user_id: 15, type: Limit}
+- {symbol: XYZ, side: Buy, price: 100.255, size: 5, user_id: 16, type: Limit}
+- {symbol: XYZ, side: Buy, price: 100.3, size: 2, user_id: 16, type: Limit}
+- {side: Buy, price: 1.5, size: 2, user_id: 16, type: Limit}
+"#;
+        let mut engine = MatchingEngine::new();
+        let instrument = Instrument {
+            price_scale: 2,
+            ..Instrument::default()
+        };
+        engine.add_instrument("XYZ", instrument);
+        let options = ProcessingOptions { skip_invalid: true };
+        let summary = process_reader(&mut engine, &data[..], options).unwrap();
+        let skipped: Vec<_> = summary.invalid.iter().map(|(index, _)| *index).collect();
+        assert_eq!(skipped, [2, 4]);
+        assert_eq!(summary.total_traded_volume, 2);
+        let xyz = engine.book("XYZ").unwrap();
+        assert_eq!(xyz.depth(2).selling, [(10025, 3, 1), (10050, 5, 1)]);
+
+        let mut yaml = Vec::new();
+        dump_book(&engine, &mut yaml).unwrap();
+        let dumped: Vec<RawBid> = serde_yaml::from_reader(&yaml[..]).unwrap();
+        let prices: Vec<_> = dumped.iter().filter_map(|raw_bid| raw_bid.price).collect();
+        assert_eq!(prices, [Price::new(10025, 2), Price::new(1005, 1)]);
+    }
+
+    #[test]
+    fn test_fractional_sizes() {
+        let data = br#"---
+- {symbol: XBT, side: Sell, price: 100, size: 0.005, user_id: 15, type: Limit}
+- {symbol: XBT, side: Buy, price: 100, size: "0.003", user_id: 16, type: Limit}
+- {symbol: XBT, side: Buy, price: 100, size: 0.0001, user_id: 16, type: Limit}
+- {symbol: XBT, side: Buy, price: 99, size: 2, user_id: 16, type: Limit}
+"#;
+        let mut engine = MatchingEngine::new();
+        let instrument = Instrument {
+            size_scale: 3,
+            ..Instrument::default()
+        };
+        engine.add_instrument("XBT", instrument);
+        let options = ProcessingOptions { skip_invalid: true };
+        let summary = process_reader(&mut engine, &data[..], options).unwrap();
+        assert_eq!(summary.invalid.len(), 1);
+        assert_eq!(summary.invalid[0].0, 2);
+        assert_eq!(summary.total_traded_volume, 3);
+        let xbt = engine.book("XBT").unwrap();
+        assert_eq!(xbt.depth(1).selling, [(100, 2, 1)]);
+        assert_eq!(xbt.depth(1).buying, [(99, 2000, 1)]);
+
+        let mut json_lines = Vec::new();
+        dump_book_json_lines(&engine, &mut json_lines).unwrap();
+        let dumped = String::from_utf8(json_lines).unwrap();
+        assert!(dumped.contains(r#""size":"0.002""#), "{}", dumped);
+        assert!(dumped.contains(r#""size":2,"#), "{}", dumped);
+    }
+
+    #[test]
+    fn test_missing_price() {
+        let data = br#"---
+- side: Buy
+  size: 5
+  user_id: 15
+  type: Limit
+"#;
+        let mut engine = MatchingEngine::new();
+        let error =
+            process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap_err();
+        assert!(
+            matches!(error, Error::Invalid { entry: 0, .. }),
+            "{}",
+            error
+        );
+        assert_eq!(engine.symbols().count(), 0);
+
+        let data = br#"---
+- side: Sell
+  price: 10
+  size: 5
+  user_id: 15
+  type: Limit
+- side: Buy
+  size: 5
+  user_id: 16
+  type: FillOrKill
+"#;
+        let mut engine = MatchingEngine::new();
+        let error =
+            process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap_err();
+        assert!(
+            matches!(error, Error::Invalid { entry: 1, .. }),
+            "{}",
+            error
+        );
+        assert!(error.to_string().contains("entry 1"), "{}", error);
+        assert_eq!(engine.book("").unwrap().sell_orders().count(), 1);
+
+        let data = b"- {side: Sell, price: 10, size: 5, user_id: 15, type: Limit}\n- {side: Up}\n";
+        let error = process_reader(
+            &mut MatchingEngine::new(),
+            &data[..],
+            ProcessingOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.entry(), Some(1));
+        let error = process_reader(
+            &mut MatchingEngine::new(),
+            &b"side: Sell"[..],
+            ProcessingOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::Yaml(_)), "{}", error);
+    }
+
+    #[test]
+    fn test_skip_invalid() {
+        let data = br#"---
+- {side: Sell, price: 10, size: 5, user_id: 15, type: Limit}
+- {side: Sell, price: 10, size: 0, user_id: 15, type: Limit}
+- {side: Buy, price: 0, size: 3, user_id: 16, type: ImmediateOrCancel}
+- {side: Buy, size: 3, user_id: 16, type: Market}
+"#;
+        let mut engine = MatchingEngine::new();
+        let error =
+            process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap_err();
+        assert!(
+            matches!(error, Error::Invalid { entry: 1, .. }),
+            "{}",
+            error
+        );
+
+        let options = ProcessingOptions { skip_invalid: true };
+        let mut engine = MatchingEngine::new();
+        let summary = process_reader(&mut engine, &data[..], options).unwrap();
+        assert_eq!(summary.orders_read, 4);
+        assert_eq!(summary.total_traded_volume, 3);
+        let skipped: Vec<_> = summary.invalid.iter().map(|(index, _)| *index).collect();
+        assert_eq!(skipped, [1, 2]);
+        assert!(summary.invalid[1].1.contains("price"), "{:?}", summary);
+
+        let data = br#"{"side": "Sell", "price": 10, "size": 0, "user_id": 15, "type": "Limit"}
+{"side": "Sell", "price": 10, "size": 5, "user_id": 15, "type": "Limit"}
+"#;
+        let summary = process_json_lines(&mut MatchingEngine::new(), &data[..], options).unwrap();
+        assert_eq!(summary.invalid.len(), 1);
+        assert_eq!(summary.resting_after, 1);
+
+        let data = b"side,price,size,user_id,type\nBuy,0,3,16,Limit\nBuy,1,3,16,Limit\n";
+        let summary = process_csv(&mut MatchingEngine::new(), &data[..], options).unwrap();
+        assert_eq!(summary.invalid[0].0, 0);
+        assert_eq!(summary.resting_after, 1);
+    }
+
+    #[test]
+    fn test_process_json_lines() {
+        let data = br#"{"symbol": "ABC", "side": "Sell", "price": 10, "size": 5, "user_id": 15, "type": "Limit"}
+{"symbol": "ABC", "side": "Buy", "size": 3, "user_id": 16, "type": "Market"}
+{"side": "Buy", "price": 10, "size": 3, "user_id": 16, "type": "Limit", "time_in_force": "Day"}
+"#;
+        let mut engine = MatchingEngine::new();
+        assert!(
+            process_json_lines(&mut engine, &data[..], ProcessingOptions::default())
+                .unwrap()
+                .rejected
+                .is_empty()
+        );
+        let abc = engine.book("ABC").unwrap();
+        assert_eq!(abc.sellers.traded_volume(), 3);
+        assert_eq!(engine.book("").unwrap().buy_orders().count(), 1);
+
+        let data = br#"{"side": "Sell", "price": 10, "size": 5, "user_id": 15, "type": "Limit"}
+{"side": "Buy", "size": 3, "user_id": 16, "type": "Limit"}
+"#;
+        let mut engine = MatchingEngine::new();
+        let error =
+            process_json_lines(&mut engine, &data[..], ProcessingOptions::default()).unwrap_err();
+        assert!(
+            matches!(error, Error::Invalid { entry: 1, .. }),
+            "{}",
+            error
+        );
+        assert_eq!(engine.book("").unwrap().sell_orders().count(), 1);
+        let error = process_json_lines(
+            &mut engine,
+            &b"{\"side\": \"Sell\"}"[..],
+            ProcessingOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::Parse { entry: 0, .. }), "{}", error);
+    }
+
+    #[test]
+    fn test_huge_volume() {
+        let data = br#"{"side": "Sell", "price": 10, "size": 9223372036854775808, "user_id": 1, "type": "Limit"}
+{"side": "Buy", "price": 10, "size": 9223372036854775808, "user_id": 2, "type": "Limit"}
+{"side": "Sell", "price": 10, "size": 9223372036854775808, "user_id": 1, "type": "Limit"}
+{"side": "Buy", "price": 10, "size": 9223372036854775808, "user_id": 2, "type": "Limit"}
+"#;
+        let mut engine = MatchingEngine::new();
+        let summary =
+            process_json_lines(&mut engine, &data[..], ProcessingOptions::default()).unwrap();
+        assert_eq!(summary.trades, 2);
+        assert_eq!(summary.total_traded_volume, 1 << 64);
+    }
+
+    #[test]
+    fn test_process_csv() {
+        let data = b"side,price,size,user_id,type,time_in_force
+Sell,10,5,15,Limit,Day
+Buy,,3,16,Market,GoodTillCancel
+Buy,9,3,16,Limit,GoodTillCancel
+";
+        let mut engine = MatchingEngine::new();
+        assert!(
+            process_csv(&mut engine, &data[..], ProcessingOptions::default())
+                .unwrap()
+                .rejected
+                .is_empty()
+        );
+        let order_book = engine.book("").unwrap();
+        let selling_bids: Vec<_> = order_book.sell_orders().map(|(_id, bid)| bid).collect();
+        let expected = Bid::empty()
+            .price(10)
+            .amount(2)
+            .user_id(15)
+            .time_in_force(TimeInForce::Day);
+        assert_eq!(selling_bids, [&expected]);
+        assert_eq!(order_book.buy_orders().count(), 1);
+
+        let data = b"side,price,size,user_id,type\nBuy,,3,16,Limit\n";
+        assert!(process_csv(
+            &mut MatchingEngine::new(),
+            &data[..],
+            ProcessingOptions::default()
+        )
+        .is_err());
+    }
+    #[test]
+    fn test_ticks() {
+        let data = br#"---
+- {side: Sell, price: 10, size: 5, user_id: 15, type: Limit, time_in_force: Day}
+- Tick: {time: 86400}
+- {side: Buy, price: 10, size: 5, user_id: 16, type: Limit}
+"#;
+        let mut engine = MatchingEngine::new();
+        let summary = process_reader(&mut engine, &data[..], ProcessingOptions::default()).unwrap();
+        assert_eq!((summary.orders_read, summary.ticks), (2, 1));
+        assert_eq!(summary.trades, 0);
+        assert_eq!(engine.now(), 86400);
+        assert_eq!(engine.book("").unwrap().buy_orders().count(), 1);
+
+        let data = br#"{"side": "Sell", "price": 10, "size": 5, "user_id": 15, "type": "Limit", "time_in_force": "Day"}
+{"Tick": {"time": 100}}
+{"side": "Buy", "price": 10, "size": 2, "user_id": 16, "type": "Limit"}
+{"Tick": {"time": 50}}
+{"side": "Buy", "price": 10, "size": 2, "user_id": 16, "type": "Limit"}
+"#;
+        let mut engine = MatchingEngine::new();
+        let error =
+            process_json_lines(&mut engine, &data[..], ProcessingOptions::default()).unwrap_err();
+        assert!(
+            matches!(error, Error::Invalid { entry: 3, .. }),
+            "{}",
+            error
+        );
+        let options = ProcessingOptions { skip_invalid: true };
+        let summary = process_json_lines(&mut MatchingEngine::new(), &data[..], options).unwrap();
+        assert_eq!(summary.invalid.len(), 1);
+        assert_eq!(summary.invalid[0].0, 3);
+        assert_eq!(summary.total_traded_volume, 4);
+        let error = process_json_lines(
+            &mut MatchingEngine::new(),
+            &br#"{"Tick": {"time": 1}, "side": "Buy"}"#[..],
+            ProcessingOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::Parse { entry: 0, .. }), "{}", error);
+
+        let data = b"side,price,size,user_id,type,time_in_force
+Sell,10,5,15,Limit,Day
+Tick,86400
+Buy,10,3,16,Limit,GoodTillCancel
+";
+        let mut engine = MatchingEngine::new();
+        let summary = process_csv(&mut engine, &data[..], ProcessingOptions::default()).unwrap();
+        assert_eq!(summary.ticks, 1);
+        assert_eq!(summary.trades, 0);
+        assert_eq!(engine.book("").unwrap().buy_orders().count(), 1);
+        assert!(process_csv(
+            &mut MatchingEngine::new(),
+            &b"side,price,size,user_id,type\nTick,soon\n"[..],
+            ProcessingOptions::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_dump_book() {
+        let data = br#"---
+- {symbol: ABC, side: Sell, price: 10, size: 5, user_id: 15, type: Limit}
+- {symbol: ABC, side: Sell, price: 10, size: 4, user_id: 16, type: Limit, time_in_force: Day}
+- {symbol: ABC, side: Buy, price: 10, size: 2, user_id: 17, type: Limit}
+- {symbol: ABC, side: Buy, price: 9, size: 7, user_id: 17, type: Limit}
+- {symbol: XYZ, side: Buy, price: 3, size: 1, user_id: 18, type: Limit}
+"#;
+        let mut engine = MatchingEngine::new();
+        assert!(
+            process_reader(&mut engine, &data[..], ProcessingOptions::default())
+                .unwrap()
+                .rejected
+                .is_empty()
+        );
+        let resting = |engine: &MatchingEngine| -> Vec<_> {
+            engine
+                .symbols()
+                .map(|symbol| {
+                    let order_book = engine.book(symbol).unwrap();
+                    let selling_bids: Vec<_> =
+                        order_book.sellers.resting().map(|(_id, bid)| bid).collect();
+                    let buying_bids: Vec<_> =
+                        order_book.buyers.resting().map(|(_id, bid)| bid).collect();
+                    (symbol.to_string(), selling_bids, buying_bids)
+                })
+                .collect()
+        };
+
+        let mut yaml = Vec::new();
+        dump_book(&engine, &mut yaml).unwrap();
+        let mut reloaded = MatchingEngine::new();
+        assert!(
+            process_reader(&mut reloaded, &yaml[..], ProcessingOptions::default())
+                .unwrap()
+                .rejected
+                .is_empty()
+        );
+        assert_eq!(resting(&reloaded), resting(&engine));
+
+        let mut json_lines = Vec::new();
+        dump_book_json_lines(&engine, &mut json_lines).unwrap();
+        let mut reloaded = MatchingEngine::new();
+        assert!(
+            process_json_lines(&mut reloaded, &json_lines[..], ProcessingOptions::default())
+                .unwrap()
+                .rejected
+                .is_empty()
+        );
+        assert_eq!(resting(&reloaded), resting(&engine));
+
+        let mut csv = Vec::new();
+        dump_book_csv(&engine, &mut csv).unwrap();
+        let mut reloaded = MatchingEngine::new();
+        assert!(
+            process_csv(&mut reloaded, &csv[..], ProcessingOptions::default())
+                .unwrap()
+                .rejected
+                .is_empty()
+        );
+        assert_eq!(resting(&reloaded), resting(&engine));
+    }
+
+    #[test]
+    fn test_write_orders() {
+        use crate::generator::{GeneratorConfig, OrderGenerator};
+        use crate::order_book::OrderBook;
+
+        let config = GeneratorConfig {
+            order_types: vec![
+                (BidProcessingType::Limit, 4),
+                (BidProcessingType::Market, 1),
+            ],
+            ..GeneratorConfig::default()
+        };
+        let orders: Vec<_> = OrderGenerator::new(3, config).take(300).collect();
+        let expected = OrderBook::empty().process_batch(orders.iter().copied());
+        let check = |engine: &MatchingEngine, summary: ProcessingSummary| {
+            assert_eq!(summary.orders_read, orders.len());
+            assert!(summary.rejected.is_empty());
+            assert!(summary.invalid.is_empty());
+            assert_eq!(summary.trades, expected.trades.len());
+            assert_eq!(
+                summary.total_traded_volume,
+                expected
+                    .trades
+                    .iter()
+                    .map(|trade| u128::from(trade.amount))
+                    .sum::<u128>()
+            );
+            assert_eq!(engine.symbols().collect::<Vec<_>>(), ["ABC"]);
+        };
+
+        let mut yaml = Vec::new();
+        write_orders("ABC", orders.iter().copied(), &mut yaml).unwrap();
+        let mut engine = MatchingEngine::new();
+        let summary = process_reader(&mut engine, &yaml[..], ProcessingOptions::default());
+        check(&engine, summary.unwrap());
+
+        let mut json_lines = Vec::new();
+        write_orders_json_lines("ABC", orders.iter().copied(), &mut json_lines).unwrap();
+        let mut engine = MatchingEngine::new();
+        let summary =
+            process_json_lines(&mut engine, &json_lines[..], ProcessingOptions::default());
+        check(&engine, summary.unwrap());
+
+        let mut csv = Vec::new();
+        write_orders_csv("ABC", orders.iter().copied(), &mut csv).unwrap();
+        let mut engine = MatchingEngine::new();
+        let summary = process_csv(&mut engine, &csv[..], ProcessingOptions::default());
+        check(&engine, summary.unwrap());
+    }
 }
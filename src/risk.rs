@@ -0,0 +1,254 @@
+//! Pre-trade risk checks.
+
+use crate::{
+    bids::{BidProcessingType, Side},
+    instrument::Rejection,
+    listener::OrderInfo,
+    order_book::OrderBook,
+};
+use serde_derive::Deserialize;
+use std::collections::BTreeSet;
+
+/// A check a [`MatchingEngine`](crate::MatchingEngine) runs against every incoming bid before it
+/// reaches the book, like the risk gateway of an exchange.
+///
+/// Checks run after the validation against the instrument, in the order they have been
+/// registered; the first rejection wins.
+pub trait RiskCheck {
+    /// Checks the `order` about to be submitted to the `book`, which is `None` if the book of the
+    /// symbol hasn't been created yet (so it's empty).
+    fn check(
+        &mut self,
+        order: &OrderInfo,
+        bid_type: BidProcessingType,
+        book: Option<&OrderBook>,
+    ) -> Result<(), Rejection>;
+}
+
+/// Static limits of the incoming bids, the risk check every engine runs.
+///
+/// Usually loaded as a part of the [`Config`](crate::Config):
+///
+/// ```yaml
+/// risk_limits:
+///   max_order_size: 1000
+///   max_notional: 1000000
+///   max_open_orders: 50
+///   banned_users: [13]
+///   credit_limit: 5000000
+/// ```
+///
+/// All the limits are optional.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct RiskLimits {
+    /// Maximum amount of a single bid.
+    pub max_order_size: Option<u64>,
+    /// Maximum price times amount of a single bid. `Market` bids have no price, so they are not
+    /// checked.
+    pub max_notional: Option<u64>,
+    /// Maximum number of bids a user may have resting in the book, including the incoming one.
+    pub max_open_orders: Option<usize>,
+    /// Users whose bids are rejected.
+    pub banned_users: BTreeSet<u64>,
+    /// Maximum buying exposure of a user: the cash the user owes, plus the price times the
+    /// remaining amount of the user's resting buying bids, plus the notional of the incoming
+    /// buying bid (what it would be filled for right now, for a `Market` bid). Selling bids are
+    /// not checked.
+    ///
+    /// The cash is taken from the [accounts](crate::OrderBook::accounts) of the book, so a credit
+    /// limit makes the books track positions.
+    pub credit_limit: Option<u64>,
+}
+
+impl RiskCheck for RiskLimits {
+    fn check(
+        &mut self,
+        order: &OrderInfo,
+        bid_type: BidProcessingType,
+        book: Option<&OrderBook>,
+    ) -> Result<(), Rejection> {
+        if self.banned_users.contains(&order.user_id) {
+            return Err(Rejection::UserBanned {
+                user_id: order.user_id,
+            });
+        }
+        if let Some(max_order_size) = self.max_order_size {
+            if order.amount > max_order_size {
+                return Err(Rejection::OrderTooLarge {
+                    amount: order.amount,
+                    max_order_size,
+                });
+            }
+        }
+        if let Some(max_notional) = self.max_notional {
+            let notional = u128::from(order.price) * u128::from(order.amount);
+            if bid_type != BidProcessingType::Market && notional > u128::from(max_notional) {
+                return Err(Rejection::NotionalTooLarge {
+                    notional,
+                    max_notional,
+                });
+            }
+        }
+        if let (Some(credit_limit), Side::Buy) = (self.credit_limit, order.side) {
+            let exposure = book.map_or(0, |book| book.buying_exposure(order.user_id))
+                + match bid_type {
+                    BidProcessingType::Market => {
+                        book.map_or(0, |book| book.preview_buy(u64::MAX, order.amount).notional)
+                    }
+                    _ => u128::from(order.price) * u128::from(order.amount),
+                };
+            if exposure > u128::from(credit_limit) {
+                return Err(Rejection::CreditLimitExceeded {
+                    user_id: order.user_id,
+                    exposure,
+                    credit_limit,
+                });
+            }
+        }
+        if let Some(max_open_orders) = self.max_open_orders {
+            let open_orders = book.map_or(0, |book| book.open_orders(order.user_id));
+            if open_orders >= max_open_orders {
+                return Err(Rejection::TooManyOpenOrders {
+                    user_id: order.user_id,
+                    max_open_orders,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{bids::Bid, config::Config, engine::MatchingEngine};
+
+    #[test]
+    fn test_limits() {
+        let config = Config {
+            risk_limits: RiskLimits {
+                max_order_size: Some(100),
+                max_notional: Some(5_000),
+                max_open_orders: Some(2),
+                banned_users: vec![13].into_iter().collect(),
+                credit_limit: None,
+            },
+            ..Config::default()
+        };
+        let mut engine = MatchingEngine::with_config(config);
+        let selling_bid = Bid::empty().price(100).amount(10).user_id(1);
+        let mut sell = |bid, bid_type| {
+            engine
+                .process_selling("ABC", bid, bid_type)
+                .map(|result| result.order_id)
+        };
+        assert_eq!(
+            sell(selling_bid.user_id(13), BidProcessingType::Limit),
+            Err(Rejection::UserBanned { user_id: 13 })
+        );
+        assert_eq!(
+            sell(selling_bid.amount(101), BidProcessingType::Limit),
+            Err(Rejection::OrderTooLarge {
+                amount: 101,
+                max_order_size: 100
+            })
+        );
+        assert_eq!(
+            sell(selling_bid.amount(51), BidProcessingType::Limit),
+            Err(Rejection::NotionalTooLarge {
+                notional: 5_100,
+                max_notional: 5_000
+            })
+        );
+        assert!(sell(selling_bid.amount(51), BidProcessingType::Market).is_ok());
+        assert!(sell(selling_bid, BidProcessingType::Limit).is_ok());
+        assert!(sell(selling_bid, BidProcessingType::Limit).is_ok());
+        assert_eq!(
+            sell(selling_bid, BidProcessingType::Limit),
+            Err(Rejection::TooManyOpenOrders {
+                user_id: 1,
+                max_open_orders: 2
+            })
+        );
+        assert!(sell(selling_bid.user_id(2), BidProcessingType::Limit).is_ok());
+    }
+
+    #[test]
+    fn test_credit_limit() {
+        let config = Config {
+            risk_limits: RiskLimits {
+                credit_limit: Some(2_000),
+                ..RiskLimits::default()
+            },
+            ..Config::default()
+        };
+        let mut engine = MatchingEngine::with_config(config);
+        let selling_bid = Bid::empty().price(100).amount(10).user_id(1);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        // Selling bids are not limited.
+        engine
+            .process_selling(
+                "ABC",
+                selling_bid.price(110).amount(50),
+                BidProcessingType::Limit,
+            )
+            .unwrap();
+        let buying_bid = Bid::empty().price(90).amount(10).user_id(2);
+        engine
+            .process_buying("ABC", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+        // 900 of the resting bid and 1000 of the trade.
+        engine
+            .process_buying("ABC", buying_bid.price(100), BidProcessingType::Limit)
+            .unwrap();
+        assert_eq!(
+            engine.process_buying("ABC", buying_bid.amount(2), BidProcessingType::Market),
+            Err(Rejection::CreditLimitExceeded {
+                user_id: 2,
+                exposure: 2_120,
+                credit_limit: 2_000
+            })
+        );
+        assert!(engine
+            .process_buying("ABC", buying_bid.amount(1), BidProcessingType::Limit)
+            .is_ok());
+        let accounts = engine.book("ABC").and_then(OrderBook::accounts).unwrap();
+        assert_eq!(accounts.get(2).cash, -1_000);
+    }
+
+    #[test]
+    fn test_custom_check() {
+        struct TestUsersOnly;
+
+        impl RiskCheck for TestUsersOnly {
+            fn check(
+                &mut self,
+                order: &OrderInfo,
+                _bid_type: BidProcessingType,
+                _book: Option<&OrderBook>,
+            ) -> Result<(), Rejection> {
+                if order.user_id < 100 {
+                    Ok(())
+                } else {
+                    Err(Rejection::UserBanned {
+                        user_id: order.user_id,
+                    })
+                }
+            }
+        }
+
+        let mut engine = MatchingEngine::new();
+        engine.add_risk_check(TestUsersOnly);
+        let buying_bid = Bid::empty().price(100).amount(10).user_id(100);
+        assert_eq!(
+            engine.process_buying("ABC", buying_bid, BidProcessingType::Limit),
+            Err(Rejection::UserBanned { user_id: 100 })
+        );
+        assert!(engine
+            .process_buying("ABC", buying_bid.user_id(2), BidProcessingType::Limit)
+            .is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+//! Trading sessions.
+
+use serde_derive::Deserialize;
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Phase of a trading session.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum Phase {
+    /// Before the opening auction: incoming bids are dropped.
+    PreOpen,
+    /// The opening call auction: incoming bids are added to the book without being matched.
+    OpeningAuction,
+    /// Continuous trading: incoming bids are matched as they arrive.
+    #[default]
+    Continuous,
+    /// The closing call auction: incoming bids are added to the book without being matched.
+    ClosingAuction,
+    /// After the closing auction: incoming bids are dropped.
+    Closed,
+}
+
+impl Phase {
+    /// Checks whether bids are collected for a call auction during the phase.
+    pub fn is_auction(self) -> bool {
+        matches!(self, Phase::OpeningAuction | Phase::ClosingAuction)
+    }
+
+    /// Checks whether incoming bids are accepted during the phase.
+    pub fn accepts_bids(self) -> bool {
+        !matches!(self, Phase::PreOpen | Phase::Closed)
+    }
+}
+
+/// Daily schedule of a trading session.
+///
+/// Times are the numbers of seconds since the (UTC) midnight at which the phases start, e.g. a
+/// session that opens with an auction at 9:00, trades continuously from 9:30 and closes with an
+/// auction between 17:30 and 17:35 is
+///
+/// ```yaml
+/// opening_auction: 32400
+/// continuous: 34200
+/// closing_auction: 63000
+/// closed: 63300
+/// ```
+///
+/// The rest of the day the market is [`Phase::PreOpen`] before the opening auction and
+/// [`Phase::Closed`] after the close. The times are expected to be in the ascending order.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub struct SessionSchedule {
+    /// Start of the opening auction.
+    pub opening_auction: u64,
+    /// Start of continuous trading, which uncrosses the opening auction.
+    pub continuous: u64,
+    /// Start of the closing auction.
+    pub closing_auction: u64,
+    /// End of the session, which uncrosses the closing auction.
+    pub closed: u64,
+}
+
+impl SessionSchedule {
+    /// Phase of the session at the time `now` (seconds since the Unix epoch).
+    pub fn phase_at(&self, now: u64) -> Phase {
+        let time_of_day = now % SECONDS_PER_DAY;
+        if time_of_day < self.opening_auction {
+            Phase::PreOpen
+        } else if time_of_day < self.continuous {
+            Phase::OpeningAuction
+        } else if time_of_day < self.closing_auction {
+            Phase::Continuous
+        } else if time_of_day < self.closed {
+            Phase::ClosingAuction
+        } else {
+            Phase::Closed
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_phase_at() {
+        let schedule = SessionSchedule {
+            opening_auction: 100,
+            continuous: 200,
+            closing_auction: 300,
+            closed: 400,
+        };
+        let day = SECONDS_PER_DAY;
+        let phases: Vec<_> = [0, 99, 100, 199, 200, 299, 300, 399, 400, day - 1, day + 150]
+            .iter()
+            .map(|&now| schedule.phase_at(now))
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                Phase::PreOpen,
+                Phase::PreOpen,
+                Phase::OpeningAuction,
+                Phase::OpeningAuction,
+                Phase::Continuous,
+                Phase::Continuous,
+                Phase::ClosingAuction,
+                Phase::ClosingAuction,
+                Phase::Closed,
+                Phase::Closed,
+                Phase::OpeningAuction,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_phases() {
+        let phases = [
+            (Phase::PreOpen, false, false),
+            (Phase::OpeningAuction, true, true),
+            (Phase::Continuous, false, true),
+            (Phase::ClosingAuction, true, true),
+            (Phase::Closed, false, false),
+        ];
+        for &(phase, is_auction, accepts_bids) in phases.iter() {
+            assert_eq!(phase.is_auction(), is_auction, "{:?}", phase);
+            assert_eq!(phase.accepts_bids(), accepts_bids, "{:?}", phase);
+        }
+        assert_eq!(Phase::default(), Phase::Continuous);
+    }
+
+    #[test]
+    fn test_degenerate_schedules() {
+        // No auctions: the phases of zero length are skipped.
+        let schedule = SessionSchedule {
+            opening_auction: 100,
+            continuous: 100,
+            closing_auction: 300,
+            closed: 300,
+        };
+        assert_eq!(schedule.phase_at(99), Phase::PreOpen);
+        assert_eq!(schedule.phase_at(100), Phase::Continuous);
+        assert_eq!(schedule.phase_at(300), Phase::Closed);
+
+        // Trading around the clock: the market never closes.
+        let schedule = SessionSchedule {
+            opening_auction: 0,
+            continuous: 0,
+            closing_auction: SECONDS_PER_DAY,
+            closed: SECONDS_PER_DAY,
+        };
+        for &now in [0, SECONDS_PER_DAY - 1, SECONDS_PER_DAY, u64::MAX].iter() {
+            assert_eq!(schedule.phase_at(now), Phase::Continuous, "{}", now);
+        }
+    }
+
+    #[test]
+    fn test_malformed_schedule() {
+        let result: Result<SessionSchedule, _> =
+            serde_yaml::from_str("opening_auction: 100\ncontinuous: 200\nclosed: 400\n");
+        assert!(result.is_err());
+        let result: Result<SessionSchedule, _> = serde_yaml::from_str(
+            "opening_auction: -1\ncontinuous: 200\nclosing_auction: 300\nclosed: 400\n",
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Shadow books: verifying books against their feeds while they trade.
+
+use crate::{
+    feed::{Divergence, FeedBook, FeedEvent},
+    listener::OrderBookListener,
+    order_book::OrderBook,
+};
+use log::error;
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+type Shadows = Rc<RefCell<BTreeMap<String, FeedBook>>>;
+
+/// Applies the feed of a book to its shadow.
+pub(crate) struct ShadowListener {
+    symbol: String,
+    shadows: Shadows,
+}
+
+impl OrderBookListener for ShadowListener {
+    fn on_feed_event(&mut self, event: &FeedEvent) {
+        let mut shadows = self.shadows.borrow_mut();
+        let shadow = shadows.entry(self.symbol.clone()).or_default();
+        if let Err(e) = shadow.apply_event(*event) {
+            error!("The shadow of the book {:?} is broken: {}", self.symbol, e);
+        }
+    }
+}
+
+/// Shadows of the books of an engine, rebuilt from the books' feeds, compared with the books
+/// periodically.
+pub(crate) struct ShadowBooks {
+    every: usize,
+    processed: usize,
+    shadows: Shadows,
+    divergences: BTreeMap<String, Vec<Divergence>>,
+}
+
+impl ShadowBooks {
+    /// Initializes the shadows, compared with the books once every `every` bids (at least one).
+    pub fn new(every: usize) -> Self {
+        ShadowBooks {
+            every: every.max(1),
+            processed: 0,
+            shadows: Shadows::default(),
+            divergences: BTreeMap::new(),
+        }
+    }
+
+    /// Starts shadowing a book as it is now, returns the listener to register with the book.
+    pub fn follow(&self, symbol: &str, order_book: &OrderBook) -> ShadowListener {
+        self.shadows
+            .borrow_mut()
+            .insert(symbol.to_string(), FeedBook::from_book(order_book));
+        ShadowListener {
+            symbol: symbol.to_string(),
+            shadows: Rc::clone(&self.shadows),
+        }
+    }
+
+    /// Returns a factory of the listeners of new books.
+    pub fn factory(&self) -> impl Fn(&str) -> ShadowListener {
+        let shadows = Rc::clone(&self.shadows);
+        move |symbol| ShadowListener {
+            symbol: symbol.to_string(),
+            shadows: Rc::clone(&shadows),
+        }
+    }
+
+    /// Counts a processed bid, comparing the books with their shadows once every `every` bids.
+    pub fn processed(&mut self, books: &BTreeMap<String, OrderBook>) {
+        self.processed += 1;
+        if self.processed.is_multiple_of(self.every) {
+            self.check(books);
+        }
+    }
+
+    /// Compares the books with their shadows, logging and keeping the divergences.
+    ///
+    /// Returns whether all the books match their shadows.
+    pub fn check(&mut self, books: &BTreeMap<String, OrderBook>) -> bool {
+        let shadows = self.shadows.borrow();
+        let mut matching = true;
+        for (symbol, book) in books {
+            let divergences = shadows
+                .get(symbol)
+                .map(|shadow| shadow.diff(book))
+                .unwrap_or_else(|| FeedBook::new().diff(book));
+            if divergences.is_empty() {
+                continue;
+            }
+            error!(
+                "The book {:?} has diverged from its feed: {:?}",
+                symbol, divergences
+            );
+            matching = false;
+            self.divergences.insert(symbol.clone(), divergences);
+        }
+        matching
+    }
+
+    /// The last divergences found in every book, by symbol.
+    pub fn divergences(&self) -> &BTreeMap<String, Vec<Divergence>> {
+        &self.divergences
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType, Side},
+        engine::MatchingEngine,
+        order_book::OrderId,
+    };
+
+    #[test]
+    fn test_shadow_books() {
+        let mut engine = MatchingEngine::new();
+        let selling_bid = Bid::empty().price(100).amount(10).user_id(1);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        engine.enable_shadow_books(2);
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        engine
+            .process_buying("ABC", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+        engine
+            .process_buying("XYZ", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+        engine.book_mut("XYZ").cancel(OrderId(0));
+        assert!(engine.check_shadow_books());
+        assert_eq!(engine.divergences().count(), 0);
+
+        // A lost feed message.
+        let mut order_book = OrderBook::empty();
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let mut books = BTreeMap::new();
+        books.insert("ABC".to_string(), order_book);
+        let mut shadows = ShadowBooks::new(1);
+        let mut listener = shadows.follow("ABC", &books["ABC"]);
+        shadows.processed(&books);
+        assert!(shadows.divergences().is_empty());
+        listener.on_feed_event(&FeedEvent::Cancel {
+            order_id: OrderId(0),
+        });
+        shadows.processed(&books);
+        let divergence = Divergence {
+            order_id: OrderId(0),
+            book: Some((Side::Sell, 100, 10)),
+            feed: None,
+        };
+        assert_eq!(shadows.divergences()["ABC"], [divergence]);
+        assert!(!shadows.check(&books));
+    }
+}
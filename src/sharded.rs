@@ -0,0 +1,226 @@
+//! A matching engine that spreads its books over several threads.
+
+use crate::{
+    bids::{Bid, BidProcessingType, BuyingBid, SellingBid},
+    config::Config,
+    engine::MatchingEngine,
+    instrument::Rejection,
+    listener::OrderBookListener,
+    order_book::{Depth, OrderId},
+    trade::Trade,
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::mpsc,
+    thread,
+};
+
+enum Request {
+    Selling(String, Bid<SellingBid>, BidProcessingType),
+    Buying(String, Bid<BuyingBid>, BidProcessingType),
+    Cancel(String, OrderId),
+    AdvanceTime(u64),
+    Depth(String, usize, mpsc::Sender<Option<Depth>>),
+}
+
+/// An event of a [`ShardedEngine`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShardEvent {
+    /// A trade has been executed in the book of the symbol.
+    Traded { symbol: String, trade: Trade },
+    /// A bid for the symbol has been rejected by the engine.
+    Rejected {
+        symbol: String,
+        rejection: Rejection,
+    },
+}
+
+/// Sends the trades of a book to the output channel.
+struct TradeForwarder {
+    symbol: String,
+    events: mpsc::Sender<ShardEvent>,
+}
+
+impl OrderBookListener for TradeForwarder {
+    fn on_trade(&mut self, trade: &Trade) {
+        let _ = self.events.send(ShardEvent::Traded {
+            symbol: self.symbol.clone(),
+            trade: *trade,
+        });
+    }
+}
+
+/// A set of matching threads (shards), each of them running a [`MatchingEngine`] for its part of
+/// the symbols.
+///
+/// Symbols are assigned to the shards by their hashes, so all the bids of a symbol are processed
+/// by the same thread in the order they are submitted, while different symbols are processed in
+/// parallel. Trades and rejections of all the shards are reported to a single channel.
+pub struct ShardedEngine {
+    shards: Vec<mpsc::Sender<Request>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl ShardedEngine {
+    /// Starts `shards` matching threads (at least one) with the same configuration.
+    ///
+    /// Returns the engine and the receiver of the events of all the shards. The channel is closed
+    /// once the engine is [joined](ShardedEngine::join).
+    pub fn spawn(config: Config, shards: usize) -> (Self, mpsc::Receiver<ShardEvent>) {
+        let (events, receiver) = mpsc::channel();
+        let (shards, workers) = (0..shards.max(1))
+            .map(|_| {
+                let (requests, incoming) = mpsc::channel();
+                let config = config.clone();
+                let events = events.clone();
+                let worker = thread::spawn(move || run_shard(config, incoming, events));
+                (requests, worker)
+            })
+            .unzip();
+        (ShardedEngine { shards, workers }, receiver)
+    }
+
+    fn shard(&self, symbol: &str) -> &mpsc::Sender<Request> {
+        let mut hasher = DefaultHasher::new();
+        symbol.hash(&mut hasher);
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
+
+    fn send(&self, symbol: &str, request: Request) {
+        // A shard only stops if it has panicked, which is reported by `join`.
+        let _ = self.shard(symbol).send(request);
+    }
+
+    /// Queues a selling bid for the book of the given symbol.
+    pub fn process_selling(&self, symbol: &str, bid: Bid<SellingBid>, bid_type: BidProcessingType) {
+        self.send(symbol, Request::Selling(symbol.to_string(), bid, bid_type));
+    }
+
+    /// Queues a buying bid for the book of the given symbol.
+    pub fn process_buying(&self, symbol: &str, bid: Bid<BuyingBid>, bid_type: BidProcessingType) {
+        self.send(symbol, Request::Buying(symbol.to_string(), bid, bid_type));
+    }
+
+    /// Queues a cancellation of a bid in the book of the given symbol. Its outcome is not
+    /// reported.
+    pub fn cancel(&self, symbol: &str, order_id: OrderId) {
+        self.send(symbol, Request::Cancel(symbol.to_string(), order_id));
+    }
+
+    /// Queues an advance of the clock of every book, see [`MatchingEngine::advance_time`].
+    pub fn advance_time(&self, now: u64) {
+        for shard in &self.shards {
+            let _ = shard.send(Request::AdvanceTime(now));
+        }
+    }
+
+    /// Waits for the bids queued for the symbol to be processed and returns the depth of its
+    /// book, if it exists.
+    pub fn depth(&self, symbol: &str, levels: usize) -> Option<Depth> {
+        let (reply, depth) = mpsc::channel();
+        self.send(symbol, Request::Depth(symbol.to_string(), levels, reply));
+        depth.recv().ok().flatten()
+    }
+
+    /// Waits for all the queued requests to be processed and stops the threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the threads has panicked.
+    pub fn join(self) {
+        drop(self.shards);
+        for worker in self.workers {
+            worker.join().expect("A matching thread has panicked");
+        }
+    }
+}
+
+fn run_shard(config: Config, incoming: mpsc::Receiver<Request>, events: mpsc::Sender<ShardEvent>) {
+    let mut engine = MatchingEngine::with_config(config);
+    let forwarded = events.clone();
+    engine.add_listener_factory(move |symbol| TradeForwarder {
+        symbol: symbol.to_string(),
+        events: forwarded.clone(),
+    });
+    for request in incoming {
+        let (symbol, result) = match request {
+            Request::Selling(symbol, bid, bid_type) => {
+                let result = engine.process_selling(&symbol, bid, bid_type);
+                (symbol, result)
+            }
+            Request::Buying(symbol, bid, bid_type) => {
+                let result = engine.process_buying(&symbol, bid, bid_type);
+                (symbol, result)
+            }
+            Request::Cancel(symbol, order_id) => {
+                engine.book_mut(&symbol).cancel(order_id);
+                continue;
+            }
+            Request::AdvanceTime(now) => {
+                engine.advance_time(now);
+                continue;
+            }
+            Request::Depth(symbol, levels, reply) => {
+                let _ = reply.send(engine.book(&symbol).map(|book| book.depth(levels)));
+                continue;
+            }
+        };
+        if let Err(rejection) = result {
+            let _ = events.send(ShardEvent::Rejected { symbol, rejection });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instrument::Instrument;
+
+    #[test]
+    fn test_sharded() {
+        let mut config = Config::default();
+        config.instruments.insert(
+            "XYZ".to_string(),
+            Instrument {
+                lot_size: 10,
+                ..Instrument::default()
+            },
+        );
+        let (engine, events) = ShardedEngine::spawn(config, 3);
+        let symbols = ["ABC", "DEF", "GHI", "XYZ"];
+        for &symbol in &symbols {
+            for user_id in 0..10 {
+                let selling_bid = Bid::empty().price(100).amount(10).user_id(user_id);
+                engine.process_selling(symbol, selling_bid, BidProcessingType::Limit);
+            }
+            let buying_bid = Bid::empty().price(100).amount(25).user_id(99);
+            engine.process_buying(symbol, buying_bid, BidProcessingType::Limit);
+        }
+        assert_eq!(engine.depth("ABC", 1).unwrap().selling, [(100, 75, 8)]);
+        assert_eq!(engine.depth("JKL", 1), None);
+        engine.join();
+
+        let events: Vec<_> = events.into_iter().collect();
+        for &symbol in &symbols[..3] {
+            let traded: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    ShardEvent::Traded { symbol: s, trade } if s == symbol => {
+                        Some((trade.maker_user_id, trade.amount))
+                    }
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(traded, [(0, 10), (1, 10), (2, 5)]);
+        }
+        let rejected = events
+            .iter()
+            .filter(|event| match event {
+                ShardEvent::Rejected { symbol, .. } => symbol == "XYZ",
+                _ => false,
+            })
+            .count();
+        assert_eq!(rejected, 1);
+    }
+}
@@ -0,0 +1,245 @@
+//! An order book shared between threads.
+
+use crate::{
+    bids::{Bid, BidProcessingType, BuyingBid, SellingBid},
+    order_book::{Amendment, Cancellation, Depth, OrderBook, OrderId, ProcessingResult},
+};
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{mpsc, Arc, Condvar, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+type Request = Box<dyn FnOnce(&mut OrderBook) + Send>;
+
+/// A handle to an order book owned by a dedicated matching thread.
+///
+/// Requests from all the handles are queued and applied to the book one by one, in the order they
+/// arrive. Every request returns a [`Reply`], which can either be waited for or awaited. The
+/// thread stops once all the handles are dropped.
+#[derive(Clone)]
+pub struct SharedOrderBook {
+    requests: mpsc::Sender<Request>,
+}
+
+impl SharedOrderBook {
+    /// Starts a matching thread with the book made by `init`.
+    ///
+    /// The book is made in the matching thread, since its listeners don't have to be `Send`.
+    pub fn spawn(init: impl FnOnce() -> OrderBook + Send + 'static) -> Self {
+        let (requests, incoming) = mpsc::channel::<Request>();
+        thread::spawn(move || {
+            let mut order_book = init();
+            for request in incoming {
+                request(&mut order_book);
+            }
+        });
+        SharedOrderBook { requests }
+    }
+
+    /// Runs `f` against the book in the matching thread.
+    pub fn with<T, F>(&self, f: F) -> Reply<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut OrderBook) -> T + Send + 'static,
+    {
+        let (responder, reply) = channel();
+        // If the thread has stopped, the request is dropped along with the responder, so the
+        // reply reports the disconnection.
+        let _ = self
+            .requests
+            .send(Box::new(move |order_book| responder.send(f(order_book))));
+        reply
+    }
+
+    /// See [`OrderBook::process_selling`].
+    pub fn process_selling(
+        &self,
+        bid: Bid<SellingBid>,
+        bid_type: BidProcessingType,
+    ) -> Reply<ProcessingResult> {
+        self.with(move |order_book| order_book.process_selling(bid, bid_type))
+    }
+
+    /// See [`OrderBook::process_buying`].
+    pub fn process_buying(
+        &self,
+        bid: Bid<BuyingBid>,
+        bid_type: BidProcessingType,
+    ) -> Reply<ProcessingResult> {
+        self.with(move |order_book| order_book.process_buying(bid, bid_type))
+    }
+
+    /// See [`OrderBook::cancel`].
+    pub fn cancel(&self, order_id: OrderId) -> Reply<Cancellation> {
+        self.with(move |order_book| order_book.cancel(order_id))
+    }
+
+    /// See [`OrderBook::amend`].
+    pub fn amend(&self, order_id: OrderId, new_price: u64, new_size: u64) -> Reply<Amendment> {
+        self.with(move |order_book| order_book.amend(order_id, new_price, new_size))
+    }
+
+    /// See [`OrderBook::depth`].
+    pub fn depth(&self, levels: usize) -> Reply<Depth> {
+        self.with(move |order_book| order_book.depth(levels))
+    }
+}
+
+/// The matching thread has stopped (panicked) before handling a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("The matching thread has stopped")
+    }
+}
+
+impl Error for Disconnected {}
+
+struct Slot<T> {
+    value: Option<T>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+type Shared<T> = Arc<(Mutex<Slot<T>>, Condvar)>;
+
+fn lock<T>(shared: &Shared<T>) -> MutexGuard<'_, Slot<T>> {
+    shared
+        .0
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn channel<T>() -> (Responder<T>, Reply<T>) {
+    let shared = Arc::new((
+        Mutex::new(Slot {
+            value: None,
+            closed: false,
+            waker: None,
+        }),
+        Condvar::new(),
+    ));
+    (
+        Responder {
+            shared: Arc::clone(&shared),
+        },
+        Reply { shared },
+    )
+}
+
+/// The sending half of a [`Reply`]. Dropping it closes the reply.
+struct Responder<T> {
+    shared: Shared<T>,
+}
+
+impl<T> Responder<T> {
+    fn send(self, value: T) {
+        lock(&self.shared).value = Some(value);
+    }
+}
+
+impl<T> Drop for Responder<T> {
+    fn drop(&mut self) {
+        let mut slot = lock(&self.shared);
+        slot.closed = true;
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+        self.shared.1.notify_all();
+    }
+}
+
+/// Outcome of a request to a [`SharedOrderBook`].
+pub struct Reply<T> {
+    shared: Shared<T>,
+}
+
+impl<T> Reply<T> {
+    /// Blocks the current thread until the request is handled.
+    pub fn wait(self) -> Result<T, Disconnected> {
+        let mut slot = lock(&self.shared);
+        loop {
+            if let Some(value) = slot.value.take() {
+                return Ok(value);
+            }
+            if slot.closed {
+                return Err(Disconnected);
+            }
+            slot = self
+                .shared
+                .1
+                .wait(slot)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+impl<T> Future for Reply<T> {
+    type Output = Result<T, Disconnected>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut slot = lock(&self.shared);
+        if let Some(value) = slot.value.take() {
+            Poll::Ready(Ok(value))
+        } else if slot.closed {
+            Poll::Ready(Err(Disconnected))
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_shareable<T: Send + Sync + Clone>() {}
+
+    #[test]
+    fn test_shared() {
+        assert_shareable::<SharedOrderBook>();
+        let shared = SharedOrderBook::spawn(OrderBook::empty);
+        let producers: Vec<_> = (0..4)
+            .map(|user_id| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for price in 100..110 {
+                        let bid = Bid::empty().price(price).amount(1).user_id(user_id);
+                        shared
+                            .process_selling(bid, BidProcessingType::Limit)
+                            .wait()
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        producers
+            .into_iter()
+            .for_each(|producer| producer.join().unwrap());
+        let depth = shared.depth(1).wait().unwrap();
+        assert_eq!(depth.selling, [(100, 4, 4)]);
+
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(9);
+        let mut reply = shared.process_buying(buying_bid, BidProcessingType::Limit);
+        let mut cx = Context::from_waker(Waker::noop());
+        let result = loop {
+            if let Poll::Ready(result) = Pin::new(&mut reply).poll(&mut cx) {
+                break result.unwrap();
+            }
+            thread::yield_now();
+        };
+        assert_eq!(result.trades.len(), 3);
+
+        let panicking = shared.with::<(), _>(|_order_book| panic!("Stop the matching thread"));
+        assert_eq!(panicking.wait(), Err(Disconnected));
+        assert_eq!(shared.cancel(OrderId(0)).wait(), Err(Disconnected));
+    }
+}
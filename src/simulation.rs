@@ -0,0 +1,206 @@
+//! Hypothetical processing of bids.
+
+use crate::{bids::Bid, trade::Trade};
+use std::{error::Error, fmt};
+
+/// What would happen to a bid if it was processed by the book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation<BidKind> {
+    /// Trades against resting bids, in the order they would happen.
+    pub trades: Vec<Trade>,
+    /// The part of the bid that would be added to the book.
+    pub rest: Option<Bid<BidKind>>,
+}
+
+impl<BidKind> Simulation<BidKind> {
+    /// Total amount of items that would be traded.
+    pub fn traded_amount(&self) -> u64 {
+        self.trades.iter().map(|trade| trade.amount).sum()
+    }
+}
+
+/// How much of a bid would be filled by the resting bids of the book and at what prices, see
+/// [`OrderBook::preview_buy`](crate::OrderBook::preview_buy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quote {
+    /// Amount of items that would be filled.
+    pub filled: u64,
+    /// Total price of the filled items, i.e. the sum of their prices times their amounts.
+    pub notional: u128,
+    /// Number of price levels the fill would reach.
+    pub levels: usize,
+}
+
+impl Quote {
+    /// Average price of the filled items, if any.
+    pub fn average_price(&self) -> Option<f64> {
+        if self.filled == 0 {
+            return None;
+        }
+        Some(self.notional as f64 / self.filled as f64)
+    }
+}
+
+/// There are not enough resting bids in the book to fill the requested amount, see
+/// [`OrderBook::cost_to_fill`](crate::OrderBook::cost_to_fill). Holds what could be filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsufficientLiquidity(pub Quote);
+
+impl fmt::Display for InsufficientLiquidity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Only {} items are available", self.0.filled)
+    }
+}
+
+impl Error for InsufficientLiquidity {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{BidProcessingType, BuyingBid, SellingBid, Side},
+        OrderBook,
+    };
+
+    fn order_book() -> OrderBook {
+        let mut order_book = OrderBook::empty();
+        for &(price, amount, user_id) in &[(100, 4, 1), (90, 5, 2), (101, 3, 3), (90, 2, 4)] {
+            let bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+            order_book.process_selling(bid, BidProcessingType::Limit);
+        }
+        order_book
+    }
+
+    fn resting(order_book: &OrderBook) -> (Vec<Bid<SellingBid>>, Vec<Bid<BuyingBid>>) {
+        (
+            order_book.sellers.view_bids().cloned().collect(),
+            order_book.buyers.view_bids().cloned().collect(),
+        )
+    }
+
+    #[test]
+    fn test_simulate_limit() {
+        let mut order_book = order_book();
+        let before = resting(&order_book);
+        let bid = Bid::empty().price(100).amount(15).user_id(5);
+        let simulation = order_book.simulate_buying(bid, BidProcessingType::Limit);
+        assert_eq!(resting(&order_book), before);
+        assert_eq!(
+            simulation,
+            Simulation {
+                trades: vec![
+                    Trade {
+                        maker_user_id: 2,
+                        taker_user_id: 5,
+                        taker_side: Side::Buy,
+                        price: 90,
+                        amount: 5
+                    },
+                    Trade {
+                        maker_user_id: 4,
+                        taker_user_id: 5,
+                        taker_side: Side::Buy,
+                        price: 90,
+                        amount: 2
+                    },
+                    Trade {
+                        maker_user_id: 1,
+                        taker_user_id: 5,
+                        taker_side: Side::Buy,
+                        price: 100,
+                        amount: 4
+                    },
+                ],
+                rest: Some(bid.amount(4)),
+            }
+        );
+        assert_eq!(simulation.traded_amount(), 11);
+
+        order_book.process_buying(bid, BidProcessingType::Limit);
+        let (sellers, buyers) = resting(&order_book);
+        assert_eq!(sellers, [Bid::empty().price(101).amount(3).user_id(3)]);
+        assert_eq!(buyers, [bid.amount(4)]);
+    }
+
+    #[test]
+    fn test_simulate_fill_or_kill() {
+        let mut order_book = order_book();
+        let bid = Bid::empty().price(100).amount(12).user_id(5);
+        let simulation = order_book.simulate_buying(bid, BidProcessingType::FillOrKill);
+        assert_eq!(simulation.trades, []);
+        assert_eq!(simulation.rest, None);
+
+        let bid = bid.amount(10);
+        let simulation = order_book.simulate_buying(bid, BidProcessingType::FillOrKill);
+        assert_eq!(simulation.traded_amount(), 10);
+        assert_eq!(simulation.trades.last().unwrap().amount, 3);
+        assert_eq!(simulation.rest, None);
+
+        order_book.process_buying(bid, BidProcessingType::FillOrKill);
+        let (sellers, buyers) = resting(&order_book);
+        assert_eq!(
+            sellers,
+            [
+                Bid::empty().price(100).amount(1).user_id(1),
+                Bid::empty().price(101).amount(3).user_id(3),
+            ]
+        );
+        assert!(buyers.is_empty());
+    }
+
+    #[test]
+    fn test_simulate_immediate_or_cancel() {
+        let order_book = order_book();
+        let bid = Bid::empty().price(95).amount(10).user_id(5);
+        let simulation = order_book.simulate_buying(bid, BidProcessingType::ImmediateOrCancel);
+        assert_eq!(simulation.traded_amount(), 7);
+        assert_eq!(simulation.rest, None);
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut order_book = order_book();
+        let quote = order_book.preview_buy(100, 10);
+        let expected = Quote {
+            filled: 10,
+            notional: 90 * 7 + 100 * 3,
+            levels: 2,
+        };
+        assert_eq!(quote, expected);
+        assert_eq!(quote.average_price(), Some(93.));
+        assert_eq!(order_book.preview_buy(100, 20).filled, 11);
+        assert_eq!(order_book.preview_buy(89, 20), Quote::default());
+        assert_eq!(order_book.preview_buy(89, 20).average_price(), None);
+
+        let iceberg = Bid::empty().price(80).amount(10).display(2).user_id(6);
+        order_book.process_buying(iceberg, BidProcessingType::Limit);
+        let quote = order_book.preview_sell(0, 12);
+        assert_eq!(quote.filled, 10);
+        assert_eq!(quote.average_price(), Some(80.));
+        assert_eq!(resting(&order_book).1, [iceberg.amount(2)]);
+    }
+
+    #[test]
+    fn test_cost_to_fill() {
+        let order_book = order_book();
+        let expected = Quote {
+            filled: 12,
+            notional: 90 * 7 + 100 * 4 + 101,
+            levels: 3,
+        };
+        assert_eq!(order_book.cost_to_fill(Side::Buy, 12), Ok(expected));
+        let expected = Quote {
+            filled: 14,
+            notional: 90 * 7 + 100 * 4 + 101 * 3,
+            levels: 3,
+        };
+        assert_eq!(
+            order_book.cost_to_fill(Side::Buy, 15),
+            Err(InsufficientLiquidity(expected))
+        );
+        assert_eq!(
+            order_book.cost_to_fill(Side::Sell, 1),
+            Err(InsufficientLiquidity(Quote::default()))
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Running statistics of trades.
+
+use crate::trade::Trade;
+use std::convert::TryFrom;
+
+/// Running statistics of a stream of trades: the last price, the high and low prices, the
+/// cumulative volume and the volume-weighted average price (VWAP).
+///
+/// Totals are kept in 128 bits, so the volume never overflows. The turnover (the sum of prices
+/// times amounts) saturates, which only skews the VWAP for astronomically large trades.
+///
+/// An [`OrderBook`](crate::OrderBook) keeps the statistics of its own trades, see
+/// [`OrderBook::stats`](crate::OrderBook::stats). A standalone accumulator can be fed with trades
+/// from any other source, e.g. a replay.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TradeStats {
+    trades: u64,
+    volume: u128,
+    turnover: u128,
+    last_price: Option<u64>,
+    high: Option<u64>,
+    low: Option<u64>,
+}
+
+impl TradeStats {
+    /// Initializes empty statistics.
+    pub fn new() -> Self {
+        TradeStats::default()
+    }
+
+    /// Accounts for a trade.
+    pub fn record(&mut self, trade: &Trade) {
+        self.trades += 1;
+        self.volume += u128::from(trade.amount);
+        self.turnover = self
+            .turnover
+            .saturating_add(u128::from(trade.price) * u128::from(trade.amount));
+        self.last_price = Some(trade.price);
+        self.high = self.high.max(Some(trade.price));
+        self.low = Some(self.low.map_or(trade.price, |low| low.min(trade.price)));
+    }
+
+    /// Number of recorded trades.
+    pub fn trades(&self) -> u64 {
+        self.trades
+    }
+
+    /// Total amount of traded items, saturated at `u64::MAX`.
+    pub fn volume(&self) -> u64 {
+        u64::try_from(self.volume).unwrap_or(u64::MAX)
+    }
+
+    /// Price of the last trade, if any.
+    pub fn last_price(&self) -> Option<u64> {
+        self.last_price
+    }
+
+    /// The highest trade price, if any.
+    pub fn high(&self) -> Option<u64> {
+        self.high
+    }
+
+    /// The lowest trade price, if any.
+    pub fn low(&self) -> Option<u64> {
+        self.low
+    }
+
+    /// Volume-weighted average price of the trades (rounded down), if any items have been traded.
+    pub fn vwap(&self) -> Option<u64> {
+        if self.volume == 0 {
+            return None;
+        }
+        u64::try_from(self.turnover / self.volume).ok()
+    }
+}
+
+impl<'a> Extend<&'a Trade> for TradeStats {
+    fn extend<I: IntoIterator<Item = &'a Trade>>(&mut self, trades: I) {
+        trades.into_iter().for_each(|trade| self.record(trade))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bids::Side;
+
+    #[test]
+    fn test_stats() {
+        let trade = Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Buy,
+            price: 100,
+            amount: 10,
+        };
+        let mut stats = TradeStats::new();
+        assert_eq!(stats.vwap(), None);
+        assert_eq!(stats.last_price(), None);
+        stats.extend(&[
+            trade,
+            Trade {
+                price: 110,
+                amount: 5,
+                ..trade
+            },
+            Trade {
+                price: 95,
+                amount: 5,
+                ..trade
+            },
+        ]);
+        assert_eq!(stats.trades(), 3);
+        assert_eq!(stats.volume(), 20);
+        assert_eq!(stats.last_price(), Some(95));
+        assert_eq!(stats.high(), Some(110));
+        assert_eq!(stats.low(), Some(95));
+        // (1000 + 550 + 475) / 20
+        assert_eq!(stats.vwap(), Some(101));
+
+        let huge = Trade {
+            price: u64::MAX,
+            amount: u64::MAX,
+            ..trade
+        };
+        let mut stats = TradeStats::new();
+        stats.record(&huge);
+        assert_eq!(stats.vwap(), Some(u64::MAX));
+        stats.record(&huge);
+        assert_eq!(stats.volume(), u64::MAX);
+    }
+
+    #[test]
+    fn test_zero_amount() {
+        let mut stats = TradeStats::new();
+        stats.record(&Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Sell,
+            price: 100,
+            amount: 0,
+        });
+        assert_eq!(stats.trades(), 1);
+        assert_eq!(stats.volume(), 0);
+        assert_eq!(stats.last_price(), Some(100));
+        assert_eq!(stats.vwap(), None);
+    }
+
+    #[test]
+    fn test_saturated_turnover() {
+        let trade = Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Buy,
+            price: u64::MAX,
+            amount: u64::MAX,
+        };
+        let mut stats = TradeStats::new();
+        stats.extend(&[trade, trade, trade]);
+        stats.record(&Trade {
+            price: 1,
+            amount: 1,
+            ..trade
+        });
+        assert_eq!(stats.trades(), 4);
+        assert_eq!(stats.high(), Some(u64::MAX));
+        assert_eq!(stats.low(), Some(1));
+        // The turnover is stuck at `u128::MAX`, so the VWAP is skewed down.
+        assert_eq!(
+            stats.vwap(),
+            Some((u128::MAX / (3 * u128::from(u64::MAX) + 1)) as u64)
+        );
+    }
+}
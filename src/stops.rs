@@ -0,0 +1,215 @@
+//! Stop bids that are held until a trade prints through their trigger prices.
+
+use crate::{
+    bids::{Bid, BidProcessingType, GenericBid, Side},
+    order_book::OrderId,
+};
+use std::{cmp::Reverse, collections::BTreeMap, fmt};
+
+/// A description of a stop trigger, for logging.
+pub(crate) struct Trigger(pub Side, pub u64);
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Side::Buy => write!(f, "trade price >= {}", self.1),
+            Side::Sell => write!(f, "trade price <= {}", self.1),
+        }
+    }
+}
+
+/// A bid released by its trigger.
+pub(crate) struct TriggeredBid<BidKind> {
+    pub order_id: OrderId,
+    pub trigger_price: u64,
+    pub bid: Bid<BidKind>,
+    pub bid_type: BidProcessingType,
+}
+
+/// Stop bids of one kind, keyed by their trigger prices.
+///
+/// Buying stops are triggered by trades at or above their trigger prices, selling stops by trades
+/// at or below them.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize),
+    serde(bound = "BidKind: GenericBid")
+)]
+#[derive(Debug, Clone)]
+pub(crate) struct StopBids<BidKind> {
+    bids: BTreeMap<(u64, OrderId), (Bid<BidKind>, BidProcessingType)>,
+}
+
+impl<BidKind> Default for StopBids<BidKind> {
+    fn default() -> Self {
+        StopBids {
+            bids: BTreeMap::new(),
+        }
+    }
+}
+
+impl<BidKind: GenericBid> StopBids<BidKind> {
+    pub fn insert(
+        &mut self,
+        order_id: OrderId,
+        trigger_price: u64,
+        bid: Bid<BidKind>,
+        bid_type: BidProcessingType,
+    ) {
+        self.bids.insert((trigger_price, order_id), (bid, bid_type));
+    }
+
+    pub fn find(&self, order_id: OrderId) -> Option<&Bid<BidKind>> {
+        self.bids
+            .iter()
+            .find(|((_, id), _)| *id == order_id)
+            .map(|(_, (bid, _))| bid)
+    }
+
+    pub fn find_mut(&mut self, order_id: OrderId) -> Option<&mut Bid<BidKind>> {
+        self.bids
+            .iter_mut()
+            .find(|((_, id), _)| *id == order_id)
+            .map(|(_, (bid, _))| bid)
+    }
+
+    pub fn cancel(&mut self, order_id: OrderId) -> Option<Bid<BidKind>> {
+        let key = *self.bids.keys().find(|(_, id)| *id == order_id)?;
+        self.bids.remove(&key).map(|(bid, _)| bid)
+    }
+
+    /// Removes and returns the bids triggered by trades with prices within `low..=high`, in the
+    /// order they should be processed.
+    pub fn take_triggered(&mut self, low: u64, high: u64) -> Vec<TriggeredBid<BidKind>> {
+        let mut keys: Vec<_> = match BidKind::side() {
+            Side::Buy => self
+                .bids
+                .range(..=(high, OrderId(usize::MAX)))
+                .map(|(key, _)| *key)
+                .collect(),
+            Side::Sell => self
+                .bids
+                .range((low, OrderId(0))..)
+                .map(|(key, _)| *key)
+                .collect(),
+        };
+        if BidKind::side() == Side::Sell {
+            // The higher the trigger price of a selling stop, the earlier it's hit.
+            keys.sort_by_key(|&(trigger_price, order_id)| (Reverse(trigger_price), order_id));
+        }
+        keys.into_iter()
+            .filter_map(|key| {
+                let (bid, bid_type) = self.bids.remove(&key)?;
+                Some(TriggeredBid {
+                    order_id: key.1,
+                    trigger_price: key.0,
+                    bid,
+                    bid_type,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{BuyingBid, SellingBid},
+        OrderBook,
+    };
+
+    #[test]
+    fn test_take_triggered() {
+        let mut buying: StopBids<BuyingBid> = StopBids::default();
+        let mut selling: StopBids<SellingBid> = StopBids::default();
+        for (id, &trigger_price) in [100, 90, 110, 90].iter().enumerate() {
+            let ty = BidProcessingType::Market;
+            buying.insert(OrderId(id), trigger_price, Bid::empty().amount(1), ty);
+            selling.insert(OrderId(id), trigger_price, Bid::empty().amount(1), ty);
+        }
+        fn triggered<K>(bids: Vec<TriggeredBid<K>>) -> Vec<usize> {
+            bids.into_iter().map(|bid| bid.order_id.0).collect()
+        }
+        assert_eq!(triggered(buying.take_triggered(95, 100)), [1, 3, 0]);
+        assert!(buying.take_triggered(95, 100).is_empty());
+        assert_eq!(triggered(selling.take_triggered(95, 100)), [2, 0]);
+        assert_eq!(triggered(selling.take_triggered(80, 80)), [1, 3]);
+        assert!(selling.take_triggered(0, u64::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_stop_orders() {
+        let mut order_book = OrderBook::empty();
+        for &(price, user_id) in &[(100, 1), (101, 2), (105, 3)] {
+            let selling_bid = Bid::empty().price(price).amount(2).user_id(user_id);
+            order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        }
+        // A stop-limit bid triggered at 101 that won't pay more than 102.
+        let stop_limit = Bid::empty().price(102).amount(3).user_id(4);
+        let stop_limit = order_book.process_buying_stop(101, stop_limit, BidProcessingType::Limit);
+        assert!(stop_limit.trades.is_empty());
+        // A stop bid triggered at 100.
+        let stop = Bid::empty().amount(2).user_id(5);
+        order_book.process_buying_stop(100, stop, BidProcessingType::Market);
+        assert_eq!(order_book.last_trade_price(), None);
+
+        // Prints at 100, which triggers the stop bid, which prints at 101 and triggers the
+        // stop-limit bid.
+        let buying_bid = Bid::empty().price(100).amount(1).user_id(6);
+        let result = order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let trades: Vec<_> = result
+            .trades
+            .iter()
+            .map(|trade| {
+                (
+                    trade.taker_user_id,
+                    trade.maker_user_id,
+                    trade.price,
+                    trade.amount,
+                )
+            })
+            .collect();
+        assert_eq!(
+            trades,
+            [
+                (6, 1, 100, 1),
+                (5, 1, 100, 1),
+                (5, 2, 101, 1),
+                (4, 2, 101, 1)
+            ]
+        );
+        assert_eq!(order_book.last_trade_price(), Some(101));
+        let resting: Vec<_> = order_book.buyers.view_bids().cloned().collect();
+        assert_eq!(resting, [Bid::empty().price(102).amount(2).user_id(4)]);
+        assert!(order_book
+            .buying_stops
+            .take_triggered(0, u64::MAX)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_triggered_bid_priority() {
+        let mut order_book = OrderBook::empty();
+        // A stop-limit bid triggered at 100 that won't pay more than 99.
+        let stop_limit = Bid::empty().price(99).amount(2).user_id(1);
+        order_book.process_buying_stop(100, stop_limit, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(99).amount(2).user_id(2);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        let selling_bid = Bid::empty().price(100).amount(1).user_id(3);
+        order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let buying_bid = Bid::empty().price(100).amount(1).user_id(4);
+        order_book.process_buying(buying_bid, BidProcessingType::Limit);
+        assert_eq!(order_book.buy_orders().count(), 2);
+
+        // The triggered bid queues last at its price.
+        let selling_bid = Bid::empty().price(99).amount(3).user_id(5);
+        let result = order_book.process_selling(selling_bid, BidProcessingType::Limit);
+        let makers: Vec<_> = result
+            .trades
+            .iter()
+            .map(|trade| (trade.maker_user_id, trade.amount))
+            .collect();
+        assert_eq!(makers, [(2, 2), (1, 1)]);
+    }
+}
@@ -0,0 +1,163 @@
+//! The tape of executed trades.
+
+use crate::trade::Trade;
+use std::convert::TryFrom;
+
+/// Identifier of a trade executed in an order book.
+///
+/// Ids are assigned in the execution order, starting from one, so a missing id means a missed
+/// trade.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TradeId(pub u64);
+
+/// A trade recorded on the tape.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapeEntry {
+    /// Id of the trade.
+    pub trade_id: TradeId,
+    /// Sequence number of the processing step that has executed the trade: a submitted bid, an
+    /// uncrossed auction or resumed trading. Trades of bids released by the step share it.
+    pub seq: u64,
+    /// The trade itself.
+    pub trade: Trade,
+}
+
+/// All the trades executed in an order book, in the execution order.
+///
+/// Applying the same inputs to equally configured empty books results in the same tape, ids and
+/// sequence numbers included.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TradeTape {
+    entries: Vec<TapeEntry>,
+    seq: u64,
+}
+
+impl TradeTape {
+    /// Initializes an empty tape.
+    pub fn new() -> Self {
+        TradeTape::default()
+    }
+
+    /// Starts the next processing step and returns its sequence number.
+    pub(crate) fn next_step(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    /// Records trades of the current processing step.
+    pub(crate) fn record(&mut self, trades: &[Trade]) {
+        let seq = self.seq;
+        let first_id = self.entries.len() as u64 + 1;
+        self.entries
+            .extend(trades.iter().enumerate().map(|(i, &trade)| TapeEntry {
+                trade_id: TradeId(first_id + i as u64),
+                seq,
+                trade,
+            }));
+    }
+
+    /// Sequence number of the last processing step, zero if nothing has been processed yet.
+    pub fn last_seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Id of the last trade, if any.
+    pub fn last_trade_id(&self) -> Option<TradeId> {
+        self.entries.last().map(|entry| entry.trade_id)
+    }
+
+    /// All the recorded trades.
+    pub fn entries(&self) -> &[TapeEntry] {
+        &self.entries
+    }
+
+    /// Trades recorded after the one with the given id, e.g. to fill a gap a downstream consumer
+    /// has detected.
+    pub fn since(&self, trade_id: TradeId) -> &[TapeEntry] {
+        let start = usize::try_from(trade_id.0)
+            .unwrap_or(usize::MAX)
+            .min(self.entries.len());
+        &self.entries[start..]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bids::Side;
+
+    #[test]
+    fn test_tape() {
+        let trade = Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Buy,
+            price: 100,
+            amount: 10,
+        };
+        let mut tape = TradeTape::new();
+        assert_eq!(tape.last_trade_id(), None);
+        assert_eq!(tape.next_step(), 1);
+        tape.record(&[trade, trade]);
+        tape.next_step();
+        assert_eq!(tape.next_step(), 3);
+        tape.record(&[trade]);
+        assert_eq!(tape.last_seq(), 3);
+        assert_eq!(tape.last_trade_id(), Some(TradeId(3)));
+        let ids: Vec<_> = tape
+            .entries()
+            .iter()
+            .map(|entry| (entry.trade_id.0, entry.seq))
+            .collect();
+        assert_eq!(ids, vec![(1, 1), (2, 1), (3, 3)]);
+        assert_eq!(tape.since(TradeId(2)).len(), 1);
+        assert_eq!(tape.since(TradeId(0)).len(), 3);
+        assert!(tape.since(TradeId(5)).is_empty());
+    }
+
+    #[test]
+    fn test_empty_steps() {
+        let mut tape = TradeTape::new();
+        assert_eq!(tape.last_seq(), 0);
+        assert!(tape.since(TradeId(0)).is_empty());
+        assert!(tape.since(TradeId(u64::MAX)).is_empty());
+
+        tape.next_step();
+        tape.record(&[]);
+        assert_eq!(tape.last_seq(), 1);
+        assert_eq!(tape.last_trade_id(), None);
+
+        let trade = Trade {
+            maker_user_id: 1,
+            taker_user_id: 2,
+            taker_side: Side::Sell,
+            price: 100,
+            amount: 10,
+        };
+        tape.next_step();
+        tape.record(&[trade]);
+        tape.record(&[]);
+        tape.record(&[trade]);
+        // Both records belong to the same step, ids go on without a gap.
+        let ids: Vec<_> = tape
+            .entries()
+            .iter()
+            .map(|entry| (entry.trade_id.0, entry.seq))
+            .collect();
+        assert_eq!(ids, vec![(1, 2), (2, 2)]);
+        assert_eq!(tape.since(TradeId(1)), &tape.entries()[1..]);
+        assert!(tape.since(TradeId(u64::MAX)).is_empty());
+    }
+}
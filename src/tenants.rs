@@ -0,0 +1,156 @@
+//! Hosting several independent matching engines (tenants) in one process.
+
+use crate::{config::Config, engine::MatchingEngine, order_book::OrderBook};
+use std::collections::BTreeMap;
+
+/// Separator of the tenant and the symbol in a namespaced symbol, e.g. `class_a/ABC`.
+pub const TENANT_SEPARATOR: char = '/';
+
+/// A set of isolated [`MatchingEngine`]s, one per tenant, e.g. for hosting several class or test
+/// environments in one process.
+///
+/// Every tenant has its own configuration, books, listeners and clock, so bids of one tenant
+/// never reach the books of another one, even if their symbols are the same. Books are addressed
+/// by namespaced symbols, `<tenant>/<symbol>`.
+#[derive(Default)]
+pub struct Tenants {
+    engines: BTreeMap<String, MatchingEngine>,
+}
+
+impl Tenants {
+    /// Initializes a set without any tenants.
+    pub fn new() -> Self {
+        Tenants::default()
+    }
+
+    /// Registers a tenant with its configuration and returns its engine, e.g. to register the
+    /// tenant's listeners.
+    ///
+    /// The configuration of an existing tenant is reloaded, see
+    /// [`MatchingEngine::reload_config`].
+    pub fn add_tenant(&mut self, tenant: &str, config: Config) -> &mut MatchingEngine {
+        match self.engines.get_mut(tenant) {
+            Some(engine) => engine.reload_config(config),
+            None => {
+                self.engines
+                    .insert(tenant.to_string(), MatchingEngine::with_config(config));
+            }
+        }
+        self.engines
+            .get_mut(tenant)
+            .expect("The tenant has just been registered")
+    }
+
+    /// Removes a tenant with all its books.
+    pub fn remove_tenant(&mut self, tenant: &str) -> Option<MatchingEngine> {
+        self.engines.remove(tenant)
+    }
+
+    /// Names of the tenants, in the alphabetical order.
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.engines.keys().map(String::as_str)
+    }
+
+    /// Returns the engine of the tenant, if it's registered.
+    pub fn engine(&self, tenant: &str) -> Option<&MatchingEngine> {
+        self.engines.get(tenant)
+    }
+
+    /// Returns the engine of the tenant, if it's registered.
+    pub fn engine_mut(&mut self, tenant: &str) -> Option<&mut MatchingEngine> {
+        self.engines.get_mut(tenant)
+    }
+
+    /// Splits a namespaced symbol into the engine of its tenant and the symbol within the engine.
+    ///
+    /// Returns `None` if the symbol isn't namespaced or the tenant isn't registered.
+    pub fn resolve_mut<'a>(&mut self, symbol: &'a str) -> Option<(&mut MatchingEngine, &'a str)> {
+        let (tenant, symbol) = symbol.split_once(TENANT_SEPARATOR)?;
+        Some((self.engines.get_mut(tenant)?, symbol))
+    }
+
+    /// Returns the book of a namespaced symbol, if it exists.
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        let (tenant, symbol) = symbol.split_once(TENANT_SEPARATOR)?;
+        self.engines.get(tenant)?.book(symbol)
+    }
+
+    /// Advances the time of every tenant, see [`MatchingEngine::advance_time`].
+    pub fn advance_time(&mut self, now: u64) {
+        for engine in self.engines.values_mut() {
+            engine.advance_time(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType},
+        command::{Event, EventLog},
+        instrument::Rejection,
+        risk::RiskLimits,
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_tenants() {
+        let mut tenants = Tenants::new();
+        let events_a = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&events_a);
+        tenants
+            .add_tenant("a", Config::default())
+            .add_listener_factory(move |_symbol| EventLog(Rc::clone(&log)));
+        let limited = Config {
+            risk_limits: RiskLimits {
+                max_order_size: Some(5),
+                ..RiskLimits::default()
+            },
+            ..Config::default()
+        };
+        tenants.add_tenant("b", limited);
+        assert_eq!(tenants.tenants().collect::<Vec<_>>(), ["a", "b"]);
+
+        let selling_bid = Bid::empty().price(100).amount(10).user_id(1);
+        let (engine, symbol) = tenants.resolve_mut("a/ABC").unwrap();
+        assert_eq!(symbol, "ABC");
+        engine
+            .process_selling(symbol, selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        let (engine, symbol) = tenants.resolve_mut("b/ABC").unwrap();
+        // The same symbol of another tenant is another book, with another configuration.
+        assert!(engine
+            .process_buying(symbol, buying_bid, BidProcessingType::Limit)
+            .unwrap()
+            .trades
+            .is_empty());
+        assert!(matches!(
+            engine.process_selling(symbol, selling_bid, BidProcessingType::Limit),
+            Err(Rejection::OrderTooLarge { .. })
+        ));
+        assert_eq!(
+            tenants.book("a/ABC").unwrap().depth(1).selling,
+            [(100, 10, 1)]
+        );
+        assert_eq!(
+            tenants.book("b/ABC").unwrap().depth(1).buying,
+            [(100, 3, 1)]
+        );
+        assert!(tenants.book("c/ABC").is_none());
+        assert!(tenants.resolve_mut("ABC").is_none());
+        // Only the events of the tenant's books reach its listeners.
+        let events: Vec<_> = events_a
+            .borrow()
+            .iter()
+            .filter(|event| !matches!(event, Event::Feed(_)))
+            .cloned()
+            .collect();
+        assert!(matches!(events[..], [Event::OrderAdded(_)]), "{:?}", events);
+
+        tenants.remove_tenant("a");
+        assert!(tenants.engine("a").is_none());
+        assert!(tenants.engine_mut("b").is_some());
+    }
+}
@@ -0,0 +1,113 @@
+//! Rate limiting of the order flow of the users.
+
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+/// What happens to bids submitted by a user above the rate limit.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+pub enum ThrottleMode {
+    /// Bids are dropped.
+    #[default]
+    Reject,
+    /// Bids are queued and processed in the submission order once the user is within the limit
+    /// again.
+    Queue,
+}
+
+/// A limit of the rate of the bids of every user, a token bucket per user.
+///
+/// Every bid takes a token from the bucket of its user; buckets hold up to `burst` tokens (at
+/// least one) and are refilled with `per_second` tokens every second.
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Number of bids a user may submit per second on average.
+    pub per_second: u64,
+    /// Number of bids a user may submit at once.
+    pub burst: u64,
+    /// What happens to the bids above the limit.
+    #[serde(default)]
+    pub mode: ThrottleMode,
+}
+
+/// Token bucket of a user.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: u64,
+    updated: u64,
+}
+
+/// Token buckets of the users, see [`RateLimit`].
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    buckets: HashMap<u64, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> ThrottleMode {
+        self.limit.mode
+    }
+
+    /// Takes a token from the bucket of the user at the time `now` (in seconds), if there is one.
+    ///
+    /// Buckets of new users are full. Time going backwards doesn't refill them.
+    pub fn try_acquire(&mut self, user_id: u64, now: u64) -> bool {
+        let burst = self.limit.burst.max(1);
+        let bucket = self.buckets.entry(user_id).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_sub(bucket.updated);
+        let refill = elapsed.saturating_mul(self.limit.per_second);
+        bucket.tokens = bucket.tokens.saturating_add(refill).min(burst);
+        bucket.updated = bucket.updated.max(now);
+        if bucket.tokens == 0 {
+            return false;
+        }
+        bucket.tokens -= 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            per_second: 2,
+            burst: 3,
+            mode: ThrottleMode::Reject,
+        });
+        let acquired = |limiter: &mut RateLimiter, user_id, now| {
+            (0..5)
+                .take_while(|_| limiter.try_acquire(user_id, now))
+                .count()
+        };
+        assert_eq!(acquired(&mut limiter, 1, 10), 3);
+        assert_eq!(acquired(&mut limiter, 2, 10), 3);
+        assert_eq!(acquired(&mut limiter, 1, 10), 0);
+        assert_eq!(acquired(&mut limiter, 1, 11), 2);
+        // The clock goes backwards, nothing is refilled.
+        assert_eq!(acquired(&mut limiter, 1, 9), 0);
+        assert_eq!(acquired(&mut limiter, 1, 100), 3);
+    }
+}
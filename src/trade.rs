@@ -0,0 +1,22 @@
+//! Executed trades.
+
+use crate::bids::Side;
+
+/// An execution of an incoming (taker) bid against a bid resting in the book (maker).
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_derive::Serialize, serde_derive::Deserialize)
+)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trade {
+    /// User id of the bid that was resting in the book.
+    pub maker_user_id: u64,
+    /// User id of the incoming bid.
+    pub taker_user_id: u64,
+    /// Side of the incoming bid.
+    pub taker_side: Side,
+    /// Price of the trade, which is always the price of the resting bid.
+    pub price: u64,
+    /// Amount of traded items.
+    pub amount: u64,
+}
@@ -0,0 +1,314 @@
+//! Slicing a parent order into child orders evenly spread over time (TWAP).
+
+use crate::{
+    bids::{Bid, BidProcessingType, Side},
+    engine::MatchingEngine,
+    instrument::Rejection,
+    latency::LatencyModel,
+};
+
+/// A parent order to execute over a period of time, in equal slices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Twap {
+    pub side: Side,
+    /// Amount of items to execute.
+    pub amount: u64,
+    /// The worst price the child orders may execute at, market child orders if `None`.
+    pub limit_price: Option<u64>,
+    pub user_id: u64,
+    /// Time of the first slice, in seconds.
+    pub start: u64,
+    /// Time from the first slice to the end of the last one, in seconds.
+    pub duration: u64,
+    /// Number of slices, at least one.
+    pub slices: u64,
+}
+
+/// A child order of a [`Twap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildOrder {
+    /// Time the order has been decided on.
+    pub time: u64,
+    /// Time the order has reached the book.
+    pub arrival: u64,
+    /// Amount of items the order has been submitted for.
+    pub amount: u64,
+    /// Amount of items that have been filled.
+    pub filled: u64,
+    /// Total price of the filled items.
+    pub notional: u128,
+    /// Why the order has been rejected, if it has.
+    pub rejection: Option<Rejection>,
+}
+
+/// Outcome of a [`Twap`] execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwapReport {
+    pub side: Side,
+    /// Mid price of the book when the parent order arrived, if both sides were present.
+    pub arrival_price: Option<u64>,
+    pub children: Vec<ChildOrder>,
+}
+
+impl TwapReport {
+    /// Amount of items filled by all the child orders.
+    pub fn filled(&self) -> u64 {
+        self.children.iter().map(|child| child.filled).sum()
+    }
+
+    /// Average price of the filled items, if any.
+    pub fn average_price(&self) -> Option<f64> {
+        let filled = self.filled();
+        if filled == 0 {
+            return None;
+        }
+        let notional: u128 = self.children.iter().map(|child| child.notional).sum();
+        Some(notional as f64 / filled as f64)
+    }
+
+    /// How much worse the average price is than the arrival price, in basis points of the
+    /// arrival price: positive if buying at a higher price or selling at a lower one.
+    pub fn slippage_bps(&self) -> Option<f64> {
+        let arrival = self.arrival_price? as f64;
+        let difference = match self.side {
+            Side::Buy => self.average_price()? - arrival,
+            Side::Sell => arrival - self.average_price()?,
+        };
+        Some(difference / arrival * 10_000.)
+    }
+}
+
+impl Twap {
+    /// Time of the given slice, in seconds.
+    fn slice_time(&self, slice: u64) -> u64 {
+        let offset = u128::from(self.duration) * u128::from(slice) / u128::from(self.slices);
+        self.start + offset as u64
+    }
+
+    /// Amount the first `slices` slices execute together.
+    fn target(&self, slices: u64) -> u64 {
+        (u128::from(self.amount) * u128::from(slices) / u128::from(self.slices)) as u64
+    }
+
+    /// Executes the parent order on the book of the `symbol`, advancing the clock of the engine
+    /// from slice to slice (unless it's ahead).
+    ///
+    /// Before every slice the `market` is called with the engine and the time of the slice, e.g.
+    /// to replay the bids of other users submitted in between. Every slice is an
+    /// `ImmediateOrCancel` child order (or a `Market` one, without a limit price), which also
+    /// takes over what the previous slices haven't filled.
+    pub fn execute(
+        &self,
+        engine: &mut MatchingEngine,
+        symbol: &str,
+        market: impl FnMut(&mut MatchingEngine, u64),
+    ) -> TwapReport {
+        self.execute_with_latency(engine, symbol, &mut LatencyModel::default(), market)
+    }
+
+    /// Same as [`Twap::execute`], but the child orders reach the book the latency of the user
+    /// (drawn from the `latencies`) after the times of their slices.
+    ///
+    /// The `market` is called with the arrival times instead, and the clock of the engine
+    /// advances to them. Orders of the user arrive in the order they are sent in. A slice only
+    /// knows the fills of the child orders that have arrived by its time, and counts the ones
+    /// still on their way as filled in full, so the parent order is never overfilled.
+    pub fn execute_with_latency(
+        &self,
+        engine: &mut MatchingEngine,
+        symbol: &str,
+        latencies: &mut LatencyModel,
+        mut market: impl FnMut(&mut MatchingEngine, u64),
+    ) -> TwapReport {
+        let slices = self.slices.max(1);
+        let twap = Twap { slices, ..*self };
+        if twap.start > engine.now() {
+            engine.advance_time(twap.start);
+        }
+        let mut report = TwapReport {
+            side: twap.side,
+            arrival_price: engine.book(symbol).and_then(|book| book.mid_price()),
+            children: Vec::with_capacity(slices as usize),
+        };
+        let bid_type = match twap.limit_price {
+            Some(_) => BidProcessingType::ImmediateOrCancel,
+            None => BidProcessingType::Market,
+        };
+        let price = twap.limit_price.unwrap_or_default();
+        let mut last_arrival = 0;
+        for slice in 0..slices {
+            let time = twap.slice_time(slice);
+            let committed: u64 = report
+                .children
+                .iter()
+                .map(|child| {
+                    if child.arrival <= time {
+                        child.filled
+                    } else {
+                        child.amount
+                    }
+                })
+                .sum();
+            let amount = twap.target(slice + 1).saturating_sub(committed);
+            let latency = if amount == 0 {
+                0
+            } else {
+                latencies.sample(twap.user_id)
+            };
+            let arrival = time.saturating_add(latency).max(last_arrival);
+            last_arrival = arrival;
+            if arrival > engine.now() {
+                engine.advance_time(arrival);
+            }
+            market(engine, arrival);
+            if amount == 0 {
+                continue;
+            }
+            let result = match twap.side {
+                Side::Sell => {
+                    let selling_bid = Bid::empty()
+                        .price(price)
+                        .amount(amount)
+                        .user_id(twap.user_id);
+                    engine.process_selling(symbol, selling_bid, bid_type)
+                }
+                Side::Buy => {
+                    let buying_bid = Bid::empty()
+                        .price(price)
+                        .amount(amount)
+                        .user_id(twap.user_id);
+                    engine.process_buying(symbol, buying_bid, bid_type)
+                }
+            };
+            let mut child = ChildOrder {
+                time,
+                arrival,
+                amount,
+                filled: 0,
+                notional: 0,
+                rejection: None,
+            };
+            match result {
+                Ok(result) => {
+                    for trade in &result.trades {
+                        child.filled += trade.amount;
+                        child.notional += u128::from(trade.price) * u128::from(trade.amount);
+                    }
+                }
+                Err(rejection) => child.rejection = Some(rejection),
+            }
+            report.children.push(child);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::latency::Latency;
+
+    #[test]
+    fn test_twap() {
+        let mut engine = MatchingEngine::new();
+        let selling_bid = Bid::empty().price(100).amount(4).user_id(1);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        let buying_bid = Bid::empty().price(98).amount(4).user_id(1);
+        engine
+            .process_buying("ABC", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+
+        let twap = Twap {
+            side: Side::Buy,
+            amount: 10,
+            limit_price: Some(102),
+            user_id: 2,
+            start: 100,
+            duration: 60,
+            slices: 3,
+        };
+        let report = twap.execute(&mut engine, "ABC", |engine, time| {
+            // The sellers raise the price as the time goes.
+            if time > 100 {
+                let price = 100 + (time - 100) / 20;
+                let selling_bid = selling_bid.price(price).amount(2);
+                engine
+                    .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+                    .unwrap();
+            }
+        });
+        assert_eq!(engine.now(), 140);
+        assert_eq!(report.arrival_price, Some(99));
+        let children: Vec<_> = report
+            .children
+            .iter()
+            .map(|child| (child.time, child.amount, child.filled))
+            .collect();
+        // The last slice runs out of the selling bids within the limit price.
+        assert_eq!(children, [(100, 3, 3), (120, 3, 3), (140, 4, 2)]);
+        assert_eq!(report.filled(), 8);
+        assert_eq!(report.average_price(), Some(806. / 8.));
+        assert_eq!(report.slippage_bps(), Some((100.75 - 99.) / 99. * 10_000.));
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut engine = MatchingEngine::new();
+        let selling_bid = Bid::empty().price(100).amount(4).user_id(1);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+
+        let twap = Twap {
+            side: Side::Buy,
+            amount: 9,
+            limit_price: Some(100),
+            user_id: 2,
+            start: 100,
+            duration: 60,
+            slices: 3,
+        };
+        // The child orders take longer to arrive than the time between the slices.
+        let mut latencies = LatencyModel::new(Latency::Fixed(1), 0).user(2, Latency::Fixed(30));
+        let mut times = Vec::new();
+        let report =
+            twap.execute_with_latency(&mut engine, "ABC", &mut latencies, |engine, time| {
+                times.push(time);
+                if time == 170 {
+                    engine
+                        .process_selling("ABC", selling_bid.amount(5), BidProcessingType::Limit)
+                        .unwrap();
+                }
+            });
+        assert_eq!(times, [130, 150, 170]);
+        assert_eq!(engine.now(), 170);
+        let children: Vec<_> = report
+            .children
+            .iter()
+            .map(|child| (child.time, child.arrival, child.amount, child.filled))
+            .collect();
+        // The last slice is decided on before the second child order arrives, so its shortfall
+        // isn't taken over.
+        assert_eq!(
+            children,
+            [(100, 130, 3, 3), (120, 150, 3, 1), (140, 170, 3, 3)]
+        );
+        assert_eq!(report.filled(), 7);
+
+        // Child orders never overtake each other.
+        let mut engine = MatchingEngine::new();
+        let mut latencies =
+            LatencyModel::new(Latency::Fixed(0), 0).user(2, Latency::Uniform { min: 0, max: 50 });
+        let report = twap.execute_with_latency(&mut engine, "ABC", &mut latencies, |_, _| {});
+        assert!(report
+            .children
+            .windows(2)
+            .all(|pair| pair[0].arrival <= pair[1].arrival));
+        assert!(report
+            .children
+            .iter()
+            .all(|child| child.time <= child.arrival && child.arrival <= child.time + 50));
+    }
+}
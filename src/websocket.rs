@@ -0,0 +1,888 @@
+//! Market data over WebSocket.
+//!
+//! Subscribers choose the data they receive: they send JSON text messages to subscribe to (or
+//! unsubscribe from) a channel of the book of a symbol, and the server acknowledges each of them:
+//!
+//! ```norun
+//! {"op":"subscribe","symbol":"ABC","channel":"depth"}
+//! {"seq":1,"type":"subscribed","symbol":"ABC","channel":"depth"}
+//! ```
+//!
+//! The channels are `trades`, `depth` (price levels) and `bbo` (the best bid and offer). The
+//! server sends JSON text messages, each with a sequence number (counted for every subscriber
+//! separately, so a gap means a lost message) and the symbol of the book. Price level updates
+//! carry the total displayed amount at the level, zero meaning the level is gone, and the
+//! [checksum](crate::Depth::checksum) of the top ten levels of the book once the update is
+//! applied (the first message is wrapped here):
+//!
+//! ```norun
+//! {"seq":7,"symbol":"ABC","type":"level","side":"sell","price":100,"amount":25,
+//!  "checksum":2798320264}
+//! {"seq":8,"symbol":"ABC","type":"trade","taker_side":"buy","price":100,"amount":5}
+//! {"seq":9,"symbol":"ABC","type":"bbo","bid":{"price":99,"amount":3},"ask":null}
+//! ```
+//!
+//! Subscribing to the depth of a book first sends its current levels, with the checksum of the
+//! whole snapshot; subscribing to its best bid and offer sends the current ones. Requests that
+//! can't be understood are answered with `{"seq":..,"type":"error","message":..}`.
+//!
+//! A server [bound with tokens](WebSocketPublisher::bind_with_tokens) only accepts the clients
+//! that present one of them, either in the `token` parameter of the query
+//! (`ws://127.0.0.1:9001/?token=...`) or as `Authorization: Bearer ...`, and answers the others
+//! with `401 Unauthorized`. Each token identifies a user; the feed is public market data, so the
+//! user ids are only logged.
+//!
+//! The server speaks plain `ws://` only: the reader and the writer threads of a subscriber each
+//! own a clone of its TCP stream, and a TLS session can't be split like that. Clients that require
+//! `wss://` connect through a TLS-terminating proxy (e.g. stunnel or nginx) in front of the
+//! server; without one, the tokens travel in the clear.
+
+use crate::{
+    bids::Side,
+    feed::{FeedBook, FeedEvent},
+    listener::OrderBookListener,
+    trade::Trade,
+};
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The magic string of the opening handshake, see RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Number of the levels of each side the checksums are computed of.
+const CHECKSUM_LEVELS: usize = 10;
+
+/// How long a write to a subscriber might take before it's disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a client might take to send its opening handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of the frames queued for a subscriber, one that falls this far behind is disconnected.
+const QUEUE_LEN: usize = 1024;
+
+/// The longest message a subscriber may send.
+const MAX_REQUEST_LEN: u64 = 4096;
+
+/// A kind of the data of a book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Channel {
+    Trades,
+    Depth,
+    Bbo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Op {
+    Subscribe,
+    Unsubscribe,
+}
+
+/// A message of a subscriber.
+#[derive(Debug, Deserialize)]
+struct Request {
+    op: Op,
+    symbol: String,
+    channel: Channel,
+}
+
+/// The best bid and offer of a book, as prices and amounts.
+type Bbo = (Option<(u64, u64)>, Option<(u64, u64)>);
+
+struct Subscriber {
+    id: u64,
+    /// The stream, to cut off a subscriber that falls behind.
+    stream: TcpStream,
+    /// Frames to be written to the stream by the writer thread of the subscriber.
+    queue: SyncSender<Vec<u8>>,
+    seq: u64,
+    channels: BTreeSet<(String, Channel)>,
+}
+
+impl Subscriber {
+    /// Sends a message with the next sequence number.
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        self.enqueue(text_frame(&message.to_string()))
+    }
+
+    /// Queues a frame for the writer thread, without waiting for it.
+    fn enqueue(&self, frame: Vec<u8>) -> io::Result<()> {
+        self.queue.try_send(frame).map_err(|e| match e {
+            TrySendError::Full(_) => {
+                io::Error::new(io::ErrorKind::WouldBlock, "the subscriber is too slow")
+            }
+            TrySendError::Disconnected(_) => io::ErrorKind::BrokenPipe.into(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    next_id: u64,
+    books: BTreeMap<String, FeedBook>,
+    /// The best bids and offers last published, by symbol.
+    bbo: BTreeMap<String, Bbo>,
+    subscribers: Vec<Subscriber>,
+    /// User ids by the tokens of the clients allowed to connect, if the server is authenticated.
+    tokens: Option<BTreeMap<String, u64>>,
+}
+
+impl Shared {
+    /// Sends a message to every subscriber of the channel of the book, disconnecting the ones
+    /// that fail to receive it.
+    fn publish(&mut self, symbol: &str, channel: Channel, message: Value) {
+        let key = (symbol.to_string(), channel);
+        self.subscribers.retain_mut(|subscriber| {
+            if !subscriber.channels.contains(&key) {
+                return true;
+            }
+            match subscriber.send(message.clone()) {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!("Disconnect subscriber {}: {}", subscriber.id, e);
+                    let _ = subscriber.stream.shutdown(Shutdown::Both);
+                    false
+                }
+            }
+        });
+    }
+
+    /// Current levels of a book.
+    fn snapshot(&self, symbol: &str) -> Vec<Value> {
+        let book = match self.books.get(symbol) {
+            Some(book) => book,
+            None => return Vec::new(),
+        };
+        let depth = book.depth(usize::MAX);
+        let checksum = book.depth(CHECKSUM_LEVELS).checksum();
+        depth
+            .selling
+            .iter()
+            .map(|level| (Side::Sell, level))
+            .chain(depth.buying.iter().map(|level| (Side::Buy, level)))
+            .map(|(side, &(price, amount, _count))| {
+                level_message(symbol, side, price, amount, checksum)
+            })
+            .collect()
+    }
+
+    /// Handles a message of the subscriber, replying to it.
+    fn handle(&mut self, id: u64, text: &str) -> io::Result<()> {
+        let request = serde_json::from_str::<Request>(text);
+        let mut replies = Vec::new();
+        match &request {
+            Ok(request) => {
+                let kind = match request.op {
+                    Op::Subscribe => "subscribed",
+                    Op::Unsubscribe => "unsubscribed",
+                };
+                replies.push(json!({
+                    "type": kind,
+                    "symbol": request.symbol,
+                    "channel": request.channel,
+                }));
+                if request.op == Op::Subscribe {
+                    match request.channel {
+                        Channel::Trades => {}
+                        Channel::Depth => replies.extend(self.snapshot(&request.symbol)),
+                        Channel::Bbo => {
+                            let bbo = self
+                                .books
+                                .get(&request.symbol)
+                                .map(best)
+                                .unwrap_or_default();
+                            replies.push(bbo_message(&request.symbol, bbo));
+                        }
+                    }
+                }
+            }
+            Err(e) => replies.push(json!({"type": "error", "message": e.to_string()})),
+        }
+        let subscriber = match self.subscribers.iter_mut().find(|s| s.id == id) {
+            Some(subscriber) => subscriber,
+            None => return Ok(()),
+        };
+        if let Ok(request) = request {
+            let key = (request.symbol, request.channel);
+            match request.op {
+                Op::Subscribe => subscriber.channels.insert(key),
+                Op::Unsubscribe => subscriber.channels.remove(&key),
+            };
+        }
+        replies
+            .into_iter()
+            .try_for_each(|reply| subscriber.send(reply))
+    }
+
+    /// Sends a frame to the subscriber as is.
+    fn reply(&mut self, id: u64, frame: Vec<u8>) -> io::Result<()> {
+        match self.subscribers.iter().find(|s| s.id == id) {
+            Some(subscriber) => subscriber.enqueue(frame),
+            None => Ok(()),
+        }
+    }
+
+    fn disconnect(&mut self, id: u64) {
+        self.subscribers.retain(|subscriber| subscriber.id != id);
+    }
+}
+
+/// A WebSocket server that publishes the book updates and the trades to its subscribers.
+///
+/// Subscribers are accepted in a background thread and every connection is handled by a thread of
+/// its own, from the handshake on. The [listeners](WebSocketPublisher::listener) of the books
+/// queue the messages as the events happen, and a writer thread per subscriber sends them, so a
+/// slow subscriber never holds up the matching: one that falls too far behind is disconnected.
+#[derive(Clone)]
+pub struct WebSocketPublisher {
+    shared: Arc<Mutex<Shared>>,
+    local_addr: SocketAddr,
+}
+
+impl WebSocketPublisher {
+    /// Starts accepting subscribers on the given address.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::start(addr, None)
+    }
+
+    /// Starts accepting subscribers that present one of the `tokens` (mapped to the user ids
+    /// they identify) on the given address.
+    pub fn bind_with_tokens(
+        addr: impl ToSocketAddrs,
+        tokens: BTreeMap<String, u64>,
+    ) -> io::Result<Self> {
+        Self::start(addr, Some(tokens))
+    }
+
+    fn start(addr: impl ToSocketAddrs, tokens: Option<BTreeMap<String, u64>>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Mutex::new(Shared {
+            tokens,
+            ..Shared::default()
+        }));
+        let accepting = Arc::clone(&shared);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Unable to accept a subscriber: {}", e);
+                        continue;
+                    }
+                };
+                let shared = Arc::clone(&accepting);
+                thread::spawn(move || connect(&shared, stream));
+            }
+        });
+        Ok(WebSocketPublisher { shared, local_addr })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Number of the connected subscribers.
+    pub fn subscribers(&self) -> usize {
+        lock(&self.shared).subscribers.len()
+    }
+
+    /// Waits until a subscriber is connected, for at most the `timeout`.
+    ///
+    /// Returns whether there are any subscribers.
+    pub fn wait_for_subscriber(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.subscribers() == 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::sleep((deadline - now).min(Duration::from_millis(100)));
+        }
+        true
+    }
+
+    /// A listener that publishes the events of the book of the given symbol.
+    ///
+    /// The listener expects to be registered before any bids are added to the book. Use it with
+    /// [`MatchingEngine::add_listener_factory`](crate::MatchingEngine::add_listener_factory) to
+    /// publish every book of an engine.
+    pub fn listener(&self, symbol: &str) -> BookPublisher {
+        BookPublisher {
+            shared: Arc::clone(&self.shared),
+            symbol: symbol.to_string(),
+        }
+    }
+}
+
+/// Publishes the events of a single book, see [`WebSocketPublisher::listener`].
+pub struct BookPublisher {
+    shared: Arc<Mutex<Shared>>,
+    symbol: String,
+}
+
+impl OrderBookListener for BookPublisher {
+    fn on_trade(&mut self, trade: &Trade) {
+        let message = json!({
+            "symbol": self.symbol,
+            "type": "trade",
+            "taker_side": side_name(trade.taker_side),
+            "price": trade.price,
+            "amount": trade.amount,
+        });
+        lock(&self.shared).publish(&self.symbol, Channel::Trades, message);
+    }
+
+    fn on_feed_event(&mut self, event: &FeedEvent) {
+        let mut shared = lock(&self.shared);
+        let book = shared.books.entry(self.symbol.clone()).or_default();
+        let level = match *event {
+            FeedEvent::AddOrder { side, price, .. } => Some((side, price)),
+            FeedEvent::Execute { order_id, .. }
+            | FeedEvent::Reduce { order_id, .. }
+            | FeedEvent::Cancel { order_id } => book.order_level(order_id),
+            FeedEvent::Trade { .. } => None,
+        };
+        if let Err(e) = book.apply_event(*event) {
+            warn!("The book of {:?} is out of sync: {}", self.symbol, e);
+        }
+        let (side, price) = match level {
+            Some(level) => level,
+            None => return,
+        };
+        let amount = book.level_amount(side, price);
+        let checksum = book.depth(CHECKSUM_LEVELS).checksum();
+        let bbo = best(book);
+        let message = level_message(&self.symbol, side, price, amount, checksum);
+        shared.publish(&self.symbol, Channel::Depth, message);
+        if shared.bbo.get(&self.symbol) != Some(&bbo) {
+            shared.bbo.insert(self.symbol.clone(), bbo);
+            shared.publish(&self.symbol, Channel::Bbo, bbo_message(&self.symbol, bbo));
+        }
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> std::sync::MutexGuard<'_, Shared> {
+    // The state stays consistent even if a thread has panicked while holding the lock.
+    shared
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+fn level_message(symbol: &str, side: Side, price: u64, amount: u64, checksum: u32) -> Value {
+    json!({
+        "symbol": symbol,
+        "type": "level",
+        "side": side_name(side),
+        "price": price,
+        "amount": amount,
+        "checksum": checksum,
+    })
+}
+
+/// The best bid and offer of a book.
+fn best(book: &FeedBook) -> Bbo {
+    let depth = book.depth(1);
+    let level = |&(price, amount, _count): &(u64, u64, usize)| (price, amount);
+    (
+        depth.buying.first().map(level),
+        depth.selling.first().map(level),
+    )
+}
+
+fn bbo_message(symbol: &str, (bid, ask): Bbo) -> Value {
+    let level = |level: Option<(u64, u64)>| {
+        level.map(|(price, amount)| json!({"price": price, "amount": amount}))
+    };
+    json!({
+        "symbol": symbol,
+        "type": "bbo",
+        "bid": level(bid),
+        "ask": level(ask),
+    })
+}
+
+/// Handles a connection: accepts the subscriber and serves its requests until it disconnects.
+fn connect(shared: &Mutex<Shared>, stream: TcpStream) {
+    let (id, reader) = match accept(shared, stream) {
+        Ok(accepted) => accepted,
+        Err(e) => {
+            warn!("Unable to accept a subscriber: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = serve(shared, id, reader) {
+        debug!("Subscriber {} is gone: {}", id, e);
+    }
+    lock(shared).disconnect(id);
+}
+
+/// Performs the opening handshake, registers the subscriber and starts its writer thread.
+///
+/// Returns the id of the subscriber and the reader of its requests.
+fn accept(
+    shared: &Mutex<Shared>,
+    mut stream: TcpStream,
+) -> io::Result<(u64, BufReader<TcpStream>)> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut key = None;
+    let mut token = None;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut first = true;
+    loop {
+        let mut line = String::new();
+        (&mut reader).take(MAX_REQUEST_LEN).read_line(&mut line)?;
+        if !line.ends_with('\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the handshake is incomplete",
+            ));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if first {
+            first = false;
+            token = query_token(line);
+        } else if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("Authorization") {
+                if let Some(bearer) = value.trim().strip_prefix("Bearer ") {
+                    token = Some(bearer.trim().to_string());
+                }
+            }
+        }
+    }
+    let key =
+        key.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a WebSocket handshake"))?;
+    let authenticated = match &lock(shared).tokens {
+        Some(tokens) => token
+            .and_then(|token| tokens.get(&token).copied())
+            .map(Some)
+            .ok_or(()),
+        None => Ok(None),
+    };
+    let user_id = match authenticated {
+        Ok(user_id) => user_id,
+        Err(()) => {
+            stream.write_all(
+                b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "no valid token",
+            ));
+        }
+    };
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    let (queue, frames) = mpsc::sync_channel(QUEUE_LEN);
+    let writer = stream.try_clone()?;
+    let mut locked = lock(shared);
+    let id = locked.next_id;
+    locked.next_id += 1;
+    if let Some(user_id) = user_id {
+        debug!("Subscriber {} is authenticated as user {}", id, user_id);
+    }
+    locked.subscribers.push(Subscriber {
+        id,
+        stream,
+        queue,
+        seq: 0,
+        channels: BTreeSet::new(),
+    });
+    thread::spawn(move || write_frames(id, writer, frames));
+    Ok((id, reader))
+}
+
+/// Writes the queued frames to a subscriber until it's disconnected, then closes the stream.
+fn write_frames(id: u64, mut stream: TcpStream, frames: Receiver<Vec<u8>>) {
+    for frame in frames {
+        if let Err(e) = stream.write_all(&frame) {
+            debug!("Unable to write to subscriber {}: {}", id, e);
+            break;
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+/// Serves the requests of a subscriber until it disconnects.
+///
+/// Fragmented messages are not supported: requests are small.
+fn serve(shared: &Mutex<Shared>, id: u64, mut reader: impl Read) -> io::Result<()> {
+    loop {
+        let (opcode, payload) = read_frame(&mut reader)?;
+        match opcode {
+            0x1 => {
+                let text = String::from_utf8_lossy(&payload);
+                lock(shared).handle(id, &text)?;
+            }
+            0x8 => {
+                lock(shared).reply(id, frame(0x8, &payload))?;
+                return Ok(());
+            }
+            0x9 => lock(shared).reply(id, frame(0xA, &payload))?,
+            _ => {}
+        }
+    }
+}
+
+/// Reads a frame of a subscriber, returning its opcode and its unmasked payload.
+fn read_frame(r: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    r.read_exact(&mut header)?;
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            r.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0; 8];
+            r.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_REQUEST_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the message is too long",
+        ));
+    }
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        r.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    r.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((header[0] & 0x0F, payload))
+}
+
+/// The `token` parameter of the query of the request line of a handshake, if any.
+fn query_token(request_line: &str) -> Option<String> {
+    let target = request_line.split_whitespace().nth(1)?;
+    let (_path, query) = target.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|parameter| parameter.strip_prefix("token="))
+        .map(str::to_string)
+}
+
+/// The `Sec-WebSocket-Accept` value for the `Sec-WebSocket-Key` of a handshake.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// An unmasked (server to client) text frame.
+fn text_frame(text: &str) -> Vec<u8> {
+    frame(0x1, text.as_bytes())
+}
+
+/// An unmasked (server to client) final frame with the opcode.
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    let len = payload.len();
+    if len < 126 {
+        frame.push(len as u8);
+    } else if len <= usize::from(u16::MAX) {
+        frame.push(126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xEFCD_AB89,
+        0x98BA_DCFE,
+        0x1032_5476,
+        0xC3D2_E1F0,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, value) in h.iter_mut().zip(&[a, b, c, d, e]) {
+            *h = h.wrapping_add(*value);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(&h) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bids::{Bid, BidProcessingType},
+        MatchingEngine,
+    };
+
+    fn read_message(stream: &mut TcpStream) -> Value {
+        let (opcode, payload) = read_frame(stream).unwrap();
+        assert_eq!(opcode, 0x1);
+        serde_json::from_slice(&payload).unwrap()
+    }
+
+    /// A masked (client to server) text frame.
+    fn client_frame(text: &str) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | text.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+        frame
+    }
+
+    fn request(stream: &mut TcpStream, op: &str, symbol: &str, channel: &str) -> Value {
+        let request = json!({"op": op, "symbol": symbol, "channel": channel});
+        stream
+            .write_all(&client_frame(&request.to_string()))
+            .unwrap();
+        read_message(stream)
+    }
+
+    /// Connects to the server and returns the stream along with the response to the handshake.
+    fn handshake(publisher: &WebSocketPublisher, path: &str, headers: &str) -> (TcpStream, String) {
+        let mut stream = TcpStream::connect(publisher.local_addr()).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+             Sec-WebSocket-Version: 13\r\n{}\r\n",
+            path, headers
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+        (stream, String::from_utf8(response).unwrap())
+    }
+
+    #[test]
+    fn test_accept_key() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn test_publish() {
+        let publisher = WebSocketPublisher::bind("127.0.0.1:0").unwrap();
+        let mut engine = MatchingEngine::new();
+        let factory = publisher.clone();
+        engine.add_listener_factory(move |symbol| factory.listener(symbol));
+        let selling_bid = Bid::empty().price(100).amount(5).user_id(1);
+        engine
+            .process_selling("ABC", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+
+        // A client that never completes its handshake doesn't hold up the others.
+        let _idle = TcpStream::connect(publisher.local_addr()).unwrap();
+        assert!(!publisher.wait_for_subscriber(Duration::from_millis(10)));
+        let (mut stream, response) = handshake(&publisher, "/", "");
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        assert!(publisher.wait_for_subscriber(Duration::from_secs(5)));
+        assert_eq!(
+            request(&mut stream, "subscribe", "ABC", "depth"),
+            json!({"seq": 1, "type": "subscribed", "symbol": "ABC", "channel": "depth"})
+        );
+        assert_eq!(
+            read_message(&mut stream),
+            json!({"seq": 2, "symbol": "ABC", "type": "level", "side": "sell", "price": 100, "amount": 5, "checksum": 4_248_396_096_u32})
+        );
+        assert_eq!(request(&mut stream, "subscribe", "ABC", "trades")["seq"], 3);
+
+        // Nothing is published for the other symbols.
+        let buying_bid = Bid::empty().price(100).amount(3).user_id(2);
+        engine
+            .process_buying("XYZ", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+        engine
+            .process_buying("ABC", buying_bid, BidProcessingType::Limit)
+            .unwrap();
+        assert_eq!(
+            read_message(&mut stream),
+            json!({"seq": 4, "symbol": "ABC", "type": "trade", "taker_side": "buy", "price": 100, "amount": 3})
+        );
+        assert_eq!(
+            read_message(&mut stream),
+            json!({"seq": 5, "symbol": "ABC", "type": "level", "side": "sell", "price": 100, "amount": 2, "checksum": 1_667_101_923_u32})
+        );
+
+        assert_eq!(
+            request(&mut stream, "unsubscribe", "ABC", "depth")["type"],
+            "unsubscribed"
+        );
+        engine
+            .process_buying("ABC", buying_bid.amount(1), BidProcessingType::Limit)
+            .unwrap();
+        assert_eq!(read_message(&mut stream)["type"], "trade");
+        assert_eq!(request(&mut stream, "subscribe", "XYZ", "bbo")["seq"], 8);
+        assert_eq!(
+            read_message(&mut stream),
+            json!({"seq": 9, "symbol": "XYZ", "type": "bbo", "bid": {"price": 100, "amount": 3}, "ask": null})
+        );
+        let selling_bid = selling_bid.price(101);
+        engine
+            .process_selling("XYZ", selling_bid, BidProcessingType::Limit)
+            .unwrap();
+        assert_eq!(
+            read_message(&mut stream),
+            json!({"seq": 10, "symbol": "XYZ", "type": "bbo", "bid": {"price": 100, "amount": 3}, "ask": {"price": 101, "amount": 5}})
+        );
+        let error = request(&mut stream, "subscribe", "XYZ", "news");
+        assert_eq!(error["type"], "error");
+        assert!(error["message"].as_str().unwrap().contains("news"));
+    }
+
+    #[test]
+    fn test_query_token() {
+        assert_eq!(
+            query_token("GET /?token=abc HTTP/1.1"),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            query_token("GET /feed?depth=1&token=abc&x=y HTTP/1.1"),
+            Some("abc".to_string())
+        );
+        assert_eq!(query_token("GET /?tokens=abc HTTP/1.1"), None);
+        assert_eq!(query_token("GET / HTTP/1.1"), None);
+        assert_eq!(query_token("GET"), None);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tokens = vec![("secret".to_string(), 7), ("other".to_string(), 8)];
+        let publisher =
+            WebSocketPublisher::bind_with_tokens("127.0.0.1:0", tokens.into_iter().collect())
+                .unwrap();
+        let unauthorized = [
+            ("/", ""),
+            ("/?token=wrong", ""),
+            ("/", "Authorization: Bearer wrong\r\n"),
+            ("/", "Authorization: Basic secret\r\n"),
+        ];
+        for &(path, headers) in unauthorized.iter() {
+            let (_stream, response) = handshake(&publisher, path, headers);
+            assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        }
+        assert_eq!(publisher.subscribers(), 0);
+
+        let (mut stream, response) = handshake(&publisher, "/?token=secret", "");
+        assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+        assert_eq!(
+            request(&mut stream, "subscribe", "ABC", "trades")["type"],
+            "subscribed"
+        );
+        let (mut stream, response) = handshake(&publisher, "/", "Authorization: Bearer other\r\n");
+        assert!(response.starts_with("HTTP/1.1 101"), "{}", response);
+        assert_eq!(request(&mut stream, "subscribe", "ABC", "bbo")["seq"], 1);
+        assert_eq!(publisher.subscribers(), 2);
+    }
+}
@@ -0,0 +1,170 @@
+//! Golden-file tests: canned scenarios are run through the engine and everything it reports is
+//! compared with the committed output.
+//!
+//! A scenario is a `yaml` file in `tests/scenarios` with an optional engine `config` and a list of
+//! `orders` in the format of `process_reader`. Its expected output lives next to it, with the
+//! `golden` extension. After an intended change of behavior the outputs are regenerated with
+//!
+//! ```sh
+//! UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+//!
+//! and the differences are reviewed along with the change.
+
+use serde_derive::Deserialize;
+use simple_stock_matcher_experiment::{
+    dump_book_json_lines, process_reader, Config, MatchingEngine, OrderBookListener, OrderInfo,
+    Phase, ProcessingOptions, Trade,
+};
+use std::{
+    cell::RefCell,
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+#[derive(Deserialize)]
+struct Scenario {
+    #[serde(default)]
+    config: Config,
+    orders: serde_yaml::Value,
+}
+
+/// Writes the events of a book as lines of the shared output, prefixed with the book's symbol.
+struct EventLog {
+    prefix: String,
+    output: Rc<RefCell<String>>,
+}
+
+impl EventLog {
+    fn order(&self, event: &str, order: &OrderInfo) {
+        let _ = writeln!(
+            self.output.borrow_mut(),
+            "{}{}: {:?} {}@{} of user {}",
+            self.prefix,
+            event,
+            order.side,
+            order.amount,
+            order.price,
+            order.user_id
+        );
+    }
+}
+
+impl OrderBookListener for EventLog {
+    fn on_trade(&mut self, trade: &Trade) {
+        let _ = writeln!(
+            self.output.borrow_mut(),
+            "{}trade: {:?} {}@{}, taker {}, maker {}",
+            self.prefix,
+            trade.taker_side,
+            trade.amount,
+            trade.price,
+            trade.taker_user_id,
+            trade.maker_user_id
+        );
+    }
+
+    fn on_order_added(&mut self, order: &OrderInfo) {
+        self.order("add", order)
+    }
+
+    fn on_order_dropped(&mut self, order: &OrderInfo) {
+        self.order("drop", order)
+    }
+
+    fn on_order_partially_filled(&mut self, order: &OrderInfo) {
+        self.order("partial fill", order)
+    }
+
+    fn on_order_cancelled(&mut self, order: &OrderInfo) {
+        self.order("cancel", order)
+    }
+
+    fn on_order_amended(&mut self, order: &OrderInfo) {
+        self.order("amend", order)
+    }
+
+    fn on_order_expired(&mut self, order: &OrderInfo) {
+        self.order("expire", order)
+    }
+
+    fn on_phase_changed(&mut self, phase: Phase) {
+        let _ = writeln!(
+            self.output.borrow_mut(),
+            "{}phase: {:?}",
+            self.prefix,
+            phase
+        );
+    }
+}
+
+/// Runs a scenario and returns its output: the events, the rejected orders and the resting bids.
+fn run(path: &Path) -> String {
+    let scenario: Scenario = serde_yaml::from_reader(fs::File::open(path).unwrap()).unwrap();
+    let output = Rc::new(RefCell::new(String::new()));
+    let mut engine = MatchingEngine::with_config(scenario.config);
+    let events = output.clone();
+    engine.add_listener_factory(move |symbol| EventLog {
+        prefix: if symbol.is_empty() {
+            String::new()
+        } else {
+            format!("{} ", symbol)
+        },
+        output: events.clone(),
+    });
+    let orders = serde_yaml::to_string(&scenario.orders).unwrap();
+    let summary = process_reader(
+        &mut engine,
+        orders.as_bytes(),
+        ProcessingOptions { skip_invalid: true },
+    )
+    .unwrap();
+    let mut output = output.borrow().clone();
+    for (index, rejection) in &summary.rejected {
+        let _ = writeln!(output, "rejected #{}: {}", index, rejection);
+    }
+    for (index, reason) in &summary.invalid {
+        let _ = writeln!(output, "invalid #{}: {}", index, reason);
+    }
+    output.push_str("book:\n");
+    let mut book = Vec::new();
+    dump_book_json_lines(&engine, &mut book).unwrap();
+    output.push_str(&String::from_utf8(book).unwrap());
+    output
+}
+
+#[test]
+fn golden() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut scenarios: Vec<PathBuf> =
+        fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "yaml")
+            })
+            .collect();
+    scenarios.sort();
+    assert!(!scenarios.is_empty(), "No scenarios found");
+    let mut mismatched = Vec::new();
+    for scenario in &scenarios {
+        let output = run(scenario);
+        let golden = scenario.with_extension("golden");
+        if update {
+            fs::write(&golden, &output).unwrap();
+        } else if fs::read_to_string(&golden).ok().as_deref() != Some(&output) {
+            eprintln!("{}:\n{}", scenario.display(), output);
+            mismatched.push(scenario.display().to_string());
+        }
+    }
+    assert!(
+        mismatched.is_empty(),
+        "Outputs of {:?} differ from the golden files, rerun with UPDATE_GOLDEN=1 if the \
+         changes are intended",
+        mismatched
+    );
+}
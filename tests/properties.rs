@@ -0,0 +1,158 @@
+//! Properties of an order book fed with random streams of orders.
+
+use proptest::prelude::*;
+use simple_stock_matcher_experiment::{
+    bids::{Bid, BidProcessingType, Side},
+    Cancellation, FeedEvent, OrderBook, OrderBookListener, OrderId, OrderInfo, Trade,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Submit {
+        side: Side,
+        price: u64,
+        amount: u64,
+        display: Option<u64>,
+        user_id: u64,
+        bid_type: BidProcessingType,
+    },
+    /// Cancels one of the submitted bids, picked by the index modulo the number of bids.
+    Cancel(usize),
+}
+
+fn bid_type() -> impl Strategy<Value = BidProcessingType> {
+    prop_oneof![
+        4 => Just(BidProcessingType::Limit),
+        1 => Just(BidProcessingType::Market),
+        1 => Just(BidProcessingType::ImmediateOrCancel),
+        1 => Just(BidProcessingType::FillOrKill),
+        1 => Just(BidProcessingType::PostOnly),
+    ]
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    let submit = (
+        prop_oneof![Just(Side::Sell), Just(Side::Buy)],
+        90..110u64,
+        1..50u64,
+        proptest::option::weighted(0.2, 1..10u64),
+        0..5u64,
+        bid_type(),
+    )
+        .prop_map(
+            |(side, price, amount, display, user_id, bid_type)| Action::Submit {
+                side,
+                price,
+                amount,
+                display,
+                user_id,
+                bid_type,
+            },
+        );
+    prop_oneof![
+        8 => submit,
+        1 => any::<usize>().prop_map(Action::Cancel),
+    ]
+}
+
+/// Trades along with the ids of their makers, and the dropped amounts.
+#[derive(Default)]
+struct Log {
+    trades: Vec<(Trade, Option<OrderId>)>,
+    dropped: u64,
+}
+
+struct Recorder(Rc<RefCell<Log>>);
+
+impl OrderBookListener for Recorder {
+    fn on_trade(&mut self, trade: &Trade) {
+        self.0.borrow_mut().trades.push((*trade, None));
+    }
+
+    fn on_order_dropped(&mut self, order: &OrderInfo) {
+        self.0.borrow_mut().dropped += order.amount;
+    }
+
+    fn on_feed_event(&mut self, event: &FeedEvent) {
+        // Every trade is followed by the execution of its maker.
+        if let FeedEvent::Execute { order_id, .. } = event {
+            if let Some((_, maker)) = self.0.borrow_mut().trades.last_mut() {
+                maker.get_or_insert(*order_id);
+            }
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn quantity_is_conserved(actions in proptest::collection::vec(action(), 1..200)) {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let mut order_book = OrderBook::empty();
+        order_book.add_listener(Recorder(log.clone()));
+        let mut submitted = 0;
+        let mut cancelled = 0;
+        let mut limits = HashMap::new();
+        let mut order_ids = Vec::new();
+        for action in actions {
+            match action {
+                Action::Submit { side, price, amount, display, user_id, bid_type } => {
+                    let result = match side {
+                        Side::Sell => {
+                            let mut bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+                            if let Some(display) = display {
+                                bid = bid.display(display);
+                            }
+                            order_book.process_selling(bid, bid_type)
+                        }
+                        Side::Buy => {
+                            let mut bid = Bid::empty().price(price).amount(amount).user_id(user_id);
+                            if let Some(display) = display {
+                                bid = bid.display(display);
+                            }
+                            order_book.process_buying(bid, bid_type)
+                        }
+                    };
+                    submitted += amount;
+                    limits.insert(result.order_id, (side, price));
+                    order_ids.push(result.order_id);
+                    for trade in &result.trades {
+                        prop_assert_ne!(trade.maker_user_id, trade.taker_user_id);
+                        prop_assert_eq!(trade.taker_side, side);
+                        if bid_type != BidProcessingType::Market {
+                            match side {
+                                Side::Buy => prop_assert!(trade.price <= price),
+                                Side::Sell => prop_assert!(trade.price >= price),
+                            }
+                        }
+                    }
+                }
+                Action::Cancel(index) => {
+                    if order_ids.is_empty() {
+                        continue;
+                    }
+                    let order_id = order_ids[index % order_ids.len()];
+                    if let Cancellation::Cancelled(order) | Cancellation::PartiallyFilled(order) =
+                        order_book.cancel(order_id)
+                    {
+                        cancelled += order.amount;
+                    }
+                }
+            }
+        }
+        let log = log.borrow();
+        for (trade, maker) in &log.trades {
+            let maker = maker.expect("A trade without an executed maker");
+            let (maker_side, maker_price) = limits[&maker];
+            prop_assert_ne!(maker_side, trade.taker_side);
+            prop_assert_eq!(trade.price, maker_price);
+        }
+        let traded: u64 = log.trades.iter().map(|(trade, _)| trade.amount).sum();
+        let resting: u64 = order_ids
+            .iter()
+            .filter_map(|&order_id| order_book.get(order_id))
+            .map(|status| status.remaining)
+            .sum();
+        prop_assert_eq!(submitted, 2 * traded + resting + log.dropped + cancelled);
+    }
+}